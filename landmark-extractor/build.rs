@@ -0,0 +1,5 @@
+fn main() {
+    // Compiles the C++ glue for dlib's CNN face detector (see `src/cnn.rs`), dlib itself is linked
+    // by `dlib-face-recognition`
+    cpp_build::build("src/lib.rs");
+}
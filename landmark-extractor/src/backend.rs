@@ -0,0 +1,100 @@
+//! Face detection and landmark prediction backends
+//!
+//! Faces can be found by any [`FaceDetectorBackend`] and their landmarks predicted by any
+//! [`LandmarkBackend`], dlib's detectors and predictors are both.
+
+use dlib_face_recognition::FaceDetectorTrait;
+use dlib_face_recognition::ImageMatrix;
+use dlib_face_recognition::LandmarkPredictorTrait;
+
+use crate::ExtractError;
+use crate::Landmarks;
+use crate::Rect;
+
+#[cfg(feature = "onnx")]
+mod face_mesh;
+#[cfg(feature = "onnx")]
+mod onnx;
+#[cfg(feature = "rustface")]
+mod rustface;
+
+#[cfg(feature = "onnx")]
+pub use self::face_mesh::FaceMeshPredictor;
+#[cfg(feature = "onnx")]
+pub use self::onnx::OnnxDetector;
+#[cfg(feature = "rustface")]
+pub use self::rustface::RustFaceDetector;
+
+/// Something that can find faces in an image
+pub trait FaceDetectorBackend {
+    /// Find the bounding boxes of all faces in this image, and their confidence score if the backend
+    /// reports one
+    fn detect(&self, image: &image::RgbImage) -> Result<Vec<(Rect, Option<f32>)>, ExtractError>;
+}
+
+/// Discards the faces found by a detector if their bounding box is smaller than `min_size` pixels
+/// (on its shortest side)
+pub struct MinFaceSize<'a, D: ?Sized> {
+    pub detector: &'a D,
+    pub min_size: u32,
+}
+
+impl<D: FaceDetectorBackend + ?Sized> FaceDetectorBackend for MinFaceSize<'_, D> {
+    fn detect(&self, image: &image::RgbImage) -> Result<Vec<(Rect, Option<f32>)>, ExtractError> {
+        let mut faces = self.detector.detect(image)?;
+        faces.retain(|(face, _)| face.width().min(face.height()) >= i64::from(self.min_size));
+        Ok(faces)
+    }
+}
+
+/// Discards the faces found by a detector if their confidence is below `min_confidence`
+///
+/// Faces without a confidence are kept
+pub struct MinConfidence<'a, D: ?Sized> {
+    pub detector: &'a D,
+    pub min_confidence: f32,
+}
+
+impl<D: FaceDetectorBackend + ?Sized> FaceDetectorBackend for MinConfidence<'_, D> {
+    fn detect(&self, image: &image::RgbImage) -> Result<Vec<(Rect, Option<f32>)>, ExtractError> {
+        let mut faces = self.detector.detect(image)?;
+        faces.retain(|(_, confidence)| !matches!(confidence, Some(c) if *c < self.min_confidence));
+        Ok(faces)
+    }
+}
+
+/// All of dlib's face detectors are backends
+impl<T: FaceDetectorTrait + ?Sized> FaceDetectorBackend for T {
+    fn detect(&self, image: &image::RgbImage) -> Result<Vec<(Rect, Option<f32>)>, ExtractError> {
+        Ok(self
+            .face_locations(&ImageMatrix::from_image(image))
+            .iter()
+            .map(|&face| (face.into(), None))
+            .collect())
+    }
+}
+
+/// Something that can predict the landmarks of a face
+pub trait LandmarkBackend {
+    /// Predict the landmarks of each of these faces (given by their bounding boxes)
+    fn predict_landmarks(
+        &self,
+        image: &image::RgbImage,
+        faces: &[Rect],
+    ) -> Result<Vec<Landmarks>, ExtractError>;
+}
+
+/// All of dlib's landmark predictors are backends
+impl<T: LandmarkPredictorTrait + ?Sized> LandmarkBackend for T {
+    fn predict_landmarks(
+        &self,
+        image: &image::RgbImage,
+        faces: &[Rect],
+    ) -> Result<Vec<Landmarks>, ExtractError> {
+        let image = ImageMatrix::from_image(image);
+        Ok(faces
+            .iter()
+            .map(|face| self.face_landmarks(&image, &face.clone().into()).into())
+            .collect())
+    }
+}
@@ -0,0 +1,107 @@
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+
+use ort::session::Session;
+use ort::value::Tensor;
+
+use super::LandmarkBackend;
+use crate::ExtractError;
+use crate::LandmarkSchema;
+use crate::Landmarks;
+use crate::Rect;
+
+/// Size of the (square) input of the model
+const INPUT_SIZE: u32 = 192;
+/// How much bigger than the detected face the crop passed to the model is
+const CROP_SCALE: f32 = 1.5;
+
+/// Dense landmark predictor running MediaPipe's
+/// [FaceMesh](https://github.com/google/mediapipe/blob/master/docs/solutions/face_mesh.md) model
+/// (converted to ONNX) through ONNX Runtime
+///
+/// Predicts 468 landmarks ([`LandmarkSchema::FaceMesh`]) per face. Expects a `1x192x192x3` input
+/// with values in `[0, 1]` and the landmarks (`x, y, z` in input pixels) as the first output.
+pub struct FaceMeshPredictor {
+    /// Running the model needs `&mut`
+    session: Mutex<Session>,
+}
+
+impl FaceMeshPredictor {
+    /// Load the ONNX model from this path
+    pub fn open(model: impl AsRef<Path>) -> Result<Self, ExtractError> {
+        let model = model.as_ref();
+        let session = Session::builder()
+            .and_then(|builder| builder.commit_from_file(model))
+            .map_err(|err| ExtractError::model_load(model, err))?;
+        Ok(Self {
+            session: Mutex::new(session),
+        })
+    }
+
+    /// Predict the landmarks of a single face
+    fn predict(&self, image: &image::RgbImage, face: &Rect) -> Result<Landmarks, ExtractError> {
+        // Square crop around the face, padded with black if it goes out of the image
+        let size = ((face.width().max(face.height()) as f32 * CROP_SCALE) as i64).max(1);
+        let left = (face.left + face.right) / 2 - size / 2;
+        let top = (face.top + face.bottom) / 2 - size / 2;
+        let crop = image::RgbImage::from_fn(size as u32, size as u32, |x, y| {
+            let (x, y) = (left + i64::from(x), top + i64::from(y));
+            if (0..i64::from(image.width())).contains(&x)
+                && (0..i64::from(image.height())).contains(&y)
+            {
+                *image.get_pixel(x as u32, y as u32)
+            } else {
+                image::Rgb([0, 0, 0])
+            }
+        });
+        let crop = image::imageops::resize(
+            &crop,
+            INPUT_SIZE,
+            INPUT_SIZE,
+            image::imageops::FilterType::Triangle,
+        );
+        let input: Vec<f32> = crop
+            .as_raw()
+            .iter()
+            .map(|&v| f32::from(v) / 255.0)
+            .collect();
+        let input_size = INPUT_SIZE as usize;
+        let input = Tensor::from_array(([1usize, input_size, input_size, 3], input))
+            .map_err(|err| ExtractError::Prediction(err.into()))?;
+
+        let mut session = self.session.lock().unwrap_or_else(PoisonError::into_inner);
+        let outputs = session
+            .run(ort::inputs![input])
+            .map_err(|err| ExtractError::Prediction(err.into()))?;
+        let (_, points) = outputs[0]
+            .try_extract_tensor::<f32>()
+            .map_err(|err| ExtractError::Prediction(err.into()))?;
+
+        let scale = size as f32 / INPUT_SIZE as f32;
+        let points: Box<[_]> = points
+            .chunks_exact(3)
+            .map(|p| (left + (p[0] * scale) as i64, top + (p[1] * scale) as i64))
+            .collect();
+        let len = points.len();
+        Landmarks::new(points, LandmarkSchema::FaceMesh).ok_or_else(|| {
+            ExtractError::Prediction(
+                format!(
+                    "expected {} landmarks, the model produced {len}",
+                    LandmarkSchema::FaceMesh.len()
+                )
+                .into(),
+            )
+        })
+    }
+}
+
+impl LandmarkBackend for FaceMeshPredictor {
+    fn predict_landmarks(
+        &self,
+        image: &image::RgbImage,
+        faces: &[Rect],
+    ) -> Result<Vec<Landmarks>, ExtractError> {
+        faces.iter().map(|face| self.predict(image, face)).collect()
+    }
+}
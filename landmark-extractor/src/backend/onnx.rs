@@ -0,0 +1,134 @@
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+
+use ort::session::Session;
+use ort::value::Tensor;
+
+use super::FaceDetectorBackend;
+use crate::ExtractError;
+use crate::Rect;
+
+/// Size of the (square) input of the model
+const INPUT_SIZE: u32 = 640;
+/// Strides of the feature maps the model outputs, in order
+const STRIDES: [usize; 3] = [8, 16, 32];
+/// Number of anchors per location of a feature map
+const ANCHORS: usize = 2;
+/// Detections with a lower score are discarded (by default)
+const SCORE_THRESHOLD: f32 = 0.5;
+/// Detections overlapping more than this with a better one are discarded
+const NMS_THRESHOLD: f32 = 0.4;
+
+/// Face detector running an [SCRFD](https://github.com/deepinsight/insightface/tree/master/detection/scrfd)
+/// model through ONNX Runtime
+///
+/// Expects the output layout of insightface's SCRFD exports: the scores of each stride (8, 16 and
+/// 32) followed by the bounding boxes of each stride (and optionally the keypoints, which are
+/// ignored). Other detectors (e.g. RetinaFace) output a different layout and aren't supported.
+pub struct OnnxDetector {
+    /// Running the model needs `&mut`
+    session: Mutex<Session>,
+    score_threshold: f32,
+}
+
+impl OnnxDetector {
+    /// Load the ONNX model from this path
+    pub fn open(model: impl AsRef<Path>) -> Result<Self, ExtractError> {
+        let model = model.as_ref();
+        let session = Session::builder()
+            .and_then(|builder| builder.commit_from_file(model))
+            .map_err(|err| ExtractError::model_load(model, err))?;
+        Ok(Self {
+            session: Mutex::new(session),
+            score_threshold: SCORE_THRESHOLD,
+        })
+    }
+
+    /// Discard detections with a score (between 0 and 1) below this (defaults to 0.5)
+    pub fn with_score_threshold(mut self, score_threshold: f32) -> Self {
+        self.score_threshold = score_threshold;
+        self
+    }
+}
+
+impl FaceDetectorBackend for OnnxDetector {
+    fn detect(&self, image: &image::RgbImage) -> Result<Vec<(Rect, Option<f32>)>, ExtractError> {
+        // Letterbox the image into the top left corner of the input
+        let scale = INPUT_SIZE as f32 / image.width().max(image.height()).max(1) as f32;
+        let resized = image::imageops::resize(
+            image,
+            ((image.width() as f32 * scale) as u32).clamp(1, INPUT_SIZE),
+            ((image.height() as f32 * scale) as u32).clamp(1, INPUT_SIZE),
+            image::imageops::FilterType::Triangle,
+        );
+        let size = INPUT_SIZE as usize;
+        let normalize = |v: u8| (f32::from(v) - 127.5) / 128.0;
+        let mut input = vec![normalize(0); 3 * size * size];
+        for (x, y, pixel) in resized.enumerate_pixels() {
+            for (c, &v) in pixel.0.iter().enumerate() {
+                input[c * size * size + y as usize * size + x as usize] = normalize(v);
+            }
+        }
+        let input = Tensor::from_array(([1usize, 3, size, size], input))
+            .map_err(|err| ExtractError::Detection(err.into()))?;
+
+        let mut session = self.session.lock().unwrap_or_else(PoisonError::into_inner);
+        let outputs = session
+            .run(ort::inputs![input])
+            .map_err(|err| ExtractError::Detection(err.into()))?;
+        if outputs.len() < 2 * STRIDES.len() {
+            return Err(ExtractError::Detection(
+                format!(
+                    "expected at least {} outputs, the model has {}",
+                    2 * STRIDES.len(),
+                    outputs.len()
+                )
+                .into(),
+            ));
+        }
+
+        let mut detections = Vec::new();
+        for (i, &stride) in STRIDES.iter().enumerate() {
+            let (_, scores) = outputs[i]
+                .try_extract_tensor::<f32>()
+                .map_err(|err| ExtractError::Detection(err.into()))?;
+            let (_, boxes) = outputs[i + STRIDES.len()]
+                .try_extract_tensor::<f32>()
+                .map_err(|err| ExtractError::Detection(err.into()))?;
+            let columns = size / stride;
+            for (anchor, &score) in scores.iter().enumerate() {
+                if score < self.score_threshold {
+                    continue;
+                }
+                let Some(distances) = boxes.get(anchor * 4..anchor * 4 + 4) else {
+                    break;
+                };
+                let location = anchor / ANCHORS;
+                let cx = ((location % columns) * stride) as f32;
+                let cy = ((location / columns) * stride) as f32;
+                let stride = stride as f32;
+                let rect = Rect {
+                    left: ((cx - distances[0] * stride) / scale) as i64,
+                    top: ((cy - distances[1] * stride) / scale) as i64,
+                    right: ((cx + distances[2] * stride) / scale) as i64,
+                    bottom: ((cy + distances[3] * stride) / scale) as i64,
+                };
+                detections.push((rect, score));
+            }
+        }
+
+        // Non maximum suppression
+        detections.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        let mut faces: Vec<(Rect, Option<f32>)> = Vec::new();
+        for (rect, score) in detections {
+            if faces
+                .iter()
+                .all(|(face, _)| face.iou(&rect) <= NMS_THRESHOLD)
+            {
+                faces.push((rect, Some(score)));
+            }
+        }
+        Ok(faces)
+    }
+}
@@ -0,0 +1,53 @@
+use std::path::Path;
+
+use super::FaceDetectorBackend;
+use crate::ExtractError;
+use crate::Rect;
+
+/// Pure Rust face detector using [SeetaFace](https://github.com/atomashpolskiy/rustface)
+///
+/// Doesn't need dlib's C++ toolchain to find faces (the landmarks are still predicted by dlib)
+#[derive(Clone)]
+pub struct RustFaceDetector {
+    model: rustface::Model,
+}
+
+impl RustFaceDetector {
+    /// Load the SeetaFace model (`seeta_fd_frontal_v1.0.bin`) from this path
+    pub fn open(model: impl AsRef<Path>) -> Result<Self, ExtractError> {
+        let path = model.as_ref();
+        let model = std::fs::File::open(path)
+            .and_then(rustface::read_model)
+            .map_err(|err| ExtractError::model_load(path, err))?;
+        Ok(Self { model })
+    }
+}
+
+impl FaceDetectorBackend for RustFaceDetector {
+    fn detect(&self, image: &image::RgbImage) -> Result<Vec<(Rect, Option<f32>)>, ExtractError> {
+        // The detector needs `&mut self`, but it is cheap to create from the model
+        let mut detector = rustface::create_detector_with_model(self.model.clone());
+        detector.set_min_face_size(20);
+        detector.set_score_thresh(2.0);
+        detector.set_pyramid_scale_factor(0.8);
+        detector.set_slide_window_step(4, 4);
+
+        let gray = image::imageops::grayscale(image);
+        let data = rustface::ImageData::new(gray.as_raw(), gray.width(), gray.height());
+        Ok(detector
+            .detect(&data)
+            .into_iter()
+            .map(|face| {
+                let bbox = face.bbox();
+                let (left, top) = (i64::from(bbox.x()), i64::from(bbox.y()));
+                let rect = Rect {
+                    left,
+                    top,
+                    right: left + i64::from(bbox.width()),
+                    bottom: top + i64::from(bbox.height()),
+                };
+                (rect, Some(face.score() as f32))
+            })
+            .collect())
+    }
+}
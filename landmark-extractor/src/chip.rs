@@ -0,0 +1,103 @@
+use crate::Face;
+use crate::LandmarkSchema;
+
+/// Position of the outer and inner corners of the left eye, the outer and inner corners of the right
+/// eye and the bottom of the nose in an aligned face chip without padding (dlib's template for the
+/// 5 point model)
+const TEMPLATE: [(f32, f32); 5] = [
+    (0.859_567_5, 0.213_498_15),
+    (0.646_060_5, 0.228_967_44),
+    (0.120_575_06, 0.213_727_45),
+    (0.334_085_06, 0.229_064_24),
+    (0.490_112_3, 0.627_797_5),
+];
+
+/// Indices of the points of this schema that correspond to the [`TEMPLATE`]
+fn template_points(schema: LandmarkSchema) -> Option<[usize; 5]> {
+    match schema {
+        LandmarkSchema::FivePoint => Some([0, 1, 2, 3, 4]),
+        LandmarkSchema::SixtyEightPoint => Some([45, 42, 36, 39, 33]),
+        LandmarkSchema::FaceMesh => Some([263, 362, 33, 133, 2]),
+        LandmarkSchema::Custom(_) => None,
+    }
+}
+
+/// Extract an aligned `size`x`size` crop of the face (like dlib's `get_face_chip`)
+///
+/// The face is rotated so that the eyes are horizontal and scaled so that it fills the crop,
+/// `padding` adds a border around it relative to the size of the face (dlib uses 0.2 by default).
+/// Areas outside of the image are black.
+///
+/// Returns [`None`] if the landmarks don't follow a known [`LandmarkSchema`]
+pub fn face_chip(
+    image: &image::RgbImage,
+    face: &Face,
+    size: u32,
+    padding: f32,
+) -> Option<image::RgbImage> {
+    let landmarks = &face.landmarks;
+    let schema = landmarks.schema();
+    if schema.len() != landmarks.len() {
+        return None;
+    }
+    let indices = template_points(schema)?;
+
+    // Least squares similarity transform from the chip to the image, treating points as complex
+    // numbers: image = a * chip + b
+    let chip: Vec<_> = TEMPLATE
+        .iter()
+        .map(|&(x, y)| {
+            let scale = size as f32 / (1.0 + 2.0 * padding);
+            ((x + padding) * scale, (y + padding) * scale)
+        })
+        .collect();
+    let points: Vec<_> = indices
+        .iter()
+        .map(|&ix| (landmarks[ix].0 as f32, landmarks[ix].1 as f32))
+        .collect();
+    let mean = |points: &[(f32, f32)]| {
+        let (x, y) = points
+            .iter()
+            .fold((0.0, 0.0), |(sx, sy), &(x, y)| (sx + x, sy + y));
+        (x / points.len() as f32, y / points.len() as f32)
+    };
+    let (cx, cy) = mean(&chip);
+    let (px, py) = mean(&points);
+    let (mut re, mut im, mut norm) = (0.0, 0.0, 0.0);
+    for (&(x, y), &(u, v)) in chip.iter().zip(&points) {
+        let (x, y, u, v) = (x - cx, y - cy, u - px, v - py);
+        // (u + iv) * conj(x + iy)
+        re += u * x + v * y;
+        im += v * x - u * y;
+        norm += x * x + y * y;
+    }
+    let (a_re, a_im) = (re / norm, im / norm);
+    let b_re = px - (a_re * cx - a_im * cy);
+    let b_im = py - (a_im * cx + a_re * cy);
+
+    Some(image::RgbImage::from_fn(size, size, |x, y| {
+        let (x, y) = (x as f32 + 0.5, y as f32 + 0.5);
+        let u = a_re * x - a_im * y + b_re;
+        let v = a_im * x + a_re * y + b_im;
+        bilinear(image, u, v)
+    }))
+}
+
+/// Sample the image at `(x, y)` with bilinear interpolation, black outside of the image
+fn bilinear(image: &image::RgbImage, x: f32, y: f32) -> image::Rgb<u8> {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let pixel = |x: f32, y: f32| -> [f32; 3] {
+        if x < 0.0 || y < 0.0 || x >= image.width() as f32 || y >= image.height() as f32 {
+            return [0.0; 3];
+        }
+        image.get_pixel(x as u32, y as u32).0.map(f32::from)
+    };
+    let (p00, p10) = (pixel(x0, y0), pixel(x0 + 1.0, y0));
+    let (p01, p11) = (pixel(x0, y0 + 1.0), pixel(x0 + 1.0, y0 + 1.0));
+    image::Rgb(std::array::from_fn(|c| {
+        let top = p00[c] * (1.0 - fx) + p10[c] * fx;
+        let bottom = p01[c] * (1.0 - fx) + p11[c] * fx;
+        (top * (1.0 - fy) + bottom * fy).round().clamp(0.0, 255.0) as u8
+    }))
+}
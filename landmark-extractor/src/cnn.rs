@@ -0,0 +1,298 @@
+use std::ffi::CString;
+use std::path::Path;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+
+use cpp::cpp;
+use cpp::cpp_class;
+use dlib_face_recognition::ImageMatrix;
+
+use crate::BoxError;
+use crate::ExtractError;
+use crate::Rect;
+
+cpp! {{
+    #include <cstdint>
+    #include <map>
+    #include <vector>
+
+    #include <dlib/dnn.h>
+
+    // The network of `mmod_human_face_detector.dat`, as defined by dlib's Python bindings
+    template <long num_filters, typename SUBNET>
+    using con5d = dlib::con<num_filters, 5, 5, 2, 2, SUBNET>;
+    template <long num_filters, typename SUBNET>
+    using con5 = dlib::con<num_filters, 5, 5, 1, 1, SUBNET>;
+    template <typename SUBNET>
+    using downsampler = dlib::relu<dlib::affine<con5d<32, dlib::relu<dlib::affine<
+        con5d<32, dlib::relu<dlib::affine<con5d<16, SUBNET>>>>>>>>>;
+    template <typename SUBNET>
+    using rcon5 = dlib::relu<dlib::affine<con5<45, SUBNET>>>;
+    using cnn_face_detector = dlib::loss_mmod<dlib::con<1, 9, 9, 1, 1,
+        rcon5<rcon5<rcon5<downsampler<dlib::input_rgb_image_pyramid<dlib::pyramid_down<6>>>>>>>>;
+
+    // Store the message of an exception in a Rust `String`
+    void cnn_set_error(void* error, const std::exception& exception) {
+        const char* message = exception.what();
+        rust!(cnn_set_error_impl [
+            error: *mut String as "void*",
+            message: *const std::os::raw::c_char as "const char*"
+        ] {
+            let message = unsafe { std::ffi::CStr::from_ptr(message) };
+            unsafe { *error = message.to_string_lossy().into_owned() };
+        });
+    }
+
+    // Append a detection to the faces found in the `image`th image
+    void cnn_push_detection(void* detections, size_t image, const dlib::mmod_rect& face) {
+        int64_t left = face.rect.left();
+        int64_t top = face.rect.top();
+        int64_t right = face.rect.right();
+        int64_t bottom = face.rect.bottom();
+        float confidence = face.detection_confidence;
+        rust!(cnn_push_detection_impl [
+            detections: *mut Vec<Vec<(Rect, f32)>> as "void*",
+            image: usize as "size_t",
+            left: i64 as "int64_t",
+            top: i64 as "int64_t",
+            right: i64 as "int64_t",
+            bottom: i64 as "int64_t",
+            confidence: f32 as "float"
+        ] {
+            let face = Rect { left, top, right, bottom };
+            unsafe { (*detections)[image].push((face, confidence)) };
+        });
+    }
+}}
+
+cpp_class!(
+    /// An instance of dlib's CNN face detector network
+    ///
+    /// The bindings' `FaceDetectorCnn` discards the confidence of the detections and can only run
+    /// on one image at a time, so we load the network ourselves.
+    unsafe struct Network as "cnn_face_detector"
+);
+
+impl Network {
+    /// Load the network from this path
+    fn open(path: &Path) -> Result<Self, BoxError> {
+        let path = CString::new(path.as_os_str().as_encoded_bytes())?;
+        let path = path.as_ptr();
+        let mut network = Self::default();
+        let mut error = String::new();
+        let error_ptr: *mut String = &mut error;
+        let loaded = unsafe {
+            cpp!([
+                mut network as "cnn_face_detector",
+                path as "const char*",
+                error_ptr as "void*"
+            ] -> bool as "bool" {
+                try {
+                    dlib::deserialize(path) >> network;
+                    return true;
+                } catch (const std::exception& exception) {
+                    cnn_set_error(error_ptr, exception);
+                    return false;
+                }
+            })
+        };
+        if loaded {
+            Ok(network)
+        } else {
+            Err(error.into())
+        }
+    }
+
+    /// Find the faces in each image and their confidence
+    ///
+    /// Images of the same size are processed in a single forward pass (dlib can only batch images
+    /// of the same size), faces with a confidence below `threshold` are discarded
+    fn detect(
+        &mut self,
+        images: &[&ImageMatrix],
+        threshold: f64,
+    ) -> Result<Vec<Vec<(Rect, f32)>>, BoxError> {
+        let mut detections = vec![Vec::new(); images.len()];
+        let mut error = String::new();
+        let network: &mut Self = self;
+        let (images, len) = (images.as_ptr(), images.len());
+        let detections_ptr: *mut Vec<Vec<(Rect, f32)>> = &mut detections;
+        let error_ptr: *mut String = &mut error;
+        let detected = unsafe {
+            cpp!([
+                network as "cnn_face_detector*",
+                images as "const dlib::matrix<dlib::rgb_pixel>* const*",
+                len as "size_t",
+                threshold as "double",
+                detections_ptr as "void*",
+                error_ptr as "void*"
+            ] -> bool as "bool" {
+                try {
+                    std::map<std::pair<long, long>, std::vector<size_t>> by_size;
+                    for (size_t i = 0; i < len; ++i) {
+                        by_size[{images[i]->nr(), images[i]->nc()}].push_back(i);
+                    }
+                    for (const auto& group : by_size) {
+                        const std::vector<size_t>& indices = group.second;
+                        std::vector<dlib::matrix<dlib::rgb_pixel>> batch;
+                        batch.reserve(indices.size());
+                        for (size_t i : indices) {
+                            batch.push_back(*images[i]);
+                        }
+                        auto faces = network->process_batch(batch, batch.size(), threshold);
+                        for (size_t j = 0; j < faces.size(); ++j) {
+                            for (const dlib::mmod_rect& face : faces[j]) {
+                                cnn_push_detection(detections_ptr, indices[j], face);
+                            }
+                        }
+                    }
+                    return true;
+                } catch (const std::exception& exception) {
+                    cnn_set_error(error_ptr, exception);
+                    return false;
+                }
+            })
+        };
+        if detected {
+            Ok(detections)
+        } else {
+            Err(error.into())
+        }
+    }
+}
+
+/// dlib's CNN face detector (`mmod_human_face_detector.dat`)
+///
+/// Owns its model, so it is freed once the detector is dropped. Can be shared between threads,
+/// each instance of the model processes one image at a time, use [`CnnDetector::open_pool`] to load
+/// several instances and process images in parallel.
+pub struct CnnDetector {
+    /// The instances that aren't in use
+    idle: Mutex<Vec<Network>>,
+    /// Notified when an instance is returned to `idle`
+    returned: Condvar,
+    /// Faces detected with a lower confidence are discarded
+    threshold: f32,
+}
+
+impl CnnDetector {
+    /// Load the CNN model from this path
+    pub fn open(model: impl AsRef<Path>) -> Result<Self, ExtractError> {
+        Self::open_pool(model, 1)
+    }
+
+    /// Load `size` instances of the CNN model from this path (at least one)
+    ///
+    /// Up to `size` images are processed in parallel, other threads wait for an instance to be
+    /// free. Each instance has its own copy of the model, so memory usage grows with `size`.
+    pub fn open_pool(model: impl AsRef<Path>, size: usize) -> Result<Self, ExtractError> {
+        let model = model.as_ref();
+        let detectors = (0..size.max(1))
+            .map(|_| Network::open(model).map_err(|err| ExtractError::model_load(model, err)))
+            .collect::<Result<_, _>>()?;
+        crate::gpu::dnn_loaded();
+        Ok(Self {
+            idle: Mutex::new(detectors),
+            returned: Condvar::new(),
+            threshold: 0.0,
+        })
+    }
+
+    /// Discard faces detected with a confidence below this (defaults to 0)
+    ///
+    /// The threshold is applied by dlib itself, a negative threshold finds more (but less certain)
+    /// faces.
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Find the faces in this image and the confidence of each detection
+    ///
+    /// Only reports faces with a confidence above [`CnnDetector::with_threshold`].
+    pub fn find_faces(&self, image: &ImageMatrix) -> Result<Vec<(Rect, f32)>, ExtractError> {
+        let mut faces = self
+            .lock()
+            .detect(&[image], self.threshold.into())
+            .map_err(ExtractError::Detection)?;
+        Ok(faces.pop().unwrap_or_default())
+    }
+
+    /// Find the faces in several images (and the confidence of each detection) in as few forward
+    /// passes as possible
+    ///
+    /// dlib runs the CNN on all the images of the same size at once, which keeps the GPU busy. The
+    /// whole batch has to fit in (GPU) memory, so split large sets of images into smaller batches.
+    /// Returns the faces of each image in the same order as `images`.
+    pub fn find_faces_batch(
+        &self,
+        images: &[ImageMatrix],
+    ) -> Result<Vec<Vec<(Rect, f32)>>, ExtractError> {
+        let images: Vec<_> = images.iter().collect();
+        self.lock()
+            .detect(&images, self.threshold.into())
+            .map_err(ExtractError::Detection)
+    }
+
+    /// Exclusive access to one of the instances, waits until one is free
+    fn lock(&self) -> CnnGuard<'_> {
+        let mut idle = self.idle.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            if let Some(detector) = idle.pop() {
+                return CnnGuard {
+                    pool: self,
+                    detector: Some(detector),
+                };
+            }
+            idle = self
+                .returned
+                .wait(idle)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+}
+
+#[cfg(feature = "image")]
+impl crate::backend::FaceDetectorBackend for CnnDetector {
+    fn detect(&self, image: &image::RgbImage) -> Result<Vec<(Rect, Option<f32>)>, ExtractError> {
+        Ok(self
+            .find_faces(&ImageMatrix::from_image(image))?
+            .into_iter()
+            .map(|(face, confidence)| (face, Some(confidence)))
+            .collect())
+    }
+}
+
+/// An instance of the model taken from a [`CnnDetector`], returned to it when dropped
+struct CnnGuard<'a> {
+    pool: &'a CnnDetector,
+    detector: Option<Network>,
+}
+
+impl std::ops::Deref for CnnGuard<'_> {
+    type Target = Network;
+
+    fn deref(&self) -> &Self::Target {
+        self.detector.as_ref().expect("only taken on drop")
+    }
+}
+
+impl std::ops::DerefMut for CnnGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.detector.as_mut().expect("only taken on drop")
+    }
+}
+
+impl Drop for CnnGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(detector) = self.detector.take() {
+            self.pool
+                .idle
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(detector);
+            self.pool.returned.notify_one();
+        }
+    }
+}
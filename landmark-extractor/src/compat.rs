@@ -0,0 +1,243 @@
+//! Deserialization of [`Face`] and [`Landmarks`] from both their current (named fields) and older
+//! (positional) layouts
+//!
+//! Formats that describe themselves (JSON, RON, CBOR) tell us which layout was used, the fields
+//! are then read with their own types, so nested enums (e.g. the [`LandmarkSchema`]) work in RON.
+
+use std::fmt;
+
+use serde::de::value::MapAccessDeserializer;
+use serde::de::value::SeqAccessDeserializer;
+use serde::de::Error;
+use serde::de::MapAccess;
+use serde::de::SeqAccess;
+use serde::de::Visitor;
+use serde::Deserialize;
+use serde::Deserializer;
+
+use crate::Embedding;
+use crate::Face;
+use crate::LandmarkSchema;
+use crate::Landmarks;
+use crate::Rect;
+
+/// The fields of a [`Face`], missing optional fields are [`None`]
+#[derive(Deserialize)]
+struct FaceFields {
+    rect: Rect,
+    landmarks: Landmarks,
+    #[serde(default)]
+    confidence: Option<f32>,
+    #[serde(default)]
+    embedding: Option<Embedding>,
+    #[serde(default)]
+    sharpness: Option<f32>,
+}
+
+impl From<FaceFields> for Face {
+    fn from(value: FaceFields) -> Self {
+        let FaceFields {
+            rect,
+            landmarks,
+            confidence,
+            embedding,
+            sharpness,
+        } = value;
+        Self {
+            rect,
+            landmarks,
+            confidence,
+            embedding,
+            sharpness,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Face {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FaceVisitor;
+
+        impl<'de> Visitor<'de> for FaceVisitor {
+            type Value = Face;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a face")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Face, A::Error> {
+                FaceFields::deserialize(MapAccessDeserializer::new(map)).map(Face::from)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Face, A::Error> {
+                FaceFields::deserialize(SeqAccessDeserializer::new(seq)).map(Face::from)
+            }
+        }
+
+        deserializer.deserialize_any(FaceVisitor)
+    }
+}
+
+/// The named fields of [`Landmarks`]
+#[derive(Deserialize)]
+struct LandmarksFields {
+    points: Box<[(i64, i64)]>,
+    #[serde(default)]
+    schema: Option<LandmarkSchema>,
+    #[serde(default)]
+    visibility: Option<Box<[bool]>>,
+}
+
+/// The first element of positional [`Landmarks`]: all the points, or the first point of a bare
+/// list of points
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PointsOrPoint {
+    Points(Box<[(i64, i64)]>),
+    Point((i64, i64)),
+}
+
+impl Landmarks {
+    /// Check that there is one visibility flag per point
+    fn validated<E: Error>(self) -> Result<Self, E> {
+        match &self.visibility {
+            Some(visibility) if visibility.len() != self.points.len() => Err(E::invalid_length(
+                visibility.len(),
+                &format!("one visibility flag per point ({})", self.points.len()).as_str(),
+            )),
+            _ => Ok(self),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Landmarks {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct LandmarksVisitor;
+
+        impl<'de> Visitor<'de> for LandmarksVisitor {
+            type Value = Landmarks;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("facial landmarks")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Landmarks, A::Error> {
+                let LandmarksFields {
+                    points,
+                    schema,
+                    visibility,
+                } = LandmarksFields::deserialize(MapAccessDeserializer::new(map))?;
+                Landmarks {
+                    points,
+                    schema,
+                    visibility,
+                }
+                .validated()
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Landmarks, A::Error> {
+                let landmarks = match seq.next_element()? {
+                    None => Landmarks {
+                        points: Box::new([]),
+                        schema: None,
+                        visibility: None,
+                    },
+                    // `(points, schema, visibility)`
+                    Some(PointsOrPoint::Points(points)) => Landmarks {
+                        points,
+                        schema: seq.next_element()?.flatten(),
+                        visibility: seq.next_element()?.flatten(),
+                    },
+                    // `[point, point, ...]`
+                    Some(PointsOrPoint::Point(first)) => {
+                        let mut points = vec![first];
+                        while let Some(point) = seq.next_element()? {
+                            points.push(point);
+                        }
+                        Landmarks {
+                            points: points.into(),
+                            schema: None,
+                            visibility: None,
+                        }
+                    }
+                };
+                landmarks.validated()
+            }
+        }
+
+        deserializer.deserialize_any(LandmarksVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECT: &str = r#"{"left":0,"top":0,"right":4,"bottom":4}"#;
+
+    #[test]
+    fn named_round_trip() {
+        let face = Face {
+            confidence: Some(0.5),
+            ..Face::new(
+                Rect {
+                    left: 0,
+                    top: 1,
+                    right: 2,
+                    bottom: 3,
+                },
+                Landmarks::new(
+                    [(1, 2), (3, 4), (5, 6), (7, 8), (9, 10)],
+                    LandmarkSchema::FivePoint,
+                )
+                .unwrap(),
+            )
+        };
+        let json = serde_json::to_string(&face).unwrap();
+        let ron = ron::to_string(&face).unwrap();
+        for read in [
+            serde_json::from_str::<Face>(&json).unwrap(),
+            ron::from_str::<Face>(&ron).unwrap(),
+        ] {
+            assert_eq!(read.rect.bottom, 3);
+            assert_eq!(&*read.landmarks, &*face.landmarks);
+            assert_eq!(read.landmarks.schema(), LandmarkSchema::FivePoint);
+            assert_eq!(read.confidence, Some(0.5));
+            assert_eq!(read.sharpness, None);
+        }
+    }
+
+    #[test]
+    fn positional_json() {
+        // The first layout: a bare list of points
+        let face: Face = serde_json::from_str(&format!("[{RECT},[[1,2],[3,4]]]")).unwrap();
+        assert_eq!(&*face.landmarks, &[(1, 2), (3, 4)]);
+        assert_eq!(face.confidence, None);
+        // Positional landmarks and face with some of the optional fields
+        let face: Face = serde_json::from_str(&format!(
+            r#"[{RECT},[[[1,2]],{{"Custom":1}},[false]],0.25]"#
+        ))
+        .unwrap();
+        assert_eq!(face.landmarks.schema(), LandmarkSchema::Custom(1));
+        assert_eq!(face.landmarks.visibility().as_deref(), Some(&[false][..]));
+        assert_eq!(face.confidence, Some(0.25));
+    }
+
+    #[test]
+    fn positional_ron() {
+        let face: Face = ron::from_str(
+            "((left:0,top:0,right:4,bottom:4),([(1,2),(3,4),(5,6),(7,8),(9,10)],Some(FivePoint),None),None,None,Some(2.0))",
+        )
+        .unwrap();
+        assert_eq!(face.landmarks.schema(), LandmarkSchema::FivePoint);
+        assert_eq!(face.sharpness, Some(2.0));
+        let face: Face = ron::from_str("((left:0,top:0,right:4,bottom:4),([(1,2)]))").unwrap();
+        assert_eq!(&*face.landmarks, &[(1, 2)]);
+    }
+
+    #[test]
+    fn mismatched_visibility() {
+        let landmarks = r#"{"points":[[1,2],[3,4]],"visibility":[true]}"#;
+        assert!(serde_json::from_str::<Landmarks>(landmarks).is_err());
+        assert!(serde_json::from_str::<Landmarks>("[[[1,2]],null,[true,false]]").is_err());
+    }
+}
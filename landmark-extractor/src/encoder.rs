@@ -0,0 +1,200 @@
+use std::path::Path;
+
+use dlib_face_recognition::FaceEncoderNetwork;
+use dlib_face_recognition::FaceEncoderTrait;
+use dlib_face_recognition::ImageMatrix;
+use dlib_face_recognition::LandmarkPredictorTrait;
+
+use crate::ExtractError;
+use crate::Faces;
+use crate::Rect;
+
+/// Faces whose embeddings are closer than this are usually the same person (dlib's suggestion)
+pub const SAME_PERSON_DISTANCE: f64 = 0.6;
+
+/// A 128 dimensional embedding of a face
+///
+/// Embeddings of the same person are close to each other (see [`SAME_PERSON_DISTANCE`])
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Embedding(Box<[f64]>);
+
+impl Embedding {
+    /// Euclidean distance between two embeddings
+    pub fn distance(&self, other: &Embedding) -> f64 {
+        self.0
+            .iter()
+            .zip(other.0.iter())
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum::<f64>()
+            .sqrt()
+    }
+}
+
+/// Group the embeddings by identity, returns the cluster of each embedding
+///
+/// Embeddings closer than `threshold` (see [`SAME_PERSON_DISTANCE`]) are in the same cluster, as
+/// are the ones linked through a chain of close embeddings. Clusters are numbered from the
+/// largest to the smallest.
+pub fn cluster(embeddings: &[&Embedding], threshold: f64) -> Vec<usize> {
+    // Union-find over the pairs closer than the threshold
+    let mut parent: Vec<usize> = (0..embeddings.len()).collect();
+    fn root(parent: &mut [usize], mut ix: usize) -> usize {
+        while parent[ix] != ix {
+            parent[ix] = parent[parent[ix]];
+            ix = parent[ix];
+        }
+        ix
+    }
+    for a in 0..embeddings.len() {
+        for b in a + 1..embeddings.len() {
+            if embeddings[a].distance(embeddings[b]) < threshold {
+                let (a, b) = (root(&mut parent, a), root(&mut parent, b));
+                parent[a.max(b)] = a.min(b);
+            }
+        }
+    }
+    let roots: Vec<_> = (0..embeddings.len())
+        .map(|ix| root(&mut parent, ix))
+        .collect();
+
+    // Number the clusters by decreasing size (ties by first appearance)
+    let mut sizes: Vec<(usize, usize)> = Vec::new();
+    for &root in &roots {
+        match sizes.iter_mut().find(|(r, _)| *r == root) {
+            Some((_, size)) => *size += 1,
+            None => sizes.push((root, 1)),
+        }
+    }
+    sizes.sort_by_key(|&(_, size)| std::cmp::Reverse(size));
+    roots
+        .iter()
+        .map(|root| {
+            sizes
+                .iter()
+                .position(|(r, _)| r == root)
+                .expect("every root has a size")
+        })
+        .collect()
+}
+
+impl std::ops::Deref for Embedding {
+    type Target = [f64];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl From<Box<[f64]>> for Embedding {
+    fn from(value: Box<[f64]>) -> Self {
+        Self(value)
+    }
+}
+
+/// Computes [`Embedding`]s of faces using dlib's face recognition ResNet
+/// (`dlib_face_recognition_resnet_model_v1.dat`)
+pub struct FaceEncoder {
+    network: FaceEncoderNetwork,
+    num_jitters: u32,
+}
+
+impl FaceEncoder {
+    /// Load the face recognition model from this path
+    pub fn open(model: impl AsRef<Path>) -> Result<Self, ExtractError> {
+        let model = model.as_ref();
+        let network =
+            FaceEncoderNetwork::open(model).map_err(|err| ExtractError::model_load(model, err))?;
+        crate::gpu::dnn_loaded();
+        Ok(Self {
+            network,
+            num_jitters: 0,
+        })
+    }
+
+    /// Average the embedding of this many randomly jittered copies of each face (defaults to 0)
+    ///
+    /// Makes the embedding slightly more accurate, but is proportionally slower
+    pub fn with_jitters(mut self, num_jitters: u32) -> Self {
+        self.num_jitters = num_jitters;
+        self
+    }
+
+    /// Compute the [`Embedding`] of each of these faces (given by their bounding boxes)
+    ///
+    /// The encoder needs dlib's landmarks to align the faces, so they are predicted again using
+    /// `predictor` (either the 5 or the 68 point model).
+    pub fn encode(
+        &self,
+        image: &ImageMatrix,
+        faces: &[Rect],
+        predictor: &impl LandmarkPredictorTrait,
+    ) -> Vec<Embedding> {
+        let landmarks: Vec<_> = faces
+            .iter()
+            .map(|face| predictor.face_landmarks(image, &face.clone().into()))
+            .collect();
+        self.network
+            .get_face_encodings(image, &landmarks, self.num_jitters)
+            .iter()
+            .map(|encoding| Embedding(encoding.to_vec().into_boxed_slice()))
+            .collect()
+    }
+
+    /// Compute and store the [`Embedding`] of all these faces
+    ///
+    /// See [`FaceEncoder::encode`]
+    pub fn encode_faces(
+        &self,
+        image: &ImageMatrix,
+        faces: &mut Faces,
+        predictor: &impl LandmarkPredictorTrait,
+    ) {
+        let rects: Vec<_> = faces.iter().map(|face| face.rect.clone()).collect();
+        let embeddings = self.encode(image, &rects, predictor);
+        for (face, embedding) in faces.0.iter_mut().zip(embeddings) {
+            face.embedding = Some(embedding);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cluster one dimensional embeddings
+    fn cluster_values(values: &[f64], threshold: f64) -> Vec<usize> {
+        let embeddings: Vec<Embedding> = values
+            .iter()
+            .map(|&value| Embedding::from(Box::from([value])))
+            .collect();
+        cluster(&embeddings.iter().collect::<Vec<_>>(), threshold)
+    }
+
+    #[test]
+    fn chains_of_close_embeddings() {
+        // The ends are further than the threshold but linked through the middle one
+        assert_eq!(cluster_values(&[0.0, 1.0, 0.5], 0.6), [0, 0, 0]);
+        assert_eq!(cluster_values(&[0.0, 1.0], 0.6), [0, 1]);
+    }
+
+    #[test]
+    fn threshold_is_exclusive() {
+        assert_eq!(cluster_values(&[0.0, 0.5], 0.5), [0, 1]);
+        assert_eq!(cluster_values(&[0.0, 0.5], 0.500001), [0, 0]);
+        assert!(cluster_values(&[], 0.5).is_empty());
+    }
+
+    #[test]
+    fn largest_cluster_first() {
+        assert_eq!(
+            cluster_values(&[10.0, 0.0, 0.1, 20.0, 20.1, 0.2], 0.5),
+            [2, 0, 0, 1, 1, 0]
+        );
+        // Clusters of the same size keep the order they first appear in
+        assert_eq!(
+            cluster_values(&[5.0, 0.0, 0.1, 5.1, 9.0], 0.5),
+            [0, 1, 1, 0, 2]
+        );
+    }
+}
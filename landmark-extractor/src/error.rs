@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+
+/// Boxed error of a backend
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Everything that can go wrong while extracting landmarks
+#[derive(Debug, thiserror::Error)]
+pub enum ExtractError {
+    /// A model couldn't be loaded
+    #[error("loading the model {}", path.display())]
+    ModelLoad {
+        path: PathBuf,
+        #[source]
+        source: BoxError,
+    },
+    /// No landmark predictor was given to the [`ExtractorBuilder`](crate::ExtractorBuilder)
+    #[error("no landmark predictor was specified")]
+    MissingPredictor,
+    /// The image couldn't be loaded or decoded
+    #[cfg(feature = "image")]
+    #[error("loading the image")]
+    Image(#[from] image::ImageError),
+    /// A raw buffer is too small for the image it should hold
+    #[error("a {width}x{height} image with a stride of {stride} doesn't fit in {len} bytes")]
+    InvalidBuffer {
+        width: usize,
+        height: usize,
+        stride: usize,
+        len: usize,
+    },
+    /// An array doesn't have the `(height, width, 3)` shape of an RGB image
+    #[cfg(feature = "ndarray")]
+    #[error("expected an array of shape (height, width, 3), found {0:?}")]
+    InvalidShape(Vec<usize>),
+    /// [`gpu::select_gpu`](crate::gpu::select_gpu) was called after loading one of dlib's DNNs
+    #[error("the GPU can't be changed after loading a neural network")]
+    GpuAlreadyInitialized,
+    /// A minimum confidence was given for a detector that doesn't report one (dlib's HOG)
+    #[error(
+        "the {0} detector doesn't report a confidence, it can't discard the less confident faces"
+    )]
+    NoConfidence(&'static str),
+    /// A backend other than dlib's was asked to work on an
+    /// [`ImageMatrix`](dlib_face_recognition::ImageMatrix)
+    #[error("the {0} only works on images, not on dlib's ImageMatrix")]
+    NeedsImage(&'static str),
+    /// A backend failed to detect the faces
+    #[error("detecting faces")]
+    Detection(#[source] BoxError),
+    /// A backend failed to predict the landmarks
+    #[error("predicting landmarks")]
+    Prediction(#[source] BoxError),
+    /// The async extraction task was cancelled (the runtime is shutting down)
+    #[cfg(feature = "async")]
+    #[error("the extraction task was cancelled")]
+    Cancelled,
+}
+
+impl ExtractError {
+    /// Helper to create a [`ExtractError::ModelLoad`] error
+    pub(crate) fn model_load(path: impl Into<PathBuf>, source: impl Into<BoxError>) -> Self {
+        Self::ModelLoad {
+            path: path.into(),
+            source: source.into(),
+        }
+    }
+}
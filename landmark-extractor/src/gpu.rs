@@ -0,0 +1,52 @@
+//! Control where dlib's DNNs (the [`CnnDetector`](crate::CnnDetector) and the
+//! [`FaceEncoder`](crate::FaceEncoder)) run
+//!
+//! Only the DNNs use CUDA, the HOG detector and the shape predictor always run on the CPU.
+
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+use crate::ExtractError;
+
+/// Whether one of dlib's DNNs was already loaded (and thus CUDA initialized)
+static DNN_LOADED: AtomicBool = AtomicBool::new(false);
+
+/// Whether dlib was built with CUDA (`DLIB_USE_CUDA`)
+///
+/// True with the `cuda` feature. Otherwise the bindings don't expose `DLIB_USE_CUDA`, but a CUDA
+/// enabled dlib links against cuDNN: on Linux this checks whether cuDNN is loaded in the process.
+pub fn cuda_enabled() -> bool {
+    cfg!(feature = "cuda") || cudnn_loaded()
+}
+
+/// Whether cuDNN is loaded in the process, [`false`] if that can't be checked
+fn cudnn_loaded() -> bool {
+    #[cfg(target_os = "linux")]
+    if let Ok(maps) = std::fs::read_to_string("/proc/self/maps") {
+        return maps.contains("libcudnn");
+    }
+    false
+}
+
+/// Run dlib's DNNs on the GPU with this index
+///
+/// dlib always uses the first visible GPU and the bindings don't expose `cuda::set_device`, so
+/// this hides the others from CUDA (by setting `CUDA_VISIBLE_DEVICES`). It only works before
+/// loading any DNN, otherwise returns [`ExtractError::GpuAlreadyInitialized`].
+///
+/// # Safety
+///
+/// Changing the environment isn't thread safe: no other thread may be running while this is
+/// called, so call it at the start of `main` (before building any thread pool).
+pub unsafe fn select_gpu(index: u32) -> Result<(), ExtractError> {
+    if DNN_LOADED.load(Ordering::Acquire) {
+        return Err(ExtractError::GpuAlreadyInitialized);
+    }
+    std::env::set_var("CUDA_VISIBLE_DEVICES", index.to_string());
+    Ok(())
+}
+
+/// Mark that one of dlib's DNNs was loaded, after this the GPU can't be changed
+pub(crate) fn dnn_loaded() {
+    DNN_LOADED.store(true, Ordering::Release);
+}
@@ -0,0 +1,23 @@
+//! Indices of the facial features in [`LandmarkSchema::SixtyEightPoint`](crate::LandmarkSchema)
+//! landmarks (the iBUG 300-W markup)
+//!
+//! Left and right are from the subject's point of view, so the left eye is on the right side of
+//! the image. Use them with [`Landmarks::subset`](crate::Landmarks::subset), e.g.
+//! `landmarks.subset(LEFT_EYE.chain(RIGHT_EYE).chain(NOSE))`.
+
+use std::ops::Range;
+
+/// Jaw line, from the right ear to the left ear
+pub const JAW: Range<usize> = 0..17;
+/// Right eyebrow
+pub const RIGHT_EYEBROW: Range<usize> = 17..22;
+/// Left eyebrow
+pub const LEFT_EYEBROW: Range<usize> = 22..27;
+/// Nose bridge and the bottom of the nose
+pub const NOSE: Range<usize> = 27..36;
+/// Right eye contour
+pub const RIGHT_EYE: Range<usize> = 36..42;
+/// Left eye contour
+pub const LEFT_EYE: Range<usize> = 42..48;
+/// Outer and inner lip contours
+pub const MOUTH: Range<usize> = 48..68;
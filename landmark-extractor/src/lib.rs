@@ -1,15 +1,191 @@
+use std::borrow::Cow;
+use std::path::PathBuf;
+
+use dlib_face_recognition::FaceDetector;
 use dlib_face_recognition::FaceDetectorTrait;
 use dlib_face_recognition::FaceLandmarks;
 use dlib_face_recognition::ImageMatrix;
+use dlib_face_recognition::LandmarkPredictor;
 use dlib_face_recognition::LandmarkPredictorTrait;
 use dlib_face_recognition::Point;
 use dlib_face_recognition::Rectangle;
 
+#[cfg(feature = "image")]
+pub mod backend;
+#[cfg(feature = "image")]
+mod chip;
+mod cnn;
+#[cfg(feature = "serde")]
+mod compat;
+mod encoder;
+mod error;
+pub mod gpu;
+pub mod landmark68;
+#[cfg(feature = "async")]
+mod nonblocking;
+mod normalize;
+mod pose;
+#[cfg(feature = "image")]
+mod quality;
+mod tracker;
+mod visibility;
+
+#[cfg(feature = "image")]
+pub use chip::face_chip;
+pub use cnn::CnnDetector;
+pub use encoder::cluster;
+pub use encoder::Embedding;
+pub use encoder::FaceEncoder;
+pub use encoder::SAME_PERSON_DISTANCE;
+pub use error::BoxError;
+pub use error::ExtractError;
+#[cfg(feature = "async")]
+pub use nonblocking::extract_landmarks_async;
+pub use normalize::Normalization;
+pub use normalize::NormalizedLandmarks;
+pub use pose::HeadPose;
+#[cfg(feature = "image")]
+pub use quality::sharpness;
+pub use tracker::FaceId;
+pub use tracker::FaceTracker;
+pub use tracker::TRACKER_IOU_THRESHOLD;
+pub use tracker::TRACKER_MAX_AGE;
+pub use visibility::OCCLUSION_THRESHOLD;
+
+#[cfg(feature = "image")]
+use backend::FaceDetectorBackend;
+#[cfg(feature = "image")]
+use backend::LandmarkBackend;
+
 /// Any number of [`Face`]s
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Faces(Box<[Face]>);
 
+impl Faces {
+    /// The face with the largest bounding box
+    pub fn largest(&self) -> Option<&Face> {
+        self.0.iter().max_by_key(|face| face.rect.area())
+    }
+
+    /// The face whose bounding box center is closest to `(x, y)`
+    ///
+    /// Use the center of the image to select the most central face.
+    pub fn closest_to(&self, (x, y): (i64, i64)) -> Option<&Face> {
+        self.0.iter().min_by_key(|face| {
+            let (cx, cy) = face.rect.center();
+            (cx - x).pow(2) + (cy - y).pow(2)
+        })
+    }
+
+    /// The face with the highest detection confidence
+    ///
+    /// Faces without a confidence are only selected if no face has one.
+    pub fn most_confident(&self) -> Option<&Face> {
+        self.0
+            .iter()
+            .max_by(|a, b| match (a.confidence, b.confidence) {
+                (Some(a), Some(b)) => a.total_cmp(&b),
+                (a, b) => a.is_some().cmp(&b.is_some()),
+            })
+    }
+
+    /// The `n`th face, in the order the detector found them
+    pub fn nth(&self, n: usize) -> Option<&Face> {
+        self.0.get(n)
+    }
+
+    /// The face whose [`Embedding`] is closest to `embedding` and its distance
+    ///
+    /// Faces without an embedding are ignored, compare the distance to [`SAME_PERSON_DISTANCE`]
+    /// to check that it is the same person.
+    pub fn most_similar(&self, embedding: &Embedding) -> Option<(&Face, f64)> {
+        self.0
+            .iter()
+            .filter_map(|face| Some((face, face.embedding.as_ref()?.distance(embedding))))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+    }
+
+    /// The face chosen by `selection` in an image of `(width, height)` pixels
+    pub fn select(&self, selection: FaceSelection, (width, height): (u32, u32)) -> Option<&Face> {
+        match selection {
+            FaceSelection::Largest => self.largest(),
+            FaceSelection::MostCentral => {
+                self.closest_to((i64::from(width) / 2, i64::from(height) / 2))
+            }
+            FaceSelection::MostConfident => self.most_confident(),
+            FaceSelection::Index(n) => self.nth(n),
+        }
+    }
+
+    /// The face with the highest [`sharpness`]
+    ///
+    /// Faces without a sharpness are only selected if no face has one.
+    pub fn sharpest(&self) -> Option<&Face> {
+        self.0
+            .iter()
+            .max_by(|a, b| match (a.sharpness, b.sharpness) {
+                (Some(a), Some(b)) => a.total_cmp(&b),
+                (a, b) => a.is_some().cmp(&b.is_some()),
+            })
+    }
+
+    /// Compute the [`sharpness`] of each face
+    #[cfg(feature = "image")]
+    pub fn compute_sharpness(&mut self, image: &image::RgbImage) {
+        for face in self.0.iter_mut() {
+            face.sharpness = sharpness(image, &face.rect);
+        }
+    }
+}
+
+/// How to choose a face in images with several of them, see [`Faces::select`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FaceSelection {
+    /// The face with the largest bounding box
+    #[default]
+    Largest,
+    /// The face closest to the center of the image
+    MostCentral,
+    /// The face with the highest detection confidence
+    MostConfident,
+    /// The `n`th face, in the order the detector found them
+    Index(usize),
+}
+
+impl std::fmt::Display for FaceSelection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Largest => write!(f, "largest"),
+            Self::MostCentral => write!(f, "most-central"),
+            Self::MostConfident => write!(f, "most-confident"),
+            Self::Index(n) => write!(f, "index:{n}"),
+        }
+    }
+}
+
+/// Parses `largest`, `most-central`, `most-confident` or `index:<n>`
+impl std::str::FromStr for FaceSelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "largest" => Ok(Self::Largest),
+            "most-central" => Ok(Self::MostCentral),
+            "most-confident" => Ok(Self::MostConfident),
+            _ => s
+                .strip_prefix("index:")
+                .and_then(|n| n.parse().ok())
+                .map(Self::Index)
+                .ok_or_else(|| {
+                    format!(
+                        "expected largest, most-central, most-confident or index:<n>, found {s}"
+                    )
+                }),
+        }
+    }
+}
+
 impl std::ops::Deref for Faces {
     type Target = [Face];
 
@@ -24,14 +200,67 @@ impl From<Faces> for Box<[Face]> {
     }
 }
 
-/// The bounding box ([`Rect`]) and [`Landmarks`] of a face
+impl FromIterator<Face> for Faces {
+    fn from_iter<T: IntoIterator<Item = Face>>(iter: T) -> Self {
+        Faces(iter.into_iter().collect())
+    }
+}
+
+/// A face found in an image
+///
+/// Deserializes the older positional layout (`(rect, landmarks, confidence, ...)`) too.
 #[derive(Debug, Clone)]
-#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-pub struct Face(pub Rect, pub Landmarks);
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Face {
+    /// The bounding box
+    pub rect: Rect,
+    pub landmarks: Landmarks,
+    /// The detection confidence, [`None`] if the detector doesn't report one (dlib's HOG detector)
+    ///
+    /// Its scale depends on the detector: dlib's CNN reports faces above 0, the ONNX detector
+    /// between 0 and 1.
+    pub confidence: Option<f32>,
+    /// Only computed if a [`FaceEncoder`] is used
+    pub embedding: Option<Embedding>,
+    /// The [`sharpness`], computed by the [`Extractor`] when it has access to the image (not with
+    /// [`Extractor::extract`])
+    pub sharpness: Option<f32>,
+}
+
+impl Face {
+    /// A face without a confidence, embedding or sharpness
+    pub fn new(rect: Rect, landmarks: Landmarks) -> Self {
+        Self {
+            rect,
+            landmarks,
+            confidence: None,
+            embedding: None,
+            sharpness: None,
+        }
+    }
+}
+
+impl Face {
+    /// Move the face by `(dx, dy)` pixels
+    #[cfg(feature = "image")]
+    fn translate(mut self, dx: i64, dy: i64) -> Self {
+        self.rect = Rect {
+            left: self.rect.left + dx,
+            top: self.rect.top + dy,
+            right: self.rect.right + dx,
+            bottom: self.rect.bottom + dy,
+        };
+        for (x, y) in self.landmarks.points.iter_mut() {
+            *x += dx;
+            *y += dy;
+        }
+        self
+    }
+}
 
 impl From<Face> for (Rectangle, Landmarks) {
     fn from(value: Face) -> Self {
-        (value.0.into(), value.1)
+        (value.rect.into(), value.landmarks)
     }
 }
 
@@ -47,6 +276,41 @@ pub struct Rect {
     pub bottom: i64,
 }
 
+impl Rect {
+    /// Width of the bounding box
+    pub fn width(&self) -> i64 {
+        self.right - self.left
+    }
+
+    /// Height of the bounding box
+    pub fn height(&self) -> i64 {
+        self.bottom - self.top
+    }
+
+    /// Area of the bounding box
+    pub fn area(&self) -> i64 {
+        self.width() * self.height()
+    }
+
+    /// Center of the bounding box
+    pub fn center(&self) -> (i64, i64) {
+        ((self.left + self.right) / 2, (self.top + self.bottom) / 2)
+    }
+
+    /// Intersection over union of two bounding boxes, 0 if they don't overlap and 1 if they are the
+    /// same
+    pub fn iou(&self, other: &Rect) -> f32 {
+        let width = self.right.min(other.right) - self.left.max(other.left);
+        let height = self.bottom.min(other.bottom) - self.top.max(other.top);
+        if width <= 0 || height <= 0 {
+            return 0.0;
+        }
+        let intersection = (width * height) as f32;
+        let union = (self.area() + other.area()) as f32 - intersection;
+        intersection / union
+    }
+}
+
 impl From<Rect> for Rectangle {
     fn from(value: Rect) -> Self {
         let Rect {
@@ -85,23 +349,104 @@ impl From<Rectangle> for Rect {
 
 /// Facial Landmarks
 ///
-/// Derives more traits unlike [`dlib_face_recognition::FaceLandmarks`]
+/// Derives more traits unlike [`dlib_face_recognition::FaceLandmarks`]. Can have a visibility mask
+/// (see [`Landmarks::visibility`]). Deserializes the older layouts too: the positional
+/// `(points, schema, visibility)` and the bare list of points.
 #[derive(Debug, Clone)]
-#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-pub struct Landmarks(Box<[(i64, i64)]>);
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Landmarks {
+    points: Box<[(i64, i64)]>,
+    /// Inferred from the number of points if missing, see [`Landmarks::schema`]
+    schema: Option<LandmarkSchema>,
+    /// One value per point, see [`Landmarks::visibility`]
+    visibility: Option<Box<[bool]>>,
+}
+
+impl Landmarks {
+    /// Create [`Landmarks`] following the specified [`LandmarkSchema`]
+    ///
+    /// Returns [`None`] if the number of points doesn't match the schema
+    pub fn new(points: impl Into<Box<[(i64, i64)]>>, schema: LandmarkSchema) -> Option<Self> {
+        let points = points.into();
+        (points.len() == schema.len()).then_some(Self {
+            points,
+            schema: Some(schema),
+            visibility: None,
+        })
+    }
+
+    /// The [`LandmarkSchema`] these landmarks follow
+    ///
+    /// Landmarks stored without a schema have it inferred from the number of points
+    pub fn schema(&self) -> LandmarkSchema {
+        self.schema
+            .unwrap_or_else(|| LandmarkSchema::from_len(self.points.len()))
+    }
+
+    /// Only the points at these indices (in this order), see [`landmark68`] for the indices of the
+    /// 68 point model
+    ///
+    /// The subset follows a [`LandmarkSchema::Custom`] schema. Returns [`None`] if an index is out
+    /// of bounds.
+    pub fn subset(&self, indices: impl IntoIterator<Item = usize>) -> Option<Landmarks> {
+        let indices: Vec<_> = indices.into_iter().collect();
+        let points: Box<[_]> = indices
+            .iter()
+            .map(|&ix| self.points.get(ix).copied())
+            .collect::<Option<_>>()?;
+        let visibility = self.visibility.as_ref().and_then(|mask| {
+            indices
+                .iter()
+                .map(|&ix| mask.get(ix).copied())
+                .collect::<Option<_>>()
+        });
+        let schema = LandmarkSchema::Custom(points.len());
+        Some(Self {
+            points,
+            schema: Some(schema),
+            visibility,
+        })
+    }
+
+    /// Whether the average [`eye_aspect_ratio`] is below `threshold` (see [`BLINK_THRESHOLD`])
+    ///
+    /// Returns [`None`] if the eye aspect ratio can't be computed for these landmarks
+    pub fn is_blinking(&self, threshold: f32) -> Option<bool> {
+        let (left, right) = eye_aspect_ratio(self)?;
+        Some((left + right) / 2.0 < threshold)
+    }
+
+    /// The centers of the left and right eyes (in that order)
+    ///
+    /// Returns [`None`] if the [`LandmarkSchema`] doesn't know where the eyes are
+    pub fn eye_centers(&self) -> Option<[(f32, f32); 2]> {
+        let schema = self.schema();
+        if schema.len() != self.points.len() {
+            return None;
+        }
+        let center = |indices: &[usize]| {
+            let (x, y) = indices.iter().fold((0.0, 0.0), |(sx, sy), &ix| {
+                let (x, y) = self.points[ix];
+                (sx + x as f32, sy + y as f32)
+            });
+            (x / indices.len() as f32, y / indices.len() as f32)
+        };
+        Some([center(schema.left_eye()?), center(schema.right_eye()?)])
+    }
+}
 
 impl std::ops::Deref for Landmarks {
     type Target = [(i64, i64)];
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.points
     }
 }
 
 impl From<Landmarks> for Box<[Point]> {
     fn from(value: Landmarks) -> Self {
         value
-            .0
+            .points
             .into_vec()
             .into_iter()
             .map(|(x, y)| Point::new(x, y))
@@ -111,29 +456,1079 @@ impl From<Landmarks> for Box<[Point]> {
 
 impl From<FaceLandmarks> for Landmarks {
     fn from(value: FaceLandmarks) -> Self {
-        Self(value.to_vec().into_iter().map(|p| (p.x(), p.y())).collect())
+        let points: Box<[_]> = value.iter().map(|p| (p.x(), p.y())).collect();
+        let schema = LandmarkSchema::from_len(points.len());
+        Self {
+            points,
+            schema: Some(schema),
+            visibility: None,
+        }
+    }
+}
+
+/// Which landmark predictor model produced a set of [`Landmarks`]
+///
+/// Landmarks can only be compared if they follow the same schema
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LandmarkSchema {
+    /// `shape_predictor_5_face_landmarks.dat`: two points per eye and one under the nose
+    FivePoint,
+    /// `shape_predictor_68_face_landmarks.dat`: the iBUG 300-W markup
+    SixtyEightPoint,
+    /// MediaPipe's FaceMesh (see [`backend::FaceMeshPredictor`])
+    FaceMesh,
+    /// Any other model with this many points
+    Custom(usize),
+}
+
+/// A part of the face, see [`LandmarkSchema::region`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaceRegion {
+    /// Both eyes
+    Eyes,
+    Nose,
+    Mouth,
+    Jaw,
+}
+
+impl std::fmt::Display for FaceRegion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Eyes => write!(f, "eyes"),
+            Self::Nose => write!(f, "nose"),
+            Self::Mouth => write!(f, "mouth"),
+            Self::Jaw => write!(f, "jaw"),
+        }
+    }
+}
+
+/// Parses `eyes`, `nose`, `mouth` or `jaw`
+impl std::str::FromStr for FaceRegion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "eyes" => Ok(Self::Eyes),
+            "nose" => Ok(Self::Nose),
+            "mouth" => Ok(Self::Mouth),
+            "jaw" => Ok(Self::Jaw),
+            _ => Err(format!("expected eyes, nose, mouth or jaw, found {s}")),
+        }
     }
 }
 
+impl LandmarkSchema {
+    /// Guess the schema from the number of points
+    pub fn from_len(len: usize) -> Self {
+        match len {
+            5 => Self::FivePoint,
+            68 => Self::SixtyEightPoint,
+            468 => Self::FaceMesh,
+            n => Self::Custom(n),
+        }
+    }
+
+    /// Number of points in this schema
+    pub fn len(&self) -> usize {
+        match self {
+            Self::FivePoint => 5,
+            Self::SixtyEightPoint => 68,
+            Self::FaceMesh => 468,
+            Self::Custom(n) => *n,
+        }
+    }
+
+    /// Whether this schema has no points
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Indices of the left eye (on the right side of the image)
+    ///
+    /// Returns [`None`] if unknown for this schema
+    pub fn left_eye(&self) -> Option<&'static [usize]> {
+        match self {
+            Self::FivePoint => Some(&[0, 1]),
+            Self::SixtyEightPoint => Some(&[42, 43, 44, 45, 46, 47]),
+            Self::FaceMesh => Some(&[
+                263, 249, 390, 373, 374, 380, 381, 382, 362, 466, 388, 387, 386, 385, 384, 398,
+            ]),
+            Self::Custom(_) => None,
+        }
+    }
+
+    /// Indices of the right eye (on the left side of the image)
+    ///
+    /// Returns [`None`] if unknown for this schema
+    pub fn right_eye(&self) -> Option<&'static [usize]> {
+        match self {
+            Self::FivePoint => Some(&[2, 3]),
+            Self::SixtyEightPoint => Some(&[36, 37, 38, 39, 40, 41]),
+            Self::FaceMesh => Some(&[
+                33, 7, 163, 144, 145, 153, 154, 155, 133, 246, 161, 160, 159, 158, 157, 173,
+            ]),
+            Self::Custom(_) => None,
+        }
+    }
+
+    /// Indices of the nose
+    ///
+    /// Returns [`None`] if unknown for this schema
+    pub fn nose(&self) -> Option<&'static [usize]> {
+        match self {
+            Self::FivePoint => Some(&[4]),
+            Self::SixtyEightPoint => Some(&[27, 28, 29, 30, 31, 32, 33, 34, 35]),
+            Self::FaceMesh => Some(&[168, 6, 197, 195, 5, 4, 1, 19, 94, 2]),
+            Self::Custom(_) => None,
+        }
+    }
+
+    /// Indices of the lip contours
+    ///
+    /// Returns [`None`] if unknown for this schema
+    pub fn mouth(&self) -> Option<&'static [usize]> {
+        match self {
+            Self::SixtyEightPoint => Some(&[
+                48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64, 65, 66, 67,
+            ]),
+            Self::FaceMesh => Some(&[
+                61, 146, 91, 181, 84, 17, 314, 405, 321, 375, 291, 185, 40, 39, 37, 0, 267, 269,
+                270, 409,
+            ]),
+            Self::FivePoint | Self::Custom(_) => None,
+        }
+    }
+
+    /// Indices of the jaw line, from ear to ear
+    ///
+    /// Returns [`None`] if unknown for this schema
+    pub fn jaw(&self) -> Option<&'static [usize]> {
+        match self {
+            Self::SixtyEightPoint => {
+                Some(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16])
+            }
+            Self::FaceMesh => Some(&[
+                234, 93, 132, 58, 172, 136, 150, 149, 176, 148, 152, 377, 400, 378, 379, 365, 397,
+                288, 361, 323, 454,
+            ]),
+            Self::FivePoint | Self::Custom(_) => None,
+        }
+    }
+
+    /// Indices of a [`FaceRegion`]
+    ///
+    /// Returns [`None`] if unknown for this schema
+    pub fn region(&self, region: FaceRegion) -> Option<Vec<usize>> {
+        let indices = match region {
+            FaceRegion::Eyes => [self.left_eye()?, self.right_eye()?].concat(),
+            FaceRegion::Nose => self.nose()?.to_vec(),
+            FaceRegion::Mouth => self.mouth()?.to_vec(),
+            FaceRegion::Jaw => self.jaw()?.to_vec(),
+        };
+        Some(indices)
+    }
+
+    /// The six points (corner, top, top, corner, bottom, bottom) of the left and right eyes used to
+    /// compute the [`eye_aspect_ratio`]
+    fn eye_contours(&self) -> Option<([usize; 6], [usize; 6])> {
+        match self {
+            Self::SixtyEightPoint => Some(([42, 43, 44, 45, 46, 47], [36, 37, 38, 39, 40, 41])),
+            Self::FaceMesh => Some((
+                [362, 385, 387, 263, 373, 380],
+                [33, 160, 158, 133, 153, 144],
+            )),
+            Self::FivePoint | Self::Custom(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for LandmarkSchema {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FivePoint => write!(f, "5 point"),
+            Self::SixtyEightPoint => write!(f, "68 point"),
+            Self::FaceMesh => write!(f, "FaceMesh 468 point"),
+            Self::Custom(n) => write!(f, "custom {n} point"),
+        }
+    }
+}
+
+/// Eyes with an [`eye_aspect_ratio`] below this are usually closed
+pub const BLINK_THRESHOLD: f32 = 0.2;
+
+/// The eye aspect ratio (EAR) of the left and right eyes
+///
+/// The ratio between the height and the width of the eye, it is roughly constant while the eye is
+/// open and drops to almost 0 when it closes. See [Real-Time Eye Blink Detection using Facial
+/// Landmarks](https://vision.fe.uni-lj.si/cvww2016/proceedings/papers/05.pdf).
+///
+/// Returns [`None`] if the [`LandmarkSchema`] doesn't have enough points around the eyes (only the
+/// 68 point and FaceMesh schemas do)
+pub fn eye_aspect_ratio(landmarks: &Landmarks) -> Option<(f32, f32)> {
+    let schema = landmarks.schema();
+    if schema.len() != landmarks.len() {
+        return None;
+    }
+    let (left, right) = schema.eye_contours()?;
+    let point = |i: usize| {
+        let (x, y) = landmarks[i];
+        (x as f32, y as f32)
+    };
+    let distance = |a: usize, b: usize| {
+        let ((ax, ay), (bx, by)) = (point(a), point(b));
+        (ax - bx).hypot(ay - by)
+    };
+    let ear = |[p1, p2, p3, p4, p5, p6]: [usize; 6]| {
+        (distance(p2, p6) + distance(p3, p5)) / (2.0 * distance(p1, p4))
+    };
+    Some((ear(left), ear(right)))
+}
+
 /// Find all faces in this image and identify the landmarks in it
+///
+/// dlib's detectors don't report a confidence, use [`extract_landmarks_cnn`] to find faces (with
+/// their confidence) with dlib's CNN detector.
 pub fn extract_landmarks(
     image: &ImageMatrix,
-    detector: &impl FaceDetectorTrait,
+    detector: &(impl FaceDetectorTrait + ?Sized),
     predictor: &impl LandmarkPredictorTrait,
 ) -> Faces {
-    let landmarks = detector
+    let detections = detector
         .face_locations(image)
         .iter()
         .cloned()
-        .map(|face| Face(face.into(), predictor.face_landmarks(image, &face).into()))
+        .map(|face| (face, None))
+        .collect::<Vec<_>>();
+
+    extract_landmarks_from_detections(image, detections, predictor)
+}
+
+/// Find all faces in this image with dlib's CNN detector and identify the landmarks in it
+///
+/// Unlike [`extract_landmarks`], the [`Face`]s have the confidence of their detection.
+pub fn extract_landmarks_cnn(
+    image: &ImageMatrix,
+    detector: &CnnDetector,
+    predictor: &impl LandmarkPredictorTrait,
+) -> Result<Faces, ExtractError> {
+    let detections = detector
+        .find_faces(image)?
+        .into_iter()
+        .map(|(face, confidence)| (face.into(), Some(confidence)));
+
+    Ok(extract_landmarks_from_detections(
+        image, detections, predictor,
+    ))
+}
+
+/// Find all faces in a batch of images with dlib's CNN detector and identify the landmarks in them
+///
+/// The images are sent to the CNN together, see [`CnnDetector::find_faces_batch`]. The returned
+/// [`Faces`] are in the same order as the images.
+pub fn extract_landmarks_cnn_batch(
+    images: &[ImageMatrix],
+    detector: &CnnDetector,
+    predictor: &impl LandmarkPredictorTrait,
+) -> Result<Vec<Faces>, ExtractError> {
+    Ok(images
+        .iter()
+        .zip(detector.find_faces_batch(images)?)
+        .map(|(image, faces)| {
+            let detections = faces
+                .into_iter()
+                .map(|(face, confidence)| (face.into(), Some(confidence)));
+            extract_landmarks_from_detections(image, detections, predictor)
+        })
+        .collect())
+}
+
+/// Identify the landmarks of already detected faces
+///
+/// Each detection is a bounding box and its (optional) confidence score
+pub fn extract_landmarks_from_detections(
+    image: &ImageMatrix,
+    detections: impl IntoIterator<Item = (Rectangle, Option<f32>)>,
+    predictor: &impl LandmarkPredictorTrait,
+) -> Faces {
+    let landmarks = detections
+        .into_iter()
+        .map(|(face, confidence)| Face {
+            confidence,
+            ..Face::new(face.into(), predictor.face_landmarks(image, &face).into())
+        })
         .collect();
 
     Faces(landmarks)
 }
 
+/// Find all faces in this image, upsampling it `upsample` times before detection, and identify the
+/// landmarks in it
+///
+/// Each upsampling step doubles the size of the image, which helps find small faces at the cost of
+/// a (much) slower detection (like dlib's `upsample_num_times`). The landmarks are predicted on the
+/// original image, so the coordinates are always in the original resolution.
+#[cfg(feature = "image")]
+pub fn extract_landmarks_upsampled(
+    image: &image::RgbImage,
+    upsample: u32,
+    detector: &(impl FaceDetectorBackend + ?Sized),
+    predictor: &(impl LandmarkBackend + ?Sized),
+) -> Result<Faces, ExtractError> {
+    extract_landmarks_scaled(image, upsample_factor(upsample), detector, predictor)
+}
+
+/// Find all faces in this image, downscaling it so its longest side is at most `max_size` pixels
+/// before detection, and identify the landmarks in it
+///
+/// Detection on large photos is slow and rarely needs the full resolution. The landmarks are
+/// predicted on the original image, so they are as precise as without downscaling and the
+/// coordinates are always in the original resolution.
+#[cfg(feature = "image")]
+pub fn extract_landmarks_downscaled(
+    image: &image::RgbImage,
+    max_size: u32,
+    detector: &(impl FaceDetectorBackend + ?Sized),
+    predictor: &(impl LandmarkBackend + ?Sized),
+) -> Result<Faces, ExtractError> {
+    let scale = downscale_factor(image, max_size).min(1.0);
+    extract_landmarks_scaled(image, scale, detector, predictor)
+}
+
+/// Find all faces in this image, resizing it by `scale` before detection, and identify the
+/// landmarks in it
+///
+/// See [`extract_landmarks_upsampled`] and [`extract_landmarks_downscaled`]
+#[cfg(feature = "image")]
+pub fn extract_landmarks_scaled(
+    image: &image::RgbImage,
+    scale: f32,
+    detector: &(impl FaceDetectorBackend + ?Sized),
+    predictor: &(impl LandmarkBackend + ?Sized),
+) -> Result<Faces, ExtractError> {
+    let width = (image.width() as f32 * scale).round() as u32;
+    let height = (image.height() as f32 * scale).round() as u32;
+    let detections = if (width, height) == image.dimensions() || width == 0 || height == 0 {
+        detector.detect(image)?
+    } else {
+        let resized =
+            image::imageops::resize(image, width, height, image::imageops::FilterType::Triangle);
+        let (sx, sy) = (
+            image.width() as f32 / width as f32,
+            image.height() as f32 / height as f32,
+        );
+        detector
+            .detect(&resized)?
+            .into_iter()
+            .map(|(face, confidence)| {
+                let face = Rect {
+                    left: (face.left as f32 * sx).round() as i64,
+                    top: (face.top as f32 * sy).round() as i64,
+                    right: (face.right as f32 * sx).round() as i64,
+                    bottom: (face.bottom as f32 * sy).round() as i64,
+                };
+                (face, confidence)
+            })
+            .collect()
+    };
+
+    let rects: Vec<_> = detections.iter().map(|(face, _)| face.clone()).collect();
+    let landmarks = predictor.predict_landmarks(image, &rects)?;
+    Ok(Faces(
+        detections
+            .into_iter()
+            .zip(landmarks)
+            .map(|((face, confidence), landmarks)| Face {
+                confidence,
+                ..Face::new(face, landmarks)
+            })
+            .collect(),
+    ))
+}
+
+/// Scale of `upsample` upsampling steps
+#[cfg(feature = "image")]
+fn upsample_factor(upsample: u32) -> f32 {
+    2f32.powi(upsample.min(i32::MAX as u32) as i32)
+}
+
+/// Scale at which the longest side of the image is `max_size` pixels
+#[cfg(feature = "image")]
+fn downscale_factor(image: &image::RgbImage, max_size: u32) -> f32 {
+    let longest = image.width().max(image.height()).max(1);
+    max_size as f32 / longest as f32
+}
+
+/// How much the region of interest is expanded on each side (relative to its size) by
+/// [`extract_landmarks_in_roi`]
+pub const ROI_EXPANSION: f32 = 0.5;
+
+/// Find all faces in a region of interest and identify the landmarks in it
+///
+/// Only searches an area around `roi` (expanded by [`ROI_EXPANSION`] on each side), which is
+/// much cheaper than searching the whole image. Useful for sequential frames where `roi` is the
+/// bounding box of the face in the previous frame.
+///
+/// Falls back to searching the whole image if no face is found in the region.
+///
+/// See [`extract_landmarks_upsampled`] for the meaning of `upsample`.
+#[cfg(feature = "image")]
+pub fn extract_landmarks_in_roi(
+    image: &image::RgbImage,
+    roi: &Rect,
+    upsample: u32,
+    detector: &(impl FaceDetectorBackend + ?Sized),
+    predictor: &(impl LandmarkBackend + ?Sized),
+) -> Result<Faces, ExtractError> {
+    let (width, height) = (i64::from(image.width()), i64::from(image.height()));
+    let dx = (roi.width() as f32 * ROI_EXPANSION) as i64;
+    let dy = (roi.height() as f32 * ROI_EXPANSION) as i64;
+    let left = (roi.left - dx).clamp(0, width);
+    let top = (roi.top - dy).clamp(0, height);
+    let right = (roi.right + dx).clamp(0, width);
+    let bottom = (roi.bottom + dy).clamp(0, height);
+
+    if right > left && bottom > top {
+        let region = image::imageops::crop_imm(
+            image,
+            left as u32,
+            top as u32,
+            (right - left) as u32,
+            (bottom - top) as u32,
+        )
+        .to_image();
+        let faces = extract_landmarks_upsampled(&region, upsample, detector, predictor)?;
+        if !faces.is_empty() {
+            return Ok(Faces(
+                faces
+                    .0
+                    .into_vec()
+                    .into_iter()
+                    .map(|face| face.translate(left, top))
+                    .collect(),
+            ));
+        }
+    }
+
+    extract_landmarks_upsampled(image, upsample, detector, predictor)
+}
+
 /// Helper function to load an [`ImageMatrix`] from a path
 #[cfg(feature = "image")]
-pub fn img_mat_from_path(img_path: &std::path::Path) -> image::ImageResult<ImageMatrix> {
-    let image = image::open(&img_path)?.into_rgb8();
-    Ok(ImageMatrix::from_image(&image))
+pub fn img_mat_from_path(img_path: &std::path::Path) -> Result<ImageMatrix, ExtractError> {
+    Ok(img_mat_from_dynamic(&image::open(img_path)?))
+}
+
+/// Borrow the image as RGB, only converting it if it isn't RGB already
+#[cfg(feature = "image")]
+pub fn rgb_from_dynamic(image: &image::DynamicImage) -> Cow<'_, image::RgbImage> {
+    match image.as_rgb8() {
+        Some(image) => Cow::Borrowed(image),
+        None => Cow::Owned(image.to_rgb8()),
+    }
+}
+
+/// Create an [`ImageMatrix`] from any image, only converting it if it isn't RGB already
+#[cfg(feature = "image")]
+pub fn img_mat_from_dynamic(image: &image::DynamicImage) -> ImageMatrix {
+    ImageMatrix::from_image(&rgb_from_dynamic(image))
+}
+
+/// Create an [`ImageMatrix`] from a grayscale image
+///
+/// dlib's bindings only accept RGB, so the image is expanded to RGB
+#[cfg(feature = "image")]
+pub fn img_mat_from_gray(image: &image::GrayImage) -> ImageMatrix {
+    use image::buffer::ConvertBuffer;
+
+    let image: image::RgbImage = image.convert();
+    ImageMatrix::from_image(&image)
+}
+
+/// Create an [`ImageMatrix`] from a raw buffer of packed RGB pixels
+///
+/// `stride` is the number of bytes between the start of two consecutive rows (at least
+/// `3 * width`). Tightly packed buffers (`stride == 3 * width`) are used as is, padded ones are
+/// repacked first.
+///
+/// Returns [`ExtractError::InvalidBuffer`] if the buffer is too small
+pub fn img_mat_from_raw_rgb(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+) -> Result<ImageMatrix, ExtractError> {
+    let invalid = || ExtractError::InvalidBuffer {
+        width,
+        height,
+        stride,
+        len: data.len(),
+    };
+    let row = width.checked_mul(3).ok_or_else(invalid)?;
+    if stride < row {
+        return Err(invalid());
+    }
+    let len = match height {
+        0 => 0,
+        height => stride
+            .checked_mul(height - 1)
+            .and_then(|len| len.checked_add(row))
+            .ok_or_else(invalid)?,
+    };
+    if data.len() < len {
+        return Err(invalid());
+    }
+    let data = if stride == row {
+        Cow::Borrowed(data)
+    } else {
+        Cow::Owned(
+            data.chunks(stride)
+                .take(height)
+                .flat_map(|line| &line[..row])
+                .copied()
+                .collect(),
+        )
+    };
+    // SAFETY: we checked that data holds `width * height` packed RGB pixels
+    Ok(unsafe { ImageMatrix::new(width, height, data.as_ptr()) })
+}
+
+/// Create an [`ImageMatrix`] from an array of RGB pixels with shape `(height, width, 3)`
+///
+/// Arrays in standard (row major) layout are used as is, others are repacked first. Useful for
+/// frames coming from NumPy or video decoders.
+#[cfg(feature = "ndarray")]
+pub fn img_mat_from_ndarray(
+    array: ndarray::ArrayView3<'_, u8>,
+) -> Result<ImageMatrix, ExtractError> {
+    let (height, width, channels) = array.dim();
+    if channels != 3 {
+        return Err(ExtractError::InvalidShape(array.shape().to_vec()));
+    }
+    match array.as_slice() {
+        Some(data) => img_mat_from_raw_rgb(data, width, height, 3 * width),
+        None => {
+            let data: Vec<_> = array.iter().copied().collect();
+            img_mat_from_raw_rgb(&data, width, height, 3 * width)
+        }
+    }
+}
+
+/// Which face detector an [`Extractor`] should use
+#[derive(Debug, Clone, Default)]
+pub enum DetectorKind {
+    /// dlib's HOG based detector, fast but misses rotated and small faces
+    #[default]
+    Hog,
+    /// dlib's CNN based detector, loaded from the model at this path
+    Cnn(PathBuf),
+    /// SeetaFace detector, loaded from the model at this path (see [`backend::RustFaceDetector`])
+    #[cfg(feature = "rustface")]
+    RustFace(PathBuf),
+    /// SCRFD detector, loaded from the ONNX model at this path (see [`backend::OnnxDetector`])
+    #[cfg(feature = "onnx")]
+    Onnx(PathBuf),
+}
+
+impl DetectorKind {
+    /// Path to the model of the detector, if it needs one
+    pub fn model_path(&self) -> Option<&std::path::Path> {
+        match self {
+            Self::Hog => None,
+            Self::Cnn(path) => Some(path),
+            #[cfg(feature = "rustface")]
+            Self::RustFace(path) => Some(path),
+            #[cfg(feature = "onnx")]
+            Self::Onnx(path) => Some(path),
+        }
+    }
+}
+
+/// Parses `hog`, `cnn:<model>`, `rustface:<model>` or `onnx:<model>`
+impl std::str::FromStr for DetectorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "hog" {
+            return Ok(Self::Hog);
+        }
+        let Some((kind, path)) = s.split_once(':') else {
+            return Err(format!("expected hog or <kind>:<model path>, found {s}"));
+        };
+        let path = PathBuf::from(path);
+        match kind {
+            "cnn" => Ok(Self::Cnn(path)),
+            #[cfg(feature = "rustface")]
+            "rustface" => Ok(Self::RustFace(path)),
+            #[cfg(feature = "onnx")]
+            "onnx" => Ok(Self::Onnx(path)),
+            _ => Err(format!("unknown (or disabled) detector {kind}")),
+        }
+    }
+}
+
+/// Which landmark predictor an [`Extractor`] should use
+#[derive(Debug, Clone)]
+pub enum PredictorKind {
+    /// dlib's shape predictor, loaded from the model at this path
+    Dlib(PathBuf),
+    /// MediaPipe's FaceMesh, loaded from the ONNX model at this path (see
+    /// [`backend::FaceMeshPredictor`])
+    #[cfg(feature = "onnx")]
+    FaceMesh(PathBuf),
+}
+
+impl PredictorKind {
+    /// Path to the model of the predictor
+    pub fn model_path(&self) -> &std::path::Path {
+        match self {
+            Self::Dlib(path) => path,
+            #[cfg(feature = "onnx")]
+            Self::FaceMesh(path) => path,
+        }
+    }
+}
+
+/// Parses `face-mesh:<model>` (needs the `onnx` feature), anything else is the path to a dlib
+/// shape predictor
+impl std::str::FromStr for PredictorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("face-mesh:") {
+            #[cfg(feature = "onnx")]
+            Some(path) => Ok(Self::FaceMesh(path.into())),
+            #[cfg(not(feature = "onnx"))]
+            Some(_) => Err("built without onnx support, face-mesh is not available".into()),
+            None => Ok(Self::Dlib(s.into())),
+        }
+    }
+}
+
+/// The landmark predictor owned by an [`Extractor`]
+enum Predictor {
+    Dlib(LandmarkPredictor),
+    /// Any other backend
+    #[cfg(feature = "image")]
+    #[allow(dead_code)] // Unused if all backends are disabled
+    Backend(Box<dyn LandmarkBackend + Send + Sync>),
+}
+
+/// The detector owned by an [`Extractor`]
+enum Detector {
+    /// The HOG detector is cheap to construct and shouldn't be shared between threads
+    /// <https://github.com/ulagbulag/dlib-face-recognition/issues/25>, so we build one per call
+    Hog,
+    /// The CNN detector is expensive to load, so we keep a pool of instances around
+    Cnn(CnnDetector),
+    /// Any other backend
+    #[cfg(feature = "image")]
+    #[allow(dead_code)] // Unused if all backends are disabled
+    Backend(Box<dyn FaceDetectorBackend + Send + Sync>),
+}
+
+/// Owns a face detector and a landmark predictor
+///
+/// Unlike [`extract_landmarks`], the models are owned by the [`Extractor`], so you can have as many
+/// independent extractors (with different models) as you want. Construct one using
+/// [`Extractor::builder`].
+pub struct Extractor {
+    detector: Detector,
+    predictor: Predictor,
+    encoder: Option<FaceEncoder>,
+    #[cfg_attr(not(feature = "image"), allow(dead_code))]
+    upsample: u32,
+    min_face_size: u32,
+    #[cfg_attr(not(feature = "image"), allow(dead_code))]
+    max_detection_size: Option<u32>,
+    #[cfg_attr(not(feature = "image"), allow(dead_code))]
+    min_confidence: Option<f32>,
+}
+
+impl Extractor {
+    /// Start building an [`Extractor`]
+    pub fn builder() -> ExtractorBuilder {
+        ExtractorBuilder::default()
+    }
+
+    /// Find all faces in this image and identify the landmarks in it (and compute their
+    /// [`Embedding`]s if there is a [`FaceEncoder`])
+    ///
+    /// Only the CNN detector reports the confidence of its detections, it fails if dlib throws an
+    /// exception while running the network.
+    ///
+    /// An [`ImageMatrix`] can't be resized, so this ignores [`ExtractorBuilder::upsample`] and
+    /// [`ExtractorBuilder::max_detection_size`], use [`Extractor::extract_image`] instead.
+    ///
+    /// Only dlib's detectors and predictors can work on an [`ImageMatrix`], other backends fail
+    /// with [`ExtractError::NeedsImage`].
+    pub fn extract(&self, image: &ImageMatrix) -> Result<Faces, ExtractError> {
+        let detections = match &self.detector {
+            Detector::Hog => FaceDetector::new()
+                .face_locations(image)
+                .iter()
+                .map(|&face| (face.into(), None))
+                .collect(),
+            Detector::Cnn(detector) => detector
+                .find_faces(image)?
+                .into_iter()
+                .map(|(face, confidence)| (face, Some(confidence)))
+                .collect(),
+            #[cfg(feature = "image")]
+            Detector::Backend(_) => return Err(ExtractError::NeedsImage("face detector")),
+        };
+        self.extract_detections(image, detections)
+    }
+
+    /// Find all faces in several images and identify the landmarks in them
+    ///
+    /// The CNN detector processes the whole batch at once (see [`CnnDetector::find_faces_batch`]),
+    /// the others one image after the other. Has the same limitations as [`Extractor::extract`].
+    pub fn extract_batch(&self, images: &[ImageMatrix]) -> Result<Vec<Faces>, ExtractError> {
+        let Detector::Cnn(detector) = &self.detector else {
+            return images.iter().map(|image| self.extract(image)).collect();
+        };
+        images
+            .iter()
+            .zip(detector.find_faces_batch(images)?)
+            .map(|(image, faces)| {
+                let detections = faces
+                    .into_iter()
+                    .map(|(face, confidence)| (face, Some(confidence)))
+                    .collect();
+                self.extract_detections(image, detections)
+            })
+            .collect()
+    }
+
+    /// Identify the landmarks of the faces found in `image` (each with its confidence), discarding
+    /// the ones smaller than [`ExtractorBuilder::min_face_size`]
+    fn extract_detections(
+        &self,
+        image: &ImageMatrix,
+        detections: Vec<(Rect, Option<f32>)>,
+    ) -> Result<Faces, ExtractError> {
+        #[allow(clippy::infallible_destructuring_match)] // Without the image feature
+        let predictor = match &self.predictor {
+            Predictor::Dlib(predictor) => predictor,
+            #[cfg(feature = "image")]
+            Predictor::Backend(_) => return Err(ExtractError::NeedsImage("landmark predictor")),
+        };
+        let min_size = i64::from(self.min_face_size);
+        let detections = detections
+            .into_iter()
+            .filter(|(face, _)| face.width().min(face.height()) >= min_size)
+            .map(|(face, confidence)| (face.into(), confidence));
+        let mut faces = extract_landmarks_from_detections(image, detections, predictor);
+        self.encode(image, &mut faces);
+        Ok(faces)
+    }
+
+    /// Find all faces in this image and identify the landmarks in it
+    ///
+    /// The image is resized before detection according to [`ExtractorBuilder::upsample`] and
+    /// [`ExtractorBuilder::max_detection_size`], see [`extract_landmarks_scaled`]
+    #[cfg(feature = "image")]
+    pub fn extract_image(&self, image: &image::RgbImage) -> Result<Faces, ExtractError> {
+        let mut scale = upsample_factor(self.upsample);
+        if let Some(max_size) = self.max_detection_size {
+            scale = scale.min(downscale_factor(image, max_size));
+        }
+        let mut faces = self.with_detector(scale, |detector| {
+            extract_landmarks_scaled(image, scale, detector, self.predictor())
+        })?;
+        faces.compute_sharpness(image);
+        self.encode_image(image, &mut faces);
+        Ok(faces)
+    }
+
+    /// Find all faces in any image and identify the landmarks in it
+    ///
+    /// Same as [`Extractor::extract_image`], but only converts the image if it isn't RGB already
+    #[cfg(feature = "image")]
+    pub fn extract_dynamic(&self, image: &image::DynamicImage) -> Result<Faces, ExtractError> {
+        self.extract_image(&rgb_from_dynamic(image))
+    }
+
+    /// Find all faces around `roi` and identify the landmarks in it
+    ///
+    /// See [`extract_landmarks_in_roi`]
+    #[cfg(feature = "image")]
+    pub fn extract_in_roi(
+        &self,
+        image: &image::RgbImage,
+        roi: &Rect,
+    ) -> Result<Faces, ExtractError> {
+        let scale = upsample_factor(self.upsample);
+        let mut faces = self.with_detector(scale, |detector| {
+            extract_landmarks_in_roi(image, roi, self.upsample, detector, self.predictor())
+        })?;
+        faces.compute_sharpness(image);
+        self.encode_image(image, &mut faces);
+        Ok(faces)
+    }
+
+    /// Compute the [`Embedding`]s of the faces if there is a [`FaceEncoder`]
+    ///
+    /// The encoder needs dlib's landmarks, so this does nothing with other predictors
+    fn encode(&self, image: &ImageMatrix, faces: &mut Faces) {
+        if let (Some(encoder), Predictor::Dlib(predictor)) = (&self.encoder, &self.predictor) {
+            encoder.encode_faces(image, faces, predictor);
+        }
+    }
+
+    /// Same as [`Extractor::encode`], but only converts the image if needed
+    #[cfg(feature = "image")]
+    fn encode_image(&self, image: &image::RgbImage, faces: &mut Faces) {
+        if self.encoder.is_some() && !faces.is_empty() {
+            self.encode(&ImageMatrix::from_image(image), faces);
+        }
+    }
+
+    /// The landmark predictor as a [`LandmarkBackend`]
+    #[cfg(feature = "image")]
+    fn predictor(&self) -> &dyn LandmarkBackend {
+        match &self.predictor {
+            Predictor::Dlib(predictor) => predictor,
+            Predictor::Backend(predictor) => predictor.as_ref(),
+        }
+    }
+
+    /// Run `f` with exclusive access to the face detector
+    ///
+    /// The detector discards faces smaller than [`ExtractorBuilder::min_face_size`] or less
+    /// confident than [`ExtractorBuilder::min_confidence`], `scale` is the scale of the images it
+    /// will run on
+    #[cfg(feature = "image")]
+    fn with_detector<R>(&self, scale: f32, f: impl FnOnce(&dyn FaceDetectorBackend) -> R) -> R {
+        let min_size = (self.min_face_size as f32 * scale) as u32;
+        let min_confidence = self.min_confidence.unwrap_or(f32::NEG_INFINITY);
+        let f = |detector: &dyn FaceDetectorBackend| {
+            let detector = backend::MinFaceSize { detector, min_size };
+            f(&backend::MinConfidence {
+                detector: &detector,
+                min_confidence,
+            })
+        };
+        match &self.detector {
+            Detector::Hog => f(&FaceDetector::new()),
+            Detector::Cnn(detector) => f(detector),
+            Detector::Backend(detector) => f(detector.as_ref()),
+        }
+    }
+}
+
+/// Builder for an [`Extractor`]
+#[derive(Debug, Clone, Default)]
+pub struct ExtractorBuilder {
+    detector: DetectorKind,
+    predictor: Option<PredictorKind>,
+    face_encoder: Option<PathBuf>,
+    upsample: u32,
+    min_face_size: u32,
+    max_detection_size: Option<u32>,
+    cnn_pool_size: usize,
+    min_confidence: Option<f32>,
+}
+
+impl ExtractorBuilder {
+    /// Use this face detector (defaults to [`DetectorKind::Hog`])
+    pub fn detector(mut self, detector: DetectorKind) -> Self {
+        self.detector = detector;
+        self
+    }
+
+    /// Use dlib's HOG face detector
+    pub fn hog(self) -> Self {
+        self.detector(DetectorKind::Hog)
+    }
+
+    /// Use dlib's CNN face detector loaded from `path`
+    pub fn cnn(self, path: impl Into<PathBuf>) -> Self {
+        self.detector(DetectorKind::Cnn(path.into()))
+    }
+
+    /// Use this landmark predictor, either this or [`ExtractorBuilder::shape_predictor`] is
+    /// required
+    pub fn predictor(mut self, predictor: PredictorKind) -> Self {
+        self.predictor = Some(predictor);
+        self
+    }
+
+    /// Path to the Shape Predictor model (also called Facial Landmarks Predictor)
+    pub fn shape_predictor(self, path: impl Into<PathBuf>) -> Self {
+        self.predictor(PredictorKind::Dlib(path.into()))
+    }
+
+    /// Compute the [`Embedding`] of each face using the face recognition model at this path
+    ///
+    /// Needs a dlib landmark predictor, see [`FaceEncoder`]
+    pub fn face_encoder(mut self, path: impl Into<PathBuf>) -> Self {
+        self.face_encoder = Some(path.into());
+        self
+    }
+
+    /// Upsample the image this many times before detection (defaults to 0)
+    ///
+    /// See [`extract_landmarks_upsampled`]
+    pub fn upsample(mut self, upsample: u32) -> Self {
+        self.upsample = upsample;
+        self
+    }
+
+    /// Discard faces smaller than this many pixels (on the shortest side of their bounding box)
+    /// before predicting their landmarks (defaults to 0)
+    ///
+    /// Useful to ignore faces in the background (posters, bystanders, etc.)
+    pub fn min_face_size(mut self, min_face_size: u32) -> Self {
+        self.min_face_size = min_face_size;
+        self
+    }
+
+    /// Downscale images so their longest side is at most this many pixels before detection
+    /// (defaults to no limit)
+    ///
+    /// Speeds up detection on large photos, the landmarks are still predicted on the full
+    /// resolution image. See [`extract_landmarks_downscaled`]
+    pub fn max_detection_size(mut self, max_size: u32) -> Self {
+        self.max_detection_size = Some(max_size);
+        self
+    }
+
+    /// Load this many instances of the CNN detector to process images in parallel (defaults to 1)
+    ///
+    /// See [`CnnDetector::open_pool`]
+    pub fn cnn_pool_size(mut self, size: usize) -> Self {
+        self.cnn_pool_size = size;
+        self
+    }
+
+    /// Discard faces detected with a lower confidence (defaults to the detector's own threshold)
+    ///
+    /// Only applies to detectors that report a confidence: the CNN (see
+    /// [`CnnDetector::with_threshold`]), ONNX (between 0 and 1) and rustface detectors. dlib's HOG
+    /// detector doesn't report one, [`ExtractorBuilder::build`] fails with
+    /// [`ExtractError::NoConfidence`] for it.
+    pub fn min_confidence(mut self, min_confidence: f32) -> Self {
+        self.min_confidence = Some(min_confidence);
+        self
+    }
+
+    /// Load the models and create the [`Extractor`]
+    pub fn build(self) -> Result<Extractor, ExtractError> {
+        if let (DetectorKind::Hog, Some(_)) = (&self.detector, self.min_confidence) {
+            return Err(ExtractError::NoConfidence("hog"));
+        }
+        let predictor = match self.predictor.ok_or(ExtractError::MissingPredictor)? {
+            PredictorKind::Dlib(path) => Predictor::Dlib(
+                LandmarkPredictor::open(&path)
+                    .map_err(|err| ExtractError::model_load(path, err))?,
+            ),
+            #[cfg(feature = "onnx")]
+            PredictorKind::FaceMesh(path) => {
+                Predictor::Backend(Box::new(backend::FaceMeshPredictor::open(path)?))
+            }
+        };
+        let detector = match self.detector {
+            DetectorKind::Hog => Detector::Hog,
+            DetectorKind::Cnn(path) => {
+                let mut detector = CnnDetector::open_pool(path, self.cnn_pool_size)?;
+                if let Some(min_confidence) = self.min_confidence {
+                    detector = detector.with_threshold(min_confidence);
+                }
+                Detector::Cnn(detector)
+            }
+            #[cfg(feature = "rustface")]
+            DetectorKind::RustFace(path) => {
+                Detector::Backend(Box::new(backend::RustFaceDetector::open(path)?))
+            }
+            #[cfg(feature = "onnx")]
+            DetectorKind::Onnx(path) => {
+                let mut detector = backend::OnnxDetector::open(path)?;
+                if let Some(min_confidence) = self.min_confidence {
+                    detector = detector.with_score_threshold(min_confidence);
+                }
+                Detector::Backend(Box::new(detector))
+            }
+        };
+        let encoder = self.face_encoder.map(FaceEncoder::open).transpose()?;
+        Ok(Extractor {
+            detector,
+            predictor,
+            encoder,
+            upsample: self.upsample,
+            min_face_size: self.min_face_size,
+            max_detection_size: self.max_detection_size,
+            min_confidence: self.min_confidence,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn face_mesh_needs_onnx() {
+        let predictor = "face-mesh:face_landmark.onnx".parse::<PredictorKind>();
+        #[cfg(feature = "onnx")]
+        assert!(matches!(predictor, Ok(PredictorKind::FaceMesh(_))));
+        #[cfg(not(feature = "onnx"))]
+        assert!(predictor.unwrap_err().contains("without onnx support"));
+        let predictor = "shape_predictor_5_face_landmarks.dat".parse::<PredictorKind>();
+        assert!(matches!(predictor, Ok(PredictorKind::Dlib(_))));
+    }
+
+    #[test]
+    fn hog_detector_has_no_confidence() {
+        let built = Extractor::builder()
+            .hog()
+            .shape_predictor("shape_predictor_68_face_landmarks.dat")
+            .min_confidence(0.5)
+            .build();
+        assert!(matches!(built, Err(ExtractError::NoConfidence("hog"))));
+    }
+
+    /// 68 point landmarks whose eyes are 30 pixels wide and `left` and `right` pixels tall
+    fn eyes(left: i64, right: i64) -> Landmarks {
+        let mut points = vec![(0, 0); 68];
+        for (start, x, height) in [(36, 100, right), (42, 200, left)] {
+            // The corners, then the top and bottom (p1, p2, p3, p4, p5, p6)
+            let contour = [
+                (x, 100),
+                (x + 10, 100 - height / 2),
+                (x + 20, 100 - height / 2),
+                (x + 30, 100),
+                (x + 20, 100 + height / 2),
+                (x + 10, 100 + height / 2),
+            ];
+            points[start..start + 6].copy_from_slice(&contour);
+        }
+        Landmarks::new(points, LandmarkSchema::SixtyEightPoint).unwrap()
+    }
+
+    #[test]
+    fn eye_aspect_ratio_of_open_and_closed_eyes() {
+        let (left, right) = eye_aspect_ratio(&eyes(10, 10)).unwrap();
+        assert!((left - 1.0 / 3.0).abs() < 1e-6 && (left - right).abs() < 1e-6);
+        // A blink of the right eye
+        let (left, right) = eye_aspect_ratio(&eyes(10, 0)).unwrap();
+        assert!(left > 0.3 && right < 1e-6, "{left} {right}");
+    }
+
+    #[test]
+    fn eye_aspect_ratio_needs_eye_contours() {
+        let five = Landmarks::new(
+            [(0, 0), (4, 0), (10, 0), (14, 0), (7, 8)],
+            LandmarkSchema::FivePoint,
+        );
+        assert_eq!(eye_aspect_ratio(&five.unwrap()), None);
+        // Landmarks that don't match their schema
+        let short = Landmarks {
+            points: Box::new([(0, 0); 5]),
+            schema: Some(LandmarkSchema::SixtyEightPoint),
+            visibility: None,
+        };
+        assert_eq!(eye_aspect_ratio(&short), None);
+    }
 }
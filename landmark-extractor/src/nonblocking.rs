@@ -0,0 +1,57 @@
+//! Async wrappers that run the extraction on tokio's blocking thread pool
+//!
+//! Detection can take hundreds of milliseconds (much more with the CNN detector), which would block
+//! the executor if called directly from async code.
+
+use std::sync::Arc;
+
+use crate::backend::FaceDetectorBackend;
+use crate::backend::LandmarkBackend;
+use crate::ExtractError;
+use crate::Extractor;
+use crate::Faces;
+
+/// Run `f` on tokio's blocking thread pool, propagating its panics
+async fn spawn_blocking<R: Send + 'static>(
+    f: impl FnOnce() -> Result<R, ExtractError> + Send + 'static,
+) -> Result<R, ExtractError> {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(err) => match err.try_into_panic() {
+            Ok(panic) => std::panic::resume_unwind(panic),
+            Err(_) => Err(ExtractError::Cancelled),
+        },
+    }
+}
+
+/// Find all faces in this image and identify the landmarks in it without blocking the executor
+///
+/// See [`extract_landmarks_upsampled`](crate::extract_landmarks_upsampled), must be called from
+/// within a tokio runtime.
+pub async fn extract_landmarks_async<D, P>(
+    image: image::RgbImage,
+    upsample: u32,
+    detector: Arc<D>,
+    predictor: Arc<P>,
+) -> Result<Faces, ExtractError>
+where
+    D: FaceDetectorBackend + Send + Sync + ?Sized + 'static,
+    P: LandmarkBackend + Send + Sync + ?Sized + 'static,
+{
+    spawn_blocking(move || {
+        crate::extract_landmarks_upsampled(&image, upsample, &*detector, &*predictor)
+    })
+    .await
+}
+
+impl Extractor {
+    /// Same as [`Extractor::extract_image`], but without blocking the executor
+    ///
+    /// Must be called from within a tokio runtime.
+    pub async fn extract_image_async(
+        self: Arc<Self>,
+        image: image::RgbImage,
+    ) -> Result<Faces, ExtractError> {
+        spawn_blocking(move || self.extract_image(&image)).await
+    }
+}
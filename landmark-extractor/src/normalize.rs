@@ -0,0 +1,161 @@
+use crate::Landmarks;
+
+/// A similarity transform that normalizes [`Landmarks`]
+///
+/// Points are translated so that `origin` ends up at `(0, 0)`, rotated by `-angle` radians and then
+/// scaled by `scale`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Normalization {
+    pub origin: (f32, f32),
+    pub angle: f32,
+    pub scale: f32,
+}
+
+impl Normalization {
+    /// Normalize a point
+    pub fn apply(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        let (x, y) = (x - self.origin.0, y - self.origin.1);
+        let (sin, cos) = (-self.angle).sin_cos();
+        (
+            (x * cos - y * sin) * self.scale,
+            (x * sin + y * cos) * self.scale,
+        )
+    }
+
+    /// Undo the normalization of a point
+    pub fn invert(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        let (x, y) = (x / self.scale, y / self.scale);
+        let (sin, cos) = self.angle.sin_cos();
+        (
+            x * cos - y * sin + self.origin.0,
+            x * sin + y * cos + self.origin.1,
+        )
+    }
+}
+
+/// [`Landmarks`] after a [`Normalization`], and the transform used
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NormalizedLandmarks {
+    pub points: Box<[(f32, f32)]>,
+    pub transform: Normalization,
+}
+
+impl Landmarks {
+    /// Normalize the landmarks so their bounding box fits in the unit square
+    ///
+    /// The top left corner of the bounding box ends up at `(0, 0)` and its longest side has length
+    /// 1, the aspect ratio is kept. Returns [`None`] if there are no landmarks or they are all the
+    /// same point.
+    pub fn normalize_to_unit_box(&self) -> Option<NormalizedLandmarks> {
+        let (&first, rest) = self.points.split_first()?;
+        let (min, max) = rest.iter().fold((first, first), |(min, max), &(x, y)| {
+            ((min.0.min(x), min.1.min(y)), (max.0.max(x), max.1.max(y)))
+        });
+        let size = (max.0 - min.0).max(max.1 - min.1);
+        if size == 0 {
+            return None;
+        }
+        let transform = Normalization {
+            origin: (min.0 as f32, min.1 as f32),
+            angle: 0.0,
+            scale: 1.0 / size as f32,
+        };
+        Some(NormalizedLandmarks {
+            points: self.normalize_with(&transform),
+            transform,
+        })
+    }
+
+    /// Normalize the landmarks by the position of the eyes
+    ///
+    /// The point between the eyes ends up at `(0, 0)`, the eyes are rotated to be horizontal (the
+    /// left eye on the positive x axis) and the distance between them is 1. Returns [`None`] if the
+    /// [`LandmarkSchema`](crate::LandmarkSchema) doesn't know where the eyes are or they are in the
+    /// same place.
+    pub fn normalize_interocular(&self) -> Option<NormalizedLandmarks> {
+        let [left, right] = self.eye_centers()?;
+        let (dx, dy) = (left.0 - right.0, left.1 - right.1);
+        let distance = dx.hypot(dy);
+        if distance == 0.0 {
+            return None;
+        }
+        let transform = Normalization {
+            origin: ((left.0 + right.0) / 2.0, (left.1 + right.1) / 2.0),
+            angle: dy.atan2(dx),
+            scale: 1.0 / distance,
+        };
+        Some(NormalizedLandmarks {
+            points: self.normalize_with(&transform),
+            transform,
+        })
+    }
+
+    /// Apply the normalization to all the landmarks
+    pub fn normalize_with(&self, transform: &Normalization) -> Box<[(f32, f32)]> {
+        self.points
+            .iter()
+            .map(|&(x, y)| transform.apply((x as f32, y as f32)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LandmarkSchema;
+
+    fn assert_close((x, y): (f32, f32), (ex, ey): (f32, f32)) {
+        assert!(
+            (x - ex).abs() < 1e-4 && (y - ey).abs() < 1e-4,
+            "({x}, {y}) != ({ex}, {ey})"
+        );
+    }
+
+    #[test]
+    fn unit_box() {
+        let landmarks = Landmarks::new(
+            [(10, 20), (30, 20), (20, 25), (10, 30), (30, 30)],
+            LandmarkSchema::FivePoint,
+        )
+        .unwrap();
+        let normalized = landmarks.normalize_to_unit_box().unwrap();
+        assert_close(normalized.points[0], (0.0, 0.0));
+        assert_close(normalized.points[4], (1.0, 0.5));
+        for (&point, &(x, y)) in normalized.points.iter().zip(landmarks.iter()) {
+            assert_close(normalized.transform.invert(point), (x as f32, y as f32));
+        }
+    }
+
+    #[test]
+    fn interocular() {
+        // Eyes 20 pixels apart, rotated by 90 degrees (the left eye below the right one)
+        let landmarks = Landmarks::new(
+            [(50, 59), (50, 61), (50, 39), (50, 41), (40, 50)],
+            LandmarkSchema::FivePoint,
+        )
+        .unwrap();
+        let normalized = landmarks.normalize_interocular().unwrap();
+        let center = |ix: usize| {
+            let (a, b) = (normalized.points[ix], normalized.points[ix + 1]);
+            ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0)
+        };
+        assert_close(center(0), (0.5, 0.0));
+        assert_close(center(2), (-0.5, 0.0));
+        assert_close(normalized.points[4], (0.0, 0.5));
+        for (&point, &(x, y)) in normalized.points.iter().zip(landmarks.iter()) {
+            assert_close(normalized.transform.invert(point), (x as f32, y as f32));
+        }
+    }
+
+    #[test]
+    fn degenerate() {
+        let same = Landmarks::new([(3, 4); 5], LandmarkSchema::FivePoint).unwrap();
+        assert_eq!(same.normalize_to_unit_box(), None);
+        assert_eq!(same.normalize_interocular(), None);
+        let empty = Landmarks::new([], LandmarkSchema::Custom(0)).unwrap();
+        assert_eq!(empty.normalize_to_unit_box(), None);
+        assert_eq!(empty.normalize_interocular(), None);
+    }
+}
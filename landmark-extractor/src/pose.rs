@@ -0,0 +1,153 @@
+use crate::LandmarkSchema;
+use crate::Landmarks;
+
+/// Rough orientation of a head, in degrees (see [`Landmarks::head_pose`])
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeadPose {
+    /// Turn to the sides, positive when the face turns towards the right of the image
+    pub yaw: f32,
+    /// Nod, positive when looking up
+    pub pitch: f32,
+}
+
+impl LandmarkSchema {
+    /// Index of the point of the nose used by [`Landmarks::head_pose`] and where it sits on a
+    /// frontal face: `(depth, drop)` in front of and below the middle of the eyes, as fractions of
+    /// the distance between the eyes
+    ///
+    /// The fractions come from the proportions of an average adult face: the centers of the eyes
+    /// are about 63 mm apart, the tip of the nose is about 32 mm in front of them and 41 mm below,
+    /// and the base of the nose 19 mm in front and 47 mm below.
+    fn nose_tip(&self) -> Option<(usize, f32, f32)> {
+        match self {
+            Self::SixtyEightPoint => Some((30, 0.5, 0.65)),
+            Self::FaceMesh => Some((1, 0.5, 0.65)),
+            // The base of the nose
+            Self::FivePoint => Some((4, 0.3, 0.75)),
+            Self::Custom(_) => None,
+        }
+    }
+}
+
+impl Landmarks {
+    /// Estimate how much the head is turned from how far the nose moves from the middle of the
+    /// eyes
+    ///
+    /// Uses the proportions of an average face, so it is only good to a few degrees: enough to
+    /// tell a frontal face from a turned one, which won't align well with a similarity transform.
+    ///
+    /// Returns [`None`] if the [`LandmarkSchema`] doesn't know where the eyes and the nose are
+    pub fn head_pose(&self) -> Option<HeadPose> {
+        let [left, right] = self.eye_centers()?;
+        let (tip, depth, drop) = self.schema().nose_tip()?;
+        let (tip_x, tip_y) = self.points[tip];
+        // Face axes without the roll: across the eyes (towards the right of the image) and down
+        let (across_x, across_y) = (left.0 - right.0, left.1 - right.1);
+        let eyes = across_x.hypot(across_y);
+        if eyes <= f32::EPSILON {
+            return None;
+        }
+        let (across_x, across_y) = (across_x / eyes, across_y / eyes);
+        let (nose_x, nose_y) = (
+            tip_x as f32 - (left.0 + right.0) / 2.0,
+            tip_y as f32 - (left.1 + right.1) / 2.0,
+        );
+        let across = (nose_x * across_x + nose_y * across_y) / eyes;
+        let down = (nose_y * across_x - nose_x * across_y) / eyes;
+
+        // The nose moves `depth · sin(yaw)` to the side while the eyes shrink by `cos(yaw)`
+        let yaw = (across / depth).atan();
+        // Below the eyes the nose is at `drop · cos(pitch) - depth · sin(pitch)`, which is
+        // `radius · cos(pitch + angle)` with `angle` the direction of the nose from the eyes
+        let down = down * yaw.cos();
+        let radius = depth.hypot(drop);
+        let angle = depth.atan2(drop);
+        // The nose can't be further than `radius` from the eyes, but noisy landmarks (or a face
+        // with other proportions) can put it there, clamp so `acos` doesn't return NaN. Faces
+        // looking down more than `angle` are reported at `-angle`.
+        let pitch = (down / radius).clamp(-1.0, 1.0).acos() - angle;
+        Some(HeadPose {
+            yaw: yaw.to_degrees(),
+            pitch: pitch.to_degrees(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Five-point landmarks of an average head (see [`LandmarkSchema::nose_tip`]) turned by `yaw`
+    /// and `pitch`, and then rolled by `roll` in the image (in degrees)
+    ///
+    /// The eyes are 1000 pixels apart, so the rounding of the coordinates doesn't matter.
+    fn head(yaw: f32, pitch: f32, roll: f32) -> Landmarks {
+        let (yaw, pitch, roll) = (yaw.to_radians(), pitch.to_radians(), roll.to_radians());
+        // (x, y, z): towards the right of the image, down and towards the camera
+        let model = [
+            (600.0, 0.0, 0.0),
+            (400.0, 0.0, 0.0),
+            (-600.0, 0.0, 0.0),
+            (-400.0, 0.0, 0.0),
+            (0.0, 750.0, 300.0),
+        ];
+        let points = model.map(|(x, y, z): (f32, f32, f32)| {
+            let (y, z) = (
+                y * pitch.cos() - z * pitch.sin(),
+                y * pitch.sin() + z * pitch.cos(),
+            );
+            let x = x * yaw.cos() + z * yaw.sin();
+            let (x, y) = (
+                x * roll.cos() - y * roll.sin(),
+                x * roll.sin() + y * roll.cos(),
+            );
+            ((x + 2000.0).round() as i64, (y + 2000.0).round() as i64)
+        });
+        Landmarks::new(points, LandmarkSchema::FivePoint).unwrap()
+    }
+
+    fn assert_pose(landmarks: &Landmarks, yaw: f32, pitch: f32) {
+        let pose = landmarks.head_pose().unwrap();
+        assert!((pose.yaw - yaw).abs() < 0.5, "{pose:?}, expected yaw {yaw}");
+        assert!(
+            (pose.pitch - pitch).abs() < 0.5,
+            "{pose:?}, expected pitch {pitch}"
+        );
+    }
+
+    #[test]
+    fn frontal() {
+        assert_pose(&head(0.0, 0.0, 0.0), 0.0, 0.0);
+        // Rolling the head in the image plane doesn't turn it
+        assert_pose(&head(0.0, 0.0, 30.0), 0.0, 0.0);
+        assert_pose(&head(0.0, 0.0, -75.0), 0.0, 0.0);
+    }
+
+    #[test]
+    fn turned() {
+        for yaw in [-60.0, -20.0, 10.0, 45.0] {
+            assert_pose(&head(yaw, 0.0, 0.0), yaw, 0.0);
+            assert_pose(&head(yaw, 0.0, 20.0), yaw, 0.0);
+        }
+    }
+
+    #[test]
+    fn tilted() {
+        for pitch in [-12.0, -5.0, 15.0, 40.0] {
+            assert_pose(&head(0.0, pitch, 0.0), 0.0, pitch);
+            assert_pose(&head(0.0, pitch, -20.0), 0.0, pitch);
+        }
+        // Beyond what the nose can reach the pitch is clamped instead of NaN
+        let mut points = head(0.0, 0.0, 0.0).to_vec();
+        points[4].1 += 2000;
+        let landmarks = Landmarks::new(points, LandmarkSchema::FivePoint).unwrap();
+        let pose = landmarks.head_pose().unwrap();
+        assert!(pose.pitch.is_finite() && pose.pitch < 0.0, "{pose:?}");
+    }
+
+    #[test]
+    fn unknown_schema() {
+        let landmarks = Landmarks::new(head(0.0, 0.0, 0.0).to_vec(), LandmarkSchema::Custom(5));
+        assert_eq!(landmarks.unwrap().head_pose(), None);
+    }
+}
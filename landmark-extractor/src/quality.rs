@@ -0,0 +1,35 @@
+use crate::Rect;
+
+/// Sharpness of the face in this bounding box: the variance of the Laplacian of its luma
+///
+/// Higher is sharper. Only comparable between faces of similar size (and lighting), useful to pick
+/// the sharpest of several shots of the same scene.
+///
+/// Returns [`None`] if the bounding box (clamped to the image) is smaller than 3x3 pixels
+pub fn sharpness(image: &image::RgbImage, rect: &Rect) -> Option<f32> {
+    let (width, height) = (i64::from(image.width()), i64::from(image.height()));
+    let left = rect.left.clamp(0, width) as u32;
+    let top = rect.top.clamp(0, height) as u32;
+    let right = rect.right.clamp(0, width) as u32;
+    let bottom = rect.bottom.clamp(0, height) as u32;
+    if right.saturating_sub(left) < 3 || bottom.saturating_sub(top) < 3 {
+        return None;
+    }
+
+    let luma = |x: u32, y: u32| {
+        let [r, g, b] = image.get_pixel(x, y).0.map(f32::from);
+        0.299 * r + 0.587 * g + 0.114 * b
+    };
+    let (mut sum, mut sum_sq, mut count) = (0.0, 0.0, 0.0);
+    for y in top + 1..bottom - 1 {
+        for x in left + 1..right - 1 {
+            let laplacian = luma(x - 1, y) + luma(x + 1, y) + luma(x, y - 1) + luma(x, y + 1)
+                - 4.0 * luma(x, y);
+            sum += laplacian;
+            sum_sq += laplacian * laplacian;
+            count += 1.0;
+        }
+    }
+    let mean = sum / count;
+    Some(sum_sq / count - mean * mean)
+}
@@ -0,0 +1,201 @@
+use crate::Faces;
+use crate::Rect;
+
+/// Default minimum [`Rect::iou`] for a face to be considered the same as a tracked one
+pub const TRACKER_IOU_THRESHOLD: f32 = 0.3;
+
+/// Default number of consecutive images a tracked face can be missing from before it is forgotten
+pub const TRACKER_MAX_AGE: u32 = 5;
+
+/// Identifies the same face across a sequence of images (see [`FaceTracker`])
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FaceId(pub u64);
+
+impl std::fmt::Display for FaceId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// A face seen in the previous images
+#[derive(Debug, Clone)]
+struct Track {
+    id: FaceId,
+    rect: Rect,
+    /// Consecutive images since it was last seen
+    missed: u32,
+}
+
+/// Assigns persistent [`FaceId`]s to the faces of consecutive images
+///
+/// Faces are matched to the ones in the previous images by the overlap ([`Rect::iou`]) of their
+/// bounding boxes, so it only works on sequences where faces don't move much between images.
+#[derive(Debug, Clone)]
+pub struct FaceTracker {
+    tracks: Vec<Track>,
+    next_id: u64,
+    iou_threshold: f32,
+    max_age: u32,
+}
+
+impl Default for FaceTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FaceTracker {
+    /// Create a tracker that hasn't seen any faces yet
+    pub fn new() -> Self {
+        Self {
+            tracks: Vec::new(),
+            next_id: 0,
+            iou_threshold: TRACKER_IOU_THRESHOLD,
+            max_age: TRACKER_MAX_AGE,
+        }
+    }
+
+    /// Minimum overlap for a face to match a tracked one (defaults to [`TRACKER_IOU_THRESHOLD`])
+    pub fn with_iou_threshold(mut self, iou_threshold: f32) -> Self {
+        self.iou_threshold = iou_threshold;
+        self
+    }
+
+    /// Forget faces missing from this many consecutive images (defaults to [`TRACKER_MAX_AGE`])
+    pub fn with_max_age(mut self, max_age: u32) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Match the faces of the next image to the tracked ones
+    ///
+    /// Returns the [`FaceId`] of each face (in the same order as `faces`), new faces get a new
+    /// [`FaceId`].
+    pub fn update(&mut self, faces: &Faces) -> Vec<FaceId> {
+        // Greedily match the pairs with the highest overlap first
+        let mut pairs: Vec<_> = faces
+            .iter()
+            .enumerate()
+            .flat_map(|(face_ix, face)| {
+                self.tracks
+                    .iter()
+                    .enumerate()
+                    .map(move |(track_ix, track)| (face.rect.iou(&track.rect), face_ix, track_ix))
+            })
+            .filter(|&(iou, _, _)| iou >= self.iou_threshold)
+            .collect();
+        pairs.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let mut ids = vec![None; faces.len()];
+        let mut matched = vec![false; self.tracks.len()];
+        for (_, face_ix, track_ix) in pairs {
+            if ids[face_ix].is_some() || matched[track_ix] {
+                continue;
+            }
+            let track = &mut self.tracks[track_ix];
+            track.rect = faces[face_ix].rect.clone();
+            track.missed = 0;
+            ids[face_ix] = Some(track.id);
+            matched[track_ix] = true;
+        }
+
+        for (track, matched) in self.tracks.iter_mut().zip(matched) {
+            if !matched {
+                track.missed += 1;
+            }
+        }
+        let max_age = self.max_age;
+        self.tracks.retain(|track| track.missed <= max_age);
+
+        faces
+            .iter()
+            .zip(ids)
+            .map(|(face, id)| {
+                id.unwrap_or_else(|| {
+                    let id = FaceId(self.next_id);
+                    self.next_id += 1;
+                    self.tracks.push(Track {
+                        id,
+                        rect: face.rect.clone(),
+                        missed: 0,
+                    });
+                    id
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Face;
+    use crate::LandmarkSchema;
+    use crate::Landmarks;
+
+    /// An image with 10×10 faces at these positions
+    fn faces(positions: &[(i64, i64)]) -> Faces {
+        positions
+            .iter()
+            .map(|&(left, top)| {
+                let rect = Rect {
+                    left,
+                    top,
+                    right: left + 10,
+                    bottom: top + 10,
+                };
+                let landmarks = Landmarks::new([(left, top); 5], LandmarkSchema::FivePoint);
+                Face::new(rect, landmarks.unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ids_persist() {
+        let mut tracker = FaceTracker::new();
+        assert_eq!(
+            tracker.update(&faces(&[(0, 0), (50, 0)])),
+            [FaceId(0), FaceId(1)]
+        );
+        // Faces that moved a little keep their ids, in whatever order they were detected
+        assert_eq!(
+            tracker.update(&faces(&[(52, 1), (1, 2)])),
+            [FaceId(1), FaceId(0)]
+        );
+        assert_eq!(tracker.update(&faces(&[(2, 2)])), [FaceId(0)]);
+    }
+
+    #[test]
+    fn new_faces_get_new_ids() {
+        let mut tracker = FaceTracker::new();
+        assert_eq!(tracker.update(&faces(&[(0, 0)])), [FaceId(0)]);
+        assert_eq!(
+            tracker.update(&faces(&[(0, 0), (100, 100)])),
+            [FaceId(0), FaceId(1)]
+        );
+        // A face that jumped too far is a new one
+        assert_eq!(tracker.update(&faces(&[(30, 0)])), [FaceId(2)]);
+        // Two faces can't share a track
+        assert_eq!(
+            tracker.update(&faces(&[(30, 0), (31, 0)])),
+            [FaceId(2), FaceId(3)]
+        );
+    }
+
+    #[test]
+    fn ids_expire() {
+        let mut tracker = FaceTracker::new().with_max_age(2);
+        assert_eq!(tracker.update(&faces(&[(0, 0)])), [FaceId(0)]);
+        for _ in 0..2 {
+            assert!(tracker.update(&faces(&[])).is_empty());
+        }
+        // Missing for max_age images is still remembered
+        assert_eq!(tracker.update(&faces(&[(0, 0)])), [FaceId(0)]);
+        for _ in 0..3 {
+            tracker.update(&faces(&[]));
+        }
+        // But not for longer
+        assert_eq!(tracker.update(&faces(&[(0, 0)])), [FaceId(1)]);
+    }
+}
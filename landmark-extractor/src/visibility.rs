@@ -0,0 +1,85 @@
+use crate::landmark68;
+use crate::LandmarkSchema;
+use crate::Landmarks;
+
+/// The far side of the face is considered occluded if it is less than this fraction of the width
+/// of the face (see [`Landmarks::estimate_visibility`])
+pub const OCCLUSION_THRESHOLD: f32 = 0.3;
+
+/// Tip of the nose in the 68 point model
+const NOSE_TIP: usize = 30;
+
+impl Landmarks {
+    /// Attach a visibility mask, `true` for the points that are visible
+    ///
+    /// Returns [`None`] if the mask doesn't have one value per point
+    pub fn with_visibility(mut self, visibility: impl Into<Box<[bool]>>) -> Option<Self> {
+        let visibility = visibility.into();
+        if visibility.len() != self.points.len() {
+            return None;
+        }
+        self.visibility = Some(visibility);
+        Some(self)
+    }
+
+    /// Which points are visible, `true` for the visible ones
+    ///
+    /// Uses the mask attached with [`Landmarks::with_visibility`] if there is one, otherwise
+    /// [`Landmarks::estimate_visibility`]. Returns [`None`] if unknown.
+    pub fn visibility(&self) -> Option<Box<[bool]>> {
+        self.visibility
+            .clone()
+            .or_else(|| self.estimate_visibility())
+    }
+
+    /// Estimate which points are occluded by the face turning sideways
+    ///
+    /// Only supported for [`LandmarkSchema::SixtyEightPoint`]: the jaw line on the far side of the
+    /// face is hidden if the distance from the nose tip to that side is less than
+    /// [`OCCLUSION_THRESHOLD`] of the width of the face.
+    pub fn estimate_visibility(&self) -> Option<Box<[bool]>> {
+        if self.schema() != LandmarkSchema::SixtyEightPoint || self.points.len() != 68 {
+            return None;
+        }
+        let x = |ix: usize| self.points[ix].0 as f32;
+        let (right, left) = (x(landmark68::JAW.start), x(landmark68::JAW.end - 1));
+        let width = left - right;
+        if width <= 0.0 {
+            return None;
+        }
+        let nose = x(NOSE_TIP);
+        let mut visibility = vec![true; self.points.len()].into_boxed_slice();
+        // The chin (the middle of the jaw line) is always visible
+        let middle = (landmark68::JAW.start + landmark68::JAW.end) / 2;
+        if (nose - right) / width < OCCLUSION_THRESHOLD {
+            visibility[landmark68::JAW.start..middle].fill(false);
+        } else if (left - nose) / width < OCCLUSION_THRESHOLD {
+            visibility[middle + 1..landmark68::JAW.end].fill(false);
+        }
+        Some(visibility)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn landmarks() -> Landmarks {
+        Landmarks::new([(0, 0), (1, 1), (2, 2)], LandmarkSchema::Custom(3)).unwrap()
+    }
+
+    #[test]
+    fn mask_length_must_match() {
+        assert!(landmarks().with_visibility([true, false]).is_none());
+        assert!(landmarks().with_visibility([true, false, true]).is_some());
+    }
+
+    #[test]
+    fn subset_keeps_the_mask() {
+        let landmarks = landmarks().with_visibility([true, false, true]).unwrap();
+        let subset = landmarks.subset([2, 1]).unwrap();
+        assert_eq!(&*subset, &[(2, 2), (1, 1)]);
+        assert_eq!(subset.visibility().as_deref(), Some(&[true, false][..]));
+        assert!(landmarks.subset([3]).is_none());
+    }
+}
@@ -0,0 +1,208 @@
+use std::path::PathBuf;
+
+use anyhow::ensure;
+use anyhow::Context;
+use clap::Args;
+use landmark_extractor::Face;
+use landmark_extractor::FaceRegion;
+use landmark_extractor::LandmarkSchema;
+use landmark_extractor::Landmarks;
+use log::info;
+use stabilizer::smoothing::Smoothing;
+use stabilizer::smoothing::DEFAULT_BETA;
+use stabilizer::smoothing::DEFAULT_L1_STRENGTH;
+use stabilizer::smoothing::DEFAULT_MIN_CUTOFF;
+use stabilizer::smoothing::DEFAULT_SMOOTHNESS;
+use stabilizer::Alignment;
+use stabilizer::Fit;
+use stabilizer::Reference;
+use stabilizer::SimilarityTransform;
+
+use crate::faces::eye_centers;
+use crate::faces::visible_points;
+use crate::features::read_landmarks;
+
+/// How to align the faces to the reference and smooth the alignment, shared by the subcommands
+/// that stabilize a sequence of images
+#[derive(Debug, Args)]
+pub struct AlignmentOpts {
+    /// Stabilize against the landmarks in this file instead of an image: a list of (x, y)
+    /// points in RON, or JSON ([x, y]) if the extension is .json
+    ///
+    /// Batches aligned to the same landmarks (e.g. a canonical template or the reference of a
+    /// previous run, see --save-reference-landmarks of the transform subcommand) end up in
+    /// exactly the same place
+    #[arg(long, conflicts_with = "mean_shape")]
+    pub reference_landmarks: Option<PathBuf>,
+    /// Stabilize against the mean shape of all the faces instead of the first image
+    ///
+    /// Avoids biasing the whole sequence towards the expression and pose of a single image
+    #[arg(long)]
+    pub mean_shape: bool,
+    /// How to align the faces: similarity (also scales them), rigid (keeps their size),
+    /// anisotropic (scales each axis independently), affine (also shears them), homography
+    /// (also corrects the perspective) or eye-line (similarity from the centers of the eyes
+    /// only, ignores expressions)
+    ///
+    /// export-transforms and stabilize-video only support the similarity and eye-line
+    /// alignments
+    #[arg(short, long, default_value = "similarity")]
+    pub alignment: Alignment,
+    /// Only align these parts of the face (comma separated): eyes, nose, mouth and jaw
+    ///
+    /// Ignoring the landmarks that move with the expression (mouth, jaw) gives steadier
+    /// results, e.g. eyes,nose
+    #[arg(long, value_delimiter = ',')]
+    pub landmarks_subset: Vec<FaceRegion>,
+    /// Smooth the transforms across the (sorted) images: one-euro (filters them in order),
+    /// kalman (smooths the whole sequence at once) or l1 (turns the whole sequence into
+    /// static, linear and parabolic segments)
+    ///
+    /// Reduces the jitter in videos and dense timelapses, only supported with the similarity
+    /// alignment
+    #[arg(long)]
+    pub smooth: Option<Smoothing>,
+    /// Minimum cutoff frequency of the smoothing (in cycles per image), lower values reduce the
+    /// jitter
+    #[arg(long, default_value_t = DEFAULT_MIN_CUTOFF)]
+    pub smooth_cutoff: f32,
+    /// Speed coefficient of the smoothing, higher values reduce the lag
+    #[arg(long, default_value_t = DEFAULT_BETA)]
+    pub smooth_beta: f32,
+    /// Smoothness of the kalman smoothing, higher values give steadier results
+    #[arg(long, default_value_t = DEFAULT_SMOOTHNESS)]
+    pub smoothness: f32,
+    /// Strength of the l1 smoothing, higher values give steadier results
+    #[arg(long, default_value_t = DEFAULT_L1_STRENGTH)]
+    pub l1_strength: f32,
+}
+
+impl AlignmentOpts {
+    /// Check the combination of options
+    pub fn check(&self) -> anyhow::Result<()> {
+        ensure!(
+            self.smooth.is_none() || self.alignment == Alignment::Similarity,
+            "smoothing is only supported with the similarity alignment"
+        );
+        Ok(())
+    }
+
+    /// The selected smoothing with the parameters of the options
+    pub fn smoothing(&self) -> Option<Smoothing> {
+        self.smooth.map(|smooth| match smooth {
+            Smoothing::OneEuro { .. } => Smoothing::OneEuro {
+                min_cutoff: self.smooth_cutoff,
+                beta: self.smooth_beta,
+            },
+            Smoothing::Kalman { .. } => Smoothing::Kalman {
+                smoothness: self.smoothness,
+            },
+            Smoothing::L1 { .. } => Smoothing::L1 {
+                strength: self.l1_strength,
+            },
+        })
+    }
+
+    /// The landmarks of --reference-landmarks or --mean-shape, [`None`] if the reference is an
+    /// image
+    ///
+    /// `faces` are the selected faces of the images, in order
+    pub fn reference_shape<'a>(
+        &self,
+        faces: impl IntoIterator<Item = &'a Face>,
+    ) -> anyhow::Result<Option<Landmarks>> {
+        let mut faces = faces.into_iter();
+        if let Some(path) = &self.reference_landmarks {
+            let schema = faces
+                .next()
+                .context("no image has a face")?
+                .landmarks
+                .schema();
+            return Ok(Some(read_landmarks(path, schema)?));
+        }
+        if !self.mean_shape {
+            return Ok(None);
+        }
+        info!("computing the mean shape");
+        let faces: Vec<_> = faces.collect();
+        let schema = faces
+            .first()
+            .context("no image has a face")?
+            .landmarks
+            .schema();
+        let shapes: Vec<Vec<_>> = faces
+            .iter()
+            .map(|face| {
+                face.landmarks
+                    .iter()
+                    .map(|&(x, y)| (x as f32, y as f32).into())
+                    .collect()
+            })
+            .collect();
+        let mean = stabilizer::mean_shape(&shapes).context("computing the mean shape")?;
+        let mean: Vec<_> = mean
+            .iter()
+            .map(|p| (p.x.round() as i64, p.y.round() as i64))
+            .collect();
+        let mean = Landmarks::new(mean, schema).context("the mean shape has the wrong length")?;
+        Ok(Some(mean))
+    }
+
+    /// The indices of the --landmarks-subset in the landmarks of this schema, [`None`] to use all
+    /// of them
+    pub fn subset(&self, schema: LandmarkSchema) -> anyhow::Result<Option<Vec<usize>>> {
+        if self.landmarks_subset.is_empty() {
+            return Ok(None);
+        }
+        let mut subset = Vec::new();
+        for &region in &self.landmarks_subset {
+            let indices = schema.region(region).with_context(|| {
+                format!("the {schema} landmarks don't know where the {region} are")
+            })?;
+            subset.extend(indices);
+        }
+        Ok(Some(subset))
+    }
+
+    /// Align each face to the reference with [`stabilizer::fit_sequence`], the missing faces (and
+    /// the faces that can't be aligned) are [`None`]
+    ///
+    /// Only for the alignments that give a [`SimilarityTransform`] (similarity and eye-line), pass
+    /// the result to [`stabilizer::smooth_sequence`] with [`AlignmentOpts::smoothing`]
+    pub fn fit_faces(
+        &self,
+        reference: &Landmarks,
+        faces: &[Option<&Face>],
+        fit: Fit,
+    ) -> anyhow::Result<Vec<Option<SimilarityTransform>>> {
+        ensure!(
+            matches!(self.alignment, Alignment::Similarity | Alignment::EyeLine),
+            "only the similarity and eye-line alignments are supported"
+        );
+        let subset = self.subset(reference.schema())?;
+        let ref_eyes = if self.alignment == Alignment::EyeLine {
+            let eyes = eye_centers(reference)
+                .context("the eye-line alignment needs landmarks that locate the eyes")?;
+            Some(eyes)
+        } else {
+            None
+        };
+        let (targets, shapes): (Vec<_>, Vec<_>) = faces
+            .iter()
+            .map(|face| {
+                let Some(face) = face else {
+                    return Default::default();
+                };
+                match &ref_eyes {
+                    Some(ref_eyes) => match eye_centers(&face.landmarks) {
+                        Some(eyes) => (ref_eyes.clone(), eyes),
+                        None => Default::default(),
+                    },
+                    None => visible_points(reference, &face.landmarks, subset.as_deref()),
+                }
+            })
+            .unzip();
+        stabilizer::fit_sequence(&shapes, Reference::PerFrame(targets), fit)
+            .context("the reference can't be used to align the faces")
+    }
+}
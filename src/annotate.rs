@@ -0,0 +1,115 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::Args;
+use image::DynamicImage;
+use image::Rgb;
+use imageproc::rect::Rect;
+
+use crate::features::read_features;
+use crate::output::common_dir;
+use crate::output::save_image;
+
+/// Options of the annotate subcommand
+#[derive(Debug, Args)]
+pub struct AnnotateOpts {
+    /// Path to the extracted features
+    features: PathBuf,
+    /// Directory where to place the annotated images
+    #[arg(short, long, default_value = "./annotated")]
+    output_dir: PathBuf,
+    /// Radius of the landmarks in pixels
+    #[arg(long, default_value_t = 2)]
+    radius: i32,
+    /// TrueType font to write the index of each landmark with (they aren't numbered without it)
+    #[arg(long)]
+    font: Option<PathBuf>,
+}
+
+pub fn run(opts: AnnotateOpts) -> anyhow::Result<()> {
+    use imageproc::drawing::draw_filled_circle_mut;
+    use imageproc::drawing::draw_hollow_rect_mut;
+    use imageproc::drawing::draw_text_mut;
+    use indicatif::*;
+
+    let AnnotateOpts {
+        features,
+        output_dir,
+        radius,
+        font,
+    } = opts;
+    let features = read_features(&features)?;
+    let features: Vec<_> = features.into_iter().collect();
+    let root = common_dir(features.iter().map(|(path, _)| path.as_path()));
+    let font = match font {
+        Some(path) => {
+            let data = std::fs::read(&path)
+                .with_context(|| format!("reading the font {}", path.display()))?;
+            let font = rusttype::Font::try_from_vec(data)
+                .with_context(|| format!("{} is not a TrueType font", path.display()))?;
+            Some(font)
+        }
+        None => None,
+    };
+
+    let style =
+        ProgressStyle::with_template("[{pos:>4}/{len:4}] {msg} {bar} [{per_sec} {eta_precise}]")
+            .expect("valid template");
+    #[cfg(feature = "rayon")]
+    use rayon::prelude::*;
+    #[cfg(feature = "rayon")]
+    let features = features.into_par_iter();
+    #[cfg(not(feature = "rayon"))]
+    let features = features.into_iter();
+    features
+        .progress_with_style(style)
+        .try_for_each(|(path, faces)| -> anyhow::Result<()> {
+            let mut img = image::open(&path)
+                .with_context(|| format!("opening image {}", path.display()))?
+                .into_rgb8();
+            for face in faces.iter() {
+                let rect = &face.rect;
+                let width = rect.width().max(1) as u32;
+                let height = rect.height().max(1) as u32;
+                let bounds = Rect::at(rect.left as i32, rect.top as i32).of_size(width, height);
+                draw_hollow_rect_mut(&mut img, bounds, Rgb([0, 255, 0]));
+                let visibility = face.landmarks.visibility();
+                for (ix, &(x, y)) in face.landmarks.iter().enumerate() {
+                    let visible = visibility
+                        .as_ref()
+                        .and_then(|visible| visible.get(ix).copied())
+                        .unwrap_or(true);
+                    // The occluded landmarks are gray
+                    let color = if visible {
+                        Rgb([255, 0, 0])
+                    } else {
+                        Rgb([128, 128, 128])
+                    };
+                    let (x, y) = (x as i32, y as i32);
+                    draw_filled_circle_mut(&mut img, (x, y), radius, color);
+                    if let Some(font) = &font {
+                        let scale = rusttype::Scale::uniform((4 * radius).max(10) as f32);
+                        let (x, y) = (x + radius + 1, y - radius);
+                        draw_text_mut(
+                            &mut img,
+                            Rgb([255, 255, 0]),
+                            x,
+                            y,
+                            scale,
+                            font,
+                            &ix.to_string(),
+                        );
+                    }
+                }
+            }
+            let relative = path
+                .strip_prefix(&root)
+                .unwrap_or_else(|_| Path::new(path.file_name().expect("valid file name")));
+            save_image(
+                &DynamicImage::ImageRgb8(img),
+                &output_dir.join(relative),
+                None,
+            )
+        })
+}
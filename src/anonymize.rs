@@ -0,0 +1,199 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::Args;
+use image::DynamicImage;
+use image::Rgb;
+use image::RgbImage;
+use imageproc::filter::gaussian_blur_f32;
+use landmark_extractor::Embedding;
+use landmark_extractor::FaceSelection;
+use landmark_extractor::SAME_PERSON_DISTANCE;
+
+use crate::faces::select_face;
+use crate::features::read_features;
+use crate::output::common_dir;
+use crate::output::save_image;
+
+/// Options of the anonymize subcommand
+#[derive(Debug, Args)]
+pub struct AnonymizeOpts {
+    /// Path to the extracted features
+    features: PathBuf,
+    /// Directory where to place the anonymized images
+    ///
+    /// The images keep their paths relative to the directory that contains all of them
+    #[arg(short, long, default_value = "./anonymized")]
+    output_dir: PathBuf,
+    /// How to hide the faces: blur, pixelate or black
+    #[arg(long, default_value = "blur")]
+    mode: Redaction,
+    /// Margin around the bounding boxes relative to their size (they are usually tight)
+    #[arg(long, default_value_t = 0.2)]
+    margin: f32,
+    /// Don't hide the face chosen in each image: largest, most-central, most-confident or
+    /// index:<n> (in the order they were detected)
+    #[arg(long, value_name = "SELECTION", conflicts_with = "subject")]
+    keep: Option<FaceSelection>,
+    /// Don't hide the faces of the person whose face is the largest in this image (of the
+    /// features file), needs the features to have embeddings (--face-encoder)
+    #[arg(long, value_name = "IMAGE")]
+    subject: Option<PathBuf>,
+}
+
+/// How to hide a face
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Redaction {
+    Blur,
+    Pixelate,
+    Black,
+}
+
+/// Parses `blur`, `pixelate` or `black`
+impl std::str::FromStr for Redaction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blur" => Ok(Self::Blur),
+            "pixelate" => Ok(Self::Pixelate),
+            "black" => Ok(Self::Black),
+            _ => Err(format!("expected blur, pixelate or black, found {s}")),
+        }
+    }
+}
+
+pub fn run(opts: AnonymizeOpts) -> anyhow::Result<()> {
+    use indicatif::*;
+
+    let AnonymizeOpts {
+        features,
+        output_dir,
+        mode,
+        margin,
+        keep,
+        subject,
+    } = opts;
+    let features = read_features(&features)?;
+    let subject = match subject {
+        Some(path) => {
+            let faces = features
+                .get(&path)
+                .or_else(|| {
+                    let path = path.canonicalize().ok()?;
+                    features.iter().find_map(|(other, faces)| {
+                        (other.canonicalize().ok()? == path).then_some(faces)
+                    })
+                })
+                .with_context(|| format!("{} is not in the features file", path.display()))?;
+            let face = faces
+                .largest()
+                .with_context(|| format!("{} has no face", path.display()))?;
+            let embedding = face.embedding.clone().with_context(|| {
+                format!(
+                    "the face in {} has no embedding, extract the features with --face-encoder",
+                    path.display()
+                )
+            })?;
+            Some(embedding)
+        }
+        None => None,
+    };
+    let features: Vec<_> = features.into_iter().collect();
+    let root = common_dir(features.iter().map(|(path, _)| path.as_path()));
+
+    let style =
+        ProgressStyle::with_template("[{pos:>4}/{len:4}] {msg} {bar} [{per_sec} {eta_precise}]")
+            .expect("valid template");
+    #[cfg(feature = "rayon")]
+    use rayon::prelude::*;
+    #[cfg(feature = "rayon")]
+    let features = features.into_par_iter();
+    #[cfg(not(feature = "rayon"))]
+    let features = features.into_iter();
+    features
+        .progress_with_style(style)
+        .try_for_each(|(path, faces)| -> anyhow::Result<()> {
+            let mut img = image::open(&path)
+                .with_context(|| format!("opening image {}", path.display()))?
+                .into_rgb8();
+            let kept = match keep {
+                Some(selection) => select_face(&path, &faces, selection),
+                None => None,
+            };
+            for face in faces.iter() {
+                if kept.is_some_and(|kept| std::ptr::eq(kept, face)) {
+                    continue;
+                }
+                let is_subject = |subject: &Embedding| {
+                    face.embedding
+                        .as_ref()
+                        .is_some_and(|embedding| embedding.distance(subject) < SAME_PERSON_DISTANCE)
+                };
+                if subject.as_ref().is_some_and(is_subject) {
+                    continue;
+                }
+                redact(&mut img, &face.rect, margin, mode);
+            }
+            let relative = path
+                .strip_prefix(&root)
+                .unwrap_or_else(|_| Path::new(path.file_name().expect("valid file name")));
+            save_image(
+                &DynamicImage::ImageRgb8(img),
+                &output_dir.join(relative),
+                None,
+            )
+        })
+}
+
+/// Hide the area of the bounding box (grown by `margin` times its size)
+fn redact(img: &mut RgbImage, rect: &landmark_extractor::Rect, margin: f32, mode: Redaction) {
+    let grow_x = (rect.width() as f32 * margin) as i64;
+    let grow_y = (rect.height() as f32 * margin) as i64;
+    let left = (rect.left - grow_x).clamp(0, img.width().into()) as u32;
+    let top = (rect.top - grow_y).clamp(0, img.height().into()) as u32;
+    let right = (rect.right + grow_x).clamp(0, img.width().into()) as u32;
+    let bottom = (rect.bottom + grow_y).clamp(0, img.height().into()) as u32;
+    if left >= right || top >= bottom {
+        return;
+    }
+    let (width, height) = (right - left, bottom - top);
+    // Hide the face at about 8 blocks (or blur radii) across, so it can't be recognized
+    let block = (width.max(height) / 8).max(1);
+    match mode {
+        Redaction::Blur => {
+            let area = image::imageops::crop_imm(img, left, top, width, height).to_image();
+            let blurred = gaussian_blur_f32(&area, block as f32);
+            image::imageops::replace(img, &blurred, left.into(), top.into());
+        }
+        Redaction::Pixelate => {
+            for y in (top..bottom).step_by(block as usize) {
+                for x in (left..right).step_by(block as usize) {
+                    let (x1, y1) = ((x + block).min(right), (y + block).min(bottom));
+                    let mut sum = [0u64; 3];
+                    for pixel in (y..y1).flat_map(|y| (x..x1).map(move |x| (x, y))) {
+                        let Rgb(channels) = img.get_pixel(pixel.0, pixel.1);
+                        for (sum, &channel) in sum.iter_mut().zip(channels) {
+                            *sum += u64::from(channel);
+                        }
+                    }
+                    let count = u64::from((x1 - x) * (y1 - y));
+                    let mean = Rgb(sum.map(|sum| (sum / count) as u8));
+                    for y in y..y1 {
+                        for x in x..x1 {
+                            img.put_pixel(x, y, mean);
+                        }
+                    }
+                }
+            }
+        }
+        Redaction::Black => {
+            for y in top..bottom {
+                for x in left..right {
+                    img.put_pixel(x, y, Rgb([0, 0, 0]));
+                }
+            }
+        }
+    }
+}
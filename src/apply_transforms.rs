@@ -0,0 +1,82 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::Args;
+use stabilizer::Fill;
+use stabilizer::Interpolation;
+
+use crate::export_transforms::read_transforms;
+use crate::output::common_dir;
+use crate::output::save_image;
+use crate::parse_size;
+
+/// Options of the apply-transforms subcommand
+#[derive(Debug, Args)]
+pub struct ApplyTransformsOpts {
+    /// Path to the transforms file (JSON or CSV, see export-transforms)
+    ///
+    /// The matrix of each image is used if present, otherwise its translation, rotation and
+    /// scale. Relative image paths are relative to the transforms file.
+    transforms: PathBuf,
+    /// Directory where to place the transformed images
+    ///
+    /// The images keep their paths relative to the directory that contains all of them
+    #[arg(short, long, default_value = "./out")]
+    output_dir: PathBuf,
+    /// How to fill the borders uncovered by the alignment (see the transform subcommand)
+    #[arg(long, default_value = "constant")]
+    fill: Fill,
+    /// How to sample the images when warping them: nearest (fastest), bilinear or bicubic
+    /// (sharpest)
+    #[arg(long, default_value = "bicubic")]
+    interpolation: Interpolation,
+    /// Size of the output images (WIDTHxHEIGHT) instead of the size of each input
+    #[arg(long, value_parser = parse_size)]
+    output_size: Option<(u32, u32)>,
+    /// Quality of the jpg and webp outputs (1-100), lower values give smaller files
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: Option<u8>,
+}
+
+pub fn run(opts: ApplyTransformsOpts) -> anyhow::Result<()> {
+    use indicatif::*;
+
+    let ApplyTransformsOpts {
+        transforms,
+        output_dir,
+        fill,
+        interpolation,
+        output_size,
+        quality,
+    } = opts;
+    let transforms = read_transforms(&transforms)?;
+    let root = common_dir(transforms.iter().map(|(path, _)| path.as_path()));
+
+    let style =
+        ProgressStyle::with_template("[{pos:>4}/{len:4}] {msg} {bar} [{per_sec} {eta_precise}]")
+            .expect("valid template");
+    #[cfg(feature = "rayon")]
+    use rayon::prelude::*;
+    #[cfg(feature = "rayon")]
+    let transforms = transforms.into_par_iter();
+    #[cfg(not(feature = "rayon"))]
+    let transforms = transforms.into_iter();
+    transforms
+        .progress_with_style(style)
+        .try_for_each(|(path, projection)| -> anyhow::Result<()> {
+            let img = image::open(&path)
+                .with_context(|| format!("opening image {}", path.display()))?
+                .into_rgb8();
+            let size = output_size.unwrap_or(img.dimensions());
+            let warped = stabilizer::warp_to_size(&img, &projection, fill, interpolation, size);
+            let relative = path
+                .strip_prefix(&root)
+                .unwrap_or_else(|_| Path::new(path.file_name().expect("valid file name")));
+            let mut out = output_dir.join(relative);
+            if fill == Fill::Transparent {
+                out.set_extension("png");
+            }
+            save_image(&warped, &out, quality)
+        })
+}
@@ -0,0 +1,170 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::Args;
+use log::info;
+use stabilizer::MeanImage;
+use stabilizer::Stacking;
+use stabilizer::DEFAULT_CLIP_SIGMA;
+
+use crate::output::save_image;
+use crate::read_file_list;
+use crate::ImageSource;
+
+/// Options of the average subcommand
+#[derive(Debug, Args)]
+pub struct AverageOpts {
+    #[command(flatten)]
+    images: ImageSource,
+    /// Path to the mean image
+    #[arg(short, long, default_value = "average.png")]
+    output: PathBuf,
+    /// File with the weight of each image, one `<path> <weight>` (or `<path>,<weight>`) per line
+    ///
+    /// The paths can be file names, the images that aren't listed have a weight of 1
+    #[arg(long)]
+    weights: Option<PathBuf>,
+    /// File listing the images to leave out (one per line, like --files-from)
+    #[arg(long, value_name = "LIST")]
+    exclude: Option<PathBuf>,
+    /// How to stack the images: mean, median or sigma-clip (mean without the outliers of each
+    /// pixel)
+    ///
+    /// Median and sigma-clip remove things that only appear in a few images (glasses, hands),
+    /// but need all the images in memory
+    #[arg(long, default_value = "mean")]
+    stacking: Stacking,
+    /// Standard deviations from the mean beyond which sigma-clip rejects a pixel
+    #[arg(long, default_value_t = DEFAULT_CLIP_SIGMA)]
+    sigma: f32,
+}
+
+pub fn run(opts: AverageOpts) -> anyhow::Result<()> {
+    use indicatif::*;
+
+    let AverageOpts {
+        images,
+        output,
+        weights,
+        exclude,
+        stacking,
+        sigma,
+    } = opts;
+    let weights = match weights {
+        Some(path) => read_weights(&path)?,
+        None => Vec::new(),
+    };
+    let excluded = match exclude {
+        Some(path) => read_file_list(&path)?,
+        None => Vec::new(),
+    };
+    let images: Vec<_> = images
+        .image_paths()?
+        .into_iter()
+        .filter(|path| !excluded.iter().any(|excluded| path.ends_with(excluded)))
+        .map(|path| {
+            let weight = weights
+                .iter()
+                .find(|(listed, _)| path.ends_with(listed))
+                .map_or(1.0, |&(_, weight)| weight);
+            (path, weight)
+        })
+        .filter(|&(_, weight)| weight > 0.0)
+        .collect();
+    let (first, _) = images.first().context("there are no images to average")?;
+    let (width, height) = image::image_dimensions(first)
+        .with_context(|| format!("reading the size of {}", first.display()))?;
+    info!("stacking {} images", images.len());
+
+    let add = |mut mean: MeanImage, (path, weight): (PathBuf, f32)| -> anyhow::Result<_> {
+        let image =
+            image::open(&path).with_context(|| format!("opening image {}", path.display()))?;
+        mean.add(&image, weight).with_context(|| {
+            format!(
+                "{} is {}x{}, not {width}x{height} like the rest",
+                path.display(),
+                image.width(),
+                image.height()
+            )
+        })?;
+        Ok(mean)
+    };
+    let style =
+        ProgressStyle::with_template("[{pos:>4}/{len:4}] {msg} {bar} [{per_sec} {eta_precise}]")
+            .expect("valid template");
+
+    if stacking != Stacking::Mean {
+        let stacking = match stacking {
+            Stacking::SigmaClip { .. } => Stacking::SigmaClip { sigma },
+            stacking => stacking,
+        };
+        let open = |(path, weight): (PathBuf, f32)| -> anyhow::Result<_> {
+            let image =
+                image::open(&path).with_context(|| format!("opening image {}", path.display()))?;
+            Ok((image.into_rgba8(), weight))
+        };
+        #[cfg(feature = "rayon")]
+        use rayon::prelude::*;
+        #[cfg(feature = "rayon")]
+        let images = images.into_par_iter();
+        #[cfg(not(feature = "rayon"))]
+        let images = images.into_iter();
+        let images: Vec<_> = images
+            .progress_with_style(style)
+            .map(open)
+            .collect::<anyhow::Result<_>>()?;
+        let stacked = stacking
+            .stack(&images)
+            .with_context(|| format!("the images are not all {width}x{height}"))?;
+        return save_image(&stacked, &output, None);
+    }
+
+    #[cfg(feature = "rayon")]
+    let mean = {
+        use rayon::prelude::*;
+        images
+            .into_par_iter()
+            .progress_with_style(style)
+            .try_fold(|| MeanImage::new(width, height), add)
+            .try_reduce(
+                || MeanImage::new(width, height),
+                |mut mean, other| {
+                    mean.merge(&other).expect("all have the same size");
+                    Ok(mean)
+                },
+            )?
+    };
+    #[cfg(not(feature = "rayon"))]
+    let mean = images
+        .into_iter()
+        .progress_with_style(style)
+        .try_fold(MeanImage::new(width, height), add)?;
+
+    save_image(&mean.mean(), &output, None)
+}
+
+/// Read the `<path> <weight>` (or `<path>,<weight>`) lines of a weights file
+fn read_weights(path: &Path) -> anyhow::Result<Vec<(PathBuf, f32)>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("reading the weights from {}", path.display()))?;
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(ix, line)| {
+            let (image, weight) = line
+                .trim()
+                .rsplit_once(|c: char| c == ',' || c.is_whitespace())
+                .and_then(|(image, weight)| Some((image.trim(), weight.parse().ok()?)))
+                .with_context(|| {
+                    format!(
+                        "{}:{}: expected <path> <weight>, found {line:?}",
+                        path.display(),
+                        ix + 1
+                    )
+                })?;
+            Ok((PathBuf::from(image), weight))
+        })
+        .collect()
+}
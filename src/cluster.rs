@@ -0,0 +1,127 @@
+use std::path::PathBuf;
+
+use anyhow::ensure;
+use anyhow::Context;
+use clap::Args;
+use image::DynamicImage;
+use landmark_extractor::SAME_PERSON_DISTANCE;
+use log::info;
+use log::warn;
+
+use crate::features::read_features;
+use crate::output::save_image;
+
+/// Options of the cluster subcommand
+#[derive(Debug, Args)]
+pub struct ClusterOpts {
+    /// Path to the features file, extracted with a face recognition model (--face-encoder)
+    features: PathBuf,
+    /// Directory where to write the list of images of each identity (`0.txt` has the most
+    /// frequent one)
+    #[arg(short, long, default_value = "clusters")]
+    output_dir: PathBuf,
+    /// Faces whose embeddings are closer than this are the same person
+    #[arg(long, default_value_t = SAME_PERSON_DISTANCE)]
+    threshold: f64,
+    /// Ignore the identities with fewer faces than this
+    #[arg(long, default_value_t = 1)]
+    min_size: usize,
+    /// Also save aligned crops of the faces of each identity in a directory (e.g. `0/`)
+    #[arg(long)]
+    crops: bool,
+    /// Width and height of the crops in pixels
+    #[arg(long, default_value_t = 128)]
+    crop_size: u32,
+}
+
+pub fn run(opts: ClusterOpts) -> anyhow::Result<()> {
+    let ClusterOpts {
+        features,
+        output_dir,
+        threshold,
+        min_size,
+        crops,
+        crop_size,
+    } = opts;
+    let features = read_features(&features)?;
+    let mut images: Vec<_> = features.iter().collect();
+    images.sort_by(|a, b| a.0.cmp(b.0));
+    let faces: Vec<_> = images
+        .iter()
+        .flat_map(|(path, faces)| {
+            faces
+                .iter()
+                .enumerate()
+                .map(move |(ix, face)| (*path, ix, face))
+        })
+        .collect();
+    let (faces, missing): (Vec<_>, Vec<_>) = faces
+        .into_iter()
+        .partition(|(_, _, face)| face.embedding.is_some());
+    ensure!(
+        !faces.is_empty(),
+        "no face has an embedding, extract the features with --face-encoder"
+    );
+    if !missing.is_empty() {
+        warn!(
+            "{} faces don't have an embedding, skipping them",
+            missing.len()
+        );
+    }
+    let embeddings: Vec<_> = faces
+        .iter()
+        .map(|(_, _, face)| face.embedding.as_ref().expect("only faces with embeddings"))
+        .collect();
+    let clusters = landmark_extractor::cluster(&embeddings, threshold);
+
+    let mut identities: Vec<Vec<_>> = Vec::new();
+    for (face, cluster) in faces.into_iter().zip(clusters) {
+        if identities.len() <= cluster {
+            identities.resize_with(cluster + 1, Vec::new);
+        }
+        identities[cluster].push(face);
+    }
+    std::fs::create_dir_all(&output_dir)
+        .with_context(|| format!("creating {} directory", output_dir.display()))?;
+    let mut kept = 0;
+    for (id, members) in identities.iter().enumerate() {
+        if members.len() < min_size {
+            continue;
+        }
+        kept += 1;
+        let mut images: Vec<_> = members.iter().map(|(path, ..)| *path).collect();
+        images.dedup();
+        println!(
+            "identity {id}: {} faces in {} images",
+            members.len(),
+            images.len()
+        );
+        let list = output_dir.join(format!("{id}.txt"));
+        let contents: String = images
+            .iter()
+            .map(|path| format!("{}\n", path.display()))
+            .collect();
+        std::fs::write(&list, contents).with_context(|| format!("writing {}", list.display()))?;
+        if crops {
+            for (path, ix, face) in members {
+                let img = image::open(path)
+                    .with_context(|| format!("opening image {}", path.display()))?
+                    .into_rgb8();
+                let Some(chip) = landmark_extractor::face_chip(&img, face, crop_size, 0.25) else {
+                    warn!("the landmarks of {} can't be aligned", path.display());
+                    continue;
+                };
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                let crop = output_dir
+                    .join(id.to_string())
+                    .join(format!("{stem}_{ix}.png"));
+                save_image(&DynamicImage::ImageRgb8(chip), &crop, None)?;
+            }
+        }
+    }
+    info!(
+        "found {} identities, {kept} with at least {min_size} faces",
+        identities.len()
+    );
+    Ok(())
+}
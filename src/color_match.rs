@@ -0,0 +1,96 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::ensure;
+use anyhow::Context;
+use clap::Args;
+use image::DynamicImage;
+use imageproc::rect::Rect;
+
+use crate::deflicker::center_region;
+use crate::output::common_dir;
+use crate::output::save_image;
+use crate::parse_region;
+use crate::ImageSource;
+
+/// Options of the color-match subcommand
+#[derive(Debug, Args)]
+pub struct ColorMatchOpts {
+    #[command(flatten)]
+    images: ImageSource,
+    /// Directory where to place the corrected images
+    ///
+    /// The images keep their paths relative to the directory that contains all of them
+    #[arg(short, long, default_value = "./color-matched")]
+    output_dir: PathBuf,
+    /// Image whose colors the others are matched to (defaults to the first one)
+    #[arg(long)]
+    reference: Option<PathBuf>,
+    /// Region (WIDTHxHEIGHT+X+Y) whose colors are matched, usually the face (defaults to the
+    /// center half of the images)
+    #[arg(long, value_parser = parse_region)]
+    region: Option<Rect>,
+    /// How much to correct the colors, from 0 (not at all) to 1 (match the reference exactly)
+    #[arg(long, default_value_t = 1.0)]
+    strength: f32,
+    /// Quality of the jpg and webp outputs (1-100), lower values give smaller files
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: Option<u8>,
+}
+
+pub fn run(opts: ColorMatchOpts) -> anyhow::Result<()> {
+    use indicatif::*;
+
+    let ColorMatchOpts {
+        images,
+        output_dir,
+        reference,
+        region,
+        strength,
+        quality,
+    } = opts;
+    ensure!(
+        (0.0..=1.0).contains(&strength),
+        "the strength must be between 0 and 1, not {strength}"
+    );
+    let mut paths = images.image_paths()?;
+    paths.sort();
+    let root = common_dir(paths.iter().map(PathBuf::as_path));
+    let reference = match reference {
+        Some(reference) => reference,
+        None => paths.first().context("there are no images")?.clone(),
+    };
+    let histograms = |path: &Path| -> anyhow::Result<_> {
+        let img = image::open(path)
+            .with_context(|| format!("opening image {}", path.display()))?
+            .into_rgb8();
+        let region = region.unwrap_or_else(|| center_region(img.dimensions()));
+        let histograms = stabilizer::color_histograms(&img, region)
+            .with_context(|| format!("the region is outside of {}", path.display()))?;
+        Ok((img, histograms))
+    };
+    let (_, target) = histograms(&reference)?;
+
+    let style =
+        ProgressStyle::with_template("[{pos:>4}/{len:4}] {msg} {bar} [{per_sec} {eta_precise}]")
+            .expect("valid template");
+    #[cfg(feature = "rayon")]
+    use rayon::prelude::*;
+    #[cfg(feature = "rayon")]
+    let iter = paths.par_iter();
+    #[cfg(not(feature = "rayon"))]
+    let iter = paths.iter();
+    iter.progress_with_style(style)
+        .try_for_each(|path| -> anyhow::Result<()> {
+            let (mut img, source) = histograms(path)?;
+            stabilizer::ColorMap::matching(&source, &target, strength).apply(&mut img);
+            let relative = path
+                .strip_prefix(&root)
+                .unwrap_or_else(|_| Path::new(path.file_name().expect("valid file name")));
+            save_image(
+                &DynamicImage::ImageRgb8(img),
+                &output_dir.join(relative),
+                quality,
+            )
+        })
+}
@@ -0,0 +1,37 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use log::info;
+
+use crate::features::read_features;
+use crate::features::write_features;
+use crate::features::FeaturesFormat;
+
+/// Options of the convert subcommand
+#[derive(Debug, Args)]
+pub struct ConvertOpts {
+    /// Path to the features file
+    input: PathBuf,
+    /// Path to the converted features file, its extension chooses the format: json, cbor, csv or
+    /// ron
+    output: PathBuf,
+    /// Whether to pretty print the converted file (JSON and RON only)
+    #[arg(short, long)]
+    pretty: bool,
+}
+
+pub fn run(opts: ConvertOpts) -> anyhow::Result<()> {
+    let ConvertOpts {
+        input,
+        output,
+        pretty,
+    } = opts;
+    let features = read_features(&input)?;
+    info!(
+        "converting {} images from {:?} to {:?}",
+        features.len(),
+        FeaturesFormat::of(&input),
+        FeaturesFormat::of(&output)
+    );
+    write_features(&output, &features, pretty)
+}
@@ -0,0 +1,181 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::Args;
+use image::DynamicImage;
+use landmark_extractor::Face;
+use landmark_extractor::FaceSelection;
+use log::info;
+use log::warn;
+
+use crate::faces::select_face;
+use crate::features::read_features;
+use crate::output::common_dir;
+use crate::output::save_image;
+
+/// Options of the crop-faces subcommand
+#[derive(Debug, Args)]
+pub struct CropFacesOpts {
+    /// Path to the features file
+    features: PathBuf,
+    /// Directory where to place the crops
+    ///
+    /// The crops keep the paths of their images relative to the directory that contains all of
+    /// them, with the index of the face appended (e.g. `2023/img_0.png`)
+    #[arg(short, long, default_value = "faces")]
+    output_dir: PathBuf,
+    /// Width and height of the crops in pixels
+    #[arg(long, default_value_t = 256)]
+    size: u32,
+    /// Margin around the faces relative to their size
+    #[arg(long, default_value_t = 0.25)]
+    padding: f32,
+    /// Only crop one face of each image: largest, most-central, most-confident or index:<n> (in
+    /// the order they were detected), by default every face is cropped
+    #[arg(long = "select-face", value_name = "SELECTION")]
+    selection: Option<FaceSelection>,
+    /// Path to the manifest, JSON if its extension is `json` and CSV otherwise (defaults to
+    /// `manifest.csv` in the output directory)
+    ///
+    /// It lists the path of each crop (relative to the output directory), its image, the index of
+    /// the face and its bounding box, confidence and sharpness.
+    #[arg(long)]
+    manifest: Option<PathBuf>,
+}
+
+pub fn run(opts: CropFacesOpts) -> anyhow::Result<()> {
+    let CropFacesOpts {
+        features,
+        output_dir,
+        size,
+        padding,
+        selection,
+        manifest,
+    } = opts;
+    use indicatif::*;
+    let features = read_features(&features)?;
+    let root = common_dir(features.keys().map(PathBuf::as_path));
+    let mut images: Vec<_> = features.iter().collect();
+    images.sort_by(|a, b| a.0.cmp(b.0));
+    let manifest = manifest.unwrap_or_else(|| output_dir.join("manifest.csv"));
+
+    let style =
+        ProgressStyle::with_template("[{pos:>4}/{len:4}] {msg} {bar} [{per_sec} {eta_precise}]")
+            .expect("valid template");
+    #[cfg(feature = "rayon")]
+    use rayon::prelude::*;
+    #[cfg(feature = "rayon")]
+    let iter = images.into_par_iter();
+    #[cfg(not(feature = "rayon"))]
+    let iter = images.into_iter();
+    let crops: Vec<Vec<_>> = iter
+        .progress_with_style(style)
+        .map(|(path, faces)| -> anyhow::Result<_> {
+            let faces: Vec<_> = match selection {
+                Some(selection) => select_face(path, faces, selection)
+                    .and_then(|face| faces.iter().position(|f| std::ptr::eq(f, face)))
+                    .map(|ix| (ix, &faces[ix]))
+                    .into_iter()
+                    .collect(),
+                None => faces.iter().enumerate().collect(),
+            };
+            if faces.is_empty() {
+                warn!("{} does not have a face, skipping", path.display());
+                return Ok(Vec::new());
+            }
+            let img = image::open(path)
+                .with_context(|| format!("opening image {}", path.display()))?
+                .into_rgb8();
+            let relative = path.strip_prefix(&root).unwrap_or(path);
+            let stem = relative.file_stem().unwrap_or_default().to_string_lossy();
+            let mut crops = Vec::new();
+            for (ix, face) in faces {
+                let Some(chip) = landmark_extractor::face_chip(&img, face, size, padding) else {
+                    warn!("the landmarks of {} can't be aligned", path.display());
+                    continue;
+                };
+                let crop = relative.with_file_name(format!("{stem}_{ix}.png"));
+                save_image(
+                    &DynamicImage::ImageRgb8(chip),
+                    &output_dir.join(&crop),
+                    None,
+                )?;
+                crops.push((crop, path, ix, face));
+            }
+            Ok(crops)
+        })
+        .collect::<anyhow::Result<_>>()?;
+    let crops: Vec<_> = crops.into_iter().flatten().collect();
+    info!("cropped {} faces", crops.len());
+
+    if let Some(dir) = manifest.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("creating {} directory", dir.display()))?;
+    }
+    let file = std::fs::File::create(&manifest)
+        .with_context(|| format!("creating the manifest {}", manifest.display()))?;
+    let writer = std::io::BufWriter::new(file);
+    if manifest.extension().is_some_and(|ext| ext == "json") {
+        let entries: Vec<_> = crops
+            .iter()
+            .map(
+                |(
+                    crop,
+                    image,
+                    ix,
+                    Face {
+                        rect,
+                        confidence,
+                        sharpness,
+                        ..
+                    },
+                )| {
+                    serde_json::json!({
+                        "crop": crop,
+                        "image": image,
+                        "face": ix,
+                        "rect": [rect.left, rect.top, rect.right, rect.bottom],
+                        "confidence": confidence,
+                        "sharpness": sharpness,
+                    })
+                },
+            )
+            .collect();
+        serde_json::to_writer_pretty(writer, &entries).context("writing the manifest")?;
+    } else {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record([
+            "crop",
+            "image",
+            "face",
+            "left",
+            "top",
+            "right",
+            "bottom",
+            "confidence",
+            "sharpness",
+        ])?;
+        for (crop, image, ix, face) in &crops {
+            let Face {
+                rect,
+                confidence,
+                sharpness,
+                ..
+            } = face;
+            let optional = |value: Option<f32>| value.map_or(String::new(), |v| v.to_string());
+            writer.write_record([
+                crop.to_string_lossy().into_owned(),
+                image.to_string_lossy().into_owned(),
+                ix.to_string(),
+                rect.left.to_string(),
+                rect.top.to_string(),
+                rect.right.to_string(),
+                rect.bottom.to_string(),
+                optional(*confidence),
+                optional(*sharpness),
+            ])?;
+        }
+        writer.flush().context("writing the manifest")?;
+    }
+    Ok(())
+}
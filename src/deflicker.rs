@@ -0,0 +1,106 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::ensure;
+use anyhow::Context;
+use clap::Args;
+use image::DynamicImage;
+use imageproc::rect::Rect;
+use log::info;
+use stabilizer::DEFAULT_DEFLICKER_RADIUS;
+
+use crate::output::common_dir;
+use crate::output::save_image;
+use crate::parse_region;
+use crate::ImageSource;
+
+/// Options of the deflicker subcommand
+#[derive(Debug, Args)]
+pub struct DeflickerOpts {
+    #[command(flatten)]
+    images: ImageSource,
+    /// Directory where to place the corrected images
+    ///
+    /// The images keep their paths relative to the directory that contains all of them
+    #[arg(short, long, default_value = "./deflickered")]
+    output_dir: PathBuf,
+    /// Number of images on each side whose average brightness each image is corrected to,
+    /// higher values give steadier results but hide slower changes
+    #[arg(long, default_value_t = DEFAULT_DEFLICKER_RADIUS)]
+    radius: usize,
+    /// Region (WIDTHxHEIGHT+X+Y) where the brightness is measured, usually the face (defaults
+    /// to the center half of the images)
+    #[arg(long, value_parser = parse_region)]
+    region: Option<Rect>,
+    /// Quality of the jpg and webp outputs (1-100), lower values give smaller files
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: Option<u8>,
+}
+
+pub fn run(opts: DeflickerOpts) -> anyhow::Result<()> {
+    use indicatif::*;
+
+    let DeflickerOpts {
+        images,
+        output_dir,
+        radius,
+        region,
+        quality,
+    } = opts;
+    let mut paths = images.image_paths()?;
+    // The images are in name order, like the frames written by transform
+    paths.sort();
+    ensure!(!paths.is_empty(), "there are no images");
+    let root = common_dir(paths.iter().map(PathBuf::as_path));
+
+    let style =
+        ProgressStyle::with_template("[{pos:>4}/{len:4}] {msg} {bar} [{per_sec} {eta_precise}]")
+            .expect("valid template");
+    #[cfg(feature = "rayon")]
+    use rayon::prelude::*;
+    info!("measuring the brightness");
+    #[cfg(feature = "rayon")]
+    let iter = paths.par_iter();
+    #[cfg(not(feature = "rayon"))]
+    let iter = paths.iter();
+    let stats: Vec<_> = iter
+        .progress_with_style(style.clone())
+        .map(|path| -> anyhow::Result<_> {
+            let img = image::open(path)
+                .with_context(|| format!("opening image {}", path.display()))?
+                .into_rgb8();
+            let region = region.unwrap_or_else(|| center_region(img.dimensions()));
+            stabilizer::luma_stats(&img, region)
+                .with_context(|| format!("the region is outside of {}", path.display()))
+        })
+        .collect::<anyhow::Result<_>>()?;
+    let corrections = stabilizer::deflicker_corrections(&stats, radius);
+
+    info!("correcting the images");
+    #[cfg(feature = "rayon")]
+    let iter = paths.par_iter().zip(corrections);
+    #[cfg(not(feature = "rayon"))]
+    let iter = paths.iter().zip(corrections);
+    iter.progress_with_style(style)
+        .try_for_each(|(path, correction)| -> anyhow::Result<()> {
+            let mut img = image::open(path)
+                .with_context(|| format!("opening image {}", path.display()))?
+                .into_rgb8();
+            correction.apply(&mut img);
+            let relative = path
+                .strip_prefix(&root)
+                .unwrap_or_else(|_| Path::new(path.file_name().expect("valid file name")));
+            save_image(
+                &DynamicImage::ImageRgb8(img),
+                &output_dir.join(relative),
+                quality,
+            )
+        })
+}
+
+/// The center half of an image of `(width, height)` pixels, where the face of the transformed
+/// images usually is
+pub fn center_region((width, height): (u32, u32)) -> Rect {
+    Rect::at((width / 4) as i32, (height / 4) as i32)
+        .of_size((width / 2).max(1), (height / 2).max(1))
+}
@@ -0,0 +1,313 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use anyhow::Context;
+use clap::Args;
+use glam::Vec2;
+use imageproc::geometric_transformations::Projection;
+use landmark_extractor::FaceSelection;
+use log::info;
+use log::warn;
+use stabilizer::Fit;
+use stabilizer::SimilarityTransform;
+
+use crate::alignment::AlignmentOpts;
+use crate::faces::select_face;
+use crate::features::check_schemas;
+use crate::features::path_in_file;
+use crate::features::read_features;
+use crate::features::resolve_path;
+use crate::features::sort_features;
+use crate::features::SortOrder;
+
+/// Options of the export-transforms subcommand
+#[derive(Debug, Args)]
+pub struct ExportTransformsOpts {
+    /// Path to the extracted features
+    features: PathBuf,
+    /// Path to the transforms file, JSON if its extension is `json` and CSV otherwise
+    ///
+    /// Each image has its translation (in pixels), rotation (in degrees), scale and the
+    /// row-major 3×3 matrix that maps the coordinates of the image to the aligned ones. Relative
+    /// image paths are relative to the transforms file, like in the features files
+    #[arg(short, long, default_value = "transforms.csv")]
+    output: PathBuf,
+    /// Image to align the others to (defaults to the first one)
+    #[arg(long, conflicts_with_all = ["mean_shape", "reference_landmarks"])]
+    reference: Option<PathBuf>,
+    /// Order of the images: name, mtime or exif-date (see the transform subcommand)
+    #[arg(long, default_value = "name")]
+    sort: SortOrder,
+    /// Which face to align in images with several of them: largest, most-central,
+    /// most-confident or index:<n> (in the order they were detected)
+    #[arg(
+        long = "select-face",
+        value_name = "SELECTION",
+        default_value = "largest"
+    )]
+    selection: FaceSelection,
+    /// Keep the images without a face (or that can't be aligned), interpolating their transform
+    /// from the neighbouring images (instead of skipping them)
+    #[arg(long)]
+    interpolate_missing: bool,
+    /// Don't scale the faces
+    #[arg(long)]
+    no_scale: bool,
+    /// Don't rotate the faces
+    #[arg(long)]
+    no_rotation: bool,
+    #[command(flatten)]
+    align: AlignmentOpts,
+}
+
+pub fn run(opts: ExportTransformsOpts) -> anyhow::Result<()> {
+    let ExportTransformsOpts {
+        features,
+        output,
+        reference,
+        sort,
+        selection,
+        interpolate_missing,
+        no_scale,
+        no_rotation,
+        align,
+    } = opts;
+    align.check()?;
+    let features = read_features(&features)?;
+    let mut features: Vec<_> = features.into_iter().collect();
+    sort_features(&mut features, sort)?;
+    check_schemas(&features)?;
+    let faces: Vec<_> = features
+        .iter()
+        .filter_map(|(path, faces)| {
+            let face = select_face(path, faces, selection);
+            if face.is_none() && !interpolate_missing {
+                warn!("{} does not have a face, skipping", path.display());
+                return None;
+            }
+            Some((path, face))
+        })
+        .collect();
+    let selected = faces.iter().filter_map(|(_, face)| *face);
+    let (ref_name, ref_landmarks) = match align.reference_shape(selected)? {
+        Some(shape) => ("the reference shape".to_string(), shape),
+        None => {
+            let (ref_path, ref_face) = match &reference {
+                Some(reference) => faces
+                    .iter()
+                    .find(|(path, _)| path.ends_with(reference))
+                    .with_context(|| {
+                        format!("{} is not in the features file", reference.display())
+                    })?,
+                None => faces
+                    .iter()
+                    .find(|(_, face)| face.is_some())
+                    .context("no image has a face")?,
+            };
+            let ref_face = ref_face.with_context(|| {
+                format!("the reference image {} has no face", ref_path.display())
+            })?;
+            (ref_path.display().to_string(), ref_face.landmarks.clone())
+        }
+    };
+
+    let fit = Fit {
+        rotate: !no_rotation,
+        scale: !no_scale,
+    };
+    let selected: Vec<_> = faces.iter().map(|(_, face)| *face).collect();
+    let fitted = align.fit_faces(&ref_landmarks, &selected, fit)?;
+    let (paths, fitted): (Vec<_>, Vec<_>) = if interpolate_missing {
+        (faces.iter().map(|(path, _)| *path).collect(), fitted)
+    } else {
+        faces
+            .iter()
+            .zip(fitted)
+            .filter_map(|((path, _), similarity)| {
+                if similarity.is_none() {
+                    warn!("{} can't be aligned, skipping", path.display());
+                }
+                Some((*path, Some(similarity?)))
+            })
+            .unzip()
+    };
+    let similarities = stabilizer::smooth_sequence(&fitted, align.smoothing())
+        .context("no image can be aligned")?;
+    let transforms: Vec<_> = paths.into_iter().zip(similarities).collect();
+    info!("aligned {} images to {ref_name}", transforms.len());
+
+    let file =
+        std::fs::File::create(&output).with_context(|| format!("creating {}", output.display()))?;
+    let writer = std::io::BufWriter::new(file);
+    if output.extension().is_some_and(|ext| ext == "json") {
+        let entries: Vec<_> = transforms
+            .iter()
+            .map(|(image, similarity)| {
+                let matrix = similarity.to_matrix();
+                Ok(serde_json::json!({
+                    "image": path_in_file(&output, image)?,
+                    "translation": [similarity.translation.x, similarity.translation.y],
+                    "rotation": similarity.rotation.to_degrees(),
+                    "scale": similarity.scale,
+                    "matrix": [&matrix[0..3], &matrix[3..6], &matrix[6..9]],
+                }))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        serde_json::to_writer_pretty(writer, &entries).context("writing the transforms")?;
+    } else {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record(TRANSFORMS_HEADER)?;
+        for (image, similarity) in &transforms {
+            let record = [
+                similarity.translation.x,
+                similarity.translation.y,
+                similarity.rotation.to_degrees(),
+                similarity.scale,
+            ]
+            .into_iter()
+            .chain(similarity.to_matrix())
+            .map(|value| value.to_string());
+            let image = path_in_file(&output, image)?;
+            let image = image.to_string_lossy().into_owned();
+            writer.write_record(std::iter::once(image).chain(record))?;
+        }
+        writer.flush().context("writing the transforms")?;
+    }
+    Ok(())
+}
+
+/// Columns of a CSV transforms file
+const TRANSFORMS_HEADER: [&str; 14] = [
+    "image",
+    "translation_x",
+    "translation_y",
+    "rotation",
+    "scale",
+    "m00",
+    "m01",
+    "m02",
+    "m10",
+    "m11",
+    "m12",
+    "m20",
+    "m21",
+    "m22",
+];
+
+/// Read a transforms file in the format of [`run`], JSON if its extension is
+/// `json` and CSV otherwise
+pub fn read_transforms(path: &Path) -> anyhow::Result<Vec<(PathBuf, Projection)>> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("opening the transforms file {}", path.display()))?;
+    let reader = std::io::BufReader::new(file);
+    // Each image as its path, matrix and (translation, rotation, scale)
+    type Entry = (PathBuf, Option<[f32; 9]>, Option<(Vec2, f32, f32)>);
+    let entries: Vec<Entry> = if path.extension().is_some_and(|ext| ext == "json") {
+        let entries: Vec<serde_json::Value> =
+            serde_json::from_reader(reader).context("deserializing the transforms")?;
+        entries
+            .iter()
+            .enumerate()
+            .map(|(ix, entry)| {
+                let context = || format!("reading transform {ix}");
+                let image = entry["image"].as_str().with_context(context)?;
+                let number = |value: &serde_json::Value| value.as_f64().map(|v| v as f32);
+                let matrix = match entry["matrix"].as_array() {
+                    Some(rows) => {
+                        let matrix: Vec<_> = rows
+                            .iter()
+                            .flat_map(|row| row.as_array().into_iter().flatten())
+                            .map(number)
+                            .collect::<Option<_>>()
+                            .with_context(context)?;
+                        Some(matrix.try_into().ok().with_context(context)?)
+                    }
+                    None => None,
+                };
+                let similarity = (|| {
+                    let translation = entry["translation"].as_array()?;
+                    let translation =
+                        Vec2::new(number(translation.first()?)?, number(translation.get(1)?)?);
+                    Some((
+                        translation,
+                        number(&entry["rotation"])?,
+                        number(&entry["scale"])?,
+                    ))
+                })();
+                Ok((image.into(), matrix, similarity))
+            })
+            .collect::<anyhow::Result<_>>()?
+    } else {
+        let mut reader = csv::Reader::from_reader(reader);
+        let header: Vec<String> = reader
+            .headers()
+            .context("reading the header")?
+            .iter()
+            .map(str::to_string)
+            .collect();
+        let column = |name: &str| header.iter().position(|column| column == name);
+        let image = column("image").context("the transforms have no image column")?;
+        let matrix: Option<Vec<_>> = TRANSFORMS_HEADER[5..].iter().map(|m| column(m)).collect();
+        let similarity: Option<Vec<_>> = TRANSFORMS_HEADER[1..5]
+            .iter()
+            .map(|name| column(name))
+            .collect();
+        reader
+            .records()
+            .enumerate()
+            .map(|(ix, record)| {
+                // The header is line 1
+                let line = ix + 2;
+                let record = record.with_context(|| format!("reading line {line}"))?;
+                let values = |columns: &[usize]| -> anyhow::Result<Option<Vec<f32>>> {
+                    let values: Vec<_> = columns
+                        .iter()
+                        .map(|&column| record.get(column).unwrap_or_default())
+                        .collect();
+                    if values.iter().all(|value| value.is_empty()) {
+                        return Ok(None);
+                    }
+                    let values = values
+                        .iter()
+                        .map(|value| value.parse())
+                        .collect::<Result<_, _>>()
+                        .with_context(|| format!("parsing line {line}"))?;
+                    Ok(Some(values))
+                };
+                let image = record
+                    .get(image)
+                    .with_context(|| format!("line {line} has no image"))?;
+                let matrix = match &matrix {
+                    Some(columns) => {
+                        values(columns)?.map(|matrix| matrix.try_into().expect("nine columns"))
+                    }
+                    None => None,
+                };
+                let similarity = match &similarity {
+                    Some(columns) => values(columns)?.map(|v| (Vec2::new(v[0], v[1]), v[2], v[3])),
+                    None => None,
+                };
+                Ok((image.into(), matrix, similarity))
+            })
+            .collect::<anyhow::Result<_>>()?
+    };
+
+    entries
+        .into_iter()
+        .map(|(image, matrix, similarity)| {
+            let projection = match (matrix, similarity) {
+                (Some(matrix), _) => Projection::from_matrix(matrix)
+                    .with_context(|| format!("the matrix of {} is singular", image.display()))?,
+                (None, Some((translation, rotation, scale))) => SimilarityTransform {
+                    translation,
+                    rotation: rotation.to_radians(),
+                    scale,
+                }
+                .to_projection(),
+                (None, None) => bail!("{} has no transform", image.display()),
+            };
+            Ok((resolve_path(path, image), projection))
+        })
+        .collect()
+}
@@ -0,0 +1,136 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use landmark_extractor::FaceSelection;
+use landmark_extractor::Faces;
+use landmark_extractor::SAME_PERSON_DISTANCE;
+use log::info;
+use log::warn;
+
+use crate::faces::face_embedding;
+use crate::features::write_features;
+use crate::features::Features;
+use crate::ExtractorOpts;
+use crate::ImageSource;
+
+pub fn run(
+    extractor: ExtractorOpts,
+    images: ImageSource,
+    output: PathBuf,
+    pretty: bool,
+    selection: Option<FaceSelection>,
+    subject: Option<PathBuf>,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    if dry_run {
+        info!("dry run, {} will not be written", output.display());
+    } else if output.exists() {
+        warn!("{} exists, making a backup", output.display());
+        let mut backup = output.clone();
+        backup.set_extension(output.extension().map_or("bak".to_string(), |ext| {
+            format!("{}.bak", ext.to_str().unwrap_or(""))
+        }));
+        std::fs::rename(&output, backup).context("trying to backup the ouput file")?;
+    }
+
+    let extractor = extractor.build()?;
+
+    let subject = match subject {
+        Some(path) => Some(face_embedding(&extractor, &path)?),
+        None => None,
+    };
+
+    let image_paths = images.image_paths()?;
+
+    use indicatif::*;
+    let style =
+        ProgressStyle::with_template("[{pos:>4}/{len:4}] {msg} {bar} [{per_sec} {eta_precise}]")
+            .expect("valid template");
+
+    #[cfg(feature = "rayon")]
+    use rayon::prelude::*;
+    #[cfg(feature = "rayon")]
+    let iter = image_paths.into_par_iter();
+
+    #[cfg(not(feature = "rayon"))]
+    let iter = image_paths.into_iter();
+
+    let extract = |path: PathBuf| -> anyhow::Result<(PathBuf, Faces)> {
+        let img = image::open(&path)
+            .with_context(|| format!("failed to open {}", path.display()))?
+            .into_rgb8();
+        let landmarks = extractor
+            .extract_image(&img)
+            .with_context(|| format!("extracting landmarks from {}", path.display()))?;
+        if let Some(subject) = &subject {
+            let face = match landmarks.most_similar(subject) {
+                Some((face, distance)) if distance < SAME_PERSON_DISTANCE => Some(face.clone()),
+                _ => {
+                    warn!("{} does not have the subject's face", path.display());
+                    None
+                }
+            };
+            return Ok((path, face.into_iter().collect()));
+        }
+        let landmarks = match selection {
+            Some(selection) => landmarks
+                .select(selection, img.dimensions())
+                .cloned()
+                .into_iter()
+                .collect(),
+            None => landmarks,
+        };
+        Ok((path, landmarks))
+    };
+    let iter = iter.progress_with_style(style);
+
+    if dry_run {
+        let results: Vec<_> = iter
+            .map(|path| (path.clone(), extract(path).map(|(_, faces)| faces)))
+            .collect();
+        report_detections(results);
+        return Ok(());
+    }
+
+    // Fail before processing the images if the output can't be written
+    std::fs::File::create(&output)
+        .with_context(|| format!("creating the features file {}", output.display()))?;
+    let features: Features = iter.map(extract).collect::<anyhow::Result<_>>()?;
+
+    info!("finished processing");
+    info!("serializing to file");
+    write_features(&output, &features, pretty)
+}
+
+/// Print how many faces were detected in each image, listing the ones that would be skipped
+fn report_detections(mut results: Vec<(PathBuf, anyhow::Result<Faces>)>) {
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+    let count = |faces: fn(usize) -> bool| {
+        results
+            .iter()
+            .filter(|(_, result)| result.as_ref().is_ok_and(|f| faces(f.len())))
+            .count()
+    };
+    println!("{} images", results.len());
+    println!("  {} with no face", count(|n| n == 0));
+    println!("  {} with one face", count(|n| n == 1));
+    println!("  {} with several faces", count(|n| n > 1));
+    println!(
+        "  {} that could not be processed",
+        results.iter().filter(|(_, result)| result.is_err()).count()
+    );
+    for (path, result) in &results {
+        match result {
+            Ok(faces) if faces.is_empty() => {
+                println!("skipped {}: no face was detected", path.display())
+            }
+            Ok(faces) if faces.len() > 1 => println!(
+                "{}: {} faces were detected, the largest one will be used (see --select-face)",
+                path.display(),
+                faces.len()
+            ),
+            Ok(_) => {}
+            Err(err) => println!("failed {}: {err:#}", path.display()),
+        }
+    }
+}
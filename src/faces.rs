@@ -0,0 +1,112 @@
+use std::path::Path;
+
+use anyhow::Context;
+use glam::Vec2;
+use landmark_extractor::Embedding;
+use landmark_extractor::Extractor;
+use landmark_extractor::Face;
+use landmark_extractor::FaceSelection;
+use landmark_extractor::Faces;
+use landmark_extractor::Landmarks;
+use log::warn;
+
+/// The [`Embedding`] of the largest face in the photo at `path`
+pub fn face_embedding(extractor: &Extractor, path: &Path) -> anyhow::Result<Embedding> {
+    let img = image::open(path)
+        .with_context(|| format!("failed to open {}", path.display()))?
+        .into_rgb8();
+    let faces = extractor
+        .extract_image(&img)
+        .with_context(|| format!("extracting landmarks from {}", path.display()))?;
+    let face = faces
+        .largest()
+        .with_context(|| format!("the photo {} has no face", path.display()))?;
+    face.embedding
+        .clone()
+        .context("identifying faces needs a face recognition model")
+}
+
+/// Select the face to stabilize if there are many
+pub fn select_face<'a>(
+    path: &Path,
+    faces: &'a Faces,
+    selection: FaceSelection,
+) -> Option<&'a Face> {
+    if faces.len() > 1 {
+        warn!(
+            "{} has {} faces, using the {selection} one",
+            path.display(),
+            faces.len()
+        );
+    }
+    // Only the most central face needs the size of the image
+    let size = if selection == FaceSelection::MostCentral {
+        match image::image_dimensions(path) {
+            Ok(size) => size,
+            Err(err) => {
+                warn!("reading the size of {}: {err}", path.display());
+                return None;
+            }
+        }
+    } else {
+        (0, 0)
+    };
+    faces.select(selection, size)
+}
+
+/// Whether the [`Landmarks::head_pose`] is within the limits (in degrees)
+///
+/// Faces with an unknown pose are considered frontal
+pub fn is_frontal(landmarks: &Landmarks, max_yaw: Option<f32>, max_pitch: Option<f32>) -> bool {
+    let Some(pose) = landmarks.head_pose() else {
+        return true;
+    };
+    max_yaw.is_none_or(|max| pose.yaw.abs() <= max)
+        && max_pitch.is_none_or(|max| pose.pitch.abs() <= max)
+}
+
+/// The centers of the left and right eyes as points
+pub fn eye_centers(landmarks: &Landmarks) -> Option<Vec<Vec2>> {
+    let eyes = landmarks.eye_centers()?;
+    Some(eyes.map(Vec2::from).to_vec())
+}
+
+/// The landmarks in `subset` (or all of them) as points
+pub fn to_points(landmarks: &Landmarks, subset: Option<&[usize]>) -> Vec<Vec2> {
+    landmarks
+        .iter()
+        .enumerate()
+        .filter(|(ix, _)| subset.is_none_or(|subset| subset.contains(ix)))
+        .map(|(_, &(x, y))| Vec2::new(x as f32, y as f32))
+        .collect()
+}
+
+/// The points of `target` and `points` that are visible in both (and in `subset` if any)
+pub fn visible_points(
+    target: &Landmarks,
+    points: &Landmarks,
+    subset: Option<&[usize]>,
+) -> (Vec<Vec2>, Vec<Vec2>) {
+    // Ignore the points occluded in either image
+    let masks = [target.visibility(), points.visibility()];
+    let visible = |ix: usize| {
+        subset.is_none_or(|subset| subset.contains(&ix))
+            && masks
+                .iter()
+                .flatten()
+                .all(|mask| mask.get(ix).copied().unwrap_or(true))
+    };
+    let target = target
+        .iter()
+        .enumerate()
+        .filter(|&(ix, _)| visible(ix))
+        .map(|(_, &(x, y))| Vec2::new(x as f32, y as f32))
+        .collect();
+    let points = points
+        .iter()
+        .enumerate()
+        .filter(|&(ix, _)| visible(ix))
+        .map(|(_, &(x, y))| Vec2::new(x as f32, y as f32))
+        .collect();
+    (target, points)
+}
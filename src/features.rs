@@ -0,0 +1,539 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::ensure;
+use anyhow::Context;
+use landmark_extractor::Face;
+use landmark_extractor::Faces;
+use landmark_extractor::LandmarkSchema;
+use landmark_extractor::Landmarks;
+use log::warn;
+
+use crate::output::write_atomically;
+
+pub type Features = HashMap<PathBuf, Faces>;
+
+/// Ensure all the landmarks were produced by the same shape predictor
+///
+/// Landmarks from different models can't be compared, so better to fail before transforming any
+/// image
+pub fn check_schemas(features: &[(PathBuf, Faces)]) -> anyhow::Result<()> {
+    let mut landmarks = features
+        .iter()
+        .flat_map(|(path, faces)| faces.iter().map(move |face| (path, &face.landmarks)));
+    let Some((ref_path, ref_landmarks)) = landmarks.next() else {
+        return Ok(());
+    };
+    let schema = ref_landmarks.schema();
+    for (path, landmarks) in std::iter::once((ref_path, ref_landmarks)).chain(landmarks) {
+        ensure!(
+            landmarks.len() == landmarks.schema().len(),
+            "{} has {} landmarks, but they should follow the {} schema",
+            path.display(),
+            landmarks.len(),
+            landmarks.schema()
+        );
+        ensure!(
+            landmarks.schema() == schema,
+            "{} has {} landmarks but {} has {schema} landmarks, all images should be processed \
+             with the same shape predictor",
+            path.display(),
+            landmarks.schema(),
+            ref_path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Read the landmarks to align to from a RON (or JSON if the extension is .json) list of (x, y)
+/// points, they have to follow `schema`
+pub fn read_landmarks(path: &Path, schema: LandmarkSchema) -> anyhow::Result<Landmarks> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("opening the landmarks file {}", path.display()))?;
+    let points: Vec<(f32, f32)> = if path.extension().is_some_and(|ext| ext == "json") {
+        serde_json::from_reader(file).context("deserializing the landmarks")?
+    } else {
+        ron::de::from_reader(file).context("deserializing the landmarks")?
+    };
+    let len = points.len();
+    let points: Vec<_> = points
+        .into_iter()
+        .map(|(x, y)| (x.round() as i64, y.round() as i64))
+        .collect();
+    Landmarks::new(points, schema).with_context(|| {
+        format!(
+            "{} has {len} points, but the faces have {schema} landmarks",
+            path.display()
+        )
+    })
+}
+
+/// Format of a features file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeaturesFormat {
+    Json,
+    Ron,
+    Cbor,
+    /// One row per face (and per image without faces), the landmarks and embeddings are RON
+    Csv,
+}
+
+impl FeaturesFormat {
+    /// The format of `path` by its extension, RON if it isn't `json`, `cbor` or `csv`
+    pub fn of(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Self::Json,
+            Some("cbor") => Self::Cbor,
+            Some("csv") => Self::Csv,
+            _ => Self::Ron,
+        }
+    }
+}
+
+/// Columns of a CSV features file
+const CSV_HEADER: [&str; 9] = [
+    "image",
+    "left",
+    "top",
+    "right",
+    "bottom",
+    "confidence",
+    "sharpness",
+    "landmarks",
+    "embedding",
+];
+
+/// Read a features file, the format is chosen by [`FeaturesFormat::of`]
+///
+/// Relative image paths are relative to the directory of the features file, they are resolved
+/// with [`resolve_path`].
+pub fn read_features(path: &Path) -> anyhow::Result<Features> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("opening the features file {}", path.display()))?;
+    let reader = std::io::BufReader::new(file);
+    let features: Features = match FeaturesFormat::of(path) {
+        FeaturesFormat::Json => {
+            serde_json::from_reader(reader).context("deserializing features")?
+        }
+        FeaturesFormat::Ron => ron::de::from_reader(reader).context("deserializing features")?,
+        FeaturesFormat::Cbor => ciborium::from_reader(reader).context("deserializing features")?,
+        FeaturesFormat::Csv => read_features_csv(reader).context("deserializing features")?,
+    };
+    Ok(features
+        .into_iter()
+        .map(|(image, faces)| (resolve_path(path, image), faces))
+        .collect())
+}
+
+/// Write the features in the format of [`read_features`]
+///
+/// Relative image paths are written relative to the directory of the features file (see
+/// [`path_in_file`]). The file is replaced atomically, so it is never left half-written.
+pub fn write_features(path: &Path, features: &Features, pretty: bool) -> anyhow::Result<()> {
+    let features: HashMap<PathBuf, &Faces> = features
+        .iter()
+        .map(|(image, faces)| Ok((path_in_file(path, image)?, faces)))
+        .collect::<anyhow::Result<_>>()?;
+    let features = &features;
+    write_atomically(path, |writer| {
+        match (FeaturesFormat::of(path), pretty) {
+            (FeaturesFormat::Json, true) => serde_json::to_writer_pretty(writer, features)?,
+            (FeaturesFormat::Json, false) => serde_json::to_writer(writer, features)?,
+            (FeaturesFormat::Ron, true) => {
+                ron::ser::to_writer_pretty(writer, features, ron::ser::PrettyConfig::default())?
+            }
+            (FeaturesFormat::Ron, false) => ron::ser::to_writer(writer, features)?,
+            (FeaturesFormat::Cbor, _) => ciborium::into_writer(features, writer)?,
+            (FeaturesFormat::Csv, _) => write_features_csv(writer, features)?,
+        }
+        Ok(())
+    })
+    .with_context(|| format!("writing the features file {}", path.display()))
+}
+
+/// The directory of `file`, `.` if it is in the current directory
+fn dir_of(file: &Path) -> &Path {
+    match file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+/// Where the image at `path` in `file` (a features or transforms file) is: relative paths are
+/// relative to the directory of `file`
+pub fn resolve_path(file: &Path, path: PathBuf) -> PathBuf {
+    match file.parent() {
+        Some(dir) if path.is_relative() => dir.join(path),
+        _ => path,
+    }
+}
+
+/// The inverse of [`resolve_path`]: `path` as it has to be written in `file` to be found again
+///
+/// Absolute paths are kept as is.
+pub fn path_in_file(file: &Path, path: &Path) -> anyhow::Result<PathBuf> {
+    if path.is_absolute() {
+        return Ok(path.to_path_buf());
+    }
+    let absolute = |path: &Path| {
+        std::path::absolute(path).with_context(|| format!("resolving {}", path.display()))
+    };
+    Ok(relative_path(&absolute(path)?, &absolute(dir_of(file))?))
+}
+
+/// `path` relative to the `base` directory (both must be absolute)
+pub fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let path: Vec<_> = path.components().collect();
+    let base: Vec<_> = base.components().collect();
+    let common = path.iter().zip(&base).take_while(|(a, b)| a == b).count();
+    let mut relative = PathBuf::new();
+    for _ in common..base.len() {
+        relative.push("..");
+    }
+    relative.extend(&path[common..]);
+    relative
+}
+
+/// Read features in the format of [`write_features_csv`]
+fn read_features_csv(reader: impl std::io::Read) -> anyhow::Result<Features> {
+    let mut features = Features::new();
+    let mut faces: HashMap<PathBuf, Vec<Face>> = HashMap::new();
+    for (ix, record) in csv::Reader::from_reader(reader).records().enumerate() {
+        // The header is line 1
+        let line = ix + 2;
+        let record = record.with_context(|| format!("reading line {line}"))?;
+        ensure!(
+            record.len() == CSV_HEADER.len(),
+            "line {line} has {} columns instead of {}",
+            record.len(),
+            CSV_HEADER.len()
+        );
+        let image = PathBuf::from(&record[0]);
+        if record[1].is_empty() {
+            // An image without faces
+            features
+                .entry(image)
+                .or_insert_with(|| Faces::from_iter([]));
+            continue;
+        }
+        let parse = |column: usize| -> anyhow::Result<Option<f32>> {
+            let value = &record[column];
+            if value.is_empty() {
+                return Ok(None);
+            }
+            let value = value
+                .parse()
+                .with_context(|| format!("parsing the {} in line {line}", CSV_HEADER[column]))?;
+            Ok(Some(value))
+        };
+        let coordinate = |column: usize| -> anyhow::Result<i64> {
+            record[column]
+                .parse()
+                .with_context(|| format!("parsing the {} in line {line}", CSV_HEADER[column]))
+        };
+        let rect = landmark_extractor::Rect {
+            left: coordinate(1)?,
+            top: coordinate(2)?,
+            right: coordinate(3)?,
+            bottom: coordinate(4)?,
+        };
+        let landmarks = ron::from_str(&record[7])
+            .with_context(|| format!("parsing the landmarks in line {line}"))?;
+        let embedding = match &record[8] {
+            "" => None,
+            embedding => Some(
+                ron::from_str(embedding)
+                    .with_context(|| format!("parsing the embedding in line {line}"))?,
+            ),
+        };
+        let face = Face {
+            rect,
+            landmarks,
+            confidence: parse(5)?,
+            embedding,
+            sharpness: parse(6)?,
+        };
+        faces.entry(image).or_default().push(face);
+    }
+    features.extend(
+        faces
+            .into_iter()
+            .map(|(image, faces)| (image, faces.into_iter().collect())),
+    );
+    Ok(features)
+}
+
+/// Write the features as CSV with the columns of [`CSV_HEADER`], one row per face
+///
+/// Images without faces get a row with only the image, so they aren't lost.
+fn write_features_csv(
+    writer: impl std::io::Write,
+    features: &HashMap<PathBuf, &Faces>,
+) -> anyhow::Result<()> {
+    let mut images: Vec<_> = features.iter().collect();
+    images.sort_by(|a, b| a.0.cmp(b.0));
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(CSV_HEADER)?;
+    for (path, faces) in images {
+        let image = path
+            .to_str()
+            .with_context(|| format!("{} is not valid UTF-8", path.display()))?;
+        if faces.is_empty() {
+            let mut record = vec![String::new(); CSV_HEADER.len()];
+            record[0] = image.to_string();
+            writer.write_record(record)?;
+            continue;
+        }
+        for Face {
+            rect,
+            landmarks,
+            confidence,
+            embedding,
+            sharpness,
+        } in faces.iter()
+        {
+            let optional = |value: Option<f32>| value.map_or(String::new(), |v| v.to_string());
+            let embedding = match embedding {
+                Some(embedding) => ron::to_string(embedding)?,
+                None => String::new(),
+            };
+            writer.write_record([
+                image.to_string(),
+                rect.left.to_string(),
+                rect.top.to_string(),
+                rect.right.to_string(),
+                rect.bottom.to_string(),
+                optional(*confidence),
+                optional(*sharpness),
+                ron::to_string(landmarks)?,
+                embedding,
+            ])?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write the landmarks in the format of [`read_landmarks`]
+pub fn write_landmarks(path: &Path, landmarks: &Landmarks) -> anyhow::Result<()> {
+    let points: Vec<_> = landmarks
+        .iter()
+        .map(|&(x, y)| (x as f32, y as f32))
+        .collect();
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("creating the landmarks file {}", path.display()))?;
+    if path.extension().is_some_and(|ext| ext == "json") {
+        serde_json::to_writer(file, &points).context("serializing the landmarks")
+    } else {
+        ron::ser::to_writer(file, &points).context("serializing the landmarks")
+    }
+}
+
+/// Order of the images
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Name,
+    Mtime,
+    ExifDate,
+}
+
+/// Parses `name`, `mtime` or `exif-date`
+impl std::str::FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(Self::Name),
+            "mtime" => Ok(Self::Mtime),
+            "exif-date" => Ok(Self::ExifDate),
+            _ => Err(format!("expected name, mtime or exif-date, found {s}")),
+        }
+    }
+}
+
+/// Sort the images in `order`, breaking ties by name
+pub fn sort_features(features: &mut Vec<(PathBuf, Faces)>, order: SortOrder) -> anyhow::Result<()> {
+    match order {
+        SortOrder::Name => features.sort_by(|a, b| a.0.cmp(&b.0)),
+        SortOrder::Mtime => {
+            let mut keyed = features
+                .drain(..)
+                .map(|feature| {
+                    let modified = std::fs::metadata(&feature.0)
+                        .and_then(|metadata| metadata.modified())
+                        .with_context(|| {
+                            format!("reading the modification time of {}", feature.0.display())
+                        })?;
+                    Ok((modified, feature))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            keyed.sort_by(|a, b| (a.0, &a.1 .0).cmp(&(b.0, &b.1 .0)));
+            features.extend(keyed.into_iter().map(|(_, feature)| feature));
+        }
+        SortOrder::ExifDate => {
+            let mut keyed: Vec<_> = features
+                .drain(..)
+                .map(|feature| {
+                    let date = exif_date(&feature.0);
+                    if date.is_none() {
+                        warn!("{} has no EXIF date", feature.0.display());
+                    }
+                    (date, feature)
+                })
+                .collect();
+            // Images without a date go last
+            keyed.sort_by(|a, b| (a.0.is_none(), a.0, &a.1 .0).cmp(&(b.0.is_none(), b.0, &b.1 .0)));
+            features.extend(keyed.into_iter().map(|(_, feature)| feature));
+        }
+    }
+    Ok(())
+}
+
+/// When the photo was taken according to its EXIF data, as (year, month, day, hour, minute,
+/// second)
+pub fn exif_date(path: &Path) -> Option<(u16, u8, u8, u8, u8, u8)> {
+    let file = std::fs::File::open(path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut std::io::BufReader::new(file))
+        .ok()?;
+    let field = exif
+        .get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY)
+        .or_else(|| exif.get_field(exif::Tag::DateTime, exif::In::PRIMARY))?;
+    let exif::Value::Ascii(ref values) = field.value else {
+        return None;
+    };
+    let date = exif::DateTime::from_ascii(values.first()?).ok()?;
+    Some((
+        date.year,
+        date.month,
+        date.day,
+        date.hour,
+        date.minute,
+        date.second,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_are_relative_to_the_file() {
+        let file = Path::new("data/landmarks.ron");
+        assert_eq!(
+            resolve_path(file, "photos/a.jpg".into()),
+            Path::new("data/photos/a.jpg")
+        );
+        assert_eq!(
+            resolve_path(Path::new("landmarks.ron"), "a.jpg".into()),
+            Path::new("a.jpg")
+        );
+        assert_eq!(
+            resolve_path(file, "/photos/a.jpg".into()),
+            Path::new("/photos/a.jpg")
+        );
+        for image in ["data/photos/a.jpg", "/photos/c.jpg"] {
+            let written = path_in_file(file, Path::new(image)).unwrap();
+            assert_eq!(resolve_path(file, written), Path::new(image));
+        }
+        assert_eq!(
+            path_in_file(file, Path::new("photos/b.jpg")).unwrap(),
+            Path::new("../photos/b.jpg")
+        );
+    }
+
+    /// Features with a face with every field, a face with only the landmarks and an image
+    /// without faces
+    fn sample() -> Features {
+        let rect = |left, top| landmark_extractor::Rect {
+            left,
+            top,
+            right: left + 10,
+            bottom: top + 10,
+        };
+        let landmarks = |x| {
+            Landmarks::new(
+                [(x, 2), (x + 3, 2), (x + 1, 4), (x, 6), (x + 3, 6)],
+                LandmarkSchema::FivePoint,
+            )
+            .unwrap()
+        };
+        let full = Face {
+            confidence: Some(0.75),
+            embedding: Some(vec![0.125, -0.5].into_boxed_slice().into()),
+            sharpness: Some(42.5),
+            ..Face::new(rect(0, 0), landmarks(1))
+        };
+        let bare = Face::new(rect(20, 5), landmarks(21));
+        Features::from([
+            ("a.jpg".into(), Faces::from_iter([full, bare])),
+            ("empty.jpg".into(), Faces::from_iter([])),
+        ])
+    }
+
+    /// The features in a comparable form (sorted by image, faces in debug format)
+    fn comparable(features: &Features) -> Vec<(PathBuf, String)> {
+        let mut features: Vec<_> = features
+            .iter()
+            .map(|(image, faces)| (image.clone(), format!("{:?}", &**faces)))
+            .collect();
+        features.sort();
+        features
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let features = sample();
+        let borrowed = features
+            .iter()
+            .map(|(image, faces)| (image.clone(), faces))
+            .collect();
+        let mut csv = Vec::new();
+        write_features_csv(&mut csv, &borrowed).unwrap();
+        let read = read_features_csv(csv.as_slice()).unwrap();
+        assert_eq!(comparable(&read), comparable(&features));
+        // The image without faces is kept
+        assert!(read[Path::new("empty.jpg")].is_empty());
+    }
+
+    #[test]
+    fn csv_rows_without_the_optional_columns() {
+        // Nine columns with only the required ones filled in (e.g. written by another tool)
+        let csv = "image,left,top,right,bottom,confidence,sharpness,landmarks,embedding\n\
+                   a.jpg,0,0,10,10,,,\"[(1,2),(4,2),(2,4),(1,6),(4,6)]\",\n\
+                   b.jpg,,,,,,,,\n";
+        let read = read_features_csv(csv.as_bytes()).unwrap();
+        let face = &read[Path::new("a.jpg")][0];
+        assert_eq!(face.rect.right, 10);
+        assert_eq!(face.landmarks.len(), 5);
+        assert_eq!(face.landmarks.schema(), LandmarkSchema::FivePoint);
+        assert_eq!((face.confidence, face.sharpness), (None, None));
+        assert!(face.embedding.is_none());
+        assert!(read[Path::new("b.jpg")].is_empty());
+        // Rows with a missing column are rejected
+        let short = "image,left\na.jpg,0\n";
+        assert!(read_features_csv(short.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn formats_are_equivalent() {
+        let dir =
+            std::env::temp_dir().join(format!("face-stabilizer-formats-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // Images next to the features file, so they are written as just their names
+        let features: Features = sample()
+            .into_iter()
+            .map(|(image, faces)| (dir.join(image), faces))
+            .collect();
+        for ext in ["json", "ron", "cbor", "csv"] {
+            for pretty in [false, true] {
+                let path = dir.join(format!("features.{ext}"));
+                write_features(&path, &features, pretty).unwrap();
+                let read = read_features(&path).unwrap();
+                assert_eq!(comparable(&read), comparable(&features), "{ext}");
+            }
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,103 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use glob::Pattern;
+use landmark_extractor::Faces;
+use log::info;
+
+use crate::faces::is_frontal;
+use crate::features::read_features;
+use crate::features::write_features;
+use crate::features::Features;
+use crate::output::common_dir;
+
+/// Options of the filter subcommand
+#[derive(Debug, Args)]
+pub struct FilterOpts {
+    /// Path to the features file
+    input: PathBuf,
+    /// Path to the filtered features file
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Whether to pretty print the filtered file
+    #[arg(short, long)]
+    pretty: bool,
+    /// Drop the faces less confident than this (the faces without a confidence are kept)
+    #[arg(long)]
+    min_confidence: Option<f32>,
+    /// Drop the faces less sharp than this (the faces without a sharpness are kept)
+    #[arg(long)]
+    min_sharpness: Option<f32>,
+    /// Drop the faces turned to the side more than this, in degrees (the faces with an unknown
+    /// pose are kept)
+    #[arg(long)]
+    max_yaw: Option<f32>,
+    /// Drop the faces tilted up or down more than this, in degrees (the faces with an unknown
+    /// pose are kept)
+    #[arg(long)]
+    max_pitch: Option<f32>,
+    /// Drop the images without faces (after dropping the faces)
+    #[arg(long)]
+    drop_empty: bool,
+    /// Drop the images with several faces (after dropping the faces)
+    #[arg(long)]
+    drop_multiple: bool,
+    /// Only keep the images matching this pattern (relative to the directory that contains all
+    /// of them, e.g. `2023/**`)
+    #[arg(long)]
+    include: Option<Pattern>,
+    /// Drop the images matching this pattern (relative to the directory that contains all of
+    /// them)
+    #[arg(long)]
+    exclude: Option<Pattern>,
+}
+
+pub fn run(opts: FilterOpts) -> anyhow::Result<()> {
+    let FilterOpts {
+        input,
+        output,
+        pretty,
+        min_confidence,
+        min_sharpness,
+        max_yaw,
+        max_pitch,
+        drop_empty,
+        drop_multiple,
+        include,
+        exclude,
+    } = opts;
+    let features = read_features(&input)?;
+    let total = features.len();
+    let root = common_dir(features.keys().map(PathBuf::as_path));
+    let above = |value: Option<f32>, min: Option<f32>| match (value, min) {
+        (Some(value), Some(min)) => value >= min,
+        _ => true,
+    };
+    let filtered: Features = features
+        .into_iter()
+        .filter(|(path, _)| {
+            let relative = path.strip_prefix(&root).unwrap_or(path);
+            include
+                .as_ref()
+                .is_none_or(|glob| glob.matches_path(relative))
+                && !exclude
+                    .as_ref()
+                    .is_some_and(|glob| glob.matches_path(relative))
+        })
+        .map(|(path, faces)| {
+            let faces = faces
+                .iter()
+                .filter(|face| {
+                    above(face.confidence, min_confidence) && above(face.sharpness, min_sharpness)
+                })
+                .filter(|face| is_frontal(&face.landmarks, max_yaw, max_pitch))
+                .cloned()
+                .collect::<Faces>();
+            (path, faces)
+        })
+        .filter(|(_, faces)| !(drop_empty && faces.is_empty()))
+        .filter(|(_, faces)| !(drop_multiple && faces.len() > 1))
+        .collect();
+    info!("kept {} of {total} images", filtered.len());
+    write_features(&output, &filtered, pretty)
+}
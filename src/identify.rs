@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::Args;
+use landmark_extractor::SAME_PERSON_DISTANCE;
+use log::warn;
+
+use crate::faces::face_embedding;
+use crate::features::read_features;
+use crate::output::write_atomically;
+use crate::parse_reference;
+use crate::ExtractorOpts;
+
+/// Options of the identify subcommand
+#[derive(Debug, Args)]
+pub struct IdentifyOpts {
+    #[command(flatten)]
+    pub extractor: ExtractorOpts,
+    /// Path to the features file, extracted with a face recognition model (--face-encoder)
+    features: PathBuf,
+    /// Photo of a known person as `NAME=PHOTO`, or `PHOTO` to name them after the file (can be
+    /// repeated)
+    #[arg(
+        short,
+        long = "reference",
+        value_name = "NAME=PHOTO",
+        required = true,
+        value_parser = parse_reference
+    )]
+    references: Vec<(String, PathBuf)>,
+    /// Where to write the identity and distance of each face, JSON if its extension is `json`
+    /// and CSV otherwise
+    #[arg(short, long, default_value = "identities.csv")]
+    output: PathBuf,
+    /// Faces farther than this from every reference are unknown
+    #[arg(long, default_value_t = SAME_PERSON_DISTANCE)]
+    threshold: f64,
+}
+
+pub fn run(opts: IdentifyOpts) -> anyhow::Result<()> {
+    let IdentifyOpts {
+        extractor,
+        features,
+        references,
+        output,
+        threshold,
+    } = opts;
+    let features = read_features(&features)?;
+    let extractor = extractor.build()?;
+    let references: Vec<_> = references
+        .into_iter()
+        .map(|(name, path)| Ok((name, face_embedding(&extractor, &path)?)))
+        .collect::<anyhow::Result<_>>()?;
+
+    let mut images: Vec<_> = features.iter().collect();
+    images.sort_by(|a, b| a.0.cmp(b.0));
+    let mut matches = Vec::new();
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for (path, faces) in images {
+        for (ix, face) in faces.iter().enumerate() {
+            let Some(embedding) = &face.embedding else {
+                warn!("face {ix} of {} doesn't have an embedding", path.display());
+                continue;
+            };
+            let (name, distance) = references
+                .iter()
+                .map(|(name, reference)| (name.as_str(), reference.distance(embedding)))
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .expect("there is at least one reference");
+            let name = (distance < threshold).then_some(name);
+            *counts.entry(name.unwrap_or("unknown")).or_default() += 1;
+            matches.push((path, ix, name, distance));
+        }
+    }
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort();
+    for (name, count) in counts {
+        println!("{name}: {count} faces");
+    }
+
+    write_atomically(&output, |writer| {
+        if output.extension().is_some_and(|ext| ext == "json") {
+            let entries: Vec<_> = matches
+                .iter()
+                .map(|(image, ix, name, distance)| {
+                    serde_json::json!({
+                        "image": image,
+                        "face": ix,
+                        "identity": name,
+                        "distance": distance,
+                    })
+                })
+                .collect();
+            serde_json::to_writer_pretty(writer, &entries).context("writing the identities")?;
+        } else {
+            let mut writer = csv::Writer::from_writer(writer);
+            writer.write_record(["image", "face", "identity", "distance"])?;
+            for (image, ix, name, distance) in &matches {
+                writer.write_record([
+                    image.to_string_lossy().into_owned(),
+                    ix.to_string(),
+                    name.unwrap_or_default().to_string(),
+                    distance.to_string(),
+                ])?;
+            }
+            writer.flush().context("writing the identities")?;
+        }
+        Ok(())
+    })
+}
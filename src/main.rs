@@ -1,145 +1,435 @@
-use std::collections::HashMap;
 use std::path::Path;
 use std::path::PathBuf;
 
-use anyhow::anyhow;
 use anyhow::bail;
-use anyhow::ensure;
 use anyhow::Context;
+use clap::Args;
 use clap::Parser;
 use clap::Subcommand;
-use dlib_face_recognition::FaceDetector;
-use dlib_face_recognition::ImageMatrix;
-use dlib_face_recognition::LandmarkPredictor;
-use imageproc::geometric_transformations::warp;
-use imageproc::geometric_transformations::Interpolation;
-use landmark_extractor::Faces;
-use landmark_extractor::Landmarks;
+use glam::Vec2;
+use glob::Pattern;
+use imageproc::rect::Rect;
+use landmark_extractor::DetectorKind;
+use landmark_extractor::Extractor;
+use landmark_extractor::FaceSelection;
+use landmark_extractor::PredictorKind;
 use log::debug;
 use log::info;
 use log::warn;
 
+mod alignment;
+mod annotate;
+mod anonymize;
+mod apply_transforms;
+mod average;
+mod cluster;
+mod color_match;
+mod convert;
+mod crop_faces;
+mod deflicker;
+mod export_transforms;
+mod extract_features;
+mod faces;
+mod features;
+mod filter;
 #[cfg(feature = "gui")]
 mod gui;
+mod identify;
+mod merge;
+mod montage;
+mod morph;
+mod output;
+mod quality_filter;
+mod rebase;
+mod stabilize_video;
+mod stats;
+mod transform;
+mod unstabilize;
+mod video;
+mod watch;
+#[cfg(feature = "webcam")]
+mod webcam;
 
 #[derive(Debug, Parser)]
 struct Opts {
+    /// Number of threads used to process the images (defaults to one per core)
+    #[cfg(feature = "rayon")]
+    #[arg(short = 'j', long, global = true, value_parser = clap::value_parser!(u16).range(1..))]
+    threads: Option<u16>,
     #[command(subcommand)]
     command: Actions,
 }
 
+/// Options to create an [`Extractor`]
+#[derive(Debug, Args)]
+struct ExtractorOpts {
+    /// Path to the Shape Predictor model (also called Facial Landmarks Predictor)
+    ///
+    /// Both the 5 and the 68 point models are supported. Use face-mesh:<model> for MediaPipe's
+    /// FaceMesh (needs the onnx feature)
+    #[arg(env, short, long)]
+    shape_predictor: PredictorKind,
+    /// Face detector to use: hog, cnn:<model>, rustface:<model> or onnx:<model>
+    ///
+    /// onnx runs insightface's SCRFD models (other ONNX detectors aren't supported). The rustface
+    /// and onnx detectors need the respective features enabled
+    #[arg(env, short, long, default_value = "hog")]
+    detector: DetectorKind,
+    /// Load this many instances of the CNN detector to process images in parallel
+    ///
+    /// Each instance has its own copy of the model
+    #[arg(long, default_value_t = 1)]
+    cnn_instances: usize,
+    /// Ignore faces detected with a lower confidence
+    ///
+    /// Only the cnn (0 by default, negative values find more faces), onnx (between 0 and 1) and
+    /// rustface detectors report a confidence, it is an error to use it with the hog detector
+    #[arg(long)]
+    min_confidence: Option<f32>,
+    /// Upsample the images this many times before detecting faces
+    ///
+    /// Each step doubles the size of the image, this helps finding small faces but is much
+    /// slower
+    #[arg(short, long, default_value_t = 0)]
+    upsample: u32,
+    /// Ignore faces smaller than this many pixels
+    ///
+    /// Useful to ignore faces in the background (posters, bystanders, etc.)
+    #[arg(short, long, default_value_t = 0)]
+    min_face_size: u32,
+    /// Downscale the images so their longest side is at most this many pixels before detecting
+    /// faces
+    ///
+    /// Makes detection on large photos much faster, the landmarks are still computed on the full
+    /// resolution image
+    #[arg(long)]
+    max_detection_size: Option<u32>,
+    /// Path to the face recognition model, used to compute an embedding of each face
+    ///
+    /// Needs a dlib shape predictor
+    #[arg(env, short = 'e', long)]
+    face_encoder: Option<PathBuf>,
+    /// Run the CNN detector and the face encoder on the GPU with this index
+    ///
+    /// Only has an effect if dlib was built with CUDA (see the cuda feature)
+    #[arg(env, long)]
+    gpu: Option<u32>,
+}
+
+impl ExtractorOpts {
+    /// Run the neural networks on the GPU chosen with --gpu (if any)
+    ///
+    /// Must be called before spawning any thread, see [`landmark_extractor::gpu::select_gpu`]
+    fn select_gpu(&self) -> anyhow::Result<()> {
+        let Some(gpu) = self.gpu else {
+            return Ok(());
+        };
+        if !landmark_extractor::gpu::cuda_enabled() {
+            warn!("dlib was built without CUDA, --gpu has no effect");
+        }
+        info!("Using GPU {gpu}");
+        // SAFETY: called from `main` before the thread pool is built
+        unsafe { landmark_extractor::gpu::select_gpu(gpu) }.context("selecting the GPU")
+    }
+
+    /// Load the models and create the [`Extractor`]
+    fn build(self) -> anyhow::Result<Extractor> {
+        let ExtractorOpts {
+            shape_predictor,
+            detector,
+            cnn_instances,
+            min_confidence,
+            upsample,
+            min_face_size,
+            max_detection_size,
+            face_encoder,
+            gpu: _,
+        } = self;
+
+        let file = shape_predictor.model_path().display();
+        info!("Loading shape predictor from {file}",);
+        if !shape_predictor.model_path().is_file() {
+            bail!("{file} is not a regular file (or doesn't exist).",);
+        }
+        if let Some(model) = detector.model_path() {
+            let file = model.display();
+            info!("Loading face detector from {file}");
+            if !model.is_file() {
+                bail!("{file} is not a regular file (or doesn't exist).");
+            }
+        }
+        let mut builder = Extractor::builder()
+            .predictor(shape_predictor)
+            .detector(detector)
+            .cnn_pool_size(cnn_instances)
+            .upsample(upsample)
+            .min_face_size(min_face_size);
+        if let Some(min_confidence) = min_confidence {
+            builder = builder.min_confidence(min_confidence);
+        }
+        if let Some(max_size) = max_detection_size {
+            builder = builder.max_detection_size(max_size);
+        }
+        if let Some(face_encoder) = face_encoder {
+            let file = face_encoder.display();
+            info!("Loading face encoder from {file}");
+            if !face_encoder.is_file() {
+                bail!("{file} is not a regular file (or doesn't exist).");
+            }
+            builder = builder.face_encoder(face_encoder);
+        }
+        builder.build().context("loading the models")
+    }
+}
+
+/// Where to find the images to process
+#[derive(Debug, Args)]
+struct ImageSource {
+    /// Path to a directory containing the images
+    #[arg(required_unless_present = "files_from")]
+    image_dir: Option<PathBuf>,
+    /// Read the paths of the images from this file (one per line) instead, `-` reads them from
+    /// stdin
+    #[arg(long, value_name = "LIST", conflicts_with_all = ["image_dir", "recursive"])]
+    files_from: Option<PathBuf>,
+    /// Also look for images in the subdirectories of image_dir
+    #[arg(short, long)]
+    recursive: bool,
+    /// Only process the images whose path (relative to image_dir) matches this pattern, e.g.
+    /// '**/*.jpg'
+    #[arg(long)]
+    glob: Option<Pattern>,
+}
+
+impl ImageSource {
+    /// The paths of the images
+    fn image_paths(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        match (&self.image_dir, &self.files_from) {
+            (_, Some(list)) => paths = read_file_list(list)?,
+            (Some(dir), None) => files_in_dir(dir, self.recursive, &mut paths)?,
+            (None, None) => unreachable!("clap requires image_dir or files_from"),
+        }
+        if let Some(glob) = &self.glob {
+            paths.retain(|path| {
+                let path = self
+                    .image_dir
+                    .as_ref()
+                    .and_then(|dir| path.strip_prefix(dir).ok())
+                    .unwrap_or(path);
+                glob.matches_path(path)
+            });
+        }
+        Ok(paths)
+    }
+}
+
+/// Read a list of paths (one per line, skipping empty lines) from `list` or stdin if it is `-`
+fn read_file_list(list: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let contents = if list == Path::new("-") {
+        std::io::read_to_string(std::io::stdin()).context("reading the image list from stdin")?
+    } else {
+        std::fs::read_to_string(list)
+            .with_context(|| format!("reading the image list from {}", list.display()))?
+    };
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Collect the files in `dir` (and its subdirectories if `recursive`)
+fn files_in_dir(dir: &Path, recursive: bool, paths: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    let entries =
+        std::fs::read_dir(dir).with_context(|| format!("trying to open {}", dir.display()))?;
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry
+            .file_type()
+            .with_context(|| format!("trying to get the file type of {}", path.display()))?;
+        if file_type.is_file() {
+            paths.push(path);
+        } else if recursive && file_type.is_dir() {
+            files_in_dir(&path, recursive, paths)?;
+        } else {
+            info!("{} is not a file, skipping", path.display());
+        }
+    }
+    Ok(())
+}
+
 #[derive(Debug, Subcommand)]
 enum Actions {
     /// Extract Features from images to process later
     ExtractFeatures {
-        /// Path to the Shape Predictor model (also called Facial Landmarks Predictor)
-        #[arg(env, short, long)]
-        shape_predictor: PathBuf,
-        /// Path to a directory containing the images you want to extract the features of
-        image_dir: PathBuf,
-        /// Path to the output file
+        #[command(flatten)]
+        extractor: ExtractorOpts,
+        #[command(flatten)]
+        images: ImageSource,
+        /// Path to the output file, its extension chooses the format: json, cbor, csv or ron
         #[arg(short, long, default_value = "landmarks.ron")]
         output: PathBuf,
         /// Whether to pretty print the extracted text
         #[arg(short, long)]
         pretty: bool,
+        /// Only keep one face of each image: largest, most-central, most-confident or index:<n>
+        /// (in the order they were detected)
+        #[arg(long = "select-face", value_name = "SELECTION")]
+        selection: Option<FaceSelection>,
+        /// Only keep the face of the person in this photo (the most similar one, if it is similar
+        /// enough), for photos where other people appear
+        ///
+        /// Needs a face recognition model (--face-encoder)
+        #[arg(long, conflicts_with = "selection")]
+        subject: Option<PathBuf>,
+        /// Only run the detection and report how many faces were found in the images (and which
+        /// ones will be skipped), without writing the output file
+        #[arg(long)]
+        dry_run: bool,
     },
-    Transform {
-        /// Path to the extracted features
-        features: PathBuf,
-        /// Directory where to place the transformed images
-        #[arg(short, long, default_value = "./out")]
-        output_dir: PathBuf,
-    },
+    Transform(transform::TransformOpts),
+    /// Watch a directory, extracting the features and transforming every new image
+    ///
+    /// The features are appended to the features file, so the whole sequence can be transformed
+    /// again later
+    Watch(watch::WatchOpts),
+    /// Stabilize the face in a video, writing the stabilized frames as numbered images
+    ///
+    /// Decodes the video with ffmpeg, which must be installed
+    StabilizeVideo(stabilize_video::VideoOpts),
+    /// Stack the (transformed) images into their mean or median, e.g. the average face over a
+    /// year
+    Average(average::AverageOpts),
+    /// Morph each image into the next one, generating the frames in between them
+    ///
+    /// Turns a photo a day into a smooth video instead of a strobing slideshow
+    Morph(morph::MorphOpts),
+    /// Arrange aligned crops of the faces in a grid (a contact sheet)
+    ///
+    /// Useful for posters and to spot the badly aligned images at a glance
+    Montage(montage::MontageOpts),
+    /// Draw the bounding boxes and landmarks of the faces onto copies of the images
+    ///
+    /// Helps to find out why some images are badly aligned
+    Annotate(annotate::AnnotateOpts),
+    /// Merge several features files into one (e.g. extracted per month or on different
+    /// machines)
+    Merge(merge::MergeOpts),
+    /// Write a copy of a features file without the images (or faces) that don't pass the filters
+    Filter(filter::FilterOpts),
+    /// Summarize a features file and list the images that will likely be badly aligned
+    Stats(stats::StatsOpts),
+    /// Convert a features file to another format (chosen by the extension of the output)
+    Convert(convert::ConvertOpts),
+    /// Move the images of a features file to another directory or make their paths relative
+    Rebase(rebase::RebaseOpts),
+    /// Export aligned crops of the faces and a manifest describing them (e.g. to build a dataset)
+    CropFaces(crop_faces::CropFacesOpts),
+    /// Group the faces by identity, to find out who is who before choosing a subject
+    Cluster(cluster::ClusterOpts),
+    /// Find out whose face each detection is by comparing them to photos of known people
+    Identify(identify::IdentifyOpts),
+    /// Blur, pixelate or black out the faces (e.g. of bystanders before publishing the images)
+    Anonymize(anonymize::AnonymizeOpts),
+    /// Write the similarity transform that aligns each image instead of warping the images
+    ///
+    /// To apply them with other tools (ffmpeg, video editors, scripts) or to edit them and
+    /// render the images with apply-transforms
+    ExportTransforms(export_transforms::ExportTransformsOpts),
+    /// Warp the images with the transforms of a transforms file (see export-transforms),
+    /// without detecting the faces again
+    ApplyTransforms(apply_transforms::ApplyTransformsOpts),
+    /// Map edited aligned images back onto the original images with the inverse transforms
+    ///
+    /// To retouch the aligned faces and put the edits back into the original photos
+    Unstabilize(unstabilize::UnstabilizeOpts),
+    /// Even out the brightness of the (transformed) images, removing the flicker of timelapses
+    Deflicker(deflicker::DeflickerOpts),
+    /// Match the colors of the (transformed) images to a reference image, evening out the
+    /// white balance across the years
+    ColorMatch(color_match::ColorMatchOpts),
+    /// Store the sharpness of every face in a features file and flag (or drop) the blurry ones
+    QualityFilter(quality_filter::QualityFilterOpts),
+    /// Show the stabilized stream of a camera in a window, to check the lighting and framing
+    #[cfg(feature = "webcam")]
+    Webcam(webcam::WebcamOpts),
     /// Launch a GUI
     #[cfg(feature = "gui")]
     GUI,
 }
 
+impl Actions {
+    /// The options of the [`Extractor`] used by the subcommand (if any)
+    fn extractor(&self) -> Option<&ExtractorOpts> {
+        match self {
+            Actions::ExtractFeatures { extractor, .. } => Some(extractor),
+            Actions::Watch(opts) => Some(&opts.extractor),
+            Actions::Identify(opts) => Some(&opts.extractor),
+            Actions::StabilizeVideo(opts) => Some(&opts.extractor),
+            #[cfg(feature = "webcam")]
+            Actions::Webcam(opts) => Some(&opts.extractor),
+            _ => None,
+        }
+    }
+}
+
 fn main() -> anyhow::Result<()> {
     // Configure using RUST_LOG=* (ie. RUST_LOG=info)
     env_logger::init();
 
     let opts = Opts::parse();
     debug!("Recieved {opts:?}");
+    // Changes the environment, so it has to happen before spawning the threads
+    if let Some(extractor) = opts.command.extractor() {
+        extractor.select_gpu()?;
+    }
+    #[cfg(feature = "rayon")]
+    if let Some(threads) = opts.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads.into())
+            .build_global()
+            .context("configuring the thread pool")?;
+    }
 
     match opts.command {
         Actions::ExtractFeatures {
-            shape_predictor,
-            image_dir,
+            extractor,
+            images,
             output,
             pretty,
-        } => extract_features(shape_predictor, image_dir, output, pretty),
-        Actions::Transform {
-            features,
-            output_dir,
-        } => {
-            ensure!(features.exists(), "could not find {}", features.display());
-            ensure!(features.is_file(), "{} is not a file", features.display());
-            let file = std::fs::File::open(features).context("opening features file")?;
-            let features: Features =
-                ron::de::from_reader(file).context("deserializing features")?;
-            let mut features: Vec<_> = features.into_iter().collect();
-            features.sort_by_cached_key(|f| f.0.clone());
-            if !output_dir.exists() {
-                std::fs::create_dir(&output_dir)
-                    .with_context(|| format!("creating {} directory", output_dir.display()))?;
-            } else {
-                ensure!(
-                    output_dir.is_dir(),
-                    "{} is not a directory",
-                    output_dir.display()
-                );
-            }
-            let out_path =
-                |file: &Path| output_dir.join(file.file_name().expect("valid file name"));
-
-            let (ref_path, ref_feat) = features.swap_remove(0);
-            ensure!(
-                ref_feat.len() == 1,
-                "reference face should have exactly one face"
-            );
-            let (_, ref_feat) = ref_feat.iter().next().unwrap().clone().into();
-            std::fs::copy(&ref_path, out_path(&ref_path))?;
-
-            use indicatif::*;
-            let style = ProgressStyle::with_template(
-                "[{pos:>4}/{len:4}] {msg} {bar} [{per_sec} {eta_precise}]",
-            )
-            .expect("valid template");
-
-            #[cfg(feature = "rayon")]
-            use rayon::prelude::*;
-            #[cfg(feature = "rayon")]
-            let features = features.into_par_iter();
-            #[cfg(not(feature = "rayon"))]
-            let features = features.into_iter();
-
-            features
-                .progress_with_style(style)
-                .map(|(img_path, img_feat)| {
-                    if img_feat.len() != 1 {
-                        warn!(
-                            "{} does not have a single face, it has {} instead",
-                            img_path.display(),
-                            img_feat.len()
-                        );
-                        return Ok(());
-                    }
-
-                    let (_, img_feat) = img_feat.iter().next().unwrap().clone().into();
-                    let img = image::open(&img_path)
-                        .with_context(|| format!("opening image {}", img_path.display()))?
-                        .into_rgb8();
-
-                    let out = out_path(&img_path);
-
-                    apply_projection(&ref_feat, &img_feat, &img)
-                        .save(&out)
-                        .with_context(|| format!("saving image to {}", out.display()))
-                })
-                .collect()
-        }
+            selection,
+            subject,
+            dry_run,
+        } => extract_features::run(
+            extractor, images, output, pretty, selection, subject, dry_run,
+        ),
+        Actions::Transform(opts) => transform::run(opts),
+        Actions::Watch(opts) => watch::run(opts),
+        Actions::StabilizeVideo(opts) => stabilize_video::run(opts),
+        Actions::Average(opts) => average::run(opts),
+        Actions::Morph(opts) => morph::run(opts),
+        Actions::Montage(opts) => montage::run(opts),
+        Actions::Annotate(opts) => annotate::run(opts),
+        Actions::Merge(opts) => merge::run(opts),
+        Actions::Filter(opts) => filter::run(opts),
+        Actions::Stats(opts) => stats::run(opts),
+        Actions::Convert(opts) => convert::run(opts),
+        Actions::Rebase(opts) => rebase::run(opts),
+        Actions::CropFaces(opts) => crop_faces::run(opts),
+        Actions::Cluster(opts) => cluster::run(opts),
+        Actions::Identify(opts) => identify::run(opts),
+        Actions::Anonymize(opts) => anonymize::run(opts),
+        Actions::ExportTransforms(opts) => export_transforms::run(opts),
+        Actions::ApplyTransforms(opts) => apply_transforms::run(opts),
+        Actions::Unstabilize(opts) => unstabilize::run(opts),
+        Actions::Deflicker(opts) => deflicker::run(opts),
+        Actions::ColorMatch(opts) => color_match::run(opts),
+        Actions::QualityFilter(opts) => quality_filter::run(opts),
+        #[cfg(feature = "webcam")]
+        Actions::Webcam(opts) => webcam::run(opts),
         #[cfg(feature = "gui")]
         Actions::GUI => gui::Gui::run(iced::Settings {
             // default_font: iced::Font::with_name("DejaVu Sans"),
@@ -149,100 +439,48 @@ fn main() -> anyhow::Result<()> {
     }
 }
 
-fn extract_features(
-    shape_predictor: PathBuf,
-    image_dir: PathBuf,
-    output: PathBuf,
-    pretty: bool,
-) -> anyhow::Result<()> {
-    if output.exists() {
-        warn!("{} exists, making a backup", output.display());
-        let mut backup = output.clone();
-        backup.set_extension(output.extension().map_or("bak".to_string(), |ext| {
-            format!("{}.bak", ext.to_str().unwrap_or(""))
-        }));
-        std::fs::rename(&output, backup).context("trying to backup the ouput file")?;
-    }
-    let output = std::fs::File::create(output)?;
+/// Parses a `WIDTHxHEIGHT` size
+fn parse_size(s: &str) -> Result<(u32, u32), String> {
+    s.split_once('x')
+        .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+        .filter(|&(width, height)| width > 0 && height > 0)
+        .ok_or_else(|| format!("expected <width>x<height>, found {s}"))
+}
 
-    let file = shape_predictor.display();
-    info!("Loading shape predictor from {file}",);
-    if !shape_predictor.is_file() {
-        bail!("{file} is not a regular file (or doesn't exist).",);
+/// Parses a `NAME=PHOTO` reference, or a `PHOTO` named after its file
+fn parse_reference(s: &str) -> Result<(String, PathBuf), String> {
+    match s.split_once('=') {
+        Some((name, photo)) if !name.is_empty() && !photo.is_empty() => {
+            Ok((name.to_string(), photo.into()))
+        }
+        Some(_) => Err(format!("expected <name>=<photo> or <photo>, found {s}")),
+        None => {
+            let photo = PathBuf::from(s);
+            let name = photo
+                .file_stem()
+                .ok_or_else(|| format!("expected <name>=<photo> or <photo>, found {s}"))?
+                .to_string_lossy()
+                .into_owned();
+            Ok((name, photo))
+        }
     }
-    let predictor = LandmarkPredictor::open(shape_predictor).map_err(|err| anyhow!(err))?;
-
-    let image_paths: Vec<_> = std::fs::read_dir(image_dir)
-        .context("trying to open image_dir")?
-        .filter_map(|dir_ent| -> Option<anyhow::Result<PathBuf>> {
-            let Ok(dir_ent) = dir_ent else { return Some(Err(dir_ent.unwrap_err().into())) };
-            let ft = match dir_ent.file_type().with_context(|| {
-                format!(
-                    "trying to get the file type of {}",
-                    dir_ent.file_name().to_string_lossy()
-                )
-            }) {
-                Ok(ft) => ft,
-                Err(err) => return Some(Err(err)),
-            };
-            if !ft.is_file() {
-                info!(
-                    "{} is not a file, skipping",
-                    dir_ent.file_name().to_string_lossy()
-                );
-                return None;
-            }
-            Some(Ok(dir_ent.path()))
-        })
-        .collect::<anyhow::Result<_>>()?;
-
-    use indicatif::*;
-    let style =
-        ProgressStyle::with_template("[{pos:>4}/{len:4}] {msg} {bar} [{per_sec} {eta_precise}]")
-            .expect("valid template");
-
-    #[cfg(feature = "rayon")]
-    use rayon::prelude::*;
-    #[cfg(feature = "rayon")]
-    let iter = image_paths.into_par_iter();
-
-    #[cfg(not(feature = "rayon"))]
-    let iter = image_paths.into_iter();
-
-    let features: Features = iter
-        .progress_with_style(style)
-        .map(|path| -> anyhow::Result<(PathBuf, Faces)> {
-            let img = image::open(&path)
-                .with_context(|| format!("failed to open {}", path.display()))?
-                .into_rgb8();
-            let mat = ImageMatrix::from_image(&img);
-            let detector = FaceDetector::new(); // Detector shouldn't be sync https://github.com/ulagbulag/dlib-face-recognition/issues/25
-            let landmarks = landmark_extractor::extract_landmarks(&mat, &detector, &predictor);
-            Ok((path, landmarks))
-        })
-        .collect::<anyhow::Result<_>>()?;
+}
 
-    info!("finished processing");
-    info!("serializing to file");
-    if pretty {
-        ron::ser::to_writer_pretty(output, &features, ron::ser::PrettyConfig::default())
-    } else {
-        ron::ser::to_writer(output, &features)
-    }
-    .context("serializing landmarks to a file")?;
-    Ok(())
+/// Parses a `WIDTHxHEIGHT+X+Y` region
+fn parse_region(s: &str) -> Result<Rect, String> {
+    let error = || format!("expected <width>x<height>+<x>+<y>, found {s}");
+    let (size, position) = s.split_once('+').ok_or_else(error)?;
+    let (width, height) = parse_size(size).map_err(|_| error())?;
+    let (x, y) = position
+        .split_once('+')
+        .and_then(|(x, y)| Some((x.parse().ok()?, y.parse().ok()?)))
+        .ok_or_else(error)?;
+    Ok(Rect::at(x, y).of_size(width, height))
 }
 
-type Features = HashMap<PathBuf, Faces>;
-
-fn apply_projection(
-    target: &Landmarks,
-    points: &Landmarks,
-    image: &image::RgbImage,
-) -> image::ImageBuffer<image::Rgb<u8>, Vec<u8>> {
-    let target = target.iter().map(|&(x, y)| (x as f32, y as f32).into());
-    let points = points.iter().map(|&(x, y)| (x as f32, y as f32).into());
-    let proj = stabilizer::procrustes_superimposition(target, points)
-        .expect("neither points nor target are empty and they have the same length");
-    warp(image, &proj, Interpolation::Bicubic, image::Rgb([0, 0, 0]))
+/// Parses an `X,Y` point
+fn parse_point(s: &str) -> Result<Vec2, String> {
+    s.split_once(',')
+        .and_then(|(x, y)| Some(Vec2::new(x.trim().parse().ok()?, y.trim().parse().ok()?)))
+        .ok_or_else(|| format!("expected <x>,<y>, found {s}"))
 }
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::ensure;
+use clap::Args;
+use log::error;
+use log::info;
+use log::warn;
+
+use crate::features::read_features;
+use crate::features::write_features;
+use crate::features::Features;
+
+/// Options of the merge subcommand
+#[derive(Debug, Args)]
+pub struct MergeOpts {
+    /// Paths to the features files
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+    /// Path to the merged features file
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Whether to pretty print the merged file
+    #[arg(short, long)]
+    pretty: bool,
+    /// Keep the features of the last file that has an image instead of failing
+    #[arg(long)]
+    overwrite: bool,
+}
+
+pub fn run(opts: MergeOpts) -> anyhow::Result<()> {
+    let MergeOpts {
+        inputs,
+        output,
+        pretty,
+        overwrite,
+    } = opts;
+    let mut merged = Features::new();
+    // Which file each image came from
+    let mut sources: HashMap<PathBuf, &Path> = HashMap::new();
+    let mut collisions = 0;
+    for input in &inputs {
+        for (path, faces) in read_features(input)? {
+            // The same image can be reached through different (relative) paths
+            let resolved = path.canonicalize().unwrap_or_else(|_| {
+                warn!("could not find {}, keeping its path as is", path.display());
+                path
+            });
+            if let Some(source) = sources.insert(resolved.clone(), input) {
+                collisions += 1;
+                let message = format!(
+                    "{} is in both {} and {}",
+                    resolved.display(),
+                    source.display(),
+                    input.display()
+                );
+                if overwrite {
+                    warn!("{message}, keeping the latter");
+                } else {
+                    error!("{message}");
+                }
+            }
+            merged.insert(resolved, faces);
+        }
+    }
+    ensure!(
+        collisions == 0 || overwrite,
+        "{collisions} images are in several files, use --overwrite to keep the last one"
+    );
+    info!("merged {} images", merged.len());
+    write_features(&output, &merged, pretty)
+}
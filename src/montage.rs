@@ -0,0 +1,179 @@
+use std::path::PathBuf;
+
+use anyhow::ensure;
+use anyhow::Context;
+use clap::Args;
+use image::DynamicImage;
+use image::Rgb;
+use image::RgbImage;
+use landmark_extractor::FaceSelection;
+use log::warn;
+
+use crate::faces::select_face;
+use crate::features::exif_date;
+use crate::features::read_features;
+use crate::features::sort_features;
+use crate::features::SortOrder;
+use crate::output::save_image;
+use crate::parse_size;
+
+/// Options of the montage subcommand
+#[derive(Debug, Args)]
+pub struct MontageOpts {
+    /// Path to the extracted features
+    features: PathBuf,
+    /// Path to the montage
+    #[arg(short, long, default_value = "montage.png")]
+    output: PathBuf,
+    /// Number of cells of the grid (COLUMNSxROWS), by default as square as possible
+    ///
+    /// The images that don't fit are left out
+    #[arg(long, value_parser = parse_size)]
+    grid: Option<(u32, u32)>,
+    /// Size of the (square) cells in pixels
+    #[arg(long, default_value_t = 128)]
+    cell_size: u32,
+    /// Border around each face, relative to its size
+    #[arg(long, default_value_t = 0.2)]
+    padding: f32,
+    /// Order of the images: name, mtime or exif-date (see the transform subcommand)
+    #[arg(long, default_value = "name")]
+    sort: SortOrder,
+    /// Which face to use in images with several of them: largest, most-central, most-confident
+    /// or index:<n> (in the order they were detected)
+    #[arg(
+        long = "select-face",
+        value_name = "SELECTION",
+        default_value = "largest"
+    )]
+    selection: FaceSelection,
+    /// Label each cell with the date the photo was taken (from its EXIF data) or its file name
+    #[arg(long, requires = "font")]
+    label: Option<Label>,
+    /// TrueType font to draw the labels with
+    #[arg(long)]
+    font: Option<PathBuf>,
+}
+
+/// What to label the cells of a montage with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Label {
+    Date,
+    Name,
+}
+
+/// Parses `date` or `name`
+impl std::str::FromStr for Label {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "date" => Ok(Self::Date),
+            "name" => Ok(Self::Name),
+            _ => Err(format!("expected date or name, found {s}")),
+        }
+    }
+}
+
+pub fn run(opts: MontageOpts) -> anyhow::Result<()> {
+    use indicatif::*;
+
+    let MontageOpts {
+        features,
+        output,
+        grid,
+        cell_size,
+        padding,
+        sort,
+        selection,
+        label,
+        font,
+    } = opts;
+    let features = read_features(&features)?;
+    let mut features: Vec<_> = features.into_iter().collect();
+    sort_features(&mut features, sort)?;
+    let mut faces: Vec<_> = features
+        .iter()
+        .filter_map(|(path, faces)| {
+            let Some(face) = select_face(path, faces, selection) else {
+                warn!("{} does not have a face, skipping", path.display());
+                return None;
+            };
+            Some((path, face))
+        })
+        .collect();
+    ensure!(!faces.is_empty(), "no image has a face");
+    let (columns, rows) = grid.unwrap_or_else(|| {
+        let columns = (faces.len() as f32).sqrt().ceil() as u32;
+        (columns, (faces.len() as u32).div_ceil(columns))
+    });
+    let cells = columns as usize * rows as usize;
+    if faces.len() > cells {
+        warn!(
+            "only the first {cells} of the {} faces fit in the grid",
+            faces.len()
+        );
+        faces.truncate(cells);
+    }
+    let font = match font {
+        Some(path) => {
+            let data = std::fs::read(&path)
+                .with_context(|| format!("reading the font {}", path.display()))?;
+            let font = rusttype::Font::try_from_vec(data)
+                .with_context(|| format!("{} is not a TrueType font", path.display()))?;
+            Some(font)
+        }
+        None => None,
+    };
+
+    let style =
+        ProgressStyle::with_template("[{pos:>4}/{len:4}] {msg} {bar} [{per_sec} {eta_precise}]")
+            .expect("valid template");
+    #[cfg(feature = "rayon")]
+    use rayon::prelude::*;
+    #[cfg(feature = "rayon")]
+    let cells = faces.into_par_iter();
+    #[cfg(not(feature = "rayon"))]
+    let cells = faces.into_iter();
+    let cells: Vec<_> = cells
+        .progress_with_style(style)
+        .map(|(path, face)| -> anyhow::Result<_> {
+            let img = image::open(path)
+                .with_context(|| format!("opening image {}", path.display()))?
+                .into_rgb8();
+            let mut chip = landmark_extractor::face_chip(&img, face, cell_size, padding)
+                .with_context(|| format!("the landmarks of {} can't be aligned", path.display()))?;
+            if let (Some(label), Some(font)) = (label, &font) {
+                let name = || path.file_name().unwrap_or_default().to_string_lossy();
+                let text = match label {
+                    Label::Date => match exif_date(path) {
+                        Some((year, month, day, ..)) => format!("{year}-{month:02}-{day:02}"),
+                        None => name().into_owned(),
+                    },
+                    Label::Name => name().into_owned(),
+                };
+                draw_label(&mut chip, font, &text);
+            }
+            Ok(chip)
+        })
+        .collect::<anyhow::Result<_>>()?;
+
+    let mut montage = RgbImage::new(columns * cell_size, rows * cell_size);
+    for (ix, cell) in cells.iter().enumerate() {
+        let (column, row) = (ix as u32 % columns, ix as u32 / columns);
+        let (x, y) = (column * cell_size, row * cell_size);
+        image::imageops::replace(&mut montage, cell, x.into(), y.into());
+    }
+    save_image(&DynamicImage::ImageRgb8(montage), &output, None)
+}
+
+/// Write `text` at the bottom left of the cell, in white with a dark shadow
+fn draw_label(cell: &mut RgbImage, font: &rusttype::Font, text: &str) {
+    use imageproc::drawing::draw_text_mut;
+
+    let size = (cell.height() as f32 / 10.0).max(8.0);
+    let scale = rusttype::Scale::uniform(size);
+    let (x, y) = (size as i32 / 2, cell.height() as i32 - size as i32 * 3 / 2);
+    draw_text_mut(cell, Rgb([0, 0, 0]), x + 1, y + 1, scale, font, text);
+    draw_text_mut(cell, Rgb([255, 255, 255]), x, y, scale, font, text);
+}
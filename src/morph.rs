@@ -0,0 +1,148 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::Args;
+use glam::Vec2;
+use image::Rgb;
+use image::RgbImage;
+use landmark_extractor::FaceSelection;
+use log::warn;
+use stabilizer::Fill;
+use stabilizer::Interpolation;
+
+use crate::faces::select_face;
+use crate::faces::to_points;
+use crate::features::check_schemas;
+use crate::features::read_features;
+use crate::features::sort_features;
+use crate::features::SortOrder;
+use crate::video;
+
+/// Options of the morph subcommand
+#[derive(Debug, Args)]
+pub struct MorphOpts {
+    /// Path to the extracted features
+    features: PathBuf,
+    /// Directory where to place the frames (as numbered images)
+    #[arg(short, long, default_value = "./out")]
+    output_dir: PathBuf,
+    /// Encode the frames into this video (e.g. out.mp4) instead, needs ffmpeg
+    #[arg(long, value_name = "VIDEO")]
+    output_video: Option<PathBuf>,
+    /// Frames per second of the output video
+    #[arg(long, default_value_t = 30.0, requires = "output_video")]
+    fps: f32,
+    /// Number of frames generated between each pair of images
+    #[arg(short = 'n', long, default_value_t = 10)]
+    frames: u32,
+    /// Order of the images: name, mtime or exif-date (see the transform subcommand)
+    #[arg(long, default_value = "name")]
+    sort: SortOrder,
+    /// Which face to morph in images with several of them: largest, most-central,
+    /// most-confident or index:<n> (in the order they were detected)
+    #[arg(
+        long = "select-face",
+        value_name = "SELECTION",
+        default_value = "largest"
+    )]
+    selection: FaceSelection,
+}
+
+pub fn run(opts: MorphOpts) -> anyhow::Result<()> {
+    use indicatif::*;
+
+    let MorphOpts {
+        features,
+        output_dir,
+        output_video,
+        fps,
+        frames,
+        sort,
+        selection,
+    } = opts;
+    let features = read_features(&features)?;
+    let mut features: Vec<_> = features.into_iter().collect();
+    sort_features(&mut features, sort)?;
+    check_schemas(&features)?;
+    let faces: Vec<_> = features
+        .iter()
+        .filter_map(|(path, faces)| {
+            let Some(face) = select_face(path, faces, selection) else {
+                warn!("{} does not have a face, skipping", path.display());
+                return None;
+            };
+            Some((path, to_points(&face.landmarks, None)))
+        })
+        .collect();
+    let (ref_path, reference) = faces.first().context("no image has a face")?;
+    let size = image::image_dimensions(ref_path)
+        .with_context(|| format!("reading the size of {}", ref_path.display()))?;
+
+    let mut encoder = match &output_video {
+        Some(path) => Some(video::Encoder::create(path, fps, size)?),
+        None => {
+            std::fs::create_dir_all(&output_dir)
+                .with_context(|| format!("creating {} directory", output_dir.display()))?;
+            None
+        }
+    };
+    let mut count = 0;
+    let mut emit = |frame: &RgbImage| -> anyhow::Result<()> {
+        match &mut encoder {
+            Some(encoder) => encoder.write(frame)?,
+            None => {
+                let path = output_dir.join(format!("{count:06}.png"));
+                frame
+                    .save(&path)
+                    .with_context(|| format!("saving image to {}", path.display()))?;
+            }
+        }
+        count += 1;
+        Ok(())
+    };
+
+    let style =
+        ProgressStyle::with_template("[{pos:>4}/{len:4}] {msg} {bar} [{per_sec} {eta_precise}]")
+            .expect("valid template");
+    // The previous image and its landmarks, aligned to the reference
+    let mut previous: Option<(RgbImage, Vec<Vec2>)> = None;
+    for (path, points) in faces.iter().progress_with_style(style) {
+        let similarity = stabilizer::procrustes_superimposition(reference.clone(), points.clone())
+            .with_context(|| format!("aligning {}", path.display()))?;
+        let img = image::open(path)
+            .with_context(|| format!("opening image {}", path.display()))?
+            .into_rgb8();
+        let projection = similarity.to_projection();
+        let fill = Fill::default();
+        let img = stabilizer::warp_to_size(&img, &projection, fill, Interpolation::Bicubic, size)
+            .into_rgb8();
+        let points: Vec<_> = points
+            .iter()
+            .map(|&p| similarity.transform_point(p))
+            .collect();
+        if let Some((prev_img, prev_points)) = &previous {
+            for step in 1..=frames {
+                let t = step as f32 / (frames + 1) as f32;
+                let between: Vec<_> = prev_points
+                    .iter()
+                    .zip(&points)
+                    .map(|(a, b)| a.lerp(*b, t))
+                    .collect();
+                let warp = |img, points| {
+                    let black = Rgb([0, 0, 0]);
+                    stabilizer::piecewise_affine::warp(img, points, &between, black)
+                        .context("the landmarks can't be triangulated")
+                };
+                let from = warp(prev_img, prev_points)?;
+                let to = warp(&img, &points)?;
+                emit(&video::blend(&from, &to, t))?;
+            }
+        }
+        emit(&img)?;
+        previous = Some((img, points));
+    }
+    match encoder {
+        Some(encoder) => encoder.finish(),
+        None => Ok(()),
+    }
+}
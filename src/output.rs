@@ -0,0 +1,181 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use image::codecs::jpeg::JpegEncoder;
+use image::DynamicImage;
+use image::ImageFormat;
+use img_parts::DynImage;
+use img_parts::ImageEXIF;
+use img_parts::ImageICC;
+
+/// Format of the output images
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Jpeg,
+    Png,
+    #[cfg(feature = "webp")]
+    WebP,
+}
+
+impl OutputFormat {
+    /// File extension of the format
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpg",
+            Self::Png => "png",
+            #[cfg(feature = "webp")]
+            Self::WebP => "webp",
+        }
+    }
+}
+
+/// Parses `jpg` (or `jpeg`), `png` or `webp`
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jpg" | "jpeg" => Ok(Self::Jpeg),
+            "png" => Ok(Self::Png),
+            #[cfg(feature = "webp")]
+            "webp" => Ok(Self::WebP),
+            #[cfg(not(feature = "webp"))]
+            "webp" => Err("webp output needs the webp feature".to_string()),
+            _ => Err(format!("expected jpg, png or webp, found {s}")),
+        }
+    }
+}
+
+/// The deepest directory that contains all the paths
+pub fn common_dir<'a>(mut paths: impl Iterator<Item = &'a Path>) -> PathBuf {
+    let Some(first) = paths.next() else {
+        return PathBuf::new();
+    };
+    let mut root = first.parent().unwrap_or(Path::new("")).to_path_buf();
+    for path in paths {
+        while !path.starts_with(&root) {
+            if !root.pop() {
+                break;
+            }
+        }
+    }
+    root
+}
+
+/// Save the image in the format of its extension (creating its directory if needed), jpg and webp
+/// images are encoded with this quality if set
+pub fn save_image(img: &DynamicImage, path: &Path, quality: Option<u8>) -> anyhow::Result<()> {
+    let context = || format!("saving image to {}", path.display());
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(context)?;
+    }
+    let format = ImageFormat::from_path(path).with_context(context)?;
+    match (format, quality) {
+        (ImageFormat::Jpeg, Some(quality)) => {
+            let file = std::fs::File::create(path).with_context(context)?;
+            JpegEncoder::new_with_quality(std::io::BufWriter::new(file), quality)
+                .encode(img.as_bytes(), img.width(), img.height(), img.color())
+                .with_context(context)
+        }
+        #[cfg(feature = "webp")]
+        (ImageFormat::WebP, quality) => {
+            use image::codecs::webp::WebPEncoder;
+            use image::codecs::webp::WebPQuality;
+            let quality = quality.map_or(WebPQuality::lossless(), WebPQuality::lossy);
+            let file = std::fs::File::create(path).with_context(context)?;
+            WebPEncoder::new_with_quality(std::io::BufWriter::new(file), quality)
+                .encode(img.as_bytes(), img.width(), img.height(), img.color())
+                .with_context(context)
+        }
+        _ => img.save(path).with_context(context),
+    }
+}
+
+/// Copy the EXIF data and the ICC color profile of `source` into `output`
+///
+/// Does nothing if either of them isn't a JPEG, PNG or WebP image
+pub fn copy_metadata(source: &Path, output: &Path) -> anyhow::Result<()> {
+    let read = |path: &Path| -> anyhow::Result<Option<DynImage>> {
+        let bytes = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+        DynImage::from_bytes(bytes.into())
+            .with_context(|| format!("reading the metadata of {}", path.display()))
+    };
+    let Some(source_image) = read(source)? else {
+        return Ok(());
+    };
+    let (exif, icc_profile) = (source_image.exif(), source_image.icc_profile());
+    if exif.is_none() && icc_profile.is_none() {
+        return Ok(());
+    }
+    let Some(mut output_image) = read(output)? else {
+        return Ok(());
+    };
+    output_image.set_exif(exif);
+    output_image.set_icc_profile(icc_profile);
+    let context = || format!("writing the metadata of {}", output.display());
+    let file = std::fs::File::create(output).with_context(context)?;
+    output_image
+        .encoder()
+        .write_to(std::io::BufWriter::new(file))
+        .with_context(context)?;
+    Ok(())
+}
+
+/// Create the file at `path` with `write`, atomically: it is written to a temporary file in the
+/// same directory that then replaces `path`, so nobody reads (or is left with) a partial file
+pub fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut std::io::BufWriter<std::fs::File>) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let name = path
+        .file_name()
+        .with_context(|| format!("{} is not a file", path.display()))?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(name);
+    temp_name.push(".tmp");
+    let temp = path.with_file_name(temp_name);
+    let written = (|| {
+        let file =
+            std::fs::File::create(&temp).with_context(|| format!("creating {}", temp.display()))?;
+        let mut writer = std::io::BufWriter::new(file);
+        write(&mut writer)?;
+        let file = writer.into_inner().map_err(|err| err.into_error())?;
+        file.sync_all()
+            .with_context(|| format!("writing {}", temp.display()))
+    })();
+    if let Err(err) = written {
+        // Best effort, the error is what matters
+        let _ = std::fs::remove_file(&temp);
+        return Err(err);
+    }
+    std::fs::rename(&temp, path)
+        .with_context(|| format!("replacing {} with {}", path.display(), temp.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn atomic_writes_keep_the_old_file_on_errors() {
+        let dir =
+            std::env::temp_dir().join(format!("face-stabilizer-atomic-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("features.ron");
+        write_atomically(&path, |writer| Ok(writer.write_all(b"old")?)).unwrap();
+        let failed = write_atomically(&path, |writer| {
+            writer.write_all(b"partial")?;
+            anyhow::bail!("interrupted")
+        });
+        assert!(failed.is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"old");
+        write_atomically(&path, |writer| Ok(writer.write_all(b"new")?)).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        // No temporary files are left behind
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,109 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::Args;
+use landmark_extractor::Face;
+use log::info;
+
+use crate::features::read_features;
+use crate::features::write_features;
+use crate::features::Features;
+
+/// Options of the quality-filter subcommand
+#[derive(Debug, Args)]
+pub struct QualityFilterOpts {
+    /// Path to the features file
+    features: PathBuf,
+    /// Where to write the annotated features file (defaults to overwriting the input)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Whether to pretty print the annotated file
+    #[arg(short, long)]
+    pretty: bool,
+    /// Faces blurrier than this (variance of the Laplacian of the face) are listed as blurry
+    #[arg(long)]
+    min_sharpness: Option<f32>,
+    /// Remove the blurry faces from the features file instead of only listing them
+    #[arg(long, requires = "min_sharpness")]
+    drop: bool,
+    /// Compute the sharpness of the faces that already have one too
+    #[arg(long)]
+    recompute: bool,
+}
+
+pub fn run(opts: QualityFilterOpts) -> anyhow::Result<()> {
+    use indicatif::*;
+
+    let QualityFilterOpts {
+        features: input,
+        output,
+        pretty,
+        min_sharpness,
+        drop,
+        recompute,
+    } = opts;
+    let output = output.unwrap_or_else(|| input.clone());
+    let features = read_features(&input)?;
+    let features: Vec<_> = features.into_iter().collect();
+
+    let style =
+        ProgressStyle::with_template("[{pos:>4}/{len:4}] {msg} {bar} [{per_sec} {eta_precise}]")
+            .expect("valid template");
+    #[cfg(feature = "rayon")]
+    use rayon::prelude::*;
+    #[cfg(feature = "rayon")]
+    let iter = features.into_par_iter();
+    #[cfg(not(feature = "rayon"))]
+    let iter = features.into_iter();
+    let mut features: Vec<_> = iter
+        .progress_with_style(style)
+        .map(|(path, mut faces)| -> anyhow::Result<_> {
+            if recompute || faces.iter().any(|face| face.sharpness.is_none()) {
+                let img = image::open(&path)
+                    .with_context(|| format!("opening image {}", path.display()))?
+                    .into_rgb8();
+                faces = faces
+                    .iter()
+                    .cloned()
+                    .map(|mut face| {
+                        if recompute || face.sharpness.is_none() {
+                            face.sharpness = landmark_extractor::sharpness(&img, &face.rect);
+                        }
+                        face
+                    })
+                    .collect();
+            }
+            Ok((path, faces))
+        })
+        .collect::<anyhow::Result<_>>()?;
+    features.sort_by(|a, b| a.0.cmp(&b.0));
+
+    if let Some(min_sharpness) = min_sharpness {
+        let is_blurry = |face: &Face| {
+            face.sharpness
+                .is_none_or(|sharpness| sharpness < min_sharpness)
+        };
+        let mut blurry = 0;
+        for (path, faces) in &mut features {
+            for (ix, face) in faces.iter().enumerate().filter(|(_, face)| is_blurry(face)) {
+                blurry += 1;
+                match face.sharpness {
+                    Some(sharpness) => {
+                        println!("{}: face {ix} is blurry ({sharpness:.1})", path.display())
+                    }
+                    None => println!("{}: face {ix} is too small to measure", path.display()),
+                }
+            }
+            if drop {
+                *faces = faces
+                    .iter()
+                    .filter(|face| !is_blurry(face))
+                    .cloned()
+                    .collect();
+            }
+        }
+        info!("{blurry} blurry faces");
+    }
+    let features: Features = features.into_iter().collect();
+    write_features(&output, &features, pretty)
+}
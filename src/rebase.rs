@@ -0,0 +1,92 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::ensure;
+use anyhow::Context;
+use clap::Args;
+use log::info;
+use log::warn;
+
+use crate::features::read_features;
+use crate::features::relative_path;
+use crate::features::write_features;
+use crate::features::Features;
+use crate::output::common_dir;
+
+/// Options of the rebase subcommand
+#[derive(Debug, Args)]
+pub struct RebaseOpts {
+    /// Path to the features file
+    features: PathBuf,
+    /// Where to write the rebased features file (defaults to overwriting the input)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Directory the images were in (defaults to the one that contains all of them)
+    #[arg(long, value_name = "DIR")]
+    from: Option<PathBuf>,
+    /// Directory the images are in now
+    #[arg(long, value_name = "DIR")]
+    to: Option<PathBuf>,
+    /// Make the paths relative to the directory of the output file, so it can be moved together
+    /// with the images
+    #[arg(long)]
+    relative: bool,
+    /// Whether to pretty print the rebased file
+    #[arg(short, long)]
+    pretty: bool,
+}
+
+pub fn run(opts: RebaseOpts) -> anyhow::Result<()> {
+    let RebaseOpts {
+        features: input,
+        output,
+        from,
+        to,
+        relative,
+        pretty,
+    } = opts;
+    ensure!(
+        to.is_some() || relative,
+        "nothing to do, use --to and/or --relative"
+    );
+    let output = output.unwrap_or_else(|| input.clone());
+    let mut features = read_features(&input)?;
+    if let Some(to) = &to {
+        let from = from.unwrap_or_else(|| common_dir(features.keys().map(PathBuf::as_path)));
+        info!(
+            "moving the images from {} to {}",
+            from.display(),
+            to.display()
+        );
+        features = rebase_features(features, &from, to);
+    }
+    if relative {
+        // Relative paths are written relative to the output file
+        let current_dir = std::env::current_dir().context("finding the current directory")?;
+        features = features
+            .into_iter()
+            .map(|(path, faces)| (relative_path(&current_dir.join(path), &current_dir), faces))
+            .collect();
+    }
+    write_features(&output, &features, pretty)
+}
+
+/// Replace the `from` prefix of the paths of the images with `to`
+///
+/// Paths outside of `from` are kept as is.
+pub fn rebase_features(features: Features, from: &Path, to: &Path) -> Features {
+    features
+        .into_iter()
+        .map(|(path, faces)| match path.strip_prefix(from) {
+            Ok(rest) => (to.join(rest), faces),
+            Err(_) => {
+                warn!(
+                    "{} is not in {}, keeping it",
+                    path.display(),
+                    from.display()
+                );
+                (path, faces)
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,133 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::Args;
+use landmark_extractor::FaceSelection;
+use log::debug;
+use log::info;
+use stabilizer::Fill;
+use stabilizer::Fit;
+use stabilizer::Interpolation;
+
+use crate::alignment::AlignmentOpts;
+use crate::output::save_image;
+use crate::output::OutputFormat;
+use crate::video;
+use crate::ExtractorOpts;
+
+/// Options of the stabilize-video subcommand
+#[derive(Debug, Args)]
+pub struct VideoOpts {
+    #[command(flatten)]
+    pub extractor: ExtractorOpts,
+    /// Path to the video
+    input: PathBuf,
+    /// Directory where to place the stabilized frames
+    #[arg(short, long, default_value = "./out")]
+    output_dir: PathBuf,
+    /// Format of the stabilized frames: jpg, png or webp (needs the webp feature)
+    #[arg(long, value_name = "FORMAT", default_value = "png")]
+    output_format: OutputFormat,
+    /// Which face to stabilize in frames with several of them: largest, most-central,
+    /// most-confident or index:<n> (in the order they were detected)
+    #[arg(
+        long = "select-face",
+        value_name = "SELECTION",
+        default_value = "largest"
+    )]
+    selection: FaceSelection,
+    #[command(flatten)]
+    align: AlignmentOpts,
+    /// How to fill the borders uncovered by the alignment (see the transform subcommand)
+    #[arg(long, default_value = "constant")]
+    fill: Fill,
+    /// How to sample the frames when warping them: nearest (fastest), bilinear or bicubic
+    /// (sharpest)
+    #[arg(long, default_value = "bicubic")]
+    interpolation: Interpolation,
+}
+
+pub fn run(opts: VideoOpts) -> anyhow::Result<()> {
+    use indicatif::*;
+
+    let VideoOpts {
+        extractor,
+        input,
+        output_dir,
+        output_format,
+        selection,
+        align,
+        fill,
+        interpolation,
+    } = opts;
+    align.check()?;
+    std::fs::create_dir_all(&output_dir)
+        .with_context(|| format!("creating {} directory", output_dir.display()))?;
+    let extractor = extractor.build()?;
+
+    // Find the face in every frame, only searching around where it was in the previous one
+    let style = ProgressStyle::with_template("[{pos:>4}] {msg} {spinner} [{per_sec}]")
+        .expect("valid template");
+    let bar = ProgressBar::new_spinner()
+        .with_style(style)
+        .with_message("detecting");
+    let mut faces = Vec::new();
+    let mut roi: Option<landmark_extractor::Rect> = None;
+    for (ix, frame) in video::Frames::open(&input)?.progress_with(bar).enumerate() {
+        let frame = frame?;
+        let found = match &roi {
+            Some(roi) => extractor.extract_in_roi(&frame, roi),
+            None => extractor.extract_image(&frame),
+        }
+        .with_context(|| format!("extracting landmarks from frame {ix}"))?;
+        let face = found.select(selection, frame.dimensions()).cloned();
+        if face.is_none() {
+            debug!("frame {ix} does not have a face");
+        }
+        roi = face.as_ref().map(|face| face.rect.clone());
+        faces.push(face);
+    }
+    info!(
+        "found a face in {}/{} frames",
+        faces.iter().flatten().count(),
+        faces.len()
+    );
+
+    // Align every frame to the first face, interpolating the frames without one
+    let reference = match align.reference_shape(faces.iter().flatten())? {
+        Some(shape) => shape,
+        None => faces
+            .iter()
+            .flatten()
+            .next()
+            .context("no face was found in the video")?
+            .landmarks
+            .clone(),
+    };
+    let selected: Vec<_> = faces.iter().map(Option::as_ref).collect();
+    let fitted = align.fit_faces(&reference, &selected, Fit::default())?;
+    let similarities = stabilizer::smooth_sequence(&fitted, align.smoothing())
+        .context("no frame can be aligned")?;
+
+    let style =
+        ProgressStyle::with_template("[{pos:>4}/{len:4}] {msg} {bar} [{per_sec} {eta_precise}]")
+            .expect("valid template");
+    let bar = ProgressBar::new(similarities.len() as u64)
+        .with_style(style)
+        .with_message("warping");
+    let extension = if fill == Fill::Transparent {
+        "png"
+    } else {
+        output_format.extension()
+    };
+    let frames = video::Frames::open(&input)?.zip(&similarities);
+    for (ix, (frame, similarity)) in frames.progress_with(bar).enumerate() {
+        let warped = stabilizer::warp(&frame?, &similarity.to_projection(), fill, interpolation);
+        save_image(
+            &warped,
+            &output_dir.join(format!("{ix:06}.{extension}")),
+            None,
+        )?;
+    }
+    Ok(())
+}
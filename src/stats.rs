@@ -0,0 +1,152 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use glam::Vec2;
+use landmark_extractor::FaceSelection;
+
+use crate::faces::select_face;
+use crate::features::read_features;
+
+/// Options of the stats subcommand
+#[derive(Debug, Args)]
+pub struct StatsOpts {
+    /// Path to the features file
+    features: PathBuf,
+    /// Which face to use in images with several of them: largest, most-central, most-confident
+    /// or index:<n> (in the order they were detected)
+    #[arg(
+        long = "select-face",
+        value_name = "SELECTION",
+        default_value = "largest"
+    )]
+    selection: FaceSelection,
+    /// Faces tilted more than this (in degrees) are reported as problematic
+    #[arg(long, default_value_t = 20.0)]
+    max_roll: f32,
+    /// Faces this many times larger or smaller than the median are reported as problematic
+    #[arg(long, default_value_t = 2.0)]
+    max_scale: f32,
+    /// Faces turned to the side more than this (in degrees) are reported as problematic
+    #[arg(long, default_value_t = 30.0)]
+    max_yaw: f32,
+    /// Faces tilted up or down more than this (in degrees) are reported as problematic
+    #[arg(long, default_value_t = 25.0)]
+    max_pitch: f32,
+}
+
+pub fn run(opts: StatsOpts) -> anyhow::Result<()> {
+    let StatsOpts {
+        features,
+        selection,
+        max_roll,
+        max_scale,
+        max_yaw,
+        max_pitch,
+    } = opts;
+    let mut features: Vec<_> = read_features(&features)?.into_iter().collect();
+    features.sort_by(|a, b| a.0.cmp(&b.0));
+    let count = |faces: fn(usize) -> bool| {
+        features
+            .iter()
+            .filter(|(_, found)| faces(found.len()))
+            .count()
+    };
+    println!("{} images", features.len());
+    println!("  {} with no face", count(|n| n == 0));
+    println!("  {} with one face", count(|n| n == 1));
+    println!("  {} with several faces", count(|n| n > 1));
+
+    // The size (side of the bounding box), roll (angle of the eye line) and pose of each face
+    let faces: Vec<_> = features
+        .iter()
+        .filter_map(|(path, found)| {
+            let face = select_face(path, found, selection)?;
+            let size = ((face.rect.width() * face.rect.height()) as f32).sqrt();
+            let roll = face.landmarks.eye_centers().map(|[left, right]| {
+                // Measure from the eye on the left of the image to the one on the right
+                let eyes = Vec2::from(right) - Vec2::from(left);
+                let eyes = if eyes.x < 0.0 { -eyes } else { eyes };
+                eyes.y.atan2(eyes.x).to_degrees()
+            });
+            Some((path, size, roll, face.landmarks.head_pose()))
+        })
+        .collect();
+    let mut sizes: Vec<_> = faces.iter().map(|&(_, size, _, _)| size).collect();
+    let mut rolls: Vec<_> = faces.iter().filter_map(|&(_, _, roll, _)| roll).collect();
+    let poses = faces.iter().filter_map(|&(_, _, _, pose)| pose);
+    let mut yaws: Vec<_> = poses.clone().map(|pose| pose.yaw).collect();
+    let mut pitches: Vec<_> = poses.map(|pose| pose.pitch).collect();
+    // Empty if no image has a face, the images are still listed below
+    let median_size = median(&mut sizes);
+    if !sizes.is_empty() {
+        println!("face size: {} pixels", distribution(&mut sizes));
+    }
+    if !rolls.is_empty() {
+        println!("roll: {} degrees", distribution(&mut rolls));
+    }
+    if !yaws.is_empty() {
+        println!("yaw: {} degrees", distribution(&mut yaws));
+        println!("pitch: {} degrees", distribution(&mut pitches));
+    }
+
+    println!("likely problematic images:");
+    for (path, found) in &features {
+        match found.len() {
+            0 => println!("  {}: no face", path.display()),
+            1 => {}
+            n => println!("  {}: {n} faces, using the {selection} one", path.display()),
+        }
+    }
+    for &(path, size, roll, pose) in &faces {
+        if let Some(median_size) = median_size {
+            let scale = size / median_size;
+            if scale > max_scale || scale < 1.0 / max_scale {
+                println!(
+                    "  {}: the face is {scale:.1}x the median size",
+                    path.display()
+                );
+            }
+        }
+        match roll {
+            Some(roll) if roll.abs() > max_roll => {
+                println!("  {}: the face is tilted {roll:.0} degrees", path.display())
+            }
+            Some(_) => {}
+            None => println!("  {}: the eyes can't be located", path.display()),
+        }
+        if let Some(pose) = pose {
+            if pose.yaw.abs() > max_yaw {
+                let yaw = pose.yaw;
+                println!("  {}: the head is turned {yaw:.0} degrees", path.display());
+            }
+            if pose.pitch.abs() > max_pitch {
+                let pitch = pose.pitch;
+                println!(
+                    "  {}: the head is tilted {pitch:.0} degrees",
+                    path.display()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The median of the values (sorts them)
+fn median(values: &mut [f32]) -> Option<f32> {
+    values.sort_by(f32::total_cmp);
+    values.get(values.len() / 2).copied()
+}
+
+/// Minimum, quartiles and maximum of the values (sorts them)
+fn distribution(values: &mut [f32]) -> String {
+    values.sort_by(f32::total_cmp);
+    let quantile = |q: f32| values[((values.len() - 1) as f32 * q).round() as usize];
+    format!(
+        "min {:.1}, 25% {:.1}, median {:.1}, 75% {:.1}, max {:.1}",
+        quantile(0.0),
+        quantile(0.25),
+        quantile(0.5),
+        quantile(0.75),
+        quantile(1.0)
+    )
+}
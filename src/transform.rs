@@ -0,0 +1,991 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use clap::Args;
+use glam::Vec2;
+use glob::Pattern;
+use image::GrayImage;
+use image::Rgb;
+use imageproc::filter::gaussian_blur_f32;
+use imageproc::geometric_transformations::Projection;
+use imageproc::rect::Rect;
+use landmark_extractor::Face;
+use landmark_extractor::FaceId;
+use landmark_extractor::FaceSelection;
+use landmark_extractor::FaceTracker;
+use landmark_extractor::Landmarks;
+use log::debug;
+use log::info;
+use log::warn;
+use stabilizer::is_reflection;
+use stabilizer::mirror;
+use stabilizer::Alignment;
+use stabilizer::Fill;
+use stabilizer::Fit;
+use stabilizer::Interpolation;
+use stabilizer::Reference;
+use stabilizer::SimilarityTransform;
+use stabilizer::Template;
+
+use crate::alignment::AlignmentOpts;
+use crate::faces::eye_centers;
+use crate::faces::is_frontal;
+use crate::faces::select_face;
+use crate::faces::to_points;
+use crate::faces::visible_points;
+use crate::features::check_schemas;
+use crate::features::read_features;
+use crate::features::sort_features;
+use crate::features::write_landmarks;
+use crate::features::SortOrder;
+use crate::output::common_dir;
+use crate::output::copy_metadata;
+use crate::output::save_image;
+use crate::output::OutputFormat;
+use crate::parse_point;
+use crate::parse_size;
+use crate::rebase::rebase_features;
+use crate::video;
+
+/// Options of the transform subcommand
+#[derive(Debug, Args)]
+pub struct TransformOpts {
+    /// Path to the extracted features
+    features: PathBuf,
+    /// Directory where to place the transformed images
+    ///
+    /// The images keep their paths relative to the directory that contains all of them
+    #[arg(short, long, default_value = "./out")]
+    output_dir: PathBuf,
+    /// Only transform the images matching this pattern (relative to the directory that
+    /// contains all of them, e.g. `**/*.jpg`)
+    #[arg(long)]
+    glob: Option<Pattern>,
+    /// Look for the images in this directory instead of the one that contains all of them in
+    /// the features file (e.g. if they were moved to another machine)
+    #[arg(long, value_name = "DIR")]
+    basedir: Option<PathBuf>,
+    /// Skip the images whose output was already made from the same file (same size and
+    /// modification time), to continue an interrupted run
+    ///
+    /// The outputs are recorded in a manifest in the output directory
+    /// (.face-stabilizer-resume.csv). Only safe if the rest of the options haven't changed
+    #[arg(long)]
+    resume: bool,
+    /// Order of the images: name, mtime (modification time) or exif-date (when the photo was
+    /// taken, images without it go last)
+    ///
+    /// The first image is the default reference, and the smoothing, rolling alignment and
+    /// face tracking follow this order
+    #[arg(long, default_value = "name")]
+    sort: SortOrder,
+    /// Don't copy the EXIF data and the color profile of the images into the outputs
+    #[arg(long)]
+    strip_metadata: bool,
+    /// Encode the transformed images into this video (e.g. out.mp4 or out.webm) instead of
+    /// saving them to the output directory
+    ///
+    /// Needs ffmpeg, the images that don't have the size of the first one are centered in it
+    #[arg(long, value_name = "VIDEO", conflicts_with = "resume")]
+    output_video: Option<PathBuf>,
+    /// Frames per second of the output video
+    #[arg(long, default_value_t = 24.0, requires = "output_video")]
+    fps: f32,
+    /// Number of frames blending each image of the output video into the next one
+    #[arg(long, default_value_t = 0, requires = "output_video")]
+    crossfade: u32,
+    /// Which face to stabilize in images with several of them: largest, most-central,
+    /// most-confident or index:<n> (in the order they were detected)
+    #[arg(
+        long = "select-face",
+        value_name = "SELECTION",
+        default_value = "largest"
+    )]
+    selection: FaceSelection,
+    /// Stabilize this tracked face instead of selecting one in each image
+    ///
+    /// Faces are tracked across the (sorted) images, they are numbered in the order they
+    /// appear starting from 0
+    #[arg(short, long)]
+    face: Option<u64>,
+    /// Stabilize against this image instead of the first one (in alphabetical order)
+    ///
+    /// Matched against the end of the paths in the features file, so the file name is enough
+    #[arg(short, long, conflicts_with_all = ["mean_shape", "reference_landmarks"])]
+    reference: Option<PathBuf>,
+    /// Save the landmarks of the reference (in the format of --reference-landmarks)
+    #[arg(long)]
+    save_reference_landmarks: Option<PathBuf>,
+    /// Ignore the landmarks that are further than this many median absolute deviations from
+    /// their place in the reference (e.g. 3)
+    #[arg(long)]
+    reject_outliers: Option<f32>,
+    /// Flip the images that are a mirror image of the reference (e.g. front camera selfies)
+    #[arg(long)]
+    unmirror: bool,
+    /// How to fill the borders uncovered by the alignment: constant (--fill-color), a color
+    /// (black, white or rrggbb), clamp or edge (repeat the edges), mirror or transparent
+    /// (saves RGBA PNGs)
+    #[arg(long, visible_alias = "fill-mode", default_value = "constant")]
+    fill: Fill,
+    /// Color of the constant fill: black, white or rrggbb
+    #[arg(long, value_parser = stabilizer::parse_color)]
+    fill_color: Option<Rgb<u8>>,
+    /// How to sample the images when warping them: nearest (fastest), bilinear or bicubic
+    /// (sharpest)
+    #[arg(long, default_value = "bicubic")]
+    interpolation: Interpolation,
+    /// Format of the output images: jpg, png or webp (needs the webp feature), by default the
+    /// format of each input
+    #[arg(long, value_name = "FORMAT")]
+    output_format: Option<OutputFormat>,
+    /// Quality of the jpg and webp outputs (1-100), lower values give smaller files
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: Option<u8>,
+    /// Size of the output images (WIDTHxHEIGHT) instead of the size of each input, with the
+    /// reference face in the center (or at --eyes-at)
+    #[arg(long, value_parser = parse_size, conflicts_with_all = ["crop", "auto_zoom"])]
+    output_size: Option<(u32, u32)>,
+    /// Place the point between the eyes of the reference face here (X,Y)
+    #[arg(long, value_parser = parse_point)]
+    eyes_at: Option<Vec2>,
+    /// Output square crops of this many pixels around the (aligned) face instead of the whole
+    /// images, e.g. to train face models or make collages
+    #[arg(
+        long,
+        value_name = "SIZE",
+        conflicts_with_all = ["output_size", "eyes_at", "crop", "auto_zoom"]
+    )]
+    crop_face: Option<u32>,
+    /// Space around the face in the --crop-face crops, in percent of the size of the face
+    #[arg(long, default_value_t = 20.0, requires = "crop_face")]
+    margin: f32,
+    /// Crop the images to the region covered by every (aligned) image, so no borders are left
+    #[arg(long)]
+    crop: bool,
+    /// Zoom into the reference face just enough that no borders are left (keeps the size of
+    /// the images)
+    #[arg(long)]
+    auto_zoom: bool,
+    /// Keep the images without a face, interpolating their alignment from the neighbouring
+    /// images (instead of skipping them)
+    ///
+    /// Only supported with the similarity alignment
+    #[arg(long)]
+    interpolate_missing: bool,
+    /// Skip the images whose face is blurrier than this (variance of the Laplacian, see the
+    /// quality-filter subcommand), or interpolate them with --interpolate-missing
+    #[arg(long)]
+    min_sharpness: Option<f32>,
+    /// Skip the images whose head is turned to the side more than this (in degrees), or
+    /// interpolate them with --interpolate-missing
+    ///
+    /// Turned heads don't align well with a similarity transform. The pose is a rough
+    /// estimate from the eyes and the nose, images where it is unknown are kept.
+    #[arg(long)]
+    max_yaw: Option<f32>,
+    /// Skip the images whose head is tilted up or down more than this (in degrees), or
+    /// interpolate them with --interpolate-missing
+    #[arg(long)]
+    max_pitch: Option<f32>,
+    /// Refine the alignment on the pixels of the reference face (instead of only the
+    /// landmarks), reaching subpixel accuracy
+    ///
+    /// Slow, only supported with the similarity alignment and a reference image (not
+    /// --mean-shape)
+    #[arg(long)]
+    refine: bool,
+    /// Align each image to the previous one instead of the reference, aligning every n-th
+    /// image to the reference to control the drift
+    ///
+    /// Follows faces that slowly change (aging, haircuts), only supported with the similarity
+    /// alignment
+    #[arg(long, value_name = "KEYFRAME_INTERVAL")]
+    rolling: Option<usize>,
+    /// Don't scale the faces, so their natural growth over the years stays visible
+    ///
+    /// Only supported with the similarity alignment
+    #[arg(long)]
+    no_scale: bool,
+    /// Don't rotate the faces
+    ///
+    /// Only supported with the similarity alignment
+    #[arg(long)]
+    no_rotation: bool,
+    #[command(flatten)]
+    align: AlignmentOpts,
+}
+
+pub fn run(opts: TransformOpts) -> anyhow::Result<()> {
+    let TransformOpts {
+        features,
+        output_dir,
+        glob,
+        basedir,
+        resume,
+        sort,
+        strip_metadata,
+        output_video,
+        fps,
+        crossfade,
+        selection,
+        face,
+        reference,
+        save_reference_landmarks,
+        reject_outliers,
+        unmirror,
+        fill,
+        fill_color,
+        interpolation,
+        output_format,
+        quality,
+        output_size,
+        eyes_at,
+        crop_face,
+        margin,
+        crop,
+        auto_zoom,
+        interpolate_missing,
+        min_sharpness,
+        max_yaw,
+        max_pitch,
+        refine,
+        rolling,
+        no_scale,
+        no_rotation,
+        align,
+    } = opts;
+    align.check()?;
+    let alignment = align.alignment;
+    ensure!(
+        !interpolate_missing || alignment == Alignment::Similarity,
+        "interpolating missing faces is only supported with the similarity alignment"
+    );
+    ensure!(
+        !refine || alignment == Alignment::Similarity,
+        "refining is only supported with the similarity alignment"
+    );
+    ensure!(
+        rolling.is_none() || alignment == Alignment::Similarity,
+        "the rolling reference is only supported with the similarity alignment"
+    );
+    let fill = match (fill, fill_color) {
+        (Fill::Constant(_), Some(color)) => Fill::Constant(color),
+        (_, Some(_)) => bail!("--fill-color only applies to the constant fill"),
+        (fill, None) => fill,
+    };
+    ensure!(
+        !(fill == Fill::Transparent && output_format == Some(OutputFormat::Jpeg)),
+        "jpg images can't be transparent"
+    );
+    let constrained = no_scale || no_rotation;
+    ensure!(
+        !constrained || alignment == Alignment::Similarity,
+        "--no-scale and --no-rotation are only supported with the similarity alignment"
+    );
+    ensure!(
+        !constrained || (rolling.is_none() && !refine),
+        "--no-scale and --no-rotation can't be used with --rolling or --refine"
+    );
+    ensure!(
+        !(refine && (align.mean_shape || align.reference_landmarks.is_some())),
+        "refining needs a reference image, it can't be used with --mean-shape or \
+         --reference-landmarks"
+    );
+    ensure!(features.exists(), "could not find {}", features.display());
+    ensure!(features.is_file(), "{} is not a file", features.display());
+    let mut features = read_features(&features)?;
+    if let Some(basedir) = &basedir {
+        let root = common_dir(features.keys().map(PathBuf::as_path));
+        features = rebase_features(features, &root, basedir);
+    }
+    let mut features: Vec<_> = features.into_iter().collect();
+    sort_features(&mut features, sort)?;
+    let order: HashMap<PathBuf, usize> = features
+        .iter()
+        .enumerate()
+        .map(|(ix, (path, _))| (path.clone(), ix))
+        .collect();
+    // The outputs keep their paths relative to the directory that has all the images
+    let root = common_dir(features.iter().map(|(path, _)| path.as_path()));
+    if let Some(glob) = &glob {
+        features.retain(|(path, _)| glob.matches_path(path.strip_prefix(&root).unwrap_or(path)));
+    }
+    check_schemas(&features)?;
+    if let Some(face) = face {
+        let mut tracker = FaceTracker::new();
+        for (_, faces) in &mut features {
+            let ids = tracker.update(faces);
+            *faces = faces
+                .iter()
+                .zip(ids)
+                .filter(|&(_, id)| id == FaceId(face))
+                .map(|(face, _)| face.clone())
+                .collect();
+        }
+    }
+    // Videos don't need the output directory
+    if output_video.is_none() && !output_dir.exists() {
+        std::fs::create_dir(&output_dir)
+            .with_context(|| format!("creating {} directory", output_dir.display()))?;
+    } else if output_video.is_none() {
+        ensure!(
+            output_dir.is_dir(),
+            "{} is not a directory",
+            output_dir.display()
+        );
+    }
+    // Where the outputs are recorded for --resume
+    let manifest = match output_video {
+        Some(_) => None,
+        None => Some(ResumeManifest::open(&output_dir, resume)?),
+    };
+    let out_path = |file: &Path| {
+        let relative = file
+            .strip_prefix(&root)
+            .unwrap_or_else(|_| Path::new(file.file_name().expect("valid file name")));
+        let mut out = output_dir.join(relative);
+        if let Some(format) = output_format {
+            out.set_extension(format.extension());
+        } else if fill == Fill::Transparent {
+            out.set_extension("png");
+        }
+        out
+    };
+
+    let selected = features
+        .iter()
+        .filter_map(|(path, faces)| select_face(path, faces, selection));
+    let (ref_path, ref_feat) = if let Some(shape) = align.reference_shape(selected)? {
+        (None, shape)
+    } else {
+        let ix = match &reference {
+            Some(reference) => features
+                .iter()
+                .position(|(path, _)| path.ends_with(reference))
+                .with_context(|| format!("{} is not in the features file", reference.display()))?,
+            None => 0,
+        };
+        ensure!(ix < features.len(), "the features file is empty");
+        let (ref_path, ref_feat) = features.remove(ix);
+        let (_, ref_feat) = select_face(&ref_path, &ref_feat, selection)
+            .with_context(|| format!("the reference image {} has no face", ref_path.display()))?
+            .clone()
+            .into();
+        (Some(ref_path), ref_feat)
+    };
+
+    let subset = align.subset(ref_feat.schema())?;
+
+    if let Some(path) = &save_reference_landmarks {
+        write_landmarks(path, &ref_feat)?;
+    }
+
+    let template = match (&ref_path, refine) {
+        (Some(ref_path), true) => {
+            let (mut min, mut max) = (Vec2::splat(f32::INFINITY), Vec2::ZERO);
+            for &(x, y) in ref_feat.iter() {
+                min = min.min(Vec2::new(x as f32, y as f32));
+                max = max.max(Vec2::new(x as f32, y as f32));
+            }
+            let size = (max - min).max(Vec2::ONE);
+            let region = Rect::at(min.x as i32, min.y as i32).of_size(size.x as u32, size.y as u32);
+            let template = Template::new(&open_gray(ref_path)?, region)
+                .context("the reference face can't be used to refine the alignment")?;
+            Some(template)
+        }
+        _ => None,
+    };
+
+    let ref_eyes = if alignment == Alignment::EyeLine {
+        let eyes = eye_centers(&ref_feat)
+            .context("the eye-line alignment needs landmarks that locate the eyes")?;
+        Some(eyes)
+    } else {
+        None
+    };
+
+    // Compute the transforms in order, so they can be smoothed
+    let (paths, points): (Vec<_>, Vec<_>) = features
+        .into_iter()
+        .filter_map(|(img_path, img_feat)| {
+            let Some(img_feat) = select_face(&img_path, &img_feat, selection) else {
+                if interpolate_missing {
+                    warn!("{} does not have a face, interpolating", img_path.display());
+                    return Some(((img_path, false), None));
+                }
+                warn!("{} does not have a face, skipping", img_path.display());
+                return None;
+            };
+            if let Some(min_sharpness) = min_sharpness {
+                let sharpness = img_feat
+                    .sharpness
+                    .or_else(|| face_sharpness(&img_path, img_feat));
+                if sharpness.is_none_or(|sharpness| sharpness < min_sharpness) {
+                    if interpolate_missing {
+                        warn!("{} is blurry, interpolating", img_path.display());
+                        return Some(((img_path, false), None));
+                    }
+                    warn!("{} is blurry, skipping", img_path.display());
+                    return None;
+                }
+            }
+            if !is_frontal(&img_feat.landmarks, max_yaw, max_pitch) {
+                if interpolate_missing {
+                    warn!("{} has a turned head, interpolating", img_path.display());
+                    return Some(((img_path, false), None));
+                }
+                warn!("{} has a turned head, skipping", img_path.display());
+                return None;
+            }
+            let (target, mut points) =
+                visible_points(&ref_feat, &img_feat.landmarks, subset.as_deref());
+            // All the landmarks, to align consecutive images with the rolling reference
+            let mut shape = to_points(&img_feat.landmarks, subset.as_deref());
+            let mirrored = is_reflection(&target, &points).unwrap_or(false);
+            if mirrored && unmirror {
+                mirror(&mut points);
+                mirror(&mut shape);
+            } else if mirrored {
+                warn!(
+                    "{} looks mirrored, use --unmirror to flip it",
+                    img_path.display()
+                );
+            }
+            let mut outliers = reject_outliers
+                .and_then(|k| stabilizer::outlier_landmarks(&target, &points, k))
+                .unwrap_or_default();
+            if target.len() - outliers.len() < alignment.min_points() {
+                warn!(
+                    "{}: too many outlier landmarks ({} of {}), using all of them",
+                    img_path.display(),
+                    outliers.len(),
+                    target.len()
+                );
+                outliers.clear();
+            }
+            let (target, points) = if outliers.is_empty() {
+                (target, points)
+            } else {
+                debug!(
+                    "{}: ignoring {} outlier landmarks",
+                    img_path.display(),
+                    outliers.len()
+                );
+                let inliers = |points: Vec<Vec2>| -> Vec<Vec2> {
+                    points
+                        .into_iter()
+                        .enumerate()
+                        .filter(|(ix, _)| !outliers.contains(ix))
+                        .map(|(_, p)| p)
+                        .collect()
+                };
+                (inliers(target), inliers(points))
+            };
+            let (target, points) = match &ref_eyes {
+                Some(ref_eyes) => {
+                    let Some(mut eyes) = eye_centers(&img_feat.landmarks) else {
+                        warn!("{} does not have eyes, skipping", img_path.display());
+                        return None;
+                    };
+                    if mirrored && unmirror {
+                        mirror(&mut eyes);
+                    }
+                    (ref_eyes.clone(), eyes)
+                }
+                None => (target, points),
+            };
+            Some((
+                (img_path, mirrored && unmirror),
+                Some((target, points, shape)),
+            ))
+        })
+        .unzip();
+    let projections: Vec<Option<Projection>> = if align.smooth.is_some()
+        || interpolate_missing
+        || refine
+        || rolling.is_some()
+        || constrained
+    {
+        let similarities: Vec<_> = if let Some(interval) = rolling {
+            let shapes: Vec<_> = points
+                .into_iter()
+                .map(|points| points.map(|(_, _, shape)| shape).unwrap_or_default())
+                .collect();
+            let reference = Reference::Shape(to_points(&ref_feat, subset.as_deref()));
+            stabilizer::align_chained(&shapes, reference, interval)
+                .context("no image can be aligned to the reference")?
+                .into_iter()
+                .map(Some)
+                .collect()
+        } else {
+            let (targets, shapes): (Vec<_>, Vec<_>) = points
+                .into_iter()
+                .map(|points| {
+                    points
+                        .map(|(target, points, _)| (target, points))
+                        .unwrap_or_default()
+                })
+                .unzip();
+            let fit = Fit {
+                rotate: !no_rotation,
+                scale: !no_scale,
+            };
+            stabilizer::fit_sequence(&shapes, Reference::PerFrame(targets), fit)
+                .context("none of the images could be aligned")?
+        };
+        let similarities = match &template {
+            Some(template) => refine_alignments(template, &paths, similarities)?,
+            None => similarities,
+        };
+        stabilizer::smooth_sequence(&similarities, align.smoothing())
+            .context("none of the images could be aligned")?
+            .iter()
+            .map(|similarity| Some(similarity.to_projection()))
+            .collect()
+    } else {
+        paths
+            .iter()
+            .zip(points)
+            .map(|((img_path, _), points)| {
+                let (target, points, _) = points.expect("only missing when interpolating");
+                let proj = alignment.superimpose(target, points);
+                if proj.is_none() {
+                    // Too few visible landmarks, or all of them in the same place
+                    warn!("{} could not be aligned, skipping", img_path.display());
+                }
+                proj
+            })
+            .collect()
+    };
+    let mut features: Vec<_> = paths
+        .into_iter()
+        .zip(projections)
+        .filter_map(|((img_path, flip), proj)| {
+            let proj = proj?;
+            let proj = if flip {
+                Projection::scale(-1.0, 1.0).and_then(proj)
+            } else {
+                proj
+            };
+            Some((img_path, proj))
+        })
+        .collect();
+
+    // Move the reference face to where it should be in the output
+    let translate = |shift: Vec2| Projection::translate(shift.x, shift.y);
+    let placement = match (crop_face, eyes_at, output_size) {
+        (Some(size), _, _) => {
+            // Fit the bounding box of the face (and the margin) in the square
+            let points = to_points(&ref_feat, None);
+            let min = points
+                .iter()
+                .copied()
+                .reduce(Vec2::min)
+                .context("no landmarks")?;
+            let max = points
+                .iter()
+                .copied()
+                .reduce(Vec2::max)
+                .context("no landmarks")?;
+            let side = (max - min).max_element() * (1.0 + 2.0 * margin / 100.0);
+            ensure!(side > 0.0, "the reference face has no size");
+            let scale = size as f32 / side;
+            Some(
+                translate(-(min + max) / 2.0)
+                    .and_then(Projection::scale(scale, scale))
+                    .and_then(translate(Vec2::splat(size as f32 / 2.0))),
+            )
+        }
+        (None, Some(eyes_at), _) => {
+            let [left, right] = ref_feat
+                .eye_centers()
+                .context("--eyes-at needs landmarks that locate the eyes")?;
+            Some(translate(
+                eyes_at - (Vec2::from(left) + Vec2::from(right)) / 2.0,
+            ))
+        }
+        (None, None, Some((width, height))) => Some(translate(
+            Vec2::new(width as f32, height as f32) / 2.0 - centroid(&ref_feat),
+        )),
+        (None, None, None) => None,
+    };
+    let output_size = crop_face.map(|size| (size, size)).or(output_size);
+    if let Some(placement) = placement {
+        for (_, proj) in &mut features {
+            *proj = proj.and_then(placement);
+        }
+    }
+
+    // The reference has to be moved/cropped/zoomed like the rest
+    if let Some(ref_path) = ref_path {
+        if let Some(placement) = placement {
+            features.insert(0, (ref_path, placement));
+        } else if crop || auto_zoom || output_video.is_some() {
+            features.insert(0, (ref_path, Projection::translate(0.0, 0.0)));
+        } else {
+            let out = out_path(&ref_path);
+            let manifest = manifest.as_ref().expect("only missing for videos");
+            if manifest.is_up_to_date(&ref_path, &out) {
+                debug!("{} is up to date, skipping", out.display());
+            } else if quality.is_none() && out.extension() == ref_path.extension() {
+                if let Some(dir) = out.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+                std::fs::copy(&ref_path, &out)?;
+                manifest.record(&ref_path, &out)?;
+            } else {
+                let mut img = image::open(&ref_path)
+                    .with_context(|| format!("opening image {}", ref_path.display()))?;
+                if fill == Fill::Transparent {
+                    // Match the other (transparent) outputs
+                    img = img.into_rgba8().into();
+                }
+                save_image(&img, &out, quality)?;
+                if !strip_metadata {
+                    copy_metadata(&ref_path, &out)?;
+                }
+                manifest.record(&ref_path, &out)?;
+            }
+        }
+    }
+    let sizes = if crop || auto_zoom {
+        features
+            .iter()
+            .map(|(path, _)| {
+                image::image_dimensions(path)
+                    .with_context(|| format!("reading the size of {}", path.display()))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?
+    } else {
+        Vec::new()
+    };
+    let frames = |features: &[(PathBuf, Projection)]| -> Vec<_> {
+        features
+            .iter()
+            .zip(&sizes)
+            .map(|((_, proj), &size)| (*proj, size))
+            .collect()
+    };
+    if auto_zoom {
+        let center = centroid(&ref_feat);
+        let center = match placement {
+            Some(placement) => Vec2::from(placement * (center.x, center.y)),
+            None => center,
+        };
+        let zoom = stabilizer::auto_zoom(&frames(&features), center)
+            .context("the reference face is not covered by every image")?;
+        info!("zooming {}x", zoom.scale);
+        let zoom = zoom.to_projection();
+        for (_, proj) in &mut features {
+            *proj = proj.and_then(zoom);
+        }
+    }
+    let region = if crop {
+        let region = stabilizer::covered_region(&frames(&features))
+            .context("no region is covered by every image")?;
+        info!("cropping to {region:?}");
+        Some(region)
+    } else {
+        None
+    };
+    let crop = |img: image::DynamicImage| match region {
+        Some(region) => img.crop_imm(
+            region.left() as u32,
+            region.top() as u32,
+            region.width(),
+            region.height(),
+        ),
+        None => img,
+    };
+
+    use indicatif::*;
+    let style =
+        ProgressStyle::with_template("[{pos:>4}/{len:4}] {msg} {bar} [{per_sec} {eta_precise}]")
+            .expect("valid template");
+
+    if let Some(output_video) = output_video {
+        // The frames are encoded one after the other, in the order of the images
+        features.sort_by_key(|(path, _)| order.get(path).copied());
+        let mut encoder: Option<video::Encoder> = None;
+        let mut previous: Option<image::RgbImage> = None;
+        for (img_path, proj) in features.into_iter().progress_with_style(style) {
+            let img = image::open(&img_path)
+                .with_context(|| format!("opening image {}", img_path.display()))?
+                .into_rgb8();
+            let size = output_size.unwrap_or(img.dimensions());
+            let frame = crop(stabilizer::warp_to_size(
+                &img,
+                &proj,
+                fill,
+                interpolation,
+                size,
+            ))
+            .into_rgb8();
+            let encoder = match &mut encoder {
+                Some(encoder) => encoder,
+                None => encoder.insert(video::Encoder::create(
+                    &output_video,
+                    fps,
+                    frame.dimensions(),
+                )?),
+            };
+            let frame = video::fit(frame, encoder.size());
+            if let Some(previous) = &previous {
+                for step in 1..=crossfade {
+                    let t = step as f32 / (crossfade + 1) as f32;
+                    encoder.write(&video::blend(previous, &frame, t))?;
+                }
+            }
+            encoder.write(&frame)?;
+            previous = Some(frame);
+        }
+        return encoder.context("there are no images to encode")?.finish();
+    }
+
+    #[cfg(feature = "rayon")]
+    use rayon::prelude::*;
+    #[cfg(feature = "rayon")]
+    let features = features.into_par_iter();
+    #[cfg(not(feature = "rayon"))]
+    let features = features.into_iter();
+
+    let manifest = manifest.expect("only missing for videos");
+    features
+        .progress_with_style(style)
+        .map(|(img_path, proj)| {
+            let out = out_path(&img_path);
+            if manifest.is_up_to_date(&img_path, &out) {
+                debug!("{} is up to date, skipping", out.display());
+                return Ok(());
+            }
+            let img = image::open(&img_path)
+                .with_context(|| format!("opening image {}", img_path.display()))?
+                .into_rgb8();
+
+            let size = output_size.unwrap_or(img.dimensions());
+            let warped = crop(stabilizer::warp_to_size(
+                &img,
+                &proj,
+                fill,
+                interpolation,
+                size,
+            ));
+            save_image(&warped, &out, quality)?;
+            if !strip_metadata {
+                copy_metadata(&img_path, &out)?;
+            }
+            manifest.record(&img_path, &out)
+        })
+        .collect()
+}
+
+/// Open an image as (slightly blurred) grayscale, for the photometric refinement
+fn open_gray(path: &Path) -> anyhow::Result<GrayImage> {
+    let img = image::open(path)
+        .with_context(|| format!("opening image {}", path.display()))?
+        .into_luma8();
+    Ok(gaussian_blur_f32(&img, 1.0))
+}
+
+/// Refine the alignment of each image on the pixels of the reference face, keeping the alignment
+/// from the landmarks if it fails
+fn refine_alignments(
+    template: &Template,
+    paths: &[(PathBuf, bool)],
+    similarities: Vec<Option<SimilarityTransform>>,
+) -> anyhow::Result<Vec<Option<SimilarityTransform>>> {
+    info!("refining the alignment of {} images", paths.len());
+    let images: Vec<_> = paths.iter().zip(similarities).collect();
+    #[cfg(feature = "rayon")]
+    use rayon::prelude::*;
+    #[cfg(feature = "rayon")]
+    let images = images.into_par_iter();
+    #[cfg(not(feature = "rayon"))]
+    let images = images.into_iter();
+    images
+        .map(|((path, flip), similarity)| {
+            let Some(similarity) = similarity else {
+                return Ok(None);
+            };
+            if *flip {
+                debug!("{}: not refining mirrored images", path.display());
+                return Ok(Some(similarity));
+            }
+            let refined = template.refine(&open_gray(path)?, similarity);
+            Ok(Some(refined.unwrap_or_else(|| {
+                warn!("{} could not be refined", path.display());
+                similarity
+            })))
+        })
+        .collect()
+}
+
+/// The [`landmark_extractor::sharpness`] of the face in the image at `path`
+fn face_sharpness(path: &Path, face: &Face) -> Option<f32> {
+    match image::open(path) {
+        Ok(img) => landmark_extractor::sharpness(&img.into_rgb8(), &face.rect),
+        Err(err) => {
+            warn!("opening image {}: {err}", path.display());
+            None
+        }
+    }
+}
+
+/// Name of the [`ResumeManifest`] in the output directory
+const RESUME_MANIFEST: &str = ".face-stabilizer-resume.csv";
+
+/// The size and modification time (nanoseconds since the epoch) of the image each output was made
+/// from, so `--resume` notices images that were replaced or edited since
+struct ResumeManifest {
+    /// What the previous runs recorded, by output
+    previous: HashMap<PathBuf, (u64, u128)>,
+    writer: std::sync::Mutex<csv::Writer<std::fs::File>>,
+}
+
+impl ResumeManifest {
+    /// Read the manifest in `output_dir` if resuming and start recording this run in it
+    fn open(output_dir: &Path, resume: bool) -> anyhow::Result<Self> {
+        let path = output_dir.join(RESUME_MANIFEST);
+        let context = || format!("opening the manifest {}", path.display());
+        let mut previous = HashMap::new();
+        if resume && path.exists() {
+            let mut reader = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .from_path(&path)
+                .with_context(context)?;
+            for record in reader.deserialize() {
+                // An interrupted run can leave the last line incomplete
+                let Ok((output, len, modified)) = record else {
+                    continue;
+                };
+                previous.insert(output, (len, modified));
+            }
+        }
+        let file = std::fs::File::create(&path).with_context(context)?;
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(file);
+        // Keep the outputs of the previous runs
+        for (output, (len, modified)) in &previous {
+            writer
+                .serialize((output, len, modified))
+                .context("writing the resume manifest")?;
+        }
+        writer.flush().context("writing the resume manifest")?;
+        Ok(Self {
+            previous,
+            writer: writer.into(),
+        })
+    }
+
+    /// Whether `output` exists, isn't empty (left behind by an interrupted write) and was made
+    /// from `source` as it is now
+    fn is_up_to_date(&self, source: &Path, output: &Path) -> bool {
+        let written = std::fs::metadata(output).is_ok_and(|metadata| metadata.len() > 0);
+        written && stamp(source).is_some_and(|stamp| self.previous.get(output) == Some(&stamp))
+    }
+
+    /// Record that `output` was made from `source`
+    fn record(&self, source: &Path, output: &Path) -> anyhow::Result<()> {
+        let Some((len, modified)) = stamp(source) else {
+            return Ok(());
+        };
+        let mut writer = self
+            .writer
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        writer
+            .serialize((output, len, modified))
+            .context("writing the resume manifest")?;
+        writer.flush().context("writing the resume manifest")
+    }
+}
+
+/// The size and modification time (nanoseconds since the epoch) of a file
+fn stamp(path: &Path) -> Option<(u64, u128)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?;
+    let modified = modified.duration_since(std::time::UNIX_EPOCH).ok()?;
+    Some((metadata.len(), modified.as_nanos()))
+}
+
+/// The center of mass of the landmarks
+fn centroid(landmarks: &Landmarks) -> Vec2 {
+    to_points(landmarks, None).into_iter().sum::<Vec2>() / landmarks.len() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn resume_manifest() {
+        let dir =
+            std::env::temp_dir().join(format!("face-stabilizer-resume-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.jpg"), dir.join("b.jpg"));
+        let (out_a, out_b) = (dir.join("out-a.jpg"), dir.join("out-b.jpg"));
+        for path in [&a, &b, &out_a, &out_b] {
+            std::fs::write(path, "image").unwrap();
+        }
+
+        let manifest = ResumeManifest::open(&dir, true).unwrap();
+        assert!(!manifest.is_up_to_date(&a, &out_a));
+        manifest.record(&a, &out_a).unwrap();
+        drop(manifest);
+        // An interrupted run leaves the last line incomplete
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(dir.join(RESUME_MANIFEST))
+            .unwrap();
+        write!(file, "{},5", out_b.display()).unwrap();
+        drop(file);
+
+        // The earlier entries are kept while the new ones are recorded
+        let manifest = ResumeManifest::open(&dir, true).unwrap();
+        assert!(manifest.is_up_to_date(&a, &out_a));
+        assert!(!manifest.is_up_to_date(&b, &out_b));
+        manifest.record(&b, &out_b).unwrap();
+        drop(manifest);
+        let manifest = ResumeManifest::open(&dir, true).unwrap();
+        assert!(manifest.is_up_to_date(&a, &out_a));
+        assert!(manifest.is_up_to_date(&b, &out_b));
+        drop(manifest);
+
+        // A changed size, modification time or an empty output are redone
+        std::fs::write(&a, "edited image").unwrap();
+        let modified = std::fs::metadata(&b).unwrap().modified().unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&b)
+            .unwrap()
+            .set_modified(modified + Duration::from_secs(1))
+            .unwrap();
+        let manifest = ResumeManifest::open(&dir, true).unwrap();
+        assert!(!manifest.is_up_to_date(&a, &out_a));
+        assert!(!manifest.is_up_to_date(&b, &out_b));
+        manifest.record(&b, &out_b).unwrap();
+        assert!(ResumeManifest::open(&dir, true)
+            .unwrap()
+            .is_up_to_date(&b, &out_b));
+        std::fs::write(&out_b, "").unwrap();
+        assert!(!ResumeManifest::open(&dir, true)
+            .unwrap()
+            .is_up_to_date(&b, &out_b));
+
+        // Without --resume everything is redone
+        std::fs::write(&out_b, "image").unwrap();
+        assert!(!ResumeManifest::open(&dir, false)
+            .unwrap()
+            .is_up_to_date(&b, &out_b));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
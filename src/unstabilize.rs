@@ -0,0 +1,104 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::Args;
+use image::DynamicImage;
+use log::warn;
+use stabilizer::Fill;
+use stabilizer::Interpolation;
+
+use crate::export_transforms::read_transforms;
+use crate::output::common_dir;
+use crate::output::save_image;
+
+/// Options of the unstabilize subcommand
+#[derive(Debug, Args)]
+pub struct UnstabilizeOpts {
+    /// Path to the transforms file the aligned images were made with (see export-transforms)
+    transforms: PathBuf,
+    /// Directory with the edited aligned images, with the paths apply-transforms gave them
+    aligned_dir: PathBuf,
+    /// Directory where to place the original images with the edits
+    ///
+    /// The images keep their paths relative to the directory that contains all of them
+    #[arg(short, long, default_value = "./unstabilized")]
+    output_dir: PathBuf,
+    /// Only write the edited areas (as transparent PNGs) instead of compositing them onto the
+    /// original images
+    #[arg(long)]
+    no_composite: bool,
+    /// How to sample the aligned images when warping them back: nearest (fastest), bilinear or
+    /// bicubic (sharpest)
+    #[arg(long, default_value = "bicubic")]
+    interpolation: Interpolation,
+    /// Quality of the jpg and webp outputs (1-100), lower values give smaller files
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: Option<u8>,
+}
+
+pub fn run(opts: UnstabilizeOpts) -> anyhow::Result<()> {
+    use indicatif::*;
+
+    let UnstabilizeOpts {
+        transforms,
+        aligned_dir,
+        output_dir,
+        no_composite,
+        interpolation,
+        quality,
+    } = opts;
+    let transforms = read_transforms(&transforms)?;
+    let root = common_dir(transforms.iter().map(|(path, _)| path.as_path()));
+
+    let style =
+        ProgressStyle::with_template("[{pos:>4}/{len:4}] {msg} {bar} [{per_sec} {eta_precise}]")
+            .expect("valid template");
+    #[cfg(feature = "rayon")]
+    use rayon::prelude::*;
+    #[cfg(feature = "rayon")]
+    let transforms = transforms.into_par_iter();
+    #[cfg(not(feature = "rayon"))]
+    let transforms = transforms.into_iter();
+    transforms
+        .progress_with_style(style)
+        .try_for_each(|(path, projection)| -> anyhow::Result<()> {
+            let relative = path
+                .strip_prefix(&root)
+                .unwrap_or_else(|_| Path::new(path.file_name().expect("valid file name")));
+            // Transparent fills turn the aligned images into PNGs
+            let aligned = [
+                aligned_dir.join(relative),
+                aligned_dir.join(relative).with_extension("png"),
+            ]
+            .into_iter()
+            .find(|aligned| aligned.is_file());
+            let Some(aligned) = aligned else {
+                warn!("{} has no aligned image, skipping", path.display());
+                return Ok(());
+            };
+            let edited = image::open(&aligned)
+                .with_context(|| format!("opening image {}", aligned.display()))?
+                .into_rgb8();
+            let size = image::image_dimensions(&path)
+                .with_context(|| format!("reading the size of {}", path.display()))?;
+            let edits = stabilizer::warp_to_size(
+                &edited,
+                &projection.invert(),
+                Fill::Transparent,
+                interpolation,
+                size,
+            );
+            let mut out = output_dir.join(relative);
+            if no_composite {
+                out.set_extension("png");
+                return save_image(&edits, &out, None);
+            }
+            let mut original = image::open(&path)
+                .with_context(|| format!("opening image {}", path.display()))?
+                .into_rgba8();
+            image::imageops::overlay(&mut original, &edits.into_rgba8(), 0, 0);
+            let composite = DynamicImage::ImageRgba8(original).into_rgb8();
+            save_image(&DynamicImage::ImageRgb8(composite), &out, quality)
+        })
+}
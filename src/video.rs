@@ -0,0 +1,245 @@
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::process::Child;
+use std::process::Command;
+use std::process::Stdio;
+
+use anyhow::ensure;
+use anyhow::Context;
+use image::RgbImage;
+
+/// Size of the frames of the first video stream of `path` (uses `ffprobe`)
+///
+/// ffmpeg applies the rotation of the video (e.g. filmed in portrait with a phone) while decoding
+/// it, so the width and height are swapped for quarter turns.
+pub fn video_size(path: &Path) -> anyhow::Result<(u32, u32)> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-select_streams", "v:0", "-of", "json"])
+        .args([
+            "-show_entries",
+            "stream=width,height:stream_tags=rotate:stream_side_data=rotation",
+        ])
+        .arg(path)
+        .output()
+        .context("running ffprobe (is ffmpeg installed?)")?;
+    ensure!(
+        output.status.success(),
+        "ffprobe failed on {}: {}",
+        path.display(),
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    let probe: serde_json::Value =
+        serde_json::from_slice(&output.stdout).context("parsing the output of ffprobe")?;
+    probed_size(&probe).with_context(|| {
+        format!(
+            "{} has no video stream (ffprobe said {probe})",
+            path.display()
+        )
+    })
+}
+
+/// Size of the decoded frames of the stream in the JSON output of `ffprobe`
+fn probed_size(probe: &serde_json::Value) -> Option<(u32, u32)> {
+    let stream = probe.get("streams")?.get(0)?;
+    let width = u32::try_from(stream.get("width")?.as_u64()?).ok()?;
+    let height = u32::try_from(stream.get("height")?.as_u64()?).ok()?;
+    // The display matrix (or the rotate tag of older versions of ffmpeg)
+    let rotation = stream
+        .get("side_data_list")
+        .and_then(|side_data| {
+            side_data
+                .as_array()?
+                .iter()
+                .find_map(|data| data.get("rotation")?.as_f64())
+        })
+        .or_else(|| stream.get("tags")?.get("rotate")?.as_str()?.parse().ok())
+        .unwrap_or(0.0);
+    if (rotation.rem_euclid(180.0) - 90.0).abs() < 1.0 {
+        Some((height, width))
+    } else {
+        Some((width, height))
+    }
+}
+
+/// The frames of a video, decoded by an `ffmpeg` subprocess
+pub struct Frames {
+    ffmpeg: Child,
+    width: u32,
+    height: u32,
+}
+
+impl Frames {
+    /// Start decoding the video at `path`
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let (width, height) = video_size(path)?;
+        let ffmpeg = Command::new("ffmpeg")
+            .args(["-v", "error", "-i"])
+            .arg(path)
+            .args(["-map", "0:v:0", "-f", "rawvideo", "-pix_fmt", "rgb24", "-"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("running ffmpeg (is it installed?)")?;
+        Ok(Self {
+            ffmpeg,
+            width,
+            height,
+        })
+    }
+}
+
+impl Iterator for Frames {
+    type Item = anyhow::Result<RgbImage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let stdout = self.ffmpeg.stdout.as_mut()?;
+        let mut buf = vec![0; self.width as usize * self.height as usize * 3];
+        match stdout.read_exact(&mut buf) {
+            Ok(()) => {
+                let frame = RgbImage::from_raw(self.width, self.height, buf)
+                    .expect("the buffer has the size of a frame");
+                Some(Ok(frame))
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+                // Close the pipe and check that ffmpeg finished decoding the video
+                self.ffmpeg.stdout = None;
+                match self.ffmpeg.wait() {
+                    Ok(status) if status.success() => None,
+                    Ok(status) => Some(Err(anyhow::anyhow!("ffmpeg failed ({status})"))),
+                    Err(err) => Some(Err(err).context("waiting for ffmpeg")),
+                }
+            }
+            Err(err) => Some(Err(err).context("reading a frame from ffmpeg")),
+        }
+    }
+}
+
+impl Drop for Frames {
+    fn drop(&mut self) {
+        // Stop decoding if the frames weren't all read
+        if self.ffmpeg.stdout.take().is_some() {
+            let _ = self.ffmpeg.kill();
+        }
+        let _ = self.ffmpeg.wait();
+    }
+}
+
+/// Encodes frames into a video with an `ffmpeg` subprocess
+pub struct Encoder {
+    ffmpeg: Child,
+    width: u32,
+    height: u32,
+}
+
+impl Encoder {
+    /// Start encoding a video of `(width, height)` frames at `fps` into `path`, the format is
+    /// chosen by its extension (e.g. mp4 or webm)
+    pub fn create(path: &Path, fps: f32, (width, height): (u32, u32)) -> anyhow::Result<Self> {
+        let ffmpeg = Command::new("ffmpeg")
+            .args(["-v", "error", "-y", "-f", "rawvideo", "-pix_fmt", "rgb24"])
+            .args(["-s", &format!("{width}x{height}"), "-r", &fps.to_string()])
+            .args(["-i", "-"])
+            // Most players need even sizes for yuv420p
+            .args([
+                "-vf",
+                "pad=ceil(iw/2)*2:ceil(ih/2)*2",
+                "-pix_fmt",
+                "yuv420p",
+            ])
+            .arg(path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .context("running ffmpeg (is it installed?)")?;
+        Ok(Self {
+            ffmpeg,
+            width,
+            height,
+        })
+    }
+
+    /// Size of the frames
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Encode the next frame, it must be [`Encoder::size`]
+    pub fn write(&mut self, frame: &RgbImage) -> anyhow::Result<()> {
+        ensure!(
+            frame.dimensions() == self.size(),
+            "the frame is {:?}, not {:?}",
+            frame.dimensions(),
+            self.size()
+        );
+        let stdin = self.ffmpeg.stdin.as_mut().expect("only closed by finish");
+        stdin
+            .write_all(frame.as_raw())
+            .context("writing a frame to ffmpeg")
+    }
+
+    /// Finish encoding the video
+    pub fn finish(mut self) -> anyhow::Result<()> {
+        drop(self.ffmpeg.stdin.take());
+        let status = self.ffmpeg.wait().context("waiting for ffmpeg")?;
+        ensure!(status.success(), "ffmpeg failed ({status})");
+        Ok(())
+    }
+}
+
+impl Drop for Encoder {
+    fn drop(&mut self) {
+        // Stop encoding if the video wasn't finished
+        if self.ffmpeg.stdin.take().is_some() {
+            let _ = self.ffmpeg.kill();
+        }
+        let _ = self.ffmpeg.wait();
+    }
+}
+
+/// Center `frame` in a black `(width, height)` frame (cropping it if it is larger)
+pub fn fit(frame: RgbImage, (width, height): (u32, u32)) -> RgbImage {
+    if frame.dimensions() == (width, height) {
+        return frame;
+    }
+    let mut canvas = RgbImage::new(width, height);
+    let x = (i64::from(width) - i64::from(frame.width())) / 2;
+    let y = (i64::from(height) - i64::from(frame.height())) / 2;
+    image::imageops::overlay(&mut canvas, &frame, x, y);
+    canvas
+}
+
+/// Mix two frames of the same size, `t = 0` gives `from` and `t = 1` gives `to`
+pub fn blend(from: &RgbImage, to: &RgbImage, t: f32) -> RgbImage {
+    let mut out = from.clone();
+    for (out, to) in out.iter_mut().zip(to.iter()) {
+        *out = (f32::from(*out) * (1.0 - t) + f32::from(*to) * t).round() as u8;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(extra: &str) -> serde_json::Value {
+        let probe = format!(r#"{{"streams":[{{"width":1920,"height":1080{extra}}}]}}"#);
+        serde_json::from_str(&probe).unwrap()
+    }
+
+    #[test]
+    fn rotated_videos_swap_the_size() {
+        assert_eq!(probed_size(&stream("")), Some((1920, 1080)));
+        let rotated = |rotation: i32| {
+            stream(&format!(
+                r#","side_data_list":[{{"side_data_type":"Display Matrix","rotation":{rotation}}}]"#
+            ))
+        };
+        assert_eq!(probed_size(&rotated(-90)), Some((1080, 1920)));
+        assert_eq!(probed_size(&rotated(90)), Some((1080, 1920)));
+        assert_eq!(probed_size(&rotated(180)), Some((1920, 1080)));
+        let tagged = stream(r#","tags":{"rotate":"270"}"#);
+        assert_eq!(probed_size(&tagged), Some((1080, 1920)));
+        let empty = serde_json::json!({ "streams": [] });
+        assert_eq!(probed_size(&empty), None);
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use clap::Args;
+use imageproc::geometric_transformations::Projection;
+use landmark_extractor::FaceSelection;
+use landmark_extractor::Faces;
+use log::info;
+use log::warn;
+use stabilizer::Alignment;
+use stabilizer::Fill;
+use stabilizer::Interpolation;
+
+use crate::faces::eye_centers;
+use crate::faces::select_face;
+use crate::faces::visible_points;
+use crate::features::read_features;
+use crate::features::write_features;
+use crate::features::Features;
+use crate::output::save_image;
+use crate::ExtractorOpts;
+
+/// Options of the watch subcommand
+#[derive(Debug, Args)]
+pub struct WatchOpts {
+    #[command(flatten)]
+    pub extractor: ExtractorOpts,
+    /// Directory where the new images will appear
+    image_dir: PathBuf,
+    /// Path to the features file, created if it doesn't exist
+    #[arg(short, long, default_value = "landmarks.ron")]
+    features: PathBuf,
+    /// Whether to pretty print the features file
+    #[arg(short, long)]
+    pretty: bool,
+    /// Directory where to place the transformed images
+    #[arg(short, long, default_value = "./out")]
+    output_dir: PathBuf,
+    /// Align the faces to the one in this image instead of the first one (by name) in the
+    /// features file
+    #[arg(short, long)]
+    reference: Option<PathBuf>,
+    /// Which face to stabilize in images with several of them: largest, most-central,
+    /// most-confident or index:<n> (in the order they were detected)
+    #[arg(
+        long = "select-face",
+        value_name = "SELECTION",
+        default_value = "largest"
+    )]
+    selection: FaceSelection,
+    /// How to align the faces (see the transform subcommand)
+    #[arg(short, long, default_value = "similarity")]
+    alignment: Alignment,
+    /// How to fill the borders uncovered by the alignment (see the transform subcommand)
+    #[arg(long, default_value = "constant")]
+    fill: Fill,
+    /// How to sample the images when warping them: nearest (fastest), bilinear or bicubic
+    /// (sharpest)
+    #[arg(long, default_value = "bicubic")]
+    interpolation: Interpolation,
+}
+
+/// How long a new file must stay untouched before processing it, so it is completely written
+const SETTLE_TIME: std::time::Duration = std::time::Duration::from_secs(1);
+
+pub fn run(opts: WatchOpts) -> anyhow::Result<()> {
+    use notify::Watcher;
+
+    let WatchOpts {
+        extractor,
+        image_dir,
+        features: features_path,
+        pretty,
+        output_dir,
+        reference,
+        selection,
+        alignment,
+        fill,
+        interpolation,
+    } = opts;
+    ensure!(
+        image_dir.is_dir(),
+        "{} is not a directory",
+        image_dir.display()
+    );
+    std::fs::create_dir_all(&output_dir)
+        .with_context(|| format!("creating {} directory", output_dir.display()))?;
+    let mut features = if features_path.exists() {
+        read_features(&features_path)?
+    } else {
+        Features::new()
+    };
+    let extractor = extractor.build()?;
+    let extract = |path: &Path| -> anyhow::Result<Faces> {
+        let img = image::open(path)
+            .with_context(|| format!("failed to open {}", path.display()))?
+            .into_rgb8();
+        extractor
+            .extract_image(&img)
+            .with_context(|| format!("extracting landmarks from {}", path.display()))
+    };
+
+    // The reference landmarks, the first new image is the reference if there is none yet
+    let reference = match reference {
+        Some(path) => {
+            let faces = match features.get(&path) {
+                Some(faces) => faces.clone(),
+                None => extract(&path)?,
+            };
+            Some((path, faces))
+        }
+        None => features
+            .iter()
+            .min_by(|a, b| a.0.cmp(b.0))
+            .map(|(path, faces)| (path.clone(), faces.clone())),
+    };
+    let mut reference = match reference {
+        Some((path, faces)) => {
+            let face = select_face(&path, &faces, selection)
+                .with_context(|| format!("the reference image {} has no face", path.display()))?;
+            Some(face.landmarks.clone())
+        }
+        None => None,
+    };
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).context("creating the file watcher")?;
+    watcher
+        .watch(&image_dir, notify::RecursiveMode::NonRecursive)
+        .with_context(|| format!("watching {}", image_dir.display()))?;
+    info!("watching {} for new images", image_dir.display());
+
+    let stabilize = |path: &Path, proj: &Projection| -> anyhow::Result<()> {
+        let img = image::open(path)
+            .with_context(|| format!("opening image {}", path.display()))?
+            .into_rgb8();
+        let warped = stabilizer::warp(&img, proj, fill, interpolation);
+        let mut out = output_dir.join(path.file_name().expect("valid file name"));
+        if fill == Fill::Transparent {
+            out.set_extension("png");
+        }
+        save_image(&warped, &out, None)
+    };
+
+    // New files and the last time they changed
+    let mut pending: HashMap<PathBuf, std::time::Instant> = HashMap::new();
+    loop {
+        match receiver.recv_timeout(SETTLE_TIME) {
+            Ok(event) => {
+                let event = event.context("watching for new images")?;
+                if event.kind.is_create() || event.kind.is_modify() {
+                    for path in event.paths {
+                        if path.is_file() && !features.contains_key(&path) {
+                            pending.insert(path, std::time::Instant::now());
+                        }
+                    }
+                }
+            }
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+                bail!("stopped watching {}", image_dir.display())
+            }
+        }
+        let mut ready: Vec<_> = pending
+            .iter()
+            .filter(|(_, changed)| changed.elapsed() >= SETTLE_TIME)
+            .map(|(path, _)| path.clone())
+            .collect();
+        ready.sort();
+        for path in ready {
+            pending.remove(&path);
+            let faces = match extract(&path) {
+                Ok(faces) => faces,
+                Err(err) => {
+                    warn!("skipping {}: {err:#}", path.display());
+                    continue;
+                }
+            };
+            info!("found {} faces in {}", faces.len(), path.display());
+            if let Some(face) = select_face(&path, &faces, selection) {
+                let target = reference.get_or_insert_with(|| face.landmarks.clone());
+                let points = if alignment == Alignment::EyeLine {
+                    eye_centers(target).zip(eye_centers(&face.landmarks))
+                } else {
+                    Some(visible_points(target, &face.landmarks, None))
+                };
+                match points.and_then(|(target, points)| alignment.superimpose(target, points)) {
+                    Some(proj) => {
+                        if let Err(err) = stabilize(&path, &proj) {
+                            warn!("skipping {}: {err:#}", path.display());
+                        }
+                    }
+                    None => warn!("{} can't be aligned, skipping", path.display()),
+                }
+            } else {
+                warn!("{} does not have a face, skipping", path.display());
+            }
+            features.insert(path, faces);
+            write_features(&features_path, &features, pretty)?;
+        }
+    }
+}
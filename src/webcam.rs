@@ -0,0 +1,135 @@
+use anyhow::Context;
+use clap::Args;
+use image::RgbImage;
+use landmark_extractor::FaceSelection;
+use landmark_extractor::Landmarks;
+use landmark_extractor::Rect;
+use minifb::Key;
+use minifb::KeyRepeat;
+use minifb::Window;
+use minifb::WindowOptions;
+use nokhwa::pixel_format::RgbFormat;
+use nokhwa::utils::CameraIndex;
+use nokhwa::utils::RequestedFormat;
+use nokhwa::utils::RequestedFormatType;
+use nokhwa::Camera;
+use stabilizer::constrained_superimposition;
+use stabilizer::smoothing::SimilarityFilter;
+use stabilizer::smoothing::DEFAULT_BETA;
+use stabilizer::smoothing::DEFAULT_MIN_CUTOFF;
+use stabilizer::Fill;
+use stabilizer::Interpolation;
+
+use crate::faces::visible_points;
+use crate::ExtractorOpts;
+
+/// Options of the webcam subcommand
+#[derive(Debug, Args)]
+pub struct WebcamOpts {
+    #[command(flatten)]
+    pub extractor: ExtractorOpts,
+    /// Index of the camera to open
+    #[arg(long, default_value_t = 0)]
+    camera: u32,
+    /// Which face to stabilize if there are several of them: largest, most-central,
+    /// most-confident or index:<n> (in the order they were detected)
+    #[arg(
+        long = "select-face",
+        value_name = "SELECTION",
+        default_value = "largest"
+    )]
+    selection: FaceSelection,
+    /// How to fill the borders uncovered by the alignment (see the transform subcommand)
+    #[arg(long, default_value = "constant")]
+    fill: Fill,
+    /// How to sample the frames when warping them: nearest (fastest), bilinear or bicubic
+    /// (sharpest)
+    #[arg(long, default_value = "bilinear")]
+    interpolation: Interpolation,
+    /// Minimum cutoff frequency of the smoothing (in cycles per frame), lower values reduce the
+    /// jitter
+    #[arg(long, default_value_t = DEFAULT_MIN_CUTOFF)]
+    smooth_cutoff: f32,
+    /// Speed coefficient of the smoothing, higher values reduce the lag
+    #[arg(long, default_value_t = DEFAULT_BETA)]
+    smooth_beta: f32,
+}
+
+/// Show the stabilized camera stream in a window until it is closed (or escape is pressed)
+///
+/// The first face seen is the reference, press space to use the current one instead.
+pub fn run(opts: WebcamOpts) -> anyhow::Result<()> {
+    let WebcamOpts {
+        extractor,
+        camera,
+        selection,
+        fill,
+        interpolation,
+        smooth_cutoff,
+        smooth_beta,
+    } = opts;
+    let extractor = extractor.build()?;
+    let format = RequestedFormat::new::<RgbFormat>(RequestedFormatType::AbsoluteHighestFrameRate);
+    let mut camera = Camera::new(CameraIndex::Index(camera), format)
+        .with_context(|| format!("opening camera {camera}"))?;
+    camera.open_stream().context("starting the camera stream")?;
+    let resolution = camera.resolution();
+    let (width, height) = (resolution.width() as usize, resolution.height() as usize);
+    let mut window = Window::new(
+        "face-stabilizer (space: new reference, escape: quit)",
+        width,
+        height,
+        WindowOptions::default(),
+    )
+    .context("opening a window")?;
+
+    let mut reference: Option<Landmarks> = None;
+    let mut roi: Option<Rect> = None;
+    let mut filter = SimilarityFilter::new(smooth_cutoff, smooth_beta);
+    let mut last = None;
+    let mut buffer = Vec::new();
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        if window.is_key_pressed(Key::Space, KeyRepeat::No) {
+            reference = None;
+            filter = SimilarityFilter::new(smooth_cutoff, smooth_beta);
+        }
+        let frame: RgbImage = camera
+            .frame()
+            .context("capturing a frame")?
+            .decode_image::<RgbFormat>()
+            .context("decoding a frame")?;
+        let faces = match &roi {
+            Some(roi) => extractor.extract_in_roi(&frame, roi),
+            None => extractor.extract_image(&frame),
+        }
+        .context("extracting landmarks")?;
+        let face = faces.select(selection, frame.dimensions());
+        roi = face.map(|face| face.rect.clone());
+        if let Some(face) = face {
+            let target = reference.get_or_insert_with(|| face.landmarks.clone());
+            let (target, points) = visible_points(target, &face.landmarks, None);
+            if let Some(similarity) = constrained_superimposition(target, points, true, true) {
+                last = Some(filter.filter(similarity));
+            }
+        }
+        // Keep the last alignment while the face is lost
+        let shown = match &last {
+            Some(similarity) => {
+                let projection = similarity.to_projection();
+                stabilizer::warp(&frame, &projection, fill, interpolation).into_rgb8()
+            }
+            None => frame,
+        };
+
+        buffer.clear();
+        buffer.extend(
+            shown
+                .pixels()
+                .map(|&image::Rgb([r, g, b])| u32::from_be_bytes([0, r, g, b])),
+        );
+        window
+            .update_with_buffer(&buffer, shown.width() as usize, shown.height() as usize)
+            .context("showing the frame")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,318 @@
+use image::DynamicImage;
+use image::RgbImage;
+use image::Rgba;
+use image::RgbaImage;
+
+/// Default number of standard deviations from the mean beyond which [`sigma_clipped_mean`]
+/// rejects a pixel
+pub const DEFAULT_CLIP_SIGMA: f32 = 2.0;
+/// Maximum number of rejection rounds of [`sigma_clipped_mean`]
+const CLIP_ITERATIONS: usize = 5;
+
+/// How to combine a stack of images into one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stacking {
+    /// Weighted mean, see [`MeanImage`]
+    Mean,
+    /// Weighted median, see [`median_image`]
+    Median,
+    /// Weighted mean without the outliers, see [`sigma_clipped_mean`]
+    SigmaClip { sigma: f32 },
+}
+
+impl Stacking {
+    /// Combine the `(image, weight)`s
+    ///
+    /// Returns [`None`] if there are no images or they don't have the same size
+    pub fn stack(&self, images: &[(RgbaImage, f32)]) -> Option<DynamicImage> {
+        match *self {
+            Self::Mean => {
+                let (first, _) = images.first()?;
+                let mut mean = MeanImage::new(first.width(), first.height());
+                for (image, weight) in images {
+                    mean.add(&DynamicImage::ImageRgba8(image.clone()), *weight)?;
+                }
+                Some(mean.mean())
+            }
+            Self::Median => median_image(images),
+            Self::SigmaClip { sigma } => sigma_clipped_mean(images, sigma),
+        }
+    }
+}
+
+/// Parses `mean`, `median` or `sigma-clip` (with the [`DEFAULT_CLIP_SIGMA`])
+impl std::str::FromStr for Stacking {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mean" => Ok(Self::Mean),
+            "median" => Ok(Self::Median),
+            "sigma-clip" => Ok(Self::SigmaClip {
+                sigma: DEFAULT_CLIP_SIGMA,
+            }),
+            _ => Err(format!("expected mean, median or sigma-clip, found {s}")),
+        }
+    }
+}
+
+/// Build an image from the color of each pixel (by index), the pixels without one are
+/// transparent (the image only has an alpha channel if there are any)
+fn from_pixels(width: u32, height: u32, pixel: impl Fn(usize) -> Option<[u8; 3]>) -> DynamicImage {
+    let pixels: Vec<_> = (0..width as usize * height as usize).map(pixel).collect();
+    if pixels.iter().all(Option::is_some) {
+        let raw = pixels.into_iter().flatten().flatten().collect();
+        DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, raw).expect("right size"))
+    } else {
+        let raw = pixels
+            .into_iter()
+            .flat_map(|pixel| match pixel {
+                Some([r, g, b]) => [r, g, b, 255],
+                None => [0; 4],
+            })
+            .collect();
+        DynamicImage::ImageRgba8(RgbaImage::from_raw(width, height, raw).expect("right size"))
+    }
+}
+
+/// A color and its weight
+type Sample = ([f32; 3], f32);
+
+/// The size of the `images`
+///
+/// Returns [`None`] if there are no images or they don't have the same size
+fn stack_size(images: &[(RgbaImage, f32)]) -> Option<(u32, u32)> {
+    let (first, _) = images.first()?;
+    let size = first.dimensions();
+    images
+        .iter()
+        .all(|(image, _)| image.dimensions() == size)
+        .then_some(size)
+}
+
+/// The samples of pixel `ix` of the `images`, their weights are scaled by their alpha
+fn pixel_samples(images: &[(RgbaImage, f32)], ix: usize) -> Vec<Sample> {
+    images
+        .iter()
+        .filter_map(|(image, weight)| {
+            let [r, g, b, a] = image.as_raw()[4 * ix..4 * ix + 4] else {
+                unreachable!("pixels have 4 channels")
+            };
+            let weight = weight * f32::from(a) / 255.0;
+            (weight > 0.0).then_some(([r, g, b].map(f32::from), weight))
+        })
+        .collect()
+}
+
+/// Round a color to 8 bits
+fn to_u8(color: [f32; 3]) -> [u8; 3] {
+    color.map(|c| c.round().clamp(0.0, 255.0) as u8)
+}
+
+/// Per-channel weighted median of a stack of (aligned) images, ignores the elements that only
+/// appear in a few of them (glasses worn occasionally, hands in frame)
+///
+/// Needs all the images in memory, transparent pixels don't count (like in [`MeanImage`]).
+///
+/// Returns [`None`] if there are no images or they don't have the same size
+pub fn median_image(images: &[(RgbaImage, f32)]) -> Option<DynamicImage> {
+    let (width, height) = stack_size(images)?;
+    Some(from_pixels(width, height, |ix| {
+        let mut samples = pixel_samples(images, ix);
+        let half = samples.iter().map(|(_, weight)| weight).sum::<f32>() / 2.0;
+        if half <= 0.0 {
+            return None;
+        }
+        let median = [0, 1, 2].map(|c| {
+            samples.sort_by(|a, b| a.0[c].total_cmp(&b.0[c]));
+            let mut cumulative = 0.0;
+            samples
+                .iter()
+                .find(|(_, weight)| {
+                    cumulative += weight;
+                    cumulative >= half
+                })
+                .map_or(0.0, |(color, _)| color[c])
+        });
+        Some(to_u8(median))
+    }))
+}
+
+/// Weighted mean of a stack of (aligned) images after rejecting, for each pixel, the colors
+/// further than `sigma` standard deviations from the mean (repeatedly)
+///
+/// Cleaner than the [`MeanImage`] and smoother than the [`median_image`], needs all the images
+/// in memory too.
+///
+/// Returns [`None`] if there are no images or they don't have the same size
+pub fn sigma_clipped_mean(images: &[(RgbaImage, f32)], sigma: f32) -> Option<DynamicImage> {
+    let (width, height) = stack_size(images)?;
+    let mean = |samples: &[Sample]| {
+        let total: f32 = samples.iter().map(|(_, weight)| weight).sum();
+        let sum = samples.iter().fold([0.0; 3], |sum, (color, weight)| {
+            [0, 1, 2].map(|c| sum[c] + color[c] * weight)
+        });
+        (total > 0.0).then(|| sum.map(|c| c / total))
+    };
+    let distance = |a: [f32; 3], b: [f32; 3]| {
+        a.iter()
+            .zip(b)
+            .map(|(a, b)| (a - b).powi(2))
+            .sum::<f32>()
+            .sqrt()
+    };
+    Some(from_pixels(width, height, |ix| {
+        let mut samples = pixel_samples(images, ix);
+        let mut center = mean(&samples)?;
+        for _ in 0..CLIP_ITERATIONS {
+            let total: f32 = samples.iter().map(|(_, weight)| weight).sum();
+            let variance = samples
+                .iter()
+                .map(|&(color, weight)| weight * distance(color, center).powi(2))
+                .sum::<f32>()
+                / total;
+            let limit = sigma * variance.sqrt();
+            let before = samples.len();
+            samples.retain(|&(color, _)| distance(color, center) <= limit);
+            if samples.len() == before {
+                break;
+            }
+            match mean(&samples) {
+                Some(new) => center = new,
+                None => break,
+            }
+        }
+        Some(to_u8(center))
+    }))
+}
+
+/// Weighted per-pixel mean of a stack of (aligned) images
+///
+/// Transparent pixels (e.g. from [`Fill::Transparent`](crate::Fill::Transparent)) don't count, so
+/// the borders are only averaged over the images that cover them.
+#[derive(Debug, Clone)]
+pub struct MeanImage {
+    width: u32,
+    height: u32,
+    /// Weighted sum of each channel
+    sum: Vec<f32>,
+    /// Sum of the weights of each pixel
+    weights: Vec<f32>,
+}
+
+impl MeanImage {
+    /// An empty stack of `(width, height)` images
+    pub fn new(width: u32, height: u32) -> Self {
+        let pixels = width as usize * height as usize;
+        Self {
+            width,
+            height,
+            sum: vec![0.0; 3 * pixels],
+            weights: vec![0.0; pixels],
+        }
+    }
+
+    /// Add `image` to the stack with this `weight`
+    ///
+    /// Returns [`None`] if it isn't the same size as the stack
+    pub fn add(&mut self, image: &DynamicImage, weight: f32) -> Option<()> {
+        if image.width() != self.width || image.height() != self.height {
+            return None;
+        }
+        let image = image.to_rgba8();
+        let pixels = image.pixels().zip(self.sum.chunks_exact_mut(3));
+        for ((&Rgba([r, g, b, a]), sum), total) in pixels.zip(&mut self.weights) {
+            let weight = weight * f32::from(a) / 255.0;
+            for (sum, value) in sum.iter_mut().zip([r, g, b]) {
+                *sum += weight * f32::from(value);
+            }
+            *total += weight;
+        }
+        Some(())
+    }
+
+    /// Add the images of another stack of the same size
+    ///
+    /// Returns [`None`] if it isn't the same size
+    pub fn merge(&mut self, other: &Self) -> Option<()> {
+        if (other.width, other.height) != (self.width, self.height) {
+            return None;
+        }
+        for (sum, value) in self.sum.iter_mut().zip(&other.sum) {
+            *sum += value;
+        }
+        for (total, weight) in self.weights.iter_mut().zip(&other.weights) {
+            *total += weight;
+        }
+        Some(())
+    }
+
+    /// The mean image, the pixels no image covers are transparent (the image only has an alpha
+    /// channel if there are any)
+    pub fn mean(&self) -> DynamicImage {
+        from_pixels(self.width, self.height, |ix| {
+            let total = self.weights[ix];
+            if total <= 0.0 {
+                return None;
+            }
+            let sum = &self.sum[3 * ix..3 * ix + 3];
+            Some(to_u8([0, 1, 2].map(|c| sum[c] / total)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgb;
+
+    use super::*;
+
+    #[test]
+    fn weighted_mean() {
+        let mut mean = MeanImage::new(2, 1);
+        let black = DynamicImage::ImageRgb8(RgbImage::new(2, 1));
+        let white = DynamicImage::ImageRgb8(RgbImage::from_pixel(2, 1, Rgb([255, 255, 255])));
+        mean.add(&black, 1.0).unwrap();
+        mean.add(&white, 3.0).unwrap();
+        let mean = mean.mean();
+        assert_eq!(mean.color(), image::ColorType::Rgb8);
+        assert_eq!(*mean.to_rgb8().get_pixel(1, 0), Rgb([191, 191, 191]));
+        let wrong_size = DynamicImage::ImageRgb8(RgbImage::new(1, 1));
+        assert!(MeanImage::new(2, 1).add(&wrong_size, 1.0).is_none());
+    }
+
+    #[test]
+    fn ignores_transparent_pixels() {
+        let mut left = RgbaImage::from_pixel(2, 1, Rgba([100, 0, 0, 255]));
+        left.put_pixel(1, 0, Rgba([0, 0, 0, 0]));
+        let mut stack = MeanImage::new(2, 1);
+        stack.add(&DynamicImage::ImageRgba8(left.clone()), 1.0);
+        let mut other = MeanImage::new(2, 1);
+        left.put_pixel(0, 0, Rgba([200, 0, 0, 255]));
+        other.add(&DynamicImage::ImageRgba8(left), 1.0);
+        stack.merge(&other).unwrap();
+        let mean = stack.mean().to_rgba8();
+        assert_eq!(*mean.get_pixel(0, 0), Rgba([150, 0, 0, 255]));
+        assert_eq!(mean.get_pixel(1, 0).0[3], 0);
+    }
+
+    #[test]
+    fn rejects_transients() {
+        let gray = RgbaImage::from_pixel(1, 1, Rgba([100, 100, 100, 255]));
+        let mut images: Vec<_> = [96, 98, 100, 102, 104]
+            .map(|v| (RgbaImage::from_pixel(1, 1, Rgba([v, v, v, 255])), 1.0))
+            .into();
+        // A hand in the frame
+        images.push((RgbaImage::from_pixel(1, 1, Rgba([250, 200, 150, 255])), 1.0));
+        let median = median_image(&images).unwrap().to_rgb8();
+        assert_eq!(*median.get_pixel(0, 0), Rgb([100, 100, 100]));
+        let clipped = sigma_clipped_mean(&images, DEFAULT_CLIP_SIGMA)
+            .unwrap()
+            .to_rgb8();
+        assert_eq!(*clipped.get_pixel(0, 0), Rgb([100, 100, 100]));
+        let mean = Stacking::Mean.stack(&images).unwrap().to_rgb8();
+        assert!(mean.get_pixel(0, 0).0[0] > 120);
+        assert_eq!("sigma-clip".parse(), Ok(Stacking::SigmaClip { sigma: 2.0 }));
+        assert!(median_image(&[(gray, 1.0), (RgbaImage::new(2, 1), 1.0)]).is_none());
+    }
+}
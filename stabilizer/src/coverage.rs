@@ -0,0 +1,226 @@
+use glam::Vec2;
+use imageproc::geometric_transformations::Projection;
+use imageproc::rect::Rect;
+
+use crate::SimilarityTransform;
+
+/// Twice the signed area of a polygon (positive if counter-clockwise in a y-up frame)
+fn signed_area(polygon: &[Vec2]) -> f32 {
+    polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .map(|(a, b)| a.perp_dot(*b))
+        .sum()
+}
+
+/// Keep the part of the convex `polygon` that is inside the convex `clip` polygon
+/// (Sutherland–Hodgman), both must have the same orientation (positive [`signed_area`])
+fn clip(mut polygon: Vec<Vec2>, clip: &[Vec2]) -> Vec<Vec2> {
+    for (&a, &b) in clip.iter().zip(clip.iter().cycle().skip(1)) {
+        let inside = |p: Vec2| (b - a).perp_dot(p - a) >= 0.0;
+        let input = std::mem::take(&mut polygon);
+        for (&p, &q) in input.iter().zip(input.iter().cycle().skip(1)) {
+            if inside(p) {
+                polygon.push(p);
+            }
+            if inside(p) != inside(q) {
+                // Intersection of pq with the line ab
+                let t = (b - a).perp_dot(a - p) / (b - a).perp_dot(q - p);
+                polygon.push(p + (q - p) * t);
+            }
+        }
+    }
+    polygon
+}
+
+/// The corners of a `width`x`height` image (positive [`signed_area`])
+fn corners(width: u32, height: u32) -> [Vec2; 4] {
+    let (w, h) = (
+        width.saturating_sub(1) as f32,
+        height.saturating_sub(1) as f32,
+    );
+    [
+        Vec2::ZERO,
+        Vec2::new(w, 0.0),
+        Vec2::new(w, h),
+        Vec2::new(0.0, h),
+    ]
+}
+
+/// The (convex) region of the output that has valid pixels in every frame
+///
+/// Each frame is given by the [`Projection`] that warps it and its size, the output of each frame
+/// has the same size as its input (like [`warp`](crate::warp)).
+///
+/// Returns [`None`] if there are no frames or the region is empty
+pub fn covered_polygon(frames: &[(Projection, (u32, u32))]) -> Option<Vec<Vec2>> {
+    let &(_, (width, height)) = frames.first()?;
+    let mut polygon = corners(width, height).to_vec();
+    for &(projection, (width, height)) in frames {
+        polygon = clip(polygon, &corners(width, height));
+        let mut quad = corners(width, height).map(|p| Vec2::from(projection * (p.x, p.y)));
+        if signed_area(&quad) < 0.0 {
+            quad.reverse();
+        }
+        polygon = clip(polygon, &quad);
+    }
+    (signed_area(&polygon) > 0.0).then_some(polygon)
+}
+
+/// Whether `p` is inside the convex `polygon` (with positive [`signed_area`])
+fn contains(polygon: &[Vec2], p: Vec2) -> bool {
+    polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .all(|(&a, &b)| (b - a).perp_dot(p - a) >= -1e-3)
+}
+
+/// The largest rectangle with the aspect ratio of the (first) frame, centered in the region that
+/// has valid pixels in every frame (see [`covered_polygon`])
+///
+/// This is the crop that produces a border-free stabilized sequence.
+///
+/// Returns [`None`] if there are no frames or no pixel is covered by all of them
+pub fn covered_region(frames: &[(Projection, (u32, u32))]) -> Option<Rect> {
+    let &(_, (width, height)) = frames.first()?;
+    let polygon = covered_polygon(frames)?;
+    let area = signed_area(&polygon);
+    let center = polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .map(|(&a, &b)| (a + b) * a.perp_dot(b))
+        .sum::<Vec2>()
+        / (3.0 * area);
+    let half = Vec2::new(width as f32, height as f32) / 2.0;
+    // The polygon is convex, so the rectangle is inside if all of its corners are
+    let fits = |scale: f32| {
+        let half = half * scale;
+        [(-1.0, -1.0), (-1.0, 1.0), (1.0, 1.0), (1.0, -1.0)]
+            .into_iter()
+            .map(|(x, y)| center + half * Vec2::new(x, y))
+            .all(|p| contains(&polygon, p))
+    };
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..32 {
+        let mid = (low + high) / 2.0;
+        if fits(mid) {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    let min = (center - half * low).ceil();
+    let max = (center + half * low).floor();
+    let size = max - min;
+    if size.min_element() < 1.0 {
+        return None;
+    }
+    Some(Rect::at(min.x as i32, min.y as i32).of_size(size.x as u32, size.y as u32))
+}
+
+/// The smallest uniform zoom around `center` (e.g. the centroid of the reference face) that hides
+/// the borders of every frame, see [`covered_polygon`]
+///
+/// The zoom is returned as a [`SimilarityTransform`] to apply after the alignment of each frame,
+/// its scale is the zoom factor (at least 1).
+///
+/// Returns [`None`] if there are no frames or `center` is not covered by all of them
+pub fn auto_zoom(frames: &[(Projection, (u32, u32))], center: Vec2) -> Option<SimilarityTransform> {
+    let &(_, (width, height)) = frames.first()?;
+    let polygon = covered_polygon(frames)?;
+    if !contains(&polygon, center) {
+        return None;
+    }
+    // Find the largest fraction of the output (shrunk around the center) that is covered, the
+    // polygon is convex so checking the corners is enough
+    let fits = |fraction: f32| {
+        corners(width, height)
+            .into_iter()
+            .all(|p| contains(&polygon, center + (p - center) * fraction))
+    };
+    if fits(1.0) {
+        return Some(SimilarityTransform::IDENTITY);
+    }
+    let (mut low, mut high) = (0.0f32, 1.0f32);
+    for _ in 0..32 {
+        let mid = (low + high) / 2.0;
+        if fits(mid) {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    if low <= f32::EPSILON {
+        return None;
+    }
+    let zoom = low.recip();
+    Some(SimilarityTransform {
+        translation: center - center * zoom,
+        rotation: 0.0,
+        scale: zoom,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intersects_translated_frames() {
+        let frames = [
+            (Projection::translate(0.0, 0.0), (101, 101)),
+            (Projection::translate(10.0, 5.0), (101, 101)),
+        ];
+        let polygon = covered_polygon(&frames).unwrap();
+        assert!((signed_area(&polygon) / 2.0 - 90.0 * 95.0).abs() < 1e-2);
+        let region = covered_region(&frames).unwrap();
+        assert!(region.left() >= 10 && region.top() >= 5);
+        assert!(region.right() <= 100 && region.bottom() <= 100);
+        assert!(region.width() >= 89, "{region:?}");
+    }
+
+    #[test]
+    fn handles_rotations() {
+        let rotation = Projection::translate(-50.0, -50.0)
+            .and_then(Projection::rotate(0.3))
+            .and_then(Projection::translate(50.0, 50.0));
+        let frames = [(rotation, (101, 101)), (rotation.invert(), (101, 101))];
+        let region = covered_region(&frames).unwrap();
+        for (projection, _) in frames {
+            let inverse = projection.invert();
+            for (x, y) in [
+                (region.left(), region.top()),
+                (region.right(), region.bottom()),
+            ] {
+                let (x, y) = inverse * (x as f32, y as f32);
+                assert!((-1e-3..=100.001).contains(&x) && (-1e-3..=100.001).contains(&y));
+            }
+        }
+    }
+
+    #[test]
+    fn zooms_to_hide_the_borders() {
+        let frames = [
+            (Projection::translate(0.0, 0.0), (101, 101)),
+            (Projection::translate(10.0, 0.0), (101, 101)),
+        ];
+        let center = Vec2::new(50.0, 50.0);
+        let zoom = auto_zoom(&frames, center).unwrap();
+        // The left edge (x = 10) has to be pushed out to x = 0
+        assert!((zoom.scale - 50.0 / 40.0).abs() < 1e-3, "{zoom:?}");
+        assert!(zoom.transform_point(center).distance(center) < 1e-3);
+        assert!(zoom.transform_point(Vec2::new(10.0, 50.0)).x <= 1e-3);
+        // No zoom needed
+        let still = [(Projection::translate(0.0, 0.0), (101, 101))];
+        assert_eq!(auto_zoom(&still, center).unwrap().scale, 1.0);
+    }
+
+    #[test]
+    fn disjoint_frames_have_no_region() {
+        let frames = [
+            (Projection::translate(0.0, 0.0), (10, 10)),
+            (Projection::translate(50.0, 0.0), (10, 10)),
+        ];
+        assert!(covered_region(&frames).is_none());
+    }
+}
@@ -0,0 +1,141 @@
+//! `f64` variants of the core routines
+//!
+//! Landmark coordinates of very large images (e.g. 8K scans) lose precision in `f32`, these
+//! variants do all the math on [`DVec2`]s.
+
+use glam::DAffine2;
+use glam::DVec2;
+use imageproc::geometric_transformations::Projection;
+
+/// `f64` variant of [`centroid`](crate::centroid)
+pub fn centroid_f64(points: &[DVec2]) -> Option<DVec2> {
+    if points.is_empty() {
+        return None;
+    }
+    Some(points.iter().sum::<DVec2>() / points.len() as f64)
+}
+
+/// `f64` variant of [`center`](crate::center)
+pub fn center_f64(points: &mut [DVec2]) -> Option<DVec2> {
+    let c = centroid_f64(points)?;
+    for point in points {
+        *point -= c;
+    }
+    Some(c)
+}
+
+/// `f64` variant of [`scaling_factor`](crate::scaling_factor)
+pub fn scaling_factor_f64(points: &[DVec2]) -> Option<f64> {
+    if points.is_empty() {
+        return None;
+    }
+    Some((points.iter().map(|p| p.length_squared()).sum::<f64>() / points.len() as f64).sqrt())
+}
+
+/// `f64` variant of [`scale`](crate::scale)
+pub fn scale_f64(points: &mut [DVec2]) -> Option<f64> {
+    let s = scaling_factor_f64(points)?;
+    for point in points {
+        *point /= s;
+    }
+    Some(s)
+}
+
+/// `f64` variant of [`rotation`](crate::rotation)
+pub fn rotation_f64(referece: &[DVec2], points: &[DVec2]) -> Option<f64> {
+    if points.is_empty() || (points.len() != referece.len()) {
+        return None;
+    }
+    let top: f64 = points
+        .iter()
+        .zip(referece)
+        .map(|(p, r)| p.x * r.y - p.y * r.x)
+        .sum();
+    let bot: f64 = points
+        .iter()
+        .zip(referece)
+        .map(|(p, r)| p.x * r.x + p.y * r.y)
+        .sum();
+    Some(top.atan2(bot))
+}
+
+/// `f64` variant of [`procrustes_superimposition`](crate::procrustes_superimposition), returns
+/// the transform as a [`DAffine2`]
+///
+/// Returns [`None`] if empty
+pub fn procrustes_superimposition_f64(
+    target: impl IntoIterator<Item = DVec2>,
+    points: impl IntoIterator<Item = DVec2>,
+) -> Option<DAffine2> {
+    let mut target: Vec<_> = target.into_iter().collect();
+    let mut points: Vec<_> = points.into_iter().collect();
+    let tt = center_f64(&mut target)?;
+    let pt = center_f64(&mut points)?;
+    let ts = scale_f64(&mut target)?;
+    let ps = scale_f64(&mut points)?;
+    let theta = rotation_f64(&target, &points)?;
+    Some(
+        DAffine2::from_translation(tt)
+            * DAffine2::from_scale_angle_translation(DVec2::splat(ts / ps), theta, DVec2::ZERO)
+            * DAffine2::from_translation(-pt),
+    )
+}
+
+/// Convert a [`DAffine2`] to a [`Projection`] (loses precision)
+///
+/// Returns [`None`] if the transform is not invertible
+pub fn affine_to_projection(affine: DAffine2) -> Option<Projection> {
+    let [a, b] = affine.matrix2.to_cols_array_2d();
+    let t = affine.translation;
+    #[rustfmt::skip]
+    let matrix = [
+        a[0], b[0], t.x,
+        a[1], b[1], t.y,
+        0.0, 0.0, 1.0,
+    ];
+    Projection::from_matrix(matrix.map(|v| v as f32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::procrustes_superimposition;
+
+    #[test]
+    fn precise_with_large_coordinates() {
+        // Points of an 8K scan, moved by a fraction of a pixel
+        let offset = DVec2::new(7680.0, 4320.0);
+        let target: Vec<_> = [(1.0, 2.0), (30.0, 3.5), (15.0, 40.0), (3.0, 28.0)]
+            .map(|p| DVec2::from(p) + offset)
+            .to_vec();
+        let shift = DVec2::new(0.001, -0.002);
+        let points: Vec<_> = target.iter().map(|&p| p + shift).collect();
+        let affine =
+            procrustes_superimposition_f64(target.iter().copied(), points.iter().copied()).unwrap();
+        for (&t, &p) in target.iter().zip(&points) {
+            let got = affine.transform_point2(p);
+            assert!(got.distance(t) < 1e-9, "{got} != {t}");
+        }
+    }
+
+    #[test]
+    fn matches_f32() {
+        let target = [(4.0, 2.0), (9.0, 3.0), (6.0, 8.0), (3.0, 7.0)].map(glam::Vec2::from);
+        let points = [(14.0, 12.0), (20.0, 25.0), (7.0, 21.0), (5.0, 15.0)].map(glam::Vec2::from);
+        let single = procrustes_superimposition(target, points)
+            .unwrap()
+            .to_projection();
+        let double = procrustes_superimposition_f64(
+            target.map(|p| p.as_dvec2()),
+            points.map(|p| p.as_dvec2()),
+        )
+        .and_then(affine_to_projection)
+        .unwrap();
+        for p in points {
+            let (ax, ay) = single * (p.x, p.y);
+            let (bx, by) = double * (p.x, p.y);
+            assert!((ax - bx).abs() < 1e-3 && (ay - by).abs() < 1e-3);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use glam::DMat3;
+use glam::DVec2;
+use glam::DVec3;
+use glam::Vec2;
+use imageproc::geometric_transformations::Projection;
+
+use crate::linalg::solve;
+
+/// Similarity transform that moves the centroid of the points to the origin and their average
+/// distance to it to √2 (Hartley normalization, improves the conditioning of the DLT)
+fn normalization(points: &[DVec2]) -> Option<DMat3> {
+    let centroid = points.iter().sum::<DVec2>() / points.len() as f64;
+    let distance = points.iter().map(|p| p.distance(centroid)).sum::<f64>() / points.len() as f64;
+    if distance <= f64::EPSILON {
+        return None;
+    }
+    let s = std::f64::consts::SQRT_2 / distance;
+    Some(DMat3::from_cols(
+        DVec3::new(s, 0.0, 0.0),
+        DVec3::new(0.0, s, 0.0),
+        DVec3::new(-s * centroid.x, -s * centroid.y, 1.0),
+    ))
+}
+
+/// Calculate the homography (projective [`Projection`]) that best maps `points` to `target`
+///
+/// Uses the normalized Direct Linear Transform, solved in the least squares sense (with the
+/// bottom right entry of the homography fixed to 1). Useful when the perspective changes between
+/// images, otherwise prefer a [`procrustes_superimposition`](crate::procrustes_superimposition)
+/// which is much less sensitive to noise.
+///
+/// Returns [`None`] if the lengths don't match or the points are degenerate (less than four
+/// points, or three of them on a line)
+pub fn homography_fit(
+    target: impl IntoIterator<Item = Vec2>,
+    points: impl IntoIterator<Item = Vec2>,
+) -> Option<Projection> {
+    let target: Vec<_> = target.into_iter().map(|p| p.as_dvec2()).collect();
+    let points: Vec<_> = points.into_iter().map(|p| p.as_dvec2()).collect();
+    if target.len() != points.len() || points.len() < 4 {
+        return None;
+    }
+    let tn = normalization(&target)?;
+    let pn = normalization(&points)?;
+
+    // Accumulate the normal equations of the 8 unknowns
+    let mut ata = [[0.0; 8]; 8];
+    let mut atb = [0.0; 8];
+    for (&t, &p) in target.iter().zip(&points) {
+        let t = tn.transform_point2(t);
+        let p = pn.transform_point2(p);
+        let rows = [
+            ([p.x, p.y, 1.0, 0.0, 0.0, 0.0, -t.x * p.x, -t.x * p.y], t.x),
+            ([0.0, 0.0, 0.0, p.x, p.y, 1.0, -t.y * p.x, -t.y * p.y], t.y),
+        ];
+        for (row, b) in rows {
+            for i in 0..8 {
+                for j in 0..8 {
+                    ata[i][j] += row[i] * row[j];
+                }
+                atb[i] += row[i] * b;
+            }
+        }
+    }
+    let h = solve(ata, atb)?;
+    let normalized = DMat3::from_cols(
+        DVec3::new(h[0], h[3], h[6]),
+        DVec3::new(h[1], h[4], h[7]),
+        DVec3::new(h[2], h[5], 1.0),
+    );
+    let h = tn.inverse() * normalized * pn;
+    let h = h.transpose().to_cols_array().map(|v| v as f32);
+    Projection::from_matrix(h)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recovers_perspective() {
+        #[rustfmt::skip]
+        let h = DMat3::from_cols_array(&[
+            1.1, 0.05, 0.0004,
+            -0.1, 0.9, 0.0002,
+            12.0, -7.0, 1.0,
+        ]);
+        let target: Vec<_> = [
+            (10.0, 20.0),
+            (50.0, 18.0),
+            (30.0, 45.0),
+            (22.0, 70.0),
+            (41.0, 68.0),
+            (5.0, 50.0),
+        ]
+        .into_iter()
+        .map(Vec2::from)
+        .collect();
+        let points: Vec<_> = target
+            .iter()
+            .map(|t| {
+                let p = h * t.as_dvec2().extend(1.0);
+                (p.truncate() / p.z).as_vec2()
+            })
+            .collect();
+        let proj = homography_fit(target.clone(), points.clone()).unwrap();
+        for (t, p) in target.iter().zip(&points) {
+            let (x, y) = proj * (p.x, p.y);
+            assert!(
+                Vec2::new(x, y).distance(*t) < 1e-2,
+                "{p} mapped to ({x}, {y}), not {t}"
+            );
+        }
+    }
+
+    #[test]
+    fn needs_four_points() {
+        let points = vec![Vec2::ZERO, Vec2::X, Vec2::Y];
+        assert!(homography_fit(points.clone(), points).is_none());
+    }
+}
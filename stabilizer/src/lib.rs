@@ -1,6 +1,67 @@
+use glam::Mat2;
 use glam::Vec2;
 use imageproc::geometric_transformations::Projection;
 
+mod average;
+mod coverage;
+mod double;
+mod homography;
+mod linalg;
+mod outliers;
+mod photometric;
+pub mod piecewise_affine;
+mod sequence;
+mod similarity;
+pub mod smoothing;
+pub mod thin_plate_spline;
+mod tone;
+mod warp;
+
+pub use average::median_image;
+pub use average::sigma_clipped_mean;
+pub use average::MeanImage;
+pub use average::Stacking;
+pub use average::DEFAULT_CLIP_SIGMA;
+pub use coverage::auto_zoom;
+pub use coverage::covered_polygon;
+pub use coverage::covered_region;
+pub use double::affine_to_projection;
+pub use double::center_f64;
+pub use double::centroid_f64;
+pub use double::procrustes_superimposition_f64;
+pub use double::rotation_f64;
+pub use double::scale_f64;
+pub use double::scaling_factor_f64;
+pub use homography::homography_fit;
+pub use outliers::median_shape;
+pub use outliers::outlier_landmarks;
+pub use outliers::rejecting_procrustes_superimposition;
+pub use outliers::DEFAULT_MAD_THRESHOLD;
+pub use photometric::Template;
+pub use photometric::PHOTOMETRIC_MAX_ITERATIONS;
+pub use sequence::align_chained;
+pub use sequence::align_sequence;
+pub use sequence::fill_missing;
+pub use sequence::fit_sequence;
+pub use sequence::smooth_sequence;
+pub use sequence::Fit;
+pub use sequence::Reference;
+pub use similarity::ReflectiveTransform;
+pub use similarity::SimilarityTransform;
+pub use tone::color_histograms;
+pub use tone::deflicker_corrections;
+pub use tone::luma_stats;
+pub use tone::ColorMap;
+pub use tone::Histograms;
+pub use tone::LumaStats;
+pub use tone::ToneCorrection;
+pub use tone::DEFAULT_DEFLICKER_RADIUS;
+pub use warp::parse_color;
+pub use warp::warp;
+pub use warp::warp_to_size;
+pub use warp::Fill;
+pub use warp::Interpolation;
+
 /// Calculates the "center of mass" of a set of points
 ///
 /// Returns [`None`] if empty
@@ -53,7 +114,10 @@ pub fn scale(points: &mut [Vec2]) -> Option<f32> {
 }
 
 /// Calculate the angle of rotation that reduces the error between [`referece`] and [`points`]. The
-/// resulting angle will be between [-π/2, π/2] radians.
+/// resulting angle will be between [-π, π] radians.
+///
+/// This is the 2D closed form of the Kabsch algorithm, so it also handles upside-down and sideways
+/// points.
 ///
 /// Expects both the reference and the points to be centered and scaled, use [`center`] and
 /// [`scale`] to achieve this.
@@ -73,10 +137,10 @@ pub fn rotation(referece: &[Vec2], points: &[Vec2]) -> Option<f32> {
         .zip(referece)
         .map(|(p, r)| p.x * r.x + p.y * r.y)
         .sum();
-    Some((top / bot).atan())
+    Some(top.atan2(bot))
 }
 
-/// Calculate the [`Projection`] that better approximates the shapes of the points.
+/// Calculate the [`SimilarityTransform`] that better approximates the shapes of the points.
 /// See the [wikipedia](https://en.wikipedia.org/wiki/Procrustes_analysis) page
 ///
 /// [`target`] is what you want the end result to be [`points`] are the points that will be
@@ -86,7 +150,7 @@ pub fn rotation(referece: &[Vec2], points: &[Vec2]) -> Option<f32> {
 pub fn procrustes_superimposition(
     target: impl IntoIterator<Item = Vec2>,
     points: impl IntoIterator<Item = Vec2>,
-) -> Option<Projection> {
+) -> Option<SimilarityTransform> {
     let mut target: Vec<_> = target.into_iter().collect();
     let mut points: Vec<_> = points.into_iter().collect();
     // Calculate translation vector
@@ -99,12 +163,866 @@ pub fn procrustes_superimposition(
     // let s = ts / ps;
     // Calculate rotation
     let theta = rotation(&target, &points)?;
-    // Create Projection
+    let s = ts / ps;
+    Some(SimilarityTransform {
+        translation: tt - s * Vec2::from_angle(theta).rotate(pt),
+        rotation: theta,
+        scale: s,
+    })
+}
+
+/// Mirror the points horizontally (`x → -x`)
+pub fn mirror(points: &mut [Vec2]) {
+    for point in points {
+        point.x = -point.x;
+    }
+}
+
+/// Whether `points` are a mirror image of `target` (e.g. a front camera selfie), that is, if the
+/// orthogonal transform that best aligns them includes a reflection
+///
+/// Returns [`None`] if empty or the lengths don't match
+pub fn is_reflection(target: &[Vec2], points: &[Vec2]) -> Option<bool> {
+    if points.is_empty() || points.len() != target.len() {
+        return None;
+    }
+    let mut target = target.to_vec();
+    let mut points = points.to_vec();
+    center(&mut target)?;
+    center(&mut points)?;
+    // Cross covariance
+    let (mut xx, mut xy, mut yx, mut yy) = (0.0, 0.0, 0.0, 0.0);
+    for (p, t) in points.iter().zip(&target) {
+        xx += p.x * t.x;
+        xy += p.x * t.y;
+        yx += p.y * t.x;
+        yy += p.y * t.y;
+    }
+    // How well the best rotation and the best reflection align the points
+    let rotation = (xx + yy).powi(2) + (xy - yx).powi(2);
+    let reflection = (xx - yy).powi(2) + (xy + yx).powi(2);
+    Some(reflection > rotation)
+}
+
+/// Same as [`procrustes_superimposition`], but if the points are a mirror image of the target
+/// they are flipped horizontally first
+///
+/// Returns [`None`] if empty or the lengths don't match
+pub fn reflective_superimposition(
+    target: impl IntoIterator<Item = Vec2>,
+    points: impl IntoIterator<Item = Vec2>,
+) -> Option<ReflectiveTransform> {
+    let target: Vec<_> = target.into_iter().collect();
+    let mut points: Vec<_> = points.into_iter().collect();
+    let reflected = is_reflection(&target, &points)?;
+    if reflected {
+        mirror(&mut points);
+    }
+    Some(ReflectiveTransform {
+        similarity: procrustes_superimposition(target, points)?,
+        reflected,
+    })
+}
+
+/// Same as [`procrustes_superimposition`], but without scaling the points (the scale is 1)
+///
+/// Only removes the translation and rotation, so the apparent size of the face is preserved.
+///
+/// Returns [`None`] if empty or the lengths don't match
+pub fn rigid_superimposition(
+    target: impl IntoIterator<Item = Vec2>,
+    points: impl IntoIterator<Item = Vec2>,
+) -> Option<SimilarityTransform> {
+    constrained_superimposition(target, points, true, false)
+}
+
+/// Same as [`procrustes_superimposition`], but only fitting the rotation and/or the scale if
+/// `rotate` and `scale` are set (otherwise they are kept at 0 and 1)
+///
+/// E.g. without the scale the natural growth of a face over the years stays visible.
+///
+/// Returns [`None`] if empty or the lengths don't match
+pub fn constrained_superimposition(
+    target: impl IntoIterator<Item = Vec2>,
+    points: impl IntoIterator<Item = Vec2>,
+    rotate: bool,
+    scale: bool,
+) -> Option<SimilarityTransform> {
+    let mut target: Vec<_> = target.into_iter().collect();
+    let mut points: Vec<_> = points.into_iter().collect();
+    if points.len() != target.len() {
+        return None;
+    }
+    let tt = center(&mut target)?;
+    let pt = center(&mut points)?;
+    let theta = if rotate {
+        rotation(&target, &points)?
+    } else {
+        0.0
+    };
+    let s = if scale {
+        // Least squares scale for the fixed rotation: Σ (R p)·t / Σ ||p||²
+        let r = Vec2::from_angle(theta);
+        let num: f32 = points
+            .iter()
+            .zip(&target)
+            .map(|(p, t)| r.rotate(*p).dot(*t))
+            .sum();
+        let den: f32 = points.iter().map(|p| p.length_squared()).sum();
+        if den <= f32::EPSILON {
+            return None;
+        }
+        num / den
+    } else {
+        1.0
+    };
+    Some(SimilarityTransform {
+        translation: tt - s * Vec2::from_angle(theta).rotate(pt),
+        rotation: theta,
+        scale: s,
+    })
+}
+
+/// Calculate the [`SimilarityTransform`] that maps the centers of the `eyes` (left and right) to
+/// the ones of the `target`
+///
+/// The classic face alignment: the rotation matches the eye lines, the scale the interocular
+/// distances and the translation the midpoints between the eyes. Ignores the rest of the face, so
+/// expressions (mouth, jaw) don't move the result.
+///
+/// Returns [`None`] if the eyes are in the same place
+pub fn eye_line_superimposition(target: [Vec2; 2], eyes: [Vec2; 2]) -> Option<SimilarityTransform> {
+    let target_line = target[0] - target[1];
+    let eye_line = eyes[0] - eyes[1];
+    if eye_line.length() <= f32::EPSILON || target_line.length() <= f32::EPSILON {
+        return None;
+    }
+    let rotation = eye_line.angle_between(target_line);
+    let scale = target_line.length() / eye_line.length();
+    let midpoint = (eyes[0] + eyes[1]) / 2.0;
+    let target_midpoint = (target[0] + target[1]) / 2.0;
+    Some(SimilarityTransform {
+        translation: target_midpoint - scale * Vec2::from_angle(rotation).rotate(midpoint),
+        rotation,
+        scale,
+    })
+}
+
+/// Calculate the affine [`Projection`] (translation, rotation, anisotropic scale and shear) that
+/// best maps `points` to `target` in the least squares sense
+///
+/// Returns [`None`] if the lengths don't match or the points are degenerate (less than three
+/// points, or all of them on a line)
+pub fn affine_fit(
+    target: impl IntoIterator<Item = Vec2>,
+    points: impl IntoIterator<Item = Vec2>,
+) -> Option<Projection> {
+    let mut target: Vec<_> = target.into_iter().collect();
+    let mut points: Vec<_> = points.into_iter().collect();
+    if target.len() != points.len() {
+        return None;
+    }
+    let tt = center(&mut target)?;
+    let pt = center(&mut points)?;
+    // Solve the normal equations on the centered points: M = (Σ t pᵀ) (Σ p pᵀ)⁻¹
+    let (tp, pp) =
+        target
+            .iter()
+            .zip(&points)
+            .fold((Mat2::ZERO, Mat2::ZERO), |(tp, pp), (&t, &p)| {
+                let outer = |a: Vec2, b: Vec2| Mat2::from_cols(a * b.x, a * b.y);
+                (tp + outer(t, p), pp + outer(p, p))
+            });
+    if pp.determinant().abs() <= f32::EPSILON {
+        return None;
+    }
+    let m = tp * pp.inverse();
+    let t = tt - m * pt;
+    #[rustfmt::skip]
+    let matrix = [
+        m.x_axis.x, m.y_axis.x, t.x,
+        m.x_axis.y, m.y_axis.y, t.y,
+        0.0, 0.0, 1.0,
+    ];
+    Projection::from_matrix(matrix)
+}
+
+/// Maximum number of iterations of [`anisotropic_fit`]
+const ANISOTROPIC_MAX_ITERATIONS: usize = 50;
+
+/// Same as [`procrustes_superimposition`], but with independent scale factors for the x and y axes
+/// of the points (`target ≈ R · diag(sx, sy) · points + t`)
+///
+/// Useful when the images come from different lenses or were stretched, where a uniform scale
+/// leaves a systematic error. The rotation and the scales are estimated alternately.
+///
+/// Returns [`None`] if the lengths don't match or the points are degenerate (all of them on a
+/// horizontal or vertical line)
+pub fn anisotropic_fit(
+    target: impl IntoIterator<Item = Vec2>,
+    points: impl IntoIterator<Item = Vec2>,
+) -> Option<Projection> {
+    let mut target: Vec<_> = target.into_iter().collect();
+    let mut points: Vec<_> = points.into_iter().collect();
+    if target.len() != points.len() {
+        return None;
+    }
+    let tt = center(&mut target)?;
+    let pt = center(&mut points)?;
+    let norm = points.iter().fold(Vec2::ZERO, |sum, p| sum + *p * *p);
+    if norm.min_element() <= f32::EPSILON {
+        return None;
+    }
+
+    let mut scales = Vec2::ONE;
+    let mut theta = 0.0;
+    for _ in 0..ANISOTROPIC_MAX_ITERATIONS {
+        let scaled: Vec<_> = points.iter().map(|&p| p * scales).collect();
+        theta = rotation(&target, &scaled)?;
+        // Undo the rotation of the target and fit each scale independently
+        let inverse = Vec2::from_angle(-theta);
+        let next = target
+            .iter()
+            .zip(&points)
+            .fold(Vec2::ZERO, |sum, (&t, &p)| sum + inverse.rotate(t) * p)
+            / norm;
+        let change = next.distance_squared(scales);
+        scales = next;
+        if change < 1e-12 {
+            break;
+        }
+    }
+
+    let m = Mat2::from_angle(theta) * Mat2::from_diagonal(scales);
+    let t = tt - m * pt;
+    #[rustfmt::skip]
+    let matrix = [
+        m.x_axis.x, m.y_axis.x, t.x,
+        m.x_axis.y, m.y_axis.y, t.y,
+        0.0, 0.0, 1.0,
+    ];
+    Projection::from_matrix(matrix)
+}
+
+/// How the points are aligned to the target
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Alignment {
+    /// Translation, rotation and uniform scale (see [`procrustes_superimposition`])
+    #[default]
+    Similarity,
+    /// Translation and rotation only (see [`rigid_superimposition`])
+    Rigid,
+    /// Translation, rotation and independent x/y scales (see [`anisotropic_fit`])
+    Anisotropic,
+    /// Any affine transform, including shear and anisotropic scale (see [`affine_fit`])
+    Affine,
+    /// Any projective transform, corrects changes of perspective (see [`homography_fit`])
+    Homography,
+    /// Translation, rotation and uniform scale from the centers of the eyes only (see
+    /// [`eye_line_superimposition`]), the points have to be the left and right eye centers
+    EyeLine,
+}
+
+impl Alignment {
+    /// The fewest pairs of points [`Alignment::superimpose`] needs to find a unique transform
+    pub fn min_points(self) -> usize {
+        match self {
+            Self::Similarity | Self::Rigid | Self::Anisotropic | Self::EyeLine => 2,
+            Self::Affine => 3,
+            Self::Homography => 4,
+        }
+    }
+
+    /// Calculate the [`Projection`] that aligns `points` to `target`
+    ///
+    /// Returns [`None`] if empty or the lengths don't match (or aren't two points for
+    /// [`Alignment::EyeLine`])
+    pub fn superimpose(
+        self,
+        target: impl IntoIterator<Item = Vec2>,
+        points: impl IntoIterator<Item = Vec2>,
+    ) -> Option<Projection> {
+        match self {
+            Self::Similarity => {
+                procrustes_superimposition(target, points).map(|s| s.to_projection())
+            }
+            Self::Rigid => rigid_superimposition(target, points).map(|s| s.to_projection()),
+            Self::Anisotropic => anisotropic_fit(target, points),
+            Self::Affine => affine_fit(target, points),
+            Self::Homography => homography_fit(target, points),
+            Self::EyeLine => {
+                let target: Vec<_> = target.into_iter().collect();
+                let points: Vec<_> = points.into_iter().collect();
+                eye_line_superimposition(target.try_into().ok()?, points.try_into().ok()?)
+                    .map(|s| s.to_projection())
+            }
+        }
+    }
+
+    /// Same as [`Alignment::superimpose`], but only the pairs of points selected by `mask` drive
+    /// the fit (e.g. the eyes and the nose bridge, see [`index_mask`])
+    ///
+    /// Returns [`None`] if `mask` doesn't have the same length as both `target` and `points`, or
+    /// the selected points can't be aligned
+    pub fn superimpose_masked(
+        self,
+        target: &[Vec2],
+        points: &[Vec2],
+        mask: &[bool],
+    ) -> Option<Projection> {
+        if mask.len() != target.len() || mask.len() != points.len() {
+            return None;
+        }
+        let selected = |points: &[Vec2]| {
+            points
+                .iter()
+                .zip(mask)
+                .filter(|&(_, &selected)| selected)
+                .map(|(&p, _)| p)
+                .collect::<Vec<_>>()
+        };
+        self.superimpose(selected(target), selected(points))
+    }
+}
+
+/// Create a mask of length `len` that selects the points at `indices`
+///
+/// Returns [`None`] if any of the indices is out of bounds
+pub fn index_mask(len: usize, indices: impl IntoIterator<Item = usize>) -> Option<Vec<bool>> {
+    let mut mask = vec![false; len];
+    for ix in indices {
+        *mask.get_mut(ix)? = true;
+    }
+    Some(mask)
+}
+
+/// Parses `similarity`, `rigid`, `anisotropic`, `affine`, `homography` or `eye-line`
+impl std::str::FromStr for Alignment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "similarity" => Ok(Self::Similarity),
+            "rigid" => Ok(Self::Rigid),
+            "anisotropic" => Ok(Self::Anisotropic),
+            "affine" => Ok(Self::Affine),
+            "homography" => Ok(Self::Homography),
+            "eye-line" => Ok(Self::EyeLine),
+            _ => Err(format!(
+                "expected similarity, rigid, anisotropic, affine, homography or eye-line, found {s}"
+            )),
+        }
+    }
+}
+
+/// Maximum number of iterations of [`mean_shape`]
+const GPA_MAX_ITERATIONS: usize = 100;
+
+/// Compute the mean shape of several shapes with Generalized Procrustes Analysis
+///
+/// Each shape is aligned (translated, scaled and rotated) to the current estimate of the mean
+/// shape, which is then recomputed as the average of the aligned shapes, until it converges. The
+/// result is placed at the average position and size of the shapes, and rotated like the first
+/// one.
+///
+/// Returns [`None`] if there are no shapes, they are empty or they don't have the same length
+pub fn mean_shape(shapes: &[Vec<Vec2>]) -> Option<Vec<Vec2>> {
+    let len = shapes.first()?.len();
+    if shapes.iter().any(|shape| shape.len() != len) {
+        return None;
+    }
+    let mut centroids = Vec2::ZERO;
+    let mut scales = 0.0;
+    let mut normalized: Vec<Vec<Vec2>> = shapes.to_vec();
+    for shape in &mut normalized {
+        centroids += center(shape)?;
+        scales += scale(shape)?;
+    }
+
+    let mut mean = normalized[0].clone();
+    for _ in 0..GPA_MAX_ITERATIONS {
+        let mut next = vec![Vec2::ZERO; len];
+        for shape in &mut normalized {
+            let rotation = Vec2::from_angle(rotation(&mean, shape)?);
+            for (point, sum) in shape.iter_mut().zip(&mut next) {
+                *point = rotation.rotate(*point);
+                *sum += *point;
+            }
+        }
+        center(&mut next)?;
+        scale(&mut next)?;
+        // Keep the orientation of the first shape (the previous mean)
+        let rotation = Vec2::from_angle(rotation(&mean, &next)?);
+        for point in &mut next {
+            *point = rotation.rotate(*point);
+        }
+        let change: f32 = mean
+            .iter()
+            .zip(&next)
+            .map(|(a, b)| a.distance_squared(*b))
+            .sum();
+        mean = next;
+        if change < 1e-10 {
+            break;
+        }
+    }
+
+    let n = shapes.len() as f32;
+    let (centroid, size) = (centroids / n, scales / n);
+    Some(mean.into_iter().map(|p| p * size + centroid).collect())
+}
+
+/// Same as [`procrustes_superimposition`], but each pair of points has a weight (non negative)
+///
+/// Points with a higher weight have more influence on the result, points with weight 0 are
+/// ignored.
+///
+/// Returns [`None`] if empty, the lengths don't match or all weights are 0
+pub fn weighted_procrustes_superimposition(
+    target: &[Vec2],
+    points: &[Vec2],
+    weights: &[f32],
+) -> Option<Projection> {
+    if target.is_empty() || target.len() != points.len() || points.len() != weights.len() {
+        return None;
+    }
+    let total: f32 = weights.iter().sum();
+    if total <= 0.0 {
+        return None;
+    }
+    let weighted_centroid = |points: &[Vec2]| {
+        points
+            .iter()
+            .zip(weights)
+            .map(|(&p, &w)| p * w)
+            .sum::<Vec2>()
+            / total
+    };
+    let tt = weighted_centroid(target);
+    let pt = weighted_centroid(points);
+    let (mut top, mut bot, mut tn, mut pn) = (0.0, 0.0, 0.0, 0.0);
+    for ((&t, &p), &w) in target.iter().zip(points).zip(weights) {
+        let (t, p) = (t - tt, p - pt);
+        top += w * (p.x * t.y - p.y * t.x);
+        bot += w * p.dot(t);
+        tn += w * t.length_squared();
+        pn += w * p.length_squared();
+    }
+    if pn <= 0.0 {
+        return None;
+    }
+    let s = (tn / pn).sqrt();
     Some(
         Projection::translate(-pt.x, -pt.y)
-            .and_then(Projection::scale(1.0 / ps, 1.0 / ps))
-            .and_then(Projection::rotate(theta))
-            .and_then(Projection::scale(ts, ts))
+            .and_then(Projection::rotate(top.atan2(bot)))
+            .and_then(Projection::scale(s, s))
             .and_then(Projection::translate(tt.x, tt.y)),
     )
 }
+
+/// Tuning constant of the Huber loss (relative to the estimated standard deviation of the errors),
+/// 1.345 gives 95% efficiency on normally distributed errors
+pub const HUBER_K: f32 = 1.345;
+
+/// A [`procrustes_superimposition`] that is robust to a few badly placed points
+///
+/// Uses iteratively reweighted least squares with the Huber loss: after each fit, the points that
+/// land far from their target are given a lower weight for the next fit. Returns the
+/// [`Projection`] and the final weight of each point (between 0 and 1, low weights are outliers).
+///
+/// Returns [`None`] if empty or the lengths don't match
+pub fn robust_procrustes_superimposition(
+    target: &[Vec2],
+    points: &[Vec2],
+    iterations: usize,
+) -> Option<(Projection, Vec<f32>)> {
+    let mut weights = vec![1.0; points.len()];
+    let mut proj = weighted_procrustes_superimposition(target, points, &weights)?;
+    for _ in 0..iterations {
+        let residuals: Vec<_> = target
+            .iter()
+            .zip(points)
+            .map(|(t, p)| Vec2::from(proj * (p.x, p.y)).distance(*t))
+            .collect();
+        // Robust estimate of the standard deviation (median absolute deviation)
+        let mut sorted = residuals.clone();
+        sorted.sort_by(f32::total_cmp);
+        let sigma = 1.4826 * sorted[sorted.len() / 2];
+        if sigma <= f32::EPSILON {
+            break;
+        }
+        let k = HUBER_K * sigma;
+        for (weight, residual) in weights.iter_mut().zip(residuals) {
+            *weight = if residual <= k { 1.0 } else { k / residual };
+        }
+        proj = weighted_procrustes_superimposition(target, points, &weights)?;
+    }
+    Some((proj, weights))
+}
+
+/// A [`procrustes_superimposition`] that ignores catastrophically wrong points
+///
+/// RANSAC: fits the transform to `iterations` random pairs of points, keeps the one where most
+/// points land less than `threshold` away from their target (the inliers) and refits it to all of
+/// its inliers. Returns the [`Projection`] and which points are inliers.
+///
+/// Returns [`None`] if there are less than two points or the lengths don't match
+pub fn ransac_procrustes_superimposition(
+    target: &[Vec2],
+    points: &[Vec2],
+    threshold: f32,
+    iterations: usize,
+    rng: &mut impl rand::Rng,
+) -> Option<(Projection, Vec<bool>)> {
+    if points.len() < 2 || target.len() != points.len() {
+        return None;
+    }
+    let inliers = |proj: &Projection| -> (Vec<bool>, f32) {
+        let mut error = 0.0;
+        let inliers = target
+            .iter()
+            .zip(points)
+            .map(|(t, p)| {
+                let distance = Vec2::from(*proj * (p.x, p.y)).distance(*t);
+                error += distance.min(threshold);
+                distance < threshold
+            })
+            .collect();
+        (inliers, error)
+    };
+
+    let mut best: Option<(Vec<bool>, usize, f32)> = None;
+    let mut weights = vec![0.0; points.len()];
+    for _ in 0..iterations {
+        let [a, b] = [0, 1].map(|_| rng.gen_range(0..points.len()));
+        if a == b {
+            continue;
+        }
+        weights.fill(0.0);
+        weights[a] = 1.0;
+        weights[b] = 1.0;
+        let Some(proj) = weighted_procrustes_superimposition(target, points, &weights) else {
+            continue;
+        };
+        let (mask, error) = inliers(&proj);
+        let count = mask.iter().filter(|&&inlier| inlier).count();
+        let better = match best {
+            Some((_, best_count, best_error)) => {
+                count > best_count || (count == best_count && error < best_error)
+            }
+            None => true,
+        };
+        if better {
+            best = Some((mask, count, error));
+        }
+    }
+
+    let weights: Vec<_> = match best {
+        Some((mask, count, _)) if count >= 2 => mask
+            .iter()
+            .map(|&inlier| f32::from(u8::from(inlier)))
+            .collect(),
+        _ => vec![1.0; points.len()],
+    };
+    let proj = weighted_procrustes_superimposition(target, points, &weights)?;
+    let (mask, _) = inliers(&proj);
+    Some((proj, mask))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+    use std::f32::consts::PI;
+
+    use glam::Vec2;
+
+    use super::*;
+
+    /// An asymmetric shape, so every rotation is distinguishable
+    fn shape() -> Vec<Vec2> {
+        vec![
+            Vec2::new(10.0, 20.0),
+            Vec2::new(50.0, 18.0),
+            Vec2::new(30.0, 45.0),
+            Vec2::new(22.0, 70.0),
+            Vec2::new(41.0, 68.0),
+        ]
+    }
+
+    /// Rotate the points `angle` radians around `pivot` and move them by `offset`
+    fn rotate(points: &[Vec2], angle: f32, pivot: Vec2, offset: Vec2) -> Vec<Vec2> {
+        let rotation = Vec2::from_angle(angle);
+        points
+            .iter()
+            .map(|&p| rotation.rotate(p - pivot) + pivot + offset)
+            .collect()
+    }
+
+    /// Superimpose a rotated copy of [`shape`] onto it and check that it lands on it
+    fn assert_superimposes(angle: f32) {
+        let target = shape();
+        let points = rotate(
+            &target,
+            angle,
+            Vec2::new(30.0, 40.0),
+            Vec2::new(100.0, -20.0),
+        );
+        let proj = procrustes_superimposition(target.clone(), points.clone())
+            .unwrap()
+            .to_projection();
+        for (t, p) in target.iter().zip(&points) {
+            let (x, y) = proj * (p.x, p.y);
+            assert!(
+                Vec2::new(x, y).distance(*t) < 1e-2,
+                "{angle} rad: {p} was mapped to ({x}, {y}) instead of {t}"
+            );
+        }
+    }
+
+    #[test]
+    fn superimposes_small_rotations() {
+        for angle in [-0.3, -0.1, 0.0, 0.1, 0.3] {
+            assert_superimposes(angle);
+        }
+    }
+
+    #[test]
+    fn needs_min_points() {
+        let target = shape();
+        let points = rotate(&target, 0.3, Vec2::ZERO, Vec2::new(7.0, 3.0));
+        for alignment in [
+            Alignment::Similarity,
+            Alignment::Rigid,
+            Alignment::Anisotropic,
+            Alignment::Affine,
+            Alignment::Homography,
+        ] {
+            let n = alignment.min_points();
+            assert!(alignment
+                .superimpose(target[..n].to_vec(), points[..n].to_vec())
+                .is_some());
+        }
+    }
+
+    #[test]
+    fn masked_fit_ignores_unselected_points() {
+        let target = shape();
+        let mut points = rotate(&target, 0.3, Vec2::ZERO, Vec2::new(7.0, 3.0));
+        // A point that would throw off the fit
+        points[4] += Vec2::new(40.0, -25.0);
+        let mask = index_mask(points.len(), 0..4).unwrap();
+        let proj = Alignment::Similarity
+            .superimpose_masked(&target, &points, &mask)
+            .unwrap();
+        for (t, p) in target.iter().zip(&points).take(4) {
+            let (x, y) = proj * (p.x, p.y);
+            assert!(
+                Vec2::new(x, y).distance(*t) < 1e-2,
+                "{p} -> ({x}, {y}) != {t}"
+            );
+        }
+        // The mask must match both point sets
+        assert!(Alignment::Similarity
+            .superimpose_masked(&target, &points, &mask[1..])
+            .is_none());
+        assert!(index_mask(3, [5]).is_none());
+    }
+
+    #[test]
+    fn detects_reflections() {
+        let target = shape();
+        let mut points = rotate(&target, 0.4, Vec2::ZERO, Vec2::new(300.0, 10.0));
+        assert_eq!(is_reflection(&target, &points), Some(false));
+        mirror(&mut points);
+        assert_eq!(is_reflection(&target, &points), Some(true));
+        let transform = reflective_superimposition(target.clone(), points.clone()).unwrap();
+        assert!(transform.reflected);
+        let proj = transform.to_projection();
+        for (t, p) in target.iter().zip(&points) {
+            let (x, y) = proj * (p.x, p.y);
+            assert!(
+                Vec2::new(x, y).distance(*t) < 1e-2,
+                "{p} -> ({x}, {y}) != {t}"
+            );
+            assert!(transform.transform_point(*p).distance(*t) < 1e-2);
+        }
+    }
+
+    #[test]
+    fn superimposes_sideways() {
+        assert_superimposes(FRAC_PI_2);
+        assert_superimposes(-FRAC_PI_2);
+        assert_superimposes(FRAC_PI_2 + 0.2);
+        assert_superimposes(-FRAC_PI_2 - 0.2);
+    }
+
+    #[test]
+    fn superimposes_upside_down() {
+        assert_superimposes(PI);
+        assert_superimposes(PI - 0.1);
+        assert_superimposes(-PI + 0.1);
+    }
+
+    #[test]
+    fn robust_fit_ignores_outliers() {
+        let target = shape();
+        let mut points = rotate(&target, 0.4, Vec2::new(30.0, 40.0), Vec2::new(5.0, 7.0));
+        points[3] += Vec2::new(40.0, -25.0);
+        let (proj, weights) = robust_procrustes_superimposition(&target, &points, 10).unwrap();
+        assert!(
+            weights[3] < 0.5,
+            "the outlier should be downweighted: {weights:?}"
+        );
+        for (ix, (t, p)) in target.iter().zip(&points).enumerate() {
+            if ix == 3 {
+                continue;
+            }
+            let (x, y) = proj * (p.x, p.y);
+            assert!(
+                Vec2::new(x, y).distance(*t) < 1.0,
+                "{p} mapped to ({x}, {y}), not {t}"
+            );
+        }
+    }
+
+    #[test]
+    fn ransac_finds_inliers() {
+        use rand::SeedableRng;
+
+        let target = shape();
+        let mut points = rotate(&target, -0.6, Vec2::new(30.0, 40.0), Vec2::new(-5.0, 12.0));
+        points[0] += Vec2::new(-60.0, 80.0);
+        points[2] += Vec2::new(90.0, 30.0);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let (proj, inliers) =
+            ransac_procrustes_superimposition(&target, &points, 1.0, 50, &mut rng).unwrap();
+        assert_eq!(inliers, [false, true, false, true, true]);
+        for (t, p) in target.iter().zip(&points).skip(3) {
+            let (x, y) = proj * (p.x, p.y);
+            assert!(
+                Vec2::new(x, y).distance(*t) < 1e-2,
+                "{p} mapped to ({x}, {y}), not {t}"
+            );
+        }
+    }
+
+    #[test]
+    fn mean_shape_of_rotated_copies() {
+        let target = shape();
+        let pivot = centroid(&target).unwrap();
+        let shapes: Vec<_> = [0.0, 0.5, -1.0, 3.0]
+            .into_iter()
+            .map(|angle| rotate(&target, angle, pivot, Vec2::ZERO))
+            .collect();
+        let mean = mean_shape(&shapes).unwrap();
+        for (m, t) in mean.iter().zip(&target) {
+            assert!(m.distance(*t) < 1e-2, "{m} should be {t}");
+        }
+    }
+
+    #[test]
+    fn eye_line_matches_the_eyes() {
+        let target = [Vec2::new(60.0, 40.0), Vec2::new(20.0, 40.0)];
+        let eyes = [Vec2::new(130.0, 95.0), Vec2::new(70.0, 75.0)];
+        let transform = eye_line_superimposition(target, eyes).unwrap();
+        for (t, e) in target.iter().zip(eyes) {
+            let got = transform.transform_point(e);
+            assert!(got.distance(*t) < 1e-3, "{got} != {t}");
+        }
+        let proj = Alignment::EyeLine.superimpose(target, eyes).unwrap();
+        let (x, y) = proj * (eyes[0].x, eyes[0].y);
+        assert!(Vec2::new(x, y).distance(target[0]) < 1e-3);
+        assert!(Alignment::EyeLine.superimpose(shape(), shape()).is_none());
+        assert!(eye_line_superimposition(target, [eyes[0]; 2]).is_none());
+    }
+
+    #[test]
+    fn constrained_matches_procrustes() {
+        let target = shape();
+        let points = rotate(&target, 0.4, Vec2::new(30.0, 40.0), Vec2::new(8.0, 3.0));
+        let full = procrustes_superimposition(target.clone(), points.clone()).unwrap();
+        let same = constrained_superimposition(target.clone(), points.clone(), true, true).unwrap();
+        assert!((full.rotation - same.rotation).abs() < 1e-5);
+        assert!((full.scale - same.scale).abs() < 1e-4);
+        assert!(full.translation.distance(same.translation) < 1e-3);
+        // Only scale and translation
+        let scaled: Vec<_> = target.iter().map(|&p| p * 1.5 + Vec2::X).collect();
+        let fixed = constrained_superimposition(target.clone(), scaled, false, true).unwrap();
+        assert_eq!(fixed.rotation, 0.0);
+        assert!((fixed.scale - 1.5f32.recip()).abs() < 1e-4, "{fixed:?}");
+        // Only translation
+        let moved = constrained_superimposition(target.clone(), points, false, false).unwrap();
+        assert_eq!((moved.rotation, moved.scale), (0.0, 1.0));
+    }
+
+    #[test]
+    fn rigid_keeps_the_size() {
+        let target = shape();
+        let points: Vec<_> = rotate(&target, 2.0, Vec2::new(30.0, 40.0), Vec2::new(8.0, 3.0))
+            .into_iter()
+            .map(|p| p * 2.0)
+            .collect();
+        let rigid = rigid_superimposition(target.clone(), points.clone()).unwrap();
+        assert_eq!(rigid.scale, 1.0);
+        let proj = rigid.to_projection();
+        let (ax, ay) = proj * (points[0].x, points[0].y);
+        let (bx, by) = proj * (points[1].x, points[1].y);
+        let size = Vec2::new(ax, ay).distance(Vec2::new(bx, by));
+        assert!((size - points[0].distance(points[1])).abs() < 1e-2);
+        assert!((size - 2.0 * target[0].distance(target[1])).abs() < 1e-2);
+    }
+
+    #[test]
+    fn anisotropic_fit_recovers_scales() {
+        let points = shape();
+        let rotation = Vec2::from_angle(0.6);
+        let target: Vec<_> = points
+            .iter()
+            .map(|&p| rotation.rotate(p * Vec2::new(1.4, 0.7)) + Vec2::new(5.0, -3.0))
+            .collect();
+        let proj = anisotropic_fit(target.clone(), points.clone()).unwrap();
+        for (t, p) in target.iter().zip(&points) {
+            let (x, y) = proj * (p.x, p.y);
+            assert!(
+                Vec2::new(x, y).distance(*t) < 1e-2,
+                "{p} -> ({x}, {y}) != {t}"
+            );
+        }
+    }
+
+    #[test]
+    fn affine_fit_recovers_shear() {
+        let target = shape();
+        // Shear and scale each axis differently
+        let points: Vec<_> = target
+            .iter()
+            .map(|p| Vec2::new(1.5 * p.x + 0.3 * p.y + 4.0, 0.8 * p.y - 2.0))
+            .collect();
+        let proj = affine_fit(target.clone(), points.clone()).unwrap();
+        for (t, p) in target.iter().zip(&points) {
+            let (x, y) = proj * (p.x, p.y);
+            assert!(
+                Vec2::new(x, y).distance(*t) < 1e-2,
+                "{p} mapped to ({x}, {y}), not {t}"
+            );
+        }
+    }
+
+    #[test]
+    fn affine_fit_rejects_collinear_points() {
+        let line: Vec<_> = (0..5).map(|i| Vec2::splat(i as f32)).collect();
+        assert!(affine_fit(line.clone(), line).is_none());
+    }
+
+    #[test]
+    fn rotation_is_full_range() {
+        let mut target = shape();
+        center(&mut target).unwrap();
+        scale(&mut target).unwrap();
+        for angle in [-3.0, -2.0, -1.0, 0.0, 1.0, 2.0, 3.0] {
+            let points = rotate(&target, angle, Vec2::ZERO, Vec2::ZERO);
+            let theta = rotation(&target, &points).unwrap();
+            assert!((theta + angle).abs() < 1e-4, "{angle} rad: got {theta}");
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::iter::Sum;
+use std::ops::Div;
+use std::ops::Mul;
+use std::ops::Sub;
+
+/// Solve `a x = b` with Gaussian elimination (with partial pivoting)
+///
+/// `a` is a square matrix given by rows, `b` holds either numbers or vectors (solving one system
+/// per coordinate). Returns `x` in the place of `b`, or [`None`] if `a` is singular.
+pub(crate) fn solve<Row, T, B>(mut a: impl AsMut<[Row]>, mut b: B) -> Option<B>
+where
+    Row: AsRef<[f64]> + AsMut<[f64]>,
+    T: Copy + Sub<Output = T> + Div<f64, Output = T> + Sum,
+    f64: Mul<T, Output = T>,
+    B: AsMut<[T]>,
+{
+    let (a, x) = (a.as_mut(), b.as_mut());
+    let n = x.len();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| {
+            let (ai, aj) = (a[i].as_ref()[col], a[j].as_ref()[col]);
+            ai.abs().total_cmp(&aj.abs())
+        })?;
+        if a[pivot].as_ref()[col].abs() <= 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        x.swap(col, pivot);
+        let (done, rest) = a.split_at_mut(col + 1);
+        let pivot_row = done[col].as_ref();
+        for (offset, row) in rest.iter_mut().enumerate() {
+            let row = row.as_mut();
+            let factor = row[col] / pivot_row[col];
+            for (value, pivot) in row[col..].iter_mut().zip(&pivot_row[col..]) {
+                *value -= pivot * factor;
+            }
+            x[col + 1 + offset] = x[col + 1 + offset] - factor * x[col];
+        }
+    }
+    for row in (0..n).rev() {
+        let coefficients = a[row].as_ref();
+        let sum: T = (row + 1..n).map(|k| coefficients[k] * x[k]).sum();
+        x[row] = (x[row] - sum) / coefficients[row];
+    }
+    Some(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::DVec2;
+
+    use super::*;
+
+    #[test]
+    fn solves_numbers_and_vectors() {
+        // Needs pivoting: the first pivot is 0
+        let a = [[0.0, 2.0, 1.0], [1.0, 1.0, 0.0], [2.0, 0.0, 3.0]];
+        let x = solve(a, [7.0, 3.0, 11.0]).unwrap();
+        for (x, expected) in x.into_iter().zip([1.0, 2.0, 3.0]) {
+            assert!((x - expected).abs() < 1e-9, "{x} != {expected}");
+        }
+        let a = vec![vec![2.0, 1.0], vec![1.0, 3.0]];
+        let b = vec![DVec2::new(3.0, 5.0), DVec2::new(4.0, 5.0)];
+        let x = solve(a, b).unwrap();
+        assert!(x[0].distance(DVec2::new(1.0, 2.0)) < 1e-9);
+        assert!(x[1].distance(DVec2::new(1.0, 1.0)) < 1e-9);
+        assert!(solve([[1.0, 2.0], [2.0, 4.0]], [1.0, 2.0]).is_none());
+    }
+}
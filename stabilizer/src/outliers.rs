@@ -0,0 +1,172 @@
+use glam::Vec2;
+
+use crate::center;
+use crate::procrustes_superimposition;
+use crate::scaling_factor;
+use crate::SimilarityTransform;
+
+/// Default number of (normalized) median absolute deviations above which a landmark is an outlier
+pub const DEFAULT_MAD_THRESHOLD: f32 = 3.0;
+/// Scales the median absolute deviation to the standard deviation of normally distributed data
+const MAD_TO_STD: f32 = 1.4826;
+
+/// Median of the values (the upper one if there is an even number of them)
+fn median(values: &mut [f32]) -> Option<f32> {
+    if values.is_empty() {
+        return None;
+    }
+    let mid = values.len() / 2;
+    let (_, median, _) = values.select_nth_unstable_by(mid, f32::total_cmp);
+    Some(*median)
+}
+
+/// Coordinate-wise median of several shapes, after aligning them to the first one
+///
+/// A robust alternative to the [`mean_shape`](crate::mean_shape): a landmark that is badly placed
+/// in a few shapes barely moves the median.
+///
+/// Returns [`None`] if there are no shapes, they are empty or they don't have the same length
+pub fn median_shape(shapes: &[Vec<Vec2>]) -> Option<Vec<Vec2>> {
+    let first = shapes.first()?;
+    if first.is_empty() || shapes.iter().any(|shape| shape.len() != first.len()) {
+        return None;
+    }
+    let aligned = shapes
+        .iter()
+        .map(|shape| {
+            let transform =
+                procrustes_superimposition(first.iter().copied(), shape.iter().copied())?;
+            Some(
+                shape
+                    .iter()
+                    .map(|&p| transform.transform_point(p))
+                    .collect(),
+            )
+        })
+        .collect::<Option<Vec<Vec<Vec2>>>>()?;
+    (0..first.len())
+        .map(|ix| {
+            let mut xs: Vec<_> = aligned.iter().map(|shape| shape[ix].x).collect();
+            let mut ys: Vec<_> = aligned.iter().map(|shape| shape[ix].y).collect();
+            Some(Vec2::new(median(&mut xs)?, median(&mut ys)?))
+        })
+        .collect()
+}
+
+/// Find the landmarks of `points` that are far from their position in `reference` (e.g. a
+/// [`median_shape`]) once aligned
+///
+/// A landmark is an outlier if its distance to the reference is more than `k` (normalized) median
+/// absolute deviations above the median distance.
+///
+/// Returns the indices of the outliers, or [`None`] if empty or the lengths don't match
+pub fn outlier_landmarks(reference: &[Vec2], points: &[Vec2], k: f32) -> Option<Vec<usize>> {
+    if reference.len() != points.len() {
+        return None;
+    }
+    let transform = procrustes_superimposition(reference.iter().copied(), points.iter().copied())?;
+    let distances: Vec<_> = reference
+        .iter()
+        .zip(points)
+        .map(|(&r, &p)| transform.transform_point(p).distance(r))
+        .collect();
+    let med = median(&mut distances.clone())?;
+    let mut deviations: Vec<_> = distances.iter().map(|d| (d - med).abs()).collect();
+    let mad = median(&mut deviations)?;
+    // Avoid flagging rounding errors when the shapes match perfectly
+    let mut centered = reference.to_vec();
+    center(&mut centered)?;
+    let floor = scaling_factor(&centered)? * 1e-3;
+    let threshold = med + k * (MAD_TO_STD * mad).max(floor);
+    Some(
+        distances
+            .iter()
+            .enumerate()
+            .filter(|&(_, &d)| d > threshold)
+            .map(|(ix, _)| ix)
+            .collect(),
+    )
+}
+
+/// Same as [`procrustes_superimposition`], but the [`outlier_landmarks`] are dropped before the fit
+///
+/// Returns the transform and the indices of the dropped landmarks, or [`None`] if empty or the
+/// lengths don't match
+pub fn rejecting_procrustes_superimposition(
+    target: &[Vec2],
+    points: &[Vec2],
+    k: f32,
+) -> Option<(SimilarityTransform, Vec<usize>)> {
+    let outliers = outlier_landmarks(target, points, k)?;
+    let inliers = |points: &[Vec2]| {
+        points
+            .iter()
+            .enumerate()
+            .filter(|(ix, _)| !outliers.contains(ix))
+            .map(|(_, &p)| p)
+            .collect::<Vec<_>>()
+    };
+    let transform = procrustes_superimposition(inliers(target), inliers(points))?;
+    Some((transform, outliers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shape() -> Vec<Vec2> {
+        [
+            (10.0, 20.0),
+            (50.0, 18.0),
+            (30.0, 45.0),
+            (22.0, 70.0),
+            (41.0, 68.0),
+            (30.0, 30.0),
+            (15.0, 50.0),
+            (45.0, 50.0),
+        ]
+        .map(Vec2::from)
+        .to_vec()
+    }
+
+    #[test]
+    fn finds_misplaced_landmarks() {
+        let reference = shape();
+        let mut points: Vec<_> = reference
+            .iter()
+            .enumerate()
+            .map(|(ix, &p)| p * 2.0 + Vec2::new(5.0, 3.0) + 0.2 * Vec2::from_angle(ix as f32))
+            .collect();
+        points[3] += Vec2::new(30.0, 10.0);
+        assert_eq!(
+            outlier_landmarks(&reference, &points, DEFAULT_MAD_THRESHOLD),
+            Some(vec![3])
+        );
+        let (transform, dropped) =
+            rejecting_procrustes_superimposition(&reference, &points, DEFAULT_MAD_THRESHOLD)
+                .unwrap();
+        assert_eq!(dropped, [3]);
+        assert!((transform.scale - 0.5).abs() < 1e-2, "{transform:?}");
+    }
+
+    #[test]
+    fn no_outliers_in_perfect_matches() {
+        let reference = shape();
+        let points: Vec<_> = reference.iter().map(|&p| p + Vec2::ONE).collect();
+        assert_eq!(
+            outlier_landmarks(&reference, &points, DEFAULT_MAD_THRESHOLD),
+            Some(vec![])
+        );
+    }
+
+    #[test]
+    fn median_ignores_a_bad_shape() {
+        let good = shape();
+        let mut bad = good.clone();
+        bad[2] += Vec2::new(40.0, 0.0);
+        let median = median_shape(&[good.clone(), good.clone(), bad]).unwrap();
+        for (m, g) in median.iter().zip(&good) {
+            assert!(m.distance(*g) < 1.0, "{m} != {g}");
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! Piecewise affine warping: the landmarks are triangulated and each triangle is warped with its
+//! own affine transform
+//!
+//! Unlike a single global transform, this also corrects local differences (e.g. expressions), which
+//! is what morph-style outputs need. Outside of the triangulation (the convex hull of the
+//! landmarks) the image is warped with the [`procrustes_superimposition`] of the landmarks.
+
+use glam::DVec2;
+use glam::Vec2;
+use image::Rgb;
+use image::RgbImage;
+use imageproc::geometric_transformations::warp_with;
+use imageproc::geometric_transformations::Interpolation;
+
+use crate::procrustes_superimposition;
+
+/// Circumcircle of a triangle, its center and squared radius
+fn circumcircle(a: DVec2, b: DVec2, c: DVec2) -> Option<(DVec2, f64)> {
+    let d = 2.0 * (a.x * (b.y - c.y) + b.x * (c.y - a.y) + c.x * (a.y - b.y));
+    if d.abs() <= f64::EPSILON {
+        return None;
+    }
+    let (a2, b2, c2) = (a.length_squared(), b.length_squared(), c.length_squared());
+    let center = DVec2::new(
+        (a2 * (b.y - c.y) + b2 * (c.y - a.y) + c2 * (a.y - b.y)) / d,
+        (a2 * (c.x - b.x) + b2 * (a.x - c.x) + c2 * (b.x - a.x)) / d,
+    );
+    Some((center, center.distance_squared(a)))
+}
+
+/// Delaunay triangulation of the points (Bowyer-Watson)
+///
+/// Returns the triangles as indices into `points`, duplicated points are only used once.
+pub fn triangulate(points: &[Vec2]) -> Vec<[usize; 3]> {
+    let n = points.len();
+    if n < 3 {
+        return Vec::new();
+    }
+    let mut vertices: Vec<DVec2> = points.iter().map(|p| p.as_dvec2()).collect();
+    // A triangle containing all the points
+    let (min, max) = vertices.iter().fold(
+        (DVec2::splat(f64::INFINITY), DVec2::splat(f64::NEG_INFINITY)),
+        |(min, max), &p| (min.min(p), max.max(p)),
+    );
+    let center = (min + max) / 2.0;
+    let size = (max - min).max_element().max(1.0) * 20.0;
+    vertices.extend([
+        center + DVec2::new(-size, -size),
+        center + DVec2::new(size, -size),
+        center + DVec2::new(0.0, size),
+    ]);
+
+    let mut triangles: Vec<([usize; 3], DVec2, f64)> = Vec::new();
+    let [a, b, c] = [n, n + 1, n + 2];
+    let (center, radius) = circumcircle(vertices[a], vertices[b], vertices[c])
+        .expect("the super triangle is not degenerate");
+    triangles.push(([a, b, c], center, radius));
+
+    for (ix, &point) in vertices[..n].iter().enumerate() {
+        if vertices[..ix].contains(&point) {
+            continue;
+        }
+        let (bad, good): (Vec<_>, Vec<_>) = triangles
+            .into_iter()
+            .partition(|&(_, center, radius)| center.distance_squared(point) < radius);
+        triangles = good;
+        // The boundary of the hole: the edges that belong to a single bad triangle
+        let edges: Vec<[usize; 2]> = bad
+            .iter()
+            .flat_map(|&([a, b, c], _, _)| [[a, b], [b, c], [c, a]])
+            .collect();
+        for &[a, b] in &edges {
+            let shared = edges
+                .iter()
+                .filter(|&&[c, d]| (a, b) == (c, d) || (a, b) == (d, c))
+                .count();
+            if shared > 1 {
+                continue;
+            }
+            if let Some((center, radius)) = circumcircle(vertices[a], vertices[b], point) {
+                triangles.push(([a, b, ix], center, radius));
+            }
+        }
+    }
+
+    triangles
+        .into_iter()
+        .map(|(triangle, _, _)| triangle)
+        .filter(|triangle| triangle.iter().all(|&ix| ix < n))
+        .collect()
+}
+
+/// Barycentric coordinates of `p` in the triangle `abc`
+fn barycentric(p: Vec2, [a, b, c]: [Vec2; 3]) -> Option<[f32; 3]> {
+    let (v0, v1, v2) = (b - a, c - a, p - a);
+    let det = v0.perp_dot(v1);
+    if det.abs() <= f32::EPSILON {
+        return None;
+    }
+    let u = v2.perp_dot(v1) / det;
+    let v = v0.perp_dot(v2) / det;
+    Some([1.0 - u - v, u, v])
+}
+
+/// Warp `image` so that the landmarks at `source` end up at `target`
+///
+/// The output has the same size as the input, pixels outside of the image are set to `default`.
+///
+/// Returns [`None`] if there are less than three landmarks or the lengths don't match
+pub fn warp(
+    image: &RgbImage,
+    source: &[Vec2],
+    target: &[Vec2],
+    default: Rgb<u8>,
+) -> Option<RgbImage> {
+    if source.len() != target.len() || source.len() < 3 {
+        return None;
+    }
+    // Maps the output (target) to the input (source)
+    let global = procrustes_superimposition(source.iter().copied(), target.iter().copied())?;
+    let triangles = triangulate(target);
+
+    // Which triangle covers each output pixel
+    let (width, height) = image.dimensions();
+    let mut cover = vec![u32::MAX; width as usize * height as usize];
+    for (tri_ix, &[a, b, c]) in triangles.iter().enumerate() {
+        let corners = [target[a], target[b], target[c]];
+        let min = corners[0].min(corners[1]).min(corners[2]).max(Vec2::ZERO);
+        let max = corners[0]
+            .max(corners[1])
+            .max(corners[2])
+            .min(Vec2::new(width as f32 - 1.0, height as f32 - 1.0));
+        if min.x > max.x || min.y > max.y {
+            continue;
+        }
+        for y in min.y.floor() as u32..=max.y.ceil() as u32 {
+            for x in min.x.floor() as u32..=max.x.ceil() as u32 {
+                let p = Vec2::new(x as f32, y as f32);
+                let Some(weights) = barycentric(p, corners) else {
+                    continue;
+                };
+                if weights.iter().all(|&w| w >= -1e-4) {
+                    cover[(y * width + x) as usize] = tri_ix as u32;
+                }
+            }
+        }
+    }
+
+    Some(warp_with(
+        image,
+        |x, y| {
+            let (ix, iy) = (x as u32, y as u32);
+            let tri_ix = if ix < width && iy < height {
+                cover[(iy * width + ix) as usize]
+            } else {
+                u32::MAX
+            };
+            let Some(&[a, b, c]) = triangles.get(tri_ix as usize) else {
+                return global.transform_point(Vec2::new(x, y)).into();
+            };
+            let p = Vec2::new(x, y);
+            match barycentric(p, [target[a], target[b], target[c]]) {
+                Some([wa, wb, wc]) => (source[a] * wa + source[b] * wb + source[c] * wc).into(),
+                None => global.transform_point(p).into(),
+            }
+        },
+        Interpolation::Bilinear,
+        default,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn triangulates_a_square() {
+        let square = [Vec2::ZERO, Vec2::X, Vec2::ONE, Vec2::Y];
+        let triangles = triangulate(&square);
+        assert_eq!(triangles.len(), 2);
+    }
+
+    #[test]
+    fn triangulation_covers_the_convex_hull() {
+        // A 4x4 grid: 9 squares, 18 triangles
+        let grid: Vec<_> = (0..16)
+            .map(|i| Vec2::new((i % 4) as f32, (i / 4) as f32 + 0.01 * (i % 3) as f32))
+            .collect();
+        let area: f32 = triangulate(&grid)
+            .iter()
+            .map(|&[a, b, c]| (grid[b] - grid[a]).perp_dot(grid[c] - grid[a]).abs() / 2.0)
+            .sum();
+        assert!((area - 9.0).abs() < 0.1, "area {area}");
+    }
+
+    #[test]
+    fn identity_warp_keeps_the_image() {
+        let image = RgbImage::from_fn(32, 32, |x, y| Rgb([(x * 8) as u8, (y * 8) as u8, 0]));
+        let landmarks = [
+            Vec2::new(4.0, 4.0),
+            Vec2::new(27.0, 5.0),
+            Vec2::new(16.0, 16.0),
+            Vec2::new(6.0, 26.0),
+            Vec2::new(25.0, 27.0),
+        ];
+        let warped = warp(&image, &landmarks, &landmarks, Rgb([0, 0, 0])).unwrap();
+        // Bilinear interpolation does not sample the last row/column
+        for (x, y, pixel) in warped
+            .enumerate_pixels()
+            .filter(|&(x, y, _)| x < 31 && y < 31)
+        {
+            let expected = image.get_pixel(x, y);
+            assert!(
+                pixel
+                    .0
+                    .iter()
+                    .zip(expected.0)
+                    .all(|(&a, b)| a.abs_diff(b) <= 1),
+                "({x}, {y}): {pixel:?} != {expected:?}"
+            );
+        }
+    }
+}
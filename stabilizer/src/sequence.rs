@@ -0,0 +1,344 @@
+use glam::Vec2;
+
+use crate::constrained_superimposition;
+use crate::mean_shape;
+use crate::procrustes_superimposition;
+use crate::smoothing::Smoothing;
+use crate::SimilarityTransform;
+
+/// What the shapes of a sequence are aligned to
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reference {
+    /// The [`mean_shape`] of the (non missing) shapes
+    Mean,
+    /// The shape of this frame
+    Frame(usize),
+    /// A fixed shape
+    Shape(Vec<Vec2>),
+    /// The reference points matching each frame, for when the frames don't all use the same
+    /// landmarks (e.g. only the visible ones)
+    PerFrame(Vec<Vec<Vec2>>),
+}
+
+/// The shapes a [`Reference`] aligns each frame to
+enum Targets {
+    Shared(Vec<Vec2>),
+    PerFrame(Vec<Vec<Vec2>>),
+}
+
+impl Targets {
+    /// The target of frame `ix`
+    fn get(&self, ix: usize) -> &[Vec2] {
+        match self {
+            Targets::Shared(shape) => shape,
+            Targets::PerFrame(targets) => targets.get(ix).map_or(&[], Vec::as_slice),
+        }
+    }
+}
+
+impl Reference {
+    /// The reference shapes of the sequence
+    fn targets(self, shapes: &[Vec<Vec2>]) -> Option<Targets> {
+        let shape = match self {
+            Reference::Mean => {
+                let present: Vec<_> = shapes
+                    .iter()
+                    .filter(|shape| !shape.is_empty())
+                    .cloned()
+                    .collect();
+                mean_shape(&present)?
+            }
+            Reference::Frame(ix) => shapes.get(ix)?.clone(),
+            Reference::Shape(shape) => shape,
+            Reference::PerFrame(targets) => return Some(Targets::PerFrame(targets)),
+        };
+        (!shape.is_empty()).then_some(Targets::Shared(shape))
+    }
+}
+
+/// Which parts of the [`SimilarityTransform`]s are fitted, the others are left as the identity
+/// (no rotation and a scale of 1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fit {
+    pub rotate: bool,
+    pub scale: bool,
+}
+
+impl Default for Fit {
+    fn default() -> Self {
+        Self {
+            rotate: true,
+            scale: true,
+        }
+    }
+}
+
+impl Fit {
+    /// Align `shape` to `target` with [`procrustes_superimposition`] (or
+    /// [`constrained_superimposition`] if the rotation or the scale are left out)
+    ///
+    /// Returns [`None`] if empty or the lengths don't match
+    pub fn superimpose(self, target: &[Vec2], shape: &[Vec2]) -> Option<SimilarityTransform> {
+        if shape.len() != target.len() {
+            return None;
+        }
+        let (target, shape) = (target.iter().copied(), shape.iter().copied());
+        if self.rotate && self.scale {
+            procrustes_superimposition(target, shape)
+        } else {
+            constrained_superimposition(target, shape, self.rotate, self.scale)
+        }
+    }
+}
+
+/// Interpolate the missing transforms from their neighbours (or copy the closest one at the start
+/// and end), see [`SimilarityTransform::interpolate_transforms`]
+///
+/// Use it to keep the frames where no face was detected instead of dropping them.
+///
+/// Returns [`None`] if all of them are missing
+pub fn fill_missing(values: &[Option<SimilarityTransform>]) -> Option<Vec<SimilarityTransform>> {
+    let known: Vec<_> = values
+        .iter()
+        .enumerate()
+        .filter_map(|(ix, t)| t.map(|t| (ix, t)))
+        .collect();
+    let (&first, &last) = (known.first()?, known.last()?);
+    let filled = values
+        .iter()
+        .enumerate()
+        .map(|(ix, &value)| match value {
+            Some(t) => t,
+            None if ix < first.0 => first.1,
+            None if ix > last.0 => last.1,
+            None => {
+                let next = known.partition_point(|&(k, _)| k < ix);
+                let (a, ta) = known[next - 1];
+                let (b, tb) = known[next];
+                SimilarityTransform::interpolate_transforms(
+                    &ta,
+                    &tb,
+                    (ix - a) as f32 / (b - a) as f32,
+                )
+            }
+        })
+        .collect();
+    Some(filled)
+}
+
+/// Align a whole sequence of shapes to a reference
+///
+/// Missing frames are represented by empty shapes (or shapes with a different number of points
+/// than the reference), their transforms are interpolated from the neighbouring frames (or copied
+/// from the closest one at the start and end of the sequence). The transforms are then smoothed
+/// if `smoothing` is set.
+///
+/// This is [`fit_sequence`] followed by [`smooth_sequence`], call them separately to adjust the
+/// fitted transforms in between.
+///
+/// Returns [`None`] if the reference can't be computed or no frame can be aligned to it
+pub fn align_sequence(
+    shapes: &[Vec<Vec2>],
+    reference: Reference,
+    smoothing: Option<Smoothing>,
+) -> Option<Vec<SimilarityTransform>> {
+    let fitted = fit_sequence(shapes, reference, Fit::default())?;
+    smooth_sequence(&fitted, smoothing)
+}
+
+/// Align each shape of a sequence to the reference, the missing frames (see [`align_sequence`])
+/// are [`None`]
+///
+/// Returns [`None`] if the reference can't be computed
+pub fn fit_sequence(
+    shapes: &[Vec<Vec2>],
+    reference: Reference,
+    fit: Fit,
+) -> Option<Vec<Option<SimilarityTransform>>> {
+    let targets = reference.targets(shapes)?;
+    let fitted = shapes
+        .iter()
+        .enumerate()
+        .map(|(ix, shape)| fit.superimpose(targets.get(ix), shape))
+        .collect();
+    Some(fitted)
+}
+
+/// Interpolate the missing transforms of a sequence (see [`fill_missing`]) and smooth them if
+/// `smoothing` is set
+///
+/// Returns [`None`] if all of them are missing
+pub fn smooth_sequence(
+    fitted: &[Option<SimilarityTransform>],
+    smoothing: Option<Smoothing>,
+) -> Option<Vec<SimilarityTransform>> {
+    let transforms = fill_missing(fitted)?;
+    Some(match smoothing {
+        Some(smoothing) => smoothing.smooth(&transforms),
+        None => transforms,
+    })
+}
+
+/// Align each frame to the previous one (a rolling reference), re-anchoring the chain to the
+/// reference every `keyframe_interval` frames
+///
+/// Chaining follows slow changes (e.g. aging in a timelapse) better than aligning every frame to
+/// the same reference, but the errors accumulate. Keyframes are aligned directly to the reference
+/// and the accumulated drift is distributed linearly across the frames between them. Missing
+/// frames are handled like in [`align_sequence`].
+///
+/// Returns [`None`] if the reference can't be computed or no frame can be aligned to it
+pub fn align_chained(
+    shapes: &[Vec<Vec2>],
+    reference: Reference,
+    keyframe_interval: usize,
+) -> Option<Vec<SimilarityTransform>> {
+    let targets = reference.targets(shapes)?;
+    let interval = keyframe_interval.max(1);
+    let direct = |ix: usize, shape: &Vec<Vec2>| Fit::default().superimpose(targets.get(ix), shape);
+
+    // Chain the frame to frame transforms, starting at the first frame that can be aligned
+    let mut chained = vec![None; shapes.len()];
+    let mut previous: Option<(&Vec<Vec2>, SimilarityTransform)> = None;
+    for (ix, shape) in shapes.iter().enumerate() {
+        let transform = match previous {
+            Some((last, chain)) if last.len() == shape.len() => {
+                procrustes_superimposition(last.iter().copied(), shape.iter().copied())
+                    .map(|step| step.compose(&chain))
+            }
+            _ => direct(ix, shape),
+        };
+        if let Some(transform) = transform {
+            chained[ix] = Some(transform);
+            previous = Some((shape, transform));
+        }
+    }
+
+    // The correction that takes the chain back to the reference at each keyframe
+    let last_frame = chained.iter().rposition(Option::is_some)?;
+    let corrections: Vec<_> = chained
+        .iter()
+        .zip(shapes)
+        .enumerate()
+        .map(|(ix, (chain, shape))| {
+            if ix % interval != 0 && ix != last_frame {
+                return None;
+            }
+            Some(chain.as_ref()?.inverse().compose(&direct(ix, shape)?))
+        })
+        .collect();
+    let corrections = fill_missing(&corrections)?;
+
+    let chained: Vec<_> = chained
+        .into_iter()
+        .zip(corrections)
+        .map(|(chain, correction)| chain.map(|chain| chain.compose(&correction)))
+        .collect();
+    fill_missing(&chained)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shape(offset: Vec2) -> Vec<Vec2> {
+        [(0.0, 0.0), (10.0, 1.0), (5.0, 12.0), (-2.0, 7.0)]
+            .map(|p| Vec2::from(p) + offset)
+            .to_vec()
+    }
+
+    #[test]
+    fn interpolates_missing_frames() {
+        let shapes = vec![
+            shape(Vec2::ZERO),
+            Vec::new(),
+            shape(Vec2::new(4.0, -2.0)),
+            Vec::new(),
+        ];
+        let transforms = align_sequence(&shapes, Reference::Frame(0), None).unwrap();
+        assert_eq!(transforms.len(), shapes.len());
+        let translation = |ix: usize| transforms[ix].translation;
+        assert!(translation(0).distance(Vec2::ZERO) < 1e-4);
+        assert!(translation(1).distance(Vec2::new(-2.0, 1.0)) < 1e-4);
+        assert!(translation(2).distance(Vec2::new(-4.0, 2.0)) < 1e-4);
+        // Copied from the last frame
+        assert_eq!(transforms[3], transforms[2]);
+    }
+
+    #[test]
+    fn aligns_to_the_mean() {
+        let shapes = vec![shape(Vec2::new(-1.0, 0.0)), shape(Vec2::new(1.0, 0.0))];
+        let transforms = align_sequence(&shapes, Reference::Mean, None).unwrap();
+        assert!(transforms[0].translation.distance(Vec2::X) < 1e-3);
+        assert!(transforms[1].translation.distance(-Vec2::X) < 1e-3);
+    }
+
+    #[test]
+    fn per_frame_targets() {
+        let reference = shape(Vec2::ZERO);
+        // The second frame only has three of the points
+        let shapes = vec![
+            shape(Vec2::new(2.0, 0.0)),
+            shape(Vec2::new(0.0, 3.0))[1..].to_vec(),
+        ];
+        let targets = vec![reference.clone(), reference[1..].to_vec()];
+        let fit = Fit {
+            rotate: false,
+            scale: false,
+        };
+        let fitted = fit_sequence(&shapes, Reference::PerFrame(targets), fit).unwrap();
+        let [Some(a), Some(b)] = fitted[..] else {
+            panic!("both frames can be aligned: {fitted:?}");
+        };
+        assert!(a.translation.distance(Vec2::new(-2.0, 0.0)) < 1e-4);
+        assert!(b.translation.distance(Vec2::new(0.0, -3.0)) < 1e-4);
+        assert_eq!((b.rotation, b.scale), (0.0, 1.0));
+        // Mismatched lengths are missing frames
+        let targets = vec![reference.clone(), reference.clone()];
+        let fitted = fit_sequence(&shapes, Reference::PerFrame(targets), fit).unwrap();
+        assert!(fitted[1].is_none());
+        assert_eq!(
+            smooth_sequence(&fitted, None).unwrap()[1],
+            fitted[0].unwrap()
+        );
+    }
+
+    #[test]
+    fn chained_keyframes_match_the_reference() {
+        let rotated = |angle: f32, offset: Vec2| -> Vec<Vec2> {
+            let rotation = Vec2::from_angle(angle);
+            shape(Vec2::ZERO)
+                .into_iter()
+                .map(|p| rotation.rotate(p) + offset)
+                .collect()
+        };
+        let mut shapes: Vec<_> = (0..11)
+            .map(|i| rotated(0.05 * i as f32, Vec2::new(i as f32, 0.0)))
+            .collect();
+        // Noise that would propagate through the whole chain
+        shapes[2][1] += Vec2::new(1.5, -1.0);
+        shapes[7] = Vec::new();
+        let chained = align_chained(&shapes, Reference::Frame(0), 5).unwrap();
+        let direct = align_sequence(&shapes, Reference::Frame(0), None).unwrap();
+        assert_eq!(chained.len(), shapes.len());
+        for ix in [0, 5, 10] {
+            let (a, b) = (chained[ix], direct[ix]);
+            assert!(
+                a.translation.distance(b.translation) < 1e-3,
+                "{ix}: {a:?} != {b:?}"
+            );
+            assert!(
+                (a.rotation - b.rotation).abs() < 1e-4,
+                "{ix}: {a:?} != {b:?}"
+            );
+            assert!((a.scale - b.scale).abs() < 1e-4, "{ix}: {a:?} != {b:?}");
+        }
+    }
+
+    #[test]
+    fn needs_an_aligned_frame() {
+        let shapes = vec![Vec::new(), Vec::new()];
+        assert!(align_sequence(&shapes, Reference::Shape(shape(Vec2::ZERO)), None).is_none());
+        assert!(align_sequence(&shapes, Reference::Frame(5), None).is_none());
+    }
+}
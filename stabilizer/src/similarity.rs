@@ -0,0 +1,276 @@
+use glam::Affine2;
+use glam::Vec2;
+use imageproc::geometric_transformations::Projection;
+
+/// A similarity transform: `p' = scale · R(rotation) · p + translation`
+///
+/// Returned by [`procrustes_superimposition`](crate::procrustes_superimposition), unlike a
+/// [`Projection`] its parameters can be inspected and modified.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimilarityTransform {
+    pub translation: Vec2,
+    /// Rotation in radians
+    pub rotation: f32,
+    pub scale: f32,
+}
+
+impl Default for SimilarityTransform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl SimilarityTransform {
+    /// The transform that does nothing
+    pub const IDENTITY: Self = Self {
+        translation: Vec2::ZERO,
+        rotation: 0.0,
+        scale: 1.0,
+    };
+
+    /// Apply the transform to a point
+    pub fn transform_point(&self, point: Vec2) -> Vec2 {
+        self.scale * Vec2::from_angle(self.rotation).rotate(point) + self.translation
+    }
+
+    /// The transform that undoes this one
+    ///
+    /// The result is not finite if the scale is 0
+    pub fn inverse(&self) -> Self {
+        let rotation = -self.rotation;
+        let scale = self.scale.recip();
+        Self {
+            translation: -scale * Vec2::from_angle(rotation).rotate(self.translation),
+            rotation,
+            scale,
+        }
+    }
+
+    /// The transform that applies `self` and then `other`
+    pub fn compose(&self, other: &Self) -> Self {
+        Self {
+            translation: other.transform_point(self.translation),
+            rotation: self.rotation + other.rotation,
+            scale: self.scale * other.scale,
+        }
+    }
+
+    /// Interpolate the parameters between `self` (`t = 0`) and `other` (`t = 1`)
+    ///
+    /// The rotation takes the shortest path
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            translation: self.translation.lerp(other.translation, t),
+            rotation: self.rotation + shortest_rotation(self.rotation, other.rotation) * t,
+            scale: self.scale + (other.scale - self.scale) * t,
+        }
+    }
+
+    /// Interpolate between `prev` (`t = 0`) and `next` (`t = 1`), e.g. for a frame where no face
+    /// was detected
+    ///
+    /// The rotation takes the shortest path and the scale is interpolated in log space, so zooming
+    /// in and out at the same rate are symmetric.
+    pub fn interpolate_transforms(prev: &Self, next: &Self, t: f32) -> Self {
+        Self {
+            translation: prev.translation.lerp(next.translation, t),
+            rotation: prev.rotation + shortest_rotation(prev.rotation, next.rotation) * t,
+            scale: prev.scale * (next.scale / prev.scale).powf(t),
+        }
+    }
+
+    /// The [`Affine2`] that applies this transform, for warping backends other than imageproc
+    pub fn to_affine2(&self) -> Affine2 {
+        Affine2::from_scale_angle_translation(
+            Vec2::splat(self.scale),
+            self.rotation,
+            self.translation,
+        )
+    }
+
+    /// The row-major 3×3 matrix that applies this transform to homogeneous coordinates (the
+    /// layout of [`Projection::from_matrix`])
+    pub fn to_matrix(&self) -> [f32; 9] {
+        let (sin, cos) = self.rotation.sin_cos();
+        let (a, b) = (self.scale * cos, self.scale * sin);
+        #[rustfmt::skip]
+        let matrix = [
+            a, -b, self.translation.x,
+            b, a, self.translation.y,
+            0.0, 0.0, 1.0,
+        ];
+        matrix
+    }
+
+    /// The [`Projection`] that applies this transform
+    pub fn to_projection(&self) -> Projection {
+        Projection::rotate(self.rotation)
+            .and_then(Projection::scale(self.scale, self.scale))
+            .and_then(Projection::translate(
+                self.translation.x,
+                self.translation.y,
+            ))
+    }
+}
+
+/// A [`SimilarityTransform`] applied after an optional horizontal flip (`x → -x`)
+///
+/// Returned by [`reflective_superimposition`](crate::reflective_superimposition), for the points
+/// that are a mirror image of the target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReflectiveTransform {
+    pub similarity: SimilarityTransform,
+    /// Whether the points are flipped before the similarity
+    pub reflected: bool,
+}
+
+impl ReflectiveTransform {
+    /// Apply the transform to a point
+    pub fn transform_point(&self, point: Vec2) -> Vec2 {
+        let point = if self.reflected {
+            Vec2::new(-point.x, point.y)
+        } else {
+            point
+        };
+        self.similarity.transform_point(point)
+    }
+
+    /// The [`Projection`] that applies this transform
+    pub fn to_projection(&self) -> Projection {
+        let similarity = self.similarity.to_projection();
+        if self.reflected {
+            Projection::scale(-1.0, 1.0).and_then(similarity)
+        } else {
+            similarity
+        }
+    }
+}
+
+impl From<SimilarityTransform> for Affine2 {
+    fn from(value: SimilarityTransform) -> Self {
+        value.to_affine2()
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl From<SimilarityTransform> for nalgebra::Similarity2<f32> {
+    fn from(value: SimilarityTransform) -> Self {
+        nalgebra::Similarity2::new(
+            nalgebra::Vector2::new(value.translation.x, value.translation.y),
+            value.rotation,
+            value.scale,
+        )
+    }
+}
+
+/// The rotation (in radians, between -π and π) that takes the angle `from` to `to` the shortest
+/// way around
+pub(crate) fn shortest_rotation(from: f32, to: f32) -> f32 {
+    let delta = to - from;
+    delta.sin().atan2(delta.cos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform() -> SimilarityTransform {
+        SimilarityTransform {
+            translation: Vec2::new(3.0, -7.0),
+            rotation: 0.7,
+            scale: 1.5,
+        }
+    }
+
+    #[test]
+    fn inverse_undoes() {
+        let t = transform();
+        let p = Vec2::new(12.0, 5.0);
+        let back = t.inverse().transform_point(t.transform_point(p));
+        assert!(back.distance(p) < 1e-4, "{back} != {p}");
+    }
+
+    #[test]
+    fn compose_applies_both() {
+        let a = transform();
+        let b = SimilarityTransform {
+            translation: Vec2::new(-1.0, 2.0),
+            rotation: -2.0,
+            scale: 0.5,
+        };
+        let p = Vec2::new(12.0, 5.0);
+        let expected = b.transform_point(a.transform_point(p));
+        let got = a.compose(&b).transform_point(p);
+        assert!(got.distance(expected) < 1e-4, "{got} != {expected}");
+    }
+
+    #[test]
+    fn projection_matches() {
+        let t = transform();
+        let p = Vec2::new(12.0, 5.0);
+        let (x, y) = t.to_projection() * (p.x, p.y);
+        assert!(Vec2::new(x, y).distance(t.transform_point(p)) < 1e-4);
+    }
+
+    #[test]
+    fn matrix_matches() {
+        let t = transform();
+        let p = Vec2::new(12.0, 5.0);
+        let projection = Projection::from_matrix(t.to_matrix()).expect("invertible");
+        let (x, y) = projection * (p.x, p.y);
+        assert!(Vec2::new(x, y).distance(t.transform_point(p)) < 1e-4);
+    }
+
+    #[test]
+    fn affine2_matches() {
+        let t = transform();
+        let p = Vec2::new(12.0, 5.0);
+        let got = t.to_affine2().transform_point2(p);
+        assert!(got.distance(t.transform_point(p)) < 1e-4);
+    }
+
+    #[cfg(feature = "nalgebra")]
+    #[test]
+    fn similarity2_matches() {
+        let t = transform();
+        let p = Vec2::new(12.0, 5.0);
+        let got = nalgebra::Similarity2::from(t) * nalgebra::Point2::new(p.x, p.y);
+        assert!(Vec2::new(got.x, got.y).distance(t.transform_point(p)) < 1e-4);
+    }
+
+    #[test]
+    fn lerp_takes_the_shortest_rotation() {
+        let a = SimilarityTransform {
+            rotation: 3.0,
+            ..SimilarityTransform::IDENTITY
+        };
+        let b = SimilarityTransform {
+            rotation: -3.0,
+            ..SimilarityTransform::IDENTITY
+        };
+        let mid = a.lerp(&b, 0.5);
+        assert!(mid.rotation.cos() < -0.99, "{}", mid.rotation);
+        assert_eq!(a.lerp(&b, 0.0), a);
+        let mid = SimilarityTransform::interpolate_transforms(&a, &b, 0.5);
+        assert!(mid.rotation.cos() < -0.99, "{}", mid.rotation);
+    }
+
+    #[test]
+    fn interpolates_the_scale_in_log_space() {
+        let a = SimilarityTransform {
+            scale: 0.5,
+            ..transform()
+        };
+        let b = SimilarityTransform {
+            translation: Vec2::new(-3.0, 1.0),
+            rotation: 0.7 - 0.4,
+            scale: 2.0,
+        };
+        let mid = SimilarityTransform::interpolate_transforms(&a, &b, 0.5);
+        assert!((mid.scale - 1.0).abs() < 1e-6, "{mid:?}");
+        assert!((mid.rotation - 0.5).abs() < 1e-6, "{mid:?}");
+        assert!(mid.translation.distance(Vec2::new(0.0, -3.0)) < 1e-6);
+        let end = SimilarityTransform::interpolate_transforms(&a, &b, 1.0);
+        assert!((end.scale - b.scale).abs() < 1e-6 && (end.rotation - b.rotation).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,496 @@
+//! Temporal smoothing of the transforms of a sequence of images
+//!
+//! The per-image transforms jitter because of the noise in the landmarks, smoothing their
+//! parameters (translation, rotation and scale) before warping gives a steadier video/timelapse.
+
+use glam::DMat2;
+use glam::DVec2;
+use glam::Vec2;
+
+use crate::similarity::shortest_rotation;
+use crate::SimilarityTransform;
+
+/// Default minimum cutoff frequency (in cycles per image) of the [`OneEuroFilter`]
+pub const DEFAULT_MIN_CUTOFF: f32 = 0.1;
+/// Default speed coefficient of the [`OneEuroFilter`]
+pub const DEFAULT_BETA: f32 = 0.01;
+/// Cutoff frequency (in cycles per image) used to smooth the derivative in the [`OneEuroFilter`]
+const DERIVATIVE_CUTOFF: f32 = 1.0;
+/// Default smoothness of the [`kalman_smooth`]er
+pub const DEFAULT_SMOOTHNESS: f32 = 100.0;
+
+/// Default strength of the [`l1_smooth`]ing
+pub const DEFAULT_L1_STRENGTH: f32 = 1.0;
+/// Weights of the L1 norms of the velocity, acceleration and jerk in [`l1_smooth`] (from
+/// Grundmann et al.)
+const L1_WEIGHTS: [f64; 3] = [10.0, 1.0, 100.0];
+/// Number of ADMM iterations of [`l1_smooth`]
+const L1_ITERATIONS: usize = 200;
+/// ADMM penalty parameter of [`l1_smooth`]
+const L1_RHO: f64 = 10.0;
+
+/// How to smooth the transforms of a sequence of images
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Smoothing {
+    /// Causal filtering with a [`SimilarityFilter`]
+    OneEuro { min_cutoff: f32, beta: f32 },
+    /// Offline smoothing of the whole sequence with [`kalman_smooth`]
+    Kalman { smoothness: f32 },
+    /// Offline "cinematic" smoothing of the whole sequence with [`l1_smooth`]
+    L1 { strength: f32 },
+}
+
+impl Smoothing {
+    /// Smooth the whole sequence of transforms
+    pub fn smooth(&self, transforms: &[SimilarityTransform]) -> Vec<SimilarityTransform> {
+        match *self {
+            Self::OneEuro { min_cutoff, beta } => {
+                let mut filter = SimilarityFilter::new(min_cutoff, beta);
+                transforms.iter().map(|&t| filter.filter(t)).collect()
+            }
+            Self::Kalman { smoothness } => kalman_smooth(transforms, smoothness),
+            Self::L1 { strength } => l1_smooth(transforms, strength),
+        }
+    }
+}
+
+/// Parses `one-euro`, `kalman` or `l1` (with the default parameters)
+impl std::str::FromStr for Smoothing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "one-euro" => Ok(Self::OneEuro {
+                min_cutoff: DEFAULT_MIN_CUTOFF,
+                beta: DEFAULT_BETA,
+            }),
+            "kalman" => Ok(Self::Kalman {
+                smoothness: DEFAULT_SMOOTHNESS,
+            }),
+            "l1" => Ok(Self::L1 {
+                strength: DEFAULT_L1_STRENGTH,
+            }),
+            _ => Err(format!("expected one-euro, kalman or l1, found {s}")),
+        }
+    }
+}
+
+/// The [One Euro filter](https://gery.casiez.net/1euro/): a low-pass filter whose cutoff
+/// frequency increases with the speed of the signal
+///
+/// Slow movements (jitter) are heavily smoothed while fast ones are followed with little lag.
+/// Assumes the samples are evenly spaced (one per image).
+#[derive(Debug, Clone)]
+pub struct OneEuroFilter {
+    min_cutoff: f32,
+    beta: f32,
+    /// Last filtered value and derivative
+    last: Option<(f32, f32)>,
+}
+
+impl OneEuroFilter {
+    /// Create a filter with the given minimum cutoff frequency (in cycles per image) and speed
+    /// coefficient
+    ///
+    /// Lower `min_cutoff` reduces jitter, higher `beta` reduces lag.
+    pub fn new(min_cutoff: f32, beta: f32) -> Self {
+        Self {
+            min_cutoff,
+            beta,
+            last: None,
+        }
+    }
+
+    /// Smoothing factor of an exponential filter with this cutoff frequency
+    fn alpha(cutoff: f32) -> f32 {
+        let tau = 1.0 / (std::f32::consts::TAU * cutoff);
+        1.0 / (1.0 + tau)
+    }
+
+    /// Filter the next sample
+    pub fn filter(&mut self, value: f32) -> f32 {
+        let Some((last, last_derivative)) = self.last else {
+            self.last = Some((value, 0.0));
+            return value;
+        };
+        let derivative =
+            last_derivative + Self::alpha(DERIVATIVE_CUTOFF) * ((value - last) - last_derivative);
+        let cutoff = self.min_cutoff + self.beta * derivative.abs();
+        let filtered = last + Self::alpha(cutoff) * (value - last);
+        self.last = Some((filtered, derivative));
+        filtered
+    }
+}
+
+impl Default for OneEuroFilter {
+    fn default() -> Self {
+        Self::new(DEFAULT_MIN_CUTOFF, DEFAULT_BETA)
+    }
+}
+
+/// Filters the parameters of a sequence of [`SimilarityTransform`] transforms with [`OneEuroFilter`]s
+#[derive(Debug, Clone, Default)]
+pub struct SimilarityFilter {
+    x: OneEuroFilter,
+    y: OneEuroFilter,
+    rotation: OneEuroFilter,
+    scale: OneEuroFilter,
+    /// Last (unwrapped) rotation, avoids jumps between -π and π
+    last_rotation: Option<f32>,
+}
+
+impl SimilarityFilter {
+    /// See [`OneEuroFilter::new`]
+    pub fn new(min_cutoff: f32, beta: f32) -> Self {
+        let filter = OneEuroFilter::new(min_cutoff, beta);
+        Self {
+            x: filter.clone(),
+            y: filter.clone(),
+            rotation: filter.clone(),
+            scale: filter,
+            last_rotation: None,
+        }
+    }
+
+    /// Filter the next transform
+    pub fn filter(&mut self, transform: SimilarityTransform) -> SimilarityTransform {
+        let rotation = match self.last_rotation {
+            Some(last) => last + shortest_rotation(last, transform.rotation),
+            None => transform.rotation,
+        };
+        self.last_rotation = Some(rotation);
+        SimilarityTransform {
+            translation: Vec2::new(
+                self.x.filter(transform.translation.x),
+                self.y.filter(transform.translation.y),
+            ),
+            rotation: self.rotation.filter(rotation),
+            scale: self.scale.filter(transform.scale),
+        }
+    }
+}
+
+/// Unwrap the angles so that consecutive ones never differ by more than π
+fn unwrap_angles(angles: impl IntoIterator<Item = f32>) -> Vec<f32> {
+    let mut last: Option<f32> = None;
+    angles
+        .into_iter()
+        .map(|angle| {
+            let angle = match last {
+                Some(last) => last + shortest_rotation(last, angle),
+                None => angle,
+            };
+            last = Some(angle);
+            angle
+        })
+        .collect()
+}
+
+/// Rauch-Tung-Striebel smoother of a scalar signal with a constant velocity model
+///
+/// `smoothness` is the ratio between the measurement and the process noise.
+fn rts_smooth(values: &[f32], smoothness: f64) -> Vec<f32> {
+    let f = DMat2::from_cols(DVec2::new(1.0, 0.0), DVec2::new(1.0, 1.0));
+    // Discrete white noise acceleration, with the measurement noise fixed to 1
+    let q = DMat2::from_cols(DVec2::new(0.25, 0.5), DVec2::new(0.5, 1.0)) * smoothness.recip();
+    let Some(&first) = values.first() else {
+        return Vec::new();
+    };
+
+    // Forward pass: predicted and filtered states
+    let mut predicted = Vec::with_capacity(values.len());
+    let mut filtered: Vec<(DVec2, DMat2)> = Vec::with_capacity(values.len());
+    let mut state = (DVec2::new(first.into(), 0.0), DMat2::IDENTITY * 1e6);
+    for &value in values {
+        let (x, p) = state;
+        let prediction = if filtered.is_empty() {
+            (x, p)
+        } else {
+            (f * x, f * p * f.transpose() + q)
+        };
+        let (x, p) = prediction;
+        let gain = p.col(0) / (p.col(0).x + 1.0);
+        let x = x + gain * (f64::from(value) - x.x);
+        let p = p - DMat2::from_cols(gain * p.row(0).x, gain * p.row(0).y);
+        predicted.push(prediction);
+        filtered.push((x, p));
+        state = (x, p);
+    }
+
+    // Backward pass
+    let mut smoothed = filtered.clone();
+    for k in (0..values.len() - 1).rev() {
+        let (xf, pf) = filtered[k];
+        let (xp, pp) = predicted[k + 1];
+        let (xs, ps) = smoothed[k + 1];
+        let c = pf * f.transpose() * pp.inverse();
+        smoothed[k] = (xf + c * (xs - xp), pf + c * (ps - pp) * c.transpose());
+    }
+    smoothed.into_iter().map(|(x, _)| x.x as f32).collect()
+}
+
+/// Smooth a whole sequence of transforms with a forward-backward (Rauch-Tung-Striebel) Kalman
+/// smoother
+///
+/// Each parameter is modeled as moving with a constant velocity. Unlike the [`SimilarityFilter`]
+/// this uses the future transforms too, so it has no lag. Higher `smoothness` gives steadier (but
+/// less responsive) trajectories.
+pub fn kalman_smooth(
+    transforms: &[SimilarityTransform],
+    smoothness: f32,
+) -> Vec<SimilarityTransform> {
+    let smoothness = f64::from(smoothness.max(f32::EPSILON));
+    let smooth = |values: Vec<f32>| rts_smooth(&values, smoothness);
+    let x = smooth(transforms.iter().map(|t| t.translation.x).collect());
+    let y = smooth(transforms.iter().map(|t| t.translation.y).collect());
+    let rotation = smooth(unwrap_angles(transforms.iter().map(|t| t.rotation)));
+    let scale = smooth(transforms.iter().map(|t| t.scale).collect());
+    (0..transforms.len())
+        .map(|i| SimilarityTransform {
+            translation: Vec2::new(x[i], y[i]),
+            rotation: rotation[i],
+            scale: scale[i],
+        })
+        .collect()
+}
+
+/// Forward differences of `x`
+fn diff(x: &[f64]) -> Vec<f64> {
+    x.windows(2).map(|w| w[1] - w[0]).collect()
+}
+
+/// Transpose of [`diff`]
+fn diff_t(y: &[f64]) -> Vec<f64> {
+    (0..=y.len())
+        .map(|i| {
+            let before = if i > 0 { y[i - 1] } else { 0.0 };
+            before - y.get(i).copied().unwrap_or(0.0)
+        })
+        .collect()
+}
+
+/// `D_k x` for the difference operators of order 1, 2 and 3
+fn differences(x: &[f64]) -> [Vec<f64>; 3] {
+    let first = diff(x);
+    let second = diff(&first);
+    let third = diff(&second);
+    [first, second, third]
+}
+
+/// `Σ D_kᵀ y_k`
+fn differences_t(y: &[Vec<f64>; 3]) -> Vec<f64> {
+    let [first, second, third] = y;
+    let mut third = diff_t(third);
+    for (a, b) in third.iter_mut().zip(second) {
+        *a += b;
+    }
+    let mut second = diff_t(&third);
+    for (a, b) in second.iter_mut().zip(first) {
+        *a += b;
+    }
+    diff_t(&second)
+}
+
+/// Solve `(I + ρ Σ D_kᵀ D_k) x = b` with conjugate gradients, starting from `x`
+fn conjugate_gradient(x: &mut [f64], b: &[f64]) {
+    let apply = |x: &[f64]| -> Vec<f64> {
+        let dtd = differences_t(&differences(x));
+        x.iter().zip(dtd).map(|(x, d)| x + L1_RHO * d).collect()
+    };
+    let dot = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(a, b)| a * b).sum::<f64>();
+    let mut r: Vec<_> = b.iter().zip(apply(x)).map(|(b, ax)| b - ax).collect();
+    let mut p = r.clone();
+    let mut rr = dot(&r, &r);
+    for _ in 0..x.len() {
+        if rr <= 1e-18 * b.len() as f64 {
+            break;
+        }
+        let ap = apply(&p);
+        let alpha = rr / dot(&p, &ap);
+        for ((x, r), (p, ap)) in x.iter_mut().zip(&mut r).zip(p.iter().zip(&ap)) {
+            *x += alpha * p;
+            *r -= alpha * ap;
+        }
+        let next = dot(&r, &r);
+        for (p, r) in p.iter_mut().zip(&r) {
+            *p = r + next / rr * *p;
+        }
+        rr = next;
+    }
+}
+
+/// Minimize `½‖x - values‖² + Σ w_k ‖D_k x‖₁` with ADMM
+///
+/// The L1 norms of the differences make the result piecewise constant/linear/parabolic.
+fn l1_smooth_signal(values: &[f32], strength: f64) -> Vec<f32> {
+    if values.len() < 4 {
+        return values.to_vec();
+    }
+    // Normalize by the noise (estimated from the median absolute difference), so the strength
+    // does not depend on the units of the parameter
+    let mut deltas: Vec<_> = values.windows(2).map(|w| (w[1] - w[0]).abs()).collect();
+    deltas.sort_by(f32::total_cmp);
+    let noise = f64::from(deltas[deltas.len() / 2]);
+    if noise <= f64::EPSILON {
+        return values.to_vec();
+    }
+    let c: Vec<_> = values.iter().map(|&v| f64::from(v) / noise).collect();
+
+    let mut x = c.clone();
+    let mut z = differences(&x);
+    let mut u = z.clone().map(|d| vec![0.0; d.len()]);
+    for _ in 0..L1_ITERATIONS {
+        // x = argmin ½‖x - c‖² + ρ/2 Σ ‖D_k x - z_k + u_k‖²
+        let zu: [Vec<_>; 3] =
+            std::array::from_fn(|k| z[k].iter().zip(&u[k]).map(|(z, u)| z - u).collect());
+        let b: Vec<_> = c
+            .iter()
+            .zip(differences_t(&zu))
+            .map(|(c, d)| c + L1_RHO * d)
+            .collect();
+        conjugate_gradient(&mut x, &b);
+        // z = argmin w_k ‖z_k‖₁ + ρ/2 ‖D_k x - z_k + u_k‖² (soft thresholding)
+        let dx = differences(&x);
+        for (((z, u), dx), weight) in z.iter_mut().zip(&mut u).zip(&dx).zip(L1_WEIGHTS) {
+            let threshold = strength * weight / L1_RHO;
+            for ((z, u), dx) in z.iter_mut().zip(u.iter_mut()).zip(dx) {
+                let v = dx + *u;
+                *z = v.signum() * (v.abs() - threshold).max(0.0);
+                *u = v - *z;
+            }
+        }
+    }
+    x.into_iter().map(|x| (x * noise) as f32).collect()
+}
+
+/// Smooth a whole sequence of transforms so that each parameter follows a "cinematic" path
+///
+/// Based on the L1 optimal camera paths of Grundmann et al. (the YouTube stabilizer): the
+/// trajectory is made of static, linear and parabolic segments instead of following every small
+/// movement. The hard crop constraints of the paper are replaced by a quadratic penalty on the
+/// distance to the original trajectory; higher `strength` gives steadier (but less faithful)
+/// paths.
+pub fn l1_smooth(transforms: &[SimilarityTransform], strength: f32) -> Vec<SimilarityTransform> {
+    let strength = f64::from(strength.max(0.0));
+    let smooth = |values: Vec<f32>| l1_smooth_signal(&values, strength);
+    let x = smooth(transforms.iter().map(|t| t.translation.x).collect());
+    let y = smooth(transforms.iter().map(|t| t.translation.y).collect());
+    let rotation = smooth(unwrap_angles(transforms.iter().map(|t| t.rotation)));
+    let scale = smooth(transforms.iter().map(|t| t.scale).collect());
+    (0..transforms.len())
+        .map(|i| SimilarityTransform {
+            translation: Vec2::new(x[i], y[i]),
+            rotation: rotation[i],
+            scale: scale[i],
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_signal_is_unchanged() {
+        let mut filter = OneEuroFilter::default();
+        for _ in 0..10 {
+            assert_eq!(filter.filter(3.5), 3.5);
+        }
+    }
+
+    #[test]
+    fn reduces_jitter() {
+        let mut filter = OneEuroFilter::default();
+        let noisy: Vec<_> = (0..100)
+            .map(|i| 10.0 + if i % 2 == 0 { 1.0 } else { -1.0 })
+            .collect();
+        let filtered: Vec<_> = noisy.iter().map(|&v| filter.filter(v)).collect();
+        let error = |values: &[f32]| values[50..].iter().map(|v| (v - 10.0).abs()).sum::<f32>();
+        assert!(error(&filtered) < error(&noisy) / 2.0);
+    }
+
+    #[test]
+    fn kalman_follows_linear_motion() {
+        // Constant velocity with alternating noise
+        let transforms: Vec<_> = (0..50)
+            .map(|i| SimilarityTransform {
+                translation: Vec2::new(i as f32 + if i % 2 == 0 { 0.5 } else { -0.5 }, 3.0),
+                rotation: 0.1,
+                scale: 2.0,
+            })
+            .collect();
+        let smoothed = kalman_smooth(&transforms, DEFAULT_SMOOTHNESS);
+        assert_eq!(smoothed.len(), transforms.len());
+        for (i, t) in smoothed.iter().enumerate().skip(5).take(40) {
+            assert!((t.translation.x - i as f32).abs() < 0.2, "{i}: {t:?}");
+            assert!((t.translation.y - 3.0).abs() < 1e-3);
+            assert!((t.rotation - 0.1).abs() < 1e-3);
+            assert!((t.scale - 2.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn kalman_handles_short_sequences() {
+        assert!(kalman_smooth(&[], DEFAULT_SMOOTHNESS).is_empty());
+        let one = SimilarityTransform {
+            translation: Vec2::ONE,
+            rotation: 0.0,
+            scale: 1.0,
+        };
+        let smoothed = kalman_smooth(&[one], DEFAULT_SMOOTHNESS);
+        assert!(smoothed[0].translation.distance(one.translation) < 1e-3);
+    }
+
+    #[test]
+    fn l1_flattens_static_segments() {
+        // A static camera that pans to a new position, with noise
+        let noise = |i: usize| [0.4, -0.3, 0.1, -0.5, 0.3][i % 5];
+        let values: Vec<_> = (0..60)
+            .map(|i| match i {
+                0..=19 => 0.0,
+                20..=39 => (i - 20) as f32,
+                _ => 20.0,
+            } + noise(i))
+            .collect();
+        let smoothed = l1_smooth_signal(&values, f64::from(DEFAULT_L1_STRENGTH));
+        let flat = |segment: &[f32]| {
+            let spread = segment
+                .iter()
+                .map(|&v| (v - segment[0]).abs())
+                .fold(0.0, f32::max);
+            assert!(spread < 0.05, "{segment:?}");
+        };
+        flat(&smoothed[..10]);
+        flat(&smoothed[50..]);
+        // No micro-movements in between, it only moves forward
+        assert!(
+            smoothed.windows(2).all(|w| w[1] - w[0] > -0.01),
+            "{smoothed:?}"
+        );
+    }
+
+    #[test]
+    fn l1_handles_short_sequences() {
+        assert!(l1_smooth(&[], DEFAULT_L1_STRENGTH).is_empty());
+        let one = SimilarityTransform {
+            translation: Vec2::ONE,
+            rotation: 0.0,
+            scale: 1.0,
+        };
+        assert_eq!(l1_smooth(&[one], DEFAULT_L1_STRENGTH), [one]);
+    }
+
+    #[test]
+    fn unwraps_rotation() {
+        let mut filter = SimilarityFilter::default();
+        let transform = |rotation| SimilarityTransform {
+            translation: Vec2::ZERO,
+            rotation,
+            scale: 1.0,
+        };
+        filter.filter(transform(std::f32::consts::PI - 0.01));
+        let filtered = filter.filter(transform(-std::f32::consts::PI + 0.01));
+        // Stays close to ±π instead of passing through 0
+        assert!(filtered.rotation.cos() < -0.99, "{}", filtered.rotation);
+    }
+}
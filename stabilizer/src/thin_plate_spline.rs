@@ -0,0 +1,237 @@
+//! Thin-plate spline warping: a smooth non-rigid alternative to
+//! [`piecewise_affine`](crate::piecewise_affine)
+//!
+//! The landmarks are first aligned with a [`procrustes_superimposition`], the remaining residuals
+//! are interpolated with a (regularized) thin-plate spline.
+
+use glam::DVec2;
+use glam::Vec2;
+use image::Rgb;
+use image::RgbImage;
+use imageproc::geometric_transformations::warp_with;
+use imageproc::geometric_transformations::Interpolation;
+
+use crate::linalg::solve;
+use crate::procrustes_superimposition;
+use crate::SimilarityTransform;
+
+/// Distance (in pixels) between the points where the spline is evaluated when warping, the
+/// rest are bilinearly interpolated
+pub const GRID_STEP: u32 = 4;
+
+/// Radial basis function of the thin-plate spline (`r² log r`)
+fn kernel(r2: f64) -> f64 {
+    if r2 <= f64::EPSILON {
+        0.0
+    } else {
+        r2 * r2.ln() / 2.0
+    }
+}
+
+/// A thin-plate spline mapping `points` onto `target`
+#[derive(Debug, Clone)]
+pub struct ThinPlateSpline {
+    /// Global alignment of the points
+    global: SimilarityTransform,
+    /// Centroid and scale used to normalize the points (makes the regularization scale invariant)
+    centroid: DVec2,
+    scale: f64,
+    /// Normalized control points
+    centers: Vec<DVec2>,
+    /// Weights of the radial basis functions
+    weights: Vec<DVec2>,
+    /// Affine part of the residual spline (constant, x and y coefficients)
+    affine: [DVec2; 3],
+}
+
+impl ThinPlateSpline {
+    /// Fit a thin-plate spline that maps `points` to `target`
+    ///
+    /// `regularization` (λ ≥ 0) controls how much local deformation is allowed: with `0.0` the
+    /// landmarks are matched exactly, as it grows the result approaches the global similarity
+    /// transform (plus the least squares affine correction of its residuals).
+    ///
+    /// Returns [`None`] if the lengths don't match, there are less than three points or they are
+    /// degenerate (e.g. all on a line)
+    pub fn fit(
+        target: impl IntoIterator<Item = Vec2>,
+        points: impl IntoIterator<Item = Vec2>,
+        regularization: f32,
+    ) -> Option<Self> {
+        let target: Vec<_> = target.into_iter().collect();
+        let points: Vec<_> = points.into_iter().collect();
+        let n = points.len();
+        if target.len() != n || n < 3 {
+            return None;
+        }
+        let global = procrustes_superimposition(target.iter().copied(), points.iter().copied())?;
+
+        let centroid = points.iter().map(|p| p.as_dvec2()).sum::<DVec2>() / n as f64;
+        let scale = points
+            .iter()
+            .map(|p| p.as_dvec2().distance(centroid))
+            .sum::<f64>()
+            / n as f64;
+        if scale <= f64::EPSILON {
+            return None;
+        }
+        let centers: Vec<_> = points
+            .iter()
+            .map(|p| (p.as_dvec2() - centroid) / scale)
+            .collect();
+        let residuals = target
+            .iter()
+            .zip(&points)
+            .map(|(&t, &p)| (t - global.transform_point(p)).as_dvec2());
+
+        // [K + λI, P; Pᵀ, 0] [w; a] = [r; 0]
+        let mut a = vec![vec![0.0; n + 3]; n + 3];
+        for (i, ci) in centers.iter().enumerate() {
+            for (j, cj) in centers.iter().enumerate() {
+                a[i][j] = kernel(ci.distance_squared(*cj));
+            }
+            a[i][i] += f64::from(regularization);
+            for (k, v) in [1.0, ci.x, ci.y].into_iter().enumerate() {
+                a[i][n + k] = v;
+                a[n + k][i] = v;
+            }
+        }
+        let b: Vec<_> = residuals.chain([DVec2::ZERO; 3]).collect();
+        let mut x = solve(a, b)?;
+        let affine = [x[n], x[n + 1], x[n + 2]];
+        x.truncate(n);
+
+        Some(Self {
+            global,
+            centroid,
+            scale,
+            centers,
+            weights: x,
+            affine,
+        })
+    }
+
+    /// Map a point
+    pub fn transform(&self, point: Vec2) -> Vec2 {
+        let p = (point.as_dvec2() - self.centroid) / self.scale;
+        let [a0, ax, ay] = self.affine;
+        let residual = self
+            .centers
+            .iter()
+            .zip(&self.weights)
+            .map(|(c, &w)| w * kernel(c.distance_squared(p)))
+            .sum::<DVec2>()
+            + a0
+            + ax * p.x
+            + ay * p.y;
+        self.global.transform_point(point) + residual.as_vec2()
+    }
+}
+
+/// Warp `image` so that the landmarks at `source` end up at `target`
+///
+/// See [`ThinPlateSpline::fit`] for the meaning of `regularization`. The output has the same size
+/// as the input, pixels outside of the image are set to `default`.
+///
+/// Returns [`None`] if the spline can't be fitted
+pub fn warp(
+    image: &RgbImage,
+    source: &[Vec2],
+    target: &[Vec2],
+    regularization: f32,
+    default: Rgb<u8>,
+) -> Option<RgbImage> {
+    // Maps the output (target) to the input (source)
+    let spline = ThinPlateSpline::fit(
+        source.iter().copied(),
+        target.iter().copied(),
+        regularization,
+    )?;
+
+    // Evaluate the spline on a coarse grid
+    let (width, height) = image.dimensions();
+    let (columns, rows) = (width / GRID_STEP + 2, height / GRID_STEP + 2);
+    let grid: Vec<_> = (0..rows)
+        .flat_map(|row| (0..columns).map(move |column| (column, row)))
+        .map(|(column, row)| {
+            let p = Vec2::new((column * GRID_STEP) as f32, (row * GRID_STEP) as f32);
+            spline.transform(p)
+        })
+        .collect();
+
+    Some(warp_with(
+        image,
+        |x, y| {
+            let (gx, gy) = (x / GRID_STEP as f32, y / GRID_STEP as f32);
+            let (column, row) = (gx as u32, gy as u32);
+            if column + 1 >= columns || row + 1 >= rows {
+                return spline.transform(Vec2::new(x, y)).into();
+            }
+            let (fx, fy) = (gx.fract(), gy.fract());
+            let at = |column: u32, row: u32| grid[(row * columns + column) as usize];
+            let top = at(column, row).lerp(at(column + 1, row), fx);
+            let bottom = at(column, row + 1).lerp(at(column + 1, row + 1), fx);
+            top.lerp(bottom, fy).into()
+        },
+        Interpolation::Bilinear,
+        default,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn landmarks() -> Vec<Vec2> {
+        vec![
+            Vec2::new(4.0, 4.0),
+            Vec2::new(27.0, 5.0),
+            Vec2::new(16.0, 16.0),
+            Vec2::new(6.0, 26.0),
+            Vec2::new(25.0, 27.0),
+            Vec2::new(14.0, 30.0),
+        ]
+    }
+
+    #[test]
+    fn interpolates_landmarks() {
+        let points = landmarks();
+        let target: Vec<_> = points
+            .iter()
+            .enumerate()
+            .map(|(i, &p)| p + Vec2::new((i % 2) as f32, (i % 3) as f32))
+            .collect();
+        let spline =
+            ThinPlateSpline::fit(target.iter().copied(), points.iter().copied(), 0.0).unwrap();
+        for (&t, &p) in target.iter().zip(&points) {
+            let got = spline.transform(p);
+            assert!(got.distance(t) < 1e-3, "{got} != {t}");
+        }
+    }
+
+    #[test]
+    fn regularization_approaches_similarity() {
+        let points = landmarks();
+        // A similarity plus some local deformation
+        let target: Vec<_> = points
+            .iter()
+            .enumerate()
+            .map(|(i, &p)| p * 1.5 + Vec2::new(3.0, -2.0) + Vec2::new((i % 2) as f32, 0.0))
+            .collect();
+        let global =
+            procrustes_superimposition(target.iter().copied(), points.iter().copied()).unwrap();
+        let spline =
+            ThinPlateSpline::fit(target.iter().copied(), points.iter().copied(), 1e6).unwrap();
+        let p = Vec2::new(10.0, 20.0);
+        // Only the small affine correction remains
+        assert!(spline.transform(p).distance(global.transform_point(p)) < 1.0);
+    }
+
+    #[test]
+    fn needs_three_points() {
+        let points = &landmarks()[..2];
+        assert!(
+            ThinPlateSpline::fit(points.iter().copied(), points.iter().copied(), 0.0).is_none()
+        );
+    }
+}
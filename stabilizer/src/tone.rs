@@ -0,0 +1,265 @@
+use image::RgbImage;
+use imageproc::rect::Rect;
+
+/// Default number of images on each side of the moving median of [`deflicker_corrections`]
+pub const DEFAULT_DEFLICKER_RADIUS: usize = 5;
+
+/// Brightness statistics of a region of an image, see [`luma_stats`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LumaStats {
+    pub mean: f32,
+    /// Standard deviation (the contrast)
+    pub std_dev: f32,
+}
+
+/// A linear brightness correction: `v' = gain · v + offset`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneCorrection {
+    pub gain: f32,
+    pub offset: f32,
+}
+
+impl ToneCorrection {
+    /// The correction that does nothing
+    pub const IDENTITY: Self = Self {
+        gain: 1.0,
+        offset: 0.0,
+    };
+
+    /// The correction that gives `from` the statistics of `to`
+    ///
+    /// Only corrects the brightness if `from` has no contrast
+    pub fn between(from: LumaStats, to: LumaStats) -> Self {
+        let gain = if from.std_dev > f32::EPSILON {
+            to.std_dev / from.std_dev
+        } else {
+            1.0
+        };
+        Self {
+            gain,
+            offset: to.mean - gain * from.mean,
+        }
+    }
+
+    /// Correct every channel of the image
+    pub fn apply(&self, image: &mut RgbImage) {
+        for value in image.iter_mut() {
+            *value = (self.gain * f32::from(*value) + self.offset)
+                .round()
+                .clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+/// A 256 bin histogram of each channel, see [`color_histograms`]
+pub type Histograms = [[u64; 256]; 3];
+
+/// Maps the values of each channel through a lookup table (e.g. [`ColorMap::matching`])
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorMap(pub [[u8; 256]; 3]);
+
+impl ColorMap {
+    /// The map that gives an image with the `source` histograms the `reference` ones (histogram
+    /// matching)
+    ///
+    /// `strength` blends between the unchanged values (0) and the matched ones (1)
+    pub fn matching(source: &Histograms, reference: &Histograms, strength: f32) -> Self {
+        let cdf = |histogram: &[u64; 256]| {
+            let total = histogram.iter().sum::<u64>().max(1) as f64;
+            let mut sum = 0;
+            histogram.map(|count| {
+                sum += count;
+                sum as f64 / total
+            })
+        };
+        let mut map = [[0; 256]; 3];
+        for ((map, source), reference) in map.iter_mut().zip(source).zip(reference) {
+            let (source, reference) = (cdf(source), cdf(reference));
+            for (value, mapped) in map.iter_mut().enumerate() {
+                // The first reference value that is as common as this one
+                let matched = reference
+                    .iter()
+                    .position(|&r| r >= source[value] - 1e-9)
+                    .unwrap_or(255) as f32;
+                let value = value as f32;
+                *mapped = (value + strength * (matched - value))
+                    .round()
+                    .clamp(0.0, 255.0) as u8;
+            }
+        }
+        Self(map)
+    }
+
+    /// Map every pixel of the image
+    pub fn apply(&self, image: &mut RgbImage) {
+        for pixel in image.pixels_mut() {
+            for (value, map) in pixel.0.iter_mut().zip(&self.0) {
+                *value = map[usize::from(*value)];
+            }
+        }
+    }
+}
+
+/// The histograms of the channels in `region` of the image
+///
+/// Returns [`None`] if the region is outside of the image
+pub fn color_histograms(image: &RgbImage, region: Rect) -> Option<Histograms> {
+    let (left, top, right, bottom) = clip(image, region)?;
+    let mut histograms = [[0; 256]; 3];
+    for y in top..bottom {
+        for x in left..right {
+            for (histogram, &value) in histograms.iter_mut().zip(&image.get_pixel(x, y).0) {
+                histogram[usize::from(value)] += 1;
+            }
+        }
+    }
+    Some(histograms)
+}
+
+/// The `(left, top, right, bottom)` (exclusive) of the part of `region` inside of the image
+fn clip(image: &RgbImage, region: Rect) -> Option<(u32, u32, u32, u32)> {
+    let left = region.left().max(0) as u32;
+    let top = region.top().max(0) as u32;
+    let right = (region.right() + 1).clamp(0, image.width() as i32) as u32;
+    let bottom = (region.bottom() + 1).clamp(0, image.height() as i32) as u32;
+    (left < right && top < bottom).then_some((left, top, right, bottom))
+}
+
+/// Luminance (Rec. 601) of a pixel
+fn luma([r, g, b]: [u8; 3]) -> f32 {
+    0.299 * f32::from(r) + 0.587 * f32::from(g) + 0.114 * f32::from(b)
+}
+
+/// Mean and standard deviation of the luminance in `region` of the image
+///
+/// Returns [`None`] if the region is outside of the image
+pub fn luma_stats(image: &RgbImage, region: Rect) -> Option<LumaStats> {
+    let (left, top, right, bottom) = clip(image, region)?;
+    let (mut sum, mut sum_sq) = (0.0f64, 0.0f64);
+    for y in top..bottom {
+        for x in left..right {
+            let value = f64::from(luma(image.get_pixel(x, y).0));
+            sum += value;
+            sum_sq += value * value;
+        }
+    }
+    let count = f64::from((right - left) * (bottom - top));
+    let mean = sum / count;
+    let variance = (sum_sq / count - mean * mean).max(0.0);
+    Some(LumaStats {
+        mean: mean as f32,
+        std_dev: variance.sqrt() as f32,
+    })
+}
+
+/// The corrections that remove the flicker of a sequence of images with these statistics
+///
+/// Each image is corrected towards the moving median of the `radius` images on each side of it,
+/// so slow changes (e.g. the seasons) are kept while the jumps between images are removed. The
+/// median ignores the odd flash or dark image instead of spreading it to its neighbours.
+pub fn deflicker_corrections(stats: &[LumaStats], radius: usize) -> Vec<ToneCorrection> {
+    let median = |mut values: Vec<f32>| {
+        values.sort_by(f32::total_cmp);
+        values[values.len() / 2]
+    };
+    (0..stats.len())
+        .map(|ix| {
+            let window = &stats[ix.saturating_sub(radius)..(ix + radius + 1).min(stats.len())];
+            let target = LumaStats {
+                mean: median(window.iter().map(|s| s.mean).collect()),
+                std_dev: median(window.iter().map(|s| s.std_dev).collect()),
+            };
+            ToneCorrection::between(stats[ix], target)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    fn stats(mean: f32, std_dev: f32) -> LumaStats {
+        LumaStats { mean, std_dev }
+    }
+
+    #[test]
+    fn stats_of_a_flat_image() {
+        let image = RgbImage::from_pixel(10, 10, Rgb([100, 100, 100]));
+        let stats = luma_stats(&image, Rect::at(2, 2).of_size(4, 4)).unwrap();
+        assert!((stats.mean - 100.0).abs() < 1e-3);
+        assert!(stats.std_dev.abs() < 1e-3);
+    }
+
+    #[test]
+    fn region_outside_of_the_image() {
+        let image = RgbImage::new(10, 10);
+        assert!(luma_stats(&image, Rect::at(20, 20).of_size(4, 4)).is_none());
+    }
+
+    #[test]
+    fn correction_matches_the_stats() {
+        let (from, to) = (stats(80.0, 10.0), stats(120.0, 20.0));
+        let correction = ToneCorrection::between(from, to);
+        assert!((correction.gain - 2.0).abs() < 1e-5);
+        assert!((correction.gain * from.mean + correction.offset - to.mean).abs() < 1e-3);
+    }
+
+    #[test]
+    fn apply_clamps() {
+        let mut image = RgbImage::from_pixel(1, 1, Rgb([200, 100, 0]));
+        let correction = ToneCorrection {
+            gain: 2.0,
+            offset: -10.0,
+        };
+        correction.apply(&mut image);
+        assert_eq!(image.get_pixel(0, 0), &Rgb([255, 190, 0]));
+    }
+
+    #[test]
+    fn deflicker_evens_out_a_flash() {
+        let mut sequence = vec![stats(100.0, 10.0); 7];
+        sequence[3] = stats(160.0, 10.0);
+        let corrections = deflicker_corrections(&sequence, 3);
+        // The flash is darkened to match the rest and its neighbours don't change
+        let flash = corrections[3].gain * 160.0 + corrections[3].offset;
+        assert!((flash - 100.0).abs() < 1e-3, "{flash}");
+        assert_eq!(corrections[0], ToneCorrection::IDENTITY);
+    }
+
+    #[test]
+    fn matching_itself_is_the_identity() {
+        let image = RgbImage::from_fn(16, 16, |x, y| Rgb([(x * 16) as u8, (y * 16) as u8, 7]));
+        let region = Rect::at(0, 0).of_size(16, 16);
+        let histograms = color_histograms(&image, region).unwrap();
+        let map = ColorMap::matching(&histograms, &histograms, 1.0);
+        let mut mapped = image.clone();
+        map.apply(&mut mapped);
+        assert_eq!(mapped, image);
+    }
+
+    #[test]
+    fn matching_shifts_the_colors() {
+        let dark = RgbImage::from_fn(16, 16, |x, _| Rgb([(x * 8) as u8, 50, 50]));
+        let bright = RgbImage::from_fn(16, 16, |x, _| Rgb([(x * 8 + 100) as u8, 150, 50]));
+        let region = Rect::at(0, 0).of_size(16, 16);
+        let source = color_histograms(&dark, region).unwrap();
+        let reference = color_histograms(&bright, region).unwrap();
+        let mut mapped = dark.clone();
+        ColorMap::matching(&source, &reference, 1.0).apply(&mut mapped);
+        assert_eq!(mapped, bright);
+        // Half the strength moves the colors half of the way
+        let mut half = dark.clone();
+        ColorMap::matching(&source, &reference, 0.5).apply(&mut half);
+        assert_eq!(half.get_pixel(0, 0), &Rgb([50, 100, 50]));
+    }
+
+    #[test]
+    fn steady_sequence_is_unchanged() {
+        let sequence = vec![stats(100.0, 10.0); 5];
+        for correction in deflicker_corrections(&sequence, 2) {
+            assert!((correction.gain - 1.0).abs() < 1e-5);
+            assert!(correction.offset.abs() < 1e-3);
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use image::DynamicImage;
+use image::ImageBuffer;
+use image::Rgb;
+use image::RgbImage;
+use image::Rgba;
+use imageproc::geometric_transformations::warp_into_with;
+use imageproc::geometric_transformations::Projection;
+
+/// Pixels added around the image when extending its edges, so the interpolation can sample the
+/// border pixels
+const PADDING: u32 = 2;
+
+/// How to fill the parts of the output that fall outside of the input image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fill {
+    /// A solid color
+    Constant(Rgb<u8>),
+    /// Repeat the pixels at the edges of the image
+    Clamp,
+    /// Reflect the image at its edges
+    Mirror,
+    /// Transparent pixels (the output has an alpha channel)
+    Transparent,
+}
+
+impl Default for Fill {
+    fn default() -> Self {
+        Self::Constant(Rgb([0, 0, 0]))
+    }
+}
+
+/// Parses `black`, `white` or a `rrggbb` color (optionally starting with `#`)
+pub fn parse_color(s: &str) -> Result<Rgb<u8>, String> {
+    match s {
+        "black" => Ok(Rgb([0, 0, 0])),
+        "white" => Ok(Rgb([255, 255, 255])),
+        _ => {
+            let hex = s.strip_prefix('#').unwrap_or(s);
+            let color = (hex.len() == 6)
+                .then(|| u32::from_str_radix(hex, 16).ok())
+                .flatten()
+                .ok_or_else(|| format!("expected black, white or rrggbb, found {s}"))?;
+            let [_, r, g, b] = color.to_be_bytes();
+            Ok(Rgb([r, g, b]))
+        }
+    }
+}
+
+/// Parses `constant` (black), `clamp` (or `edge`), `mirror`, `transparent` or a color (see
+/// [`parse_color`])
+impl std::str::FromStr for Fill {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "constant" => Ok(Self::default()),
+            "clamp" | "edge" => Ok(Self::Clamp),
+            "mirror" => Ok(Self::Mirror),
+            "transparent" => Ok(Self::Transparent),
+            _ => parse_color(s).map(Self::Constant).map_err(|_| {
+                format!(
+                    "expected constant, clamp, edge, mirror, transparent, black, white or rrggbb, \
+                     found {s}"
+                )
+            }),
+        }
+    }
+}
+
+/// How to sample the input between its pixels
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    /// The closest pixel (fastest, blocky)
+    Nearest,
+    /// Weighted average of the 4 closest pixels
+    Bilinear,
+    /// Cubic convolution of the 16 closest pixels (slowest, sharpest)
+    #[default]
+    Bicubic,
+}
+
+impl From<Interpolation> for imageproc::geometric_transformations::Interpolation {
+    fn from(value: Interpolation) -> Self {
+        match value {
+            Interpolation::Nearest => Self::Nearest,
+            Interpolation::Bilinear => Self::Bilinear,
+            Interpolation::Bicubic => Self::Bicubic,
+        }
+    }
+}
+
+/// Parses `nearest`, `bilinear` or `bicubic`
+impl std::str::FromStr for Interpolation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(Self::Nearest),
+            "bilinear" => Ok(Self::Bilinear),
+            "bicubic" => Ok(Self::Bicubic),
+            _ => Err(format!("expected nearest, bilinear or bicubic, found {s}")),
+        }
+    }
+}
+
+/// Fold a coordinate into `[0, len - 1]` by clamping it
+fn clamp(x: f32, len: u32) -> f32 {
+    x.clamp(0.0, len.saturating_sub(1) as f32)
+}
+
+/// Fold a coordinate into `[0, len - 1]` by reflecting it at the edges
+fn reflect(x: f32, len: u32) -> f32 {
+    let max = len.saturating_sub(1) as f32;
+    if max <= 0.0 {
+        return 0.0;
+    }
+    let x = x.rem_euclid(2.0 * max);
+    if x > max {
+        2.0 * max - x
+    } else {
+        x
+    }
+}
+
+/// Warp `image` with the [`Projection`], filling the pixels that fall outside of it according to
+/// `fill`
+///
+/// The output has the same size as the input (see [`warp_to_size`]), it is an RGBA image if `fill`
+/// is [`Fill::Transparent`] and an RGB image otherwise.
+pub fn warp(
+    image: &RgbImage,
+    projection: &Projection,
+    fill: Fill,
+    interpolation: Interpolation,
+) -> DynamicImage {
+    warp_to_size(image, projection, fill, interpolation, image.dimensions())
+}
+
+/// Same as [`warp`], but the output is `(width, height)` pixels
+pub fn warp_to_size(
+    image: &RgbImage,
+    projection: &Projection,
+    fill: Fill,
+    interpolation: Interpolation,
+    (out_width, out_height): (u32, u32),
+) -> DynamicImage {
+    let interpolation = interpolation.into();
+    let (width, height) = image.dimensions();
+    let inverse = projection.invert();
+    let source = |x: f32, y: f32| inverse * (x, y);
+    match fill {
+        Fill::Constant(color) => {
+            let mut out = ImageBuffer::new(out_width, out_height);
+            warp_into_with(image, source, interpolation, color, &mut out);
+            DynamicImage::ImageRgb8(out)
+        }
+        Fill::Transparent => {
+            let image = DynamicImage::ImageRgb8(image.clone()).into_rgba8();
+            let mut out = ImageBuffer::new(out_width, out_height);
+            let default = Rgba([0, 0, 0, 0]);
+            warp_into_with(&image, source, interpolation, default, &mut out);
+            DynamicImage::ImageRgba8(out)
+        }
+        Fill::Clamp | Fill::Mirror => {
+            let fold = if fill == Fill::Clamp { clamp } else { reflect };
+            // Extend the edges so the border pixels can be interpolated too
+            let padded = RgbImage::from_fn(width + 2 * PADDING, height + 2 * PADDING, |x, y| {
+                let x = fold(x as f32 - PADDING as f32, width) as u32;
+                let y = fold(y as f32 - PADDING as f32, height) as u32;
+                *image.get_pixel(x, y)
+            });
+            let mapping = |x, y| {
+                let (x, y) = source(x, y);
+                let pad = PADDING as f32;
+                (fold(x, width) + pad, fold(y, height) + pad)
+            };
+            let mut out = ImageBuffer::new(out_width, out_height);
+            let default = Rgb([0, 0, 0]);
+            warp_into_with(&padded, mapping, interpolation, default, &mut out);
+            DynamicImage::ImageRgb8(out)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient() -> RgbImage {
+        RgbImage::from_fn(16, 16, |x, y| Rgb([(x * 16) as u8, (y * 16) as u8, 100]))
+    }
+
+    #[test]
+    fn parses_fills() {
+        assert_eq!("mirror".parse(), Ok(Fill::Mirror));
+        assert_eq!("#ff8000".parse(), Ok(Fill::Constant(Rgb([255, 128, 0]))));
+        assert!("#ff80".parse::<Fill>().is_err());
+        assert_eq!("edge".parse(), Ok(Fill::Clamp));
+        assert_eq!(parse_color("00ff80"), Ok(Rgb([0, 255, 128])));
+        assert!(parse_color("mirror").is_err());
+    }
+
+    #[test]
+    fn nearest_keeps_the_pixel_values() {
+        let image = gradient();
+        let projection = Projection::translate(0.3, 0.0);
+        let nearest = warp(&image, &projection, Fill::Clamp, Interpolation::Nearest).into_rgb8();
+        assert!(nearest.pixels().all(|p| p.0[0] % 16 == 0), "{nearest:?}");
+        assert_eq!("bilinear".parse(), Ok(Interpolation::Bilinear));
+        assert!("lanczos".parse::<Interpolation>().is_err());
+    }
+
+    #[test]
+    fn warps_to_a_larger_canvas() {
+        let image = gradient();
+        let projection = Projection::translate(10.0, 4.0);
+        let out = warp_to_size(
+            &image,
+            &projection,
+            Fill::default(),
+            Interpolation::Nearest,
+            (40, 20),
+        )
+        .into_rgb8();
+        assert_eq!(out.dimensions(), (40, 20));
+        assert_eq!(*out.get_pixel(15, 9), *image.get_pixel(5, 5));
+        assert_eq!(*out.get_pixel(35, 9), Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn reflects_coordinates() {
+        assert_eq!(reflect(-2.0, 10), 2.0);
+        assert_eq!(reflect(11.0, 10), 7.0);
+        assert_eq!(reflect(4.5, 10), 4.5);
+        assert_eq!(clamp(-3.0, 10), 0.0);
+        assert_eq!(clamp(12.0, 10), 9.0);
+    }
+
+    #[test]
+    fn fills_outside_pixels() {
+        let image = gradient();
+        // Move the image 8 pixels to the right
+        let projection = Projection::translate(8.0, 0.0);
+        let warp = |fill| warp(&image, &projection, fill, Interpolation::default());
+        let constant = warp(Fill::default()).into_rgb8();
+        assert_eq!(*constant.get_pixel(2, 8), Rgb([0, 0, 0]));
+        let clamped = warp(Fill::Clamp).into_rgb8();
+        assert_eq!(*clamped.get_pixel(2, 8), *image.get_pixel(0, 8));
+        let mirrored = warp(Fill::Mirror).into_rgb8();
+        assert_eq!(*mirrored.get_pixel(2, 8), *image.get_pixel(6, 8));
+        let transparent = warp(Fill::Transparent).into_rgba8();
+        assert_eq!(transparent.get_pixel(2, 8).0[3], 0);
+        assert_eq!(transparent.get_pixel(12, 8).0[3], 255);
+    }
+}